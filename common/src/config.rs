@@ -6,40 +6,53 @@ use std::net::IpAddr;
 #[derive(envconfig::Envconfig, Debug, Clone)]
 pub struct EnvConfig {
     #[envconfig(from = "DATABASE_URL")]
-    pub database_url: String,
+    pub database_url: Option<String>,
     #[envconfig(from = "LISTEN_ADDRS")]
     pub listen_addrs: Option<String>,
 }
 
-#[allow(clippy::new_without_default)]
 impl EnvConfig {
-    pub fn new() -> Self {
-        Self::init_from_env().expect("failed to read config from env")
+    pub fn new() -> Result<Self, String> {
+        Self::init_from_env().map_err(|err| format!("failed to read config from env: {}", err))
     }
 }
 
 //TODO: add port config
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub database_url: String,
+    //only needed by the postgres stores
+    pub database_url: Option<String>,
     pub listen_addrs: Vec<HostWithPort>,
     pub default_listen_addr: HostWithPort,
 }
 
 impl Default for Config {
     fn default() -> Self {
-        let env_config = EnvConfig::new();
-        let (default_listen_addr, listen_addrs) = figure_out_listen_addrs(env_config.listen_addrs);
+        Self::new(None, None).expect("failed to read config from env")
+    }
+}
+
+impl Config {
+    //values that are given, like the ones of a config file, win over the env vars
+    pub fn new(
+        database_url: Option<String>,
+        listen_addrs: Option<Vec<String>>,
+    ) -> Result<Self, String> {
+        let env_config = EnvConfig::new()?;
+        let database_url = database_url.or(env_config.database_url);
+        let (default_listen_addr, listen_addrs) = figure_out_listen_addrs(
+            listen_addrs
+                .map(|addrs| addrs.join(","))
+                .or(env_config.listen_addrs),
+        )?;
 
-        Self {
-            database_url: env_config.database_url,
+        Ok(Self {
+            database_url,
             listen_addrs,
             default_listen_addr,
-        }
+        })
     }
-}
 
-impl Config {
     pub fn default_addr(&self) -> HostWithPort {
         self.default_listen_addr.clone()
     }
@@ -53,54 +66,52 @@ impl Config {
     }
 }
 
-fn figure_out_listen_addrs(listen_env_addrs: Option<String>) -> (HostWithPort, Vec<HostWithPort>) {
+//addrs that don't parse are an error, instead of silently listening somewhere else
+fn figure_out_listen_addrs(
+    listen_env_addrs: Option<String>,
+) -> Result<(HostWithPort, Vec<HostWithPort>), String> {
     match listen_env_addrs {
         Some(listen_env_addrs) => match listen_env_addrs
             .split(',')
             .map(TryInto::try_into)
             .collect::<Result<Vec<HostWithPort>, rsip::Error>>()
         {
-            Ok(addrs) if !addrs.is_empty() => (
+            Ok(addrs) if !addrs.is_empty() => Ok((
                 addrs.first().cloned().expect("that shouldn't happen"),
                 addrs,
-            ),
+            )),
             Ok(_) => {
                 log::warn!("Found LISTEN_ADDRS env var but returned nothing");
-                let ip_addrs = all_system_ip_addrs();
-
-                (
-                    default_ip_addr_from(&ip_addrs).into(),
-                    ip_addrs.into_iter().map(Into::into).collect(),
-                )
-            }
-            Err(err) => {
-                log::warn!("Failed to parse LISTEN_ADDRS env var: {}", err);
-                let ip_addrs = all_system_ip_addrs();
-
-                (
-                    default_ip_addr_from(&ip_addrs).into(),
-                    ip_addrs.into_iter().map(Into::into).collect(),
-                )
+                system_listen_addrs()
             }
+            Err(err) => Err(format!(
+                "invalid listen addrs {}: {}",
+                listen_env_addrs, err
+            )),
         },
         None => {
             log::warn!("missing LISTEN_ADDRS env var, will use default system IPs");
-            let ip_addrs = all_system_ip_addrs();
-
-            (
-                default_ip_addr_from(&ip_addrs).into(),
-                ip_addrs.into_iter().map(Into::into).collect(),
-            )
+            system_listen_addrs()
         }
     }
 }
 
-fn default_ip_addr_from(ip_addrs: &[IpAddr]) -> IpAddr {
-    let default_ip_addr = ip_addrs
-        .iter()
-        .find(|ip| !ip.is_loopback() && !ip.is_multicast());
+fn system_listen_addrs() -> Result<(HostWithPort, Vec<HostWithPort>), String> {
+    let ip_addrs = all_system_ip_addrs();
+    let default_ip_addr = default_ip_addr_from(&ip_addrs).ok_or("no system IP to listen on")?;
+
+    Ok((
+        default_ip_addr.into(),
+        ip_addrs.into_iter().map(Into::into).collect(),
+    ))
+}
 
-    *default_ip_addr.unwrap_or_else(|| ip_addrs.first().expect("foo"))
+fn default_ip_addr_from(ip_addrs: &[IpAddr]) -> Option<IpAddr> {
+    ip_addrs
+        .iter()
+        .find(|ip| !ip.is_loopback() && !ip.is_multicast())
+        .or_else(|| ip_addrs.first())
+        .copied()
 }
 
 fn all_system_ip_addrs() -> Vec<IpAddr> {
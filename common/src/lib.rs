@@ -3,11 +3,6 @@ extern crate envconfig_derive;
 mod config;
 pub use config::Config;
 
-use once_cell::sync::Lazy;
-use std::sync::Arc;
-
-pub static CONFIG: Lazy<Arc<Config>> = Lazy::new(|| Arc::new(config::Config::default()));
-
 pub use async_trait;
 pub use bytes;
 pub use chrono;
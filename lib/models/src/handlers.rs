@@ -1,38 +1,16 @@
-use crate::{
-    transaction::{TransactionHandler, TransactionLayerMsg},
-    transport::{TransportHandler, TransportLayerMsg},
-    tu::{TuHandler, TuLayerMsg},
-};
-use common::tokio::sync::mpsc::Sender;
+use crate::{transaction::TransactionHandler, transport::TransportHandler, tu::TuHandler};
+use common::Config;
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct Handlers {
     pub tu: TuHandler,
     pub transaction: TransactionHandler,
     pub transport: TransportHandler,
+    //the config of the element that the handlers belong to
+    pub config: Arc<Config>,
 }
 
-impl
-    From<(
-        Sender<TuLayerMsg>,
-        Sender<TransactionLayerMsg>,
-        Sender<TransportLayerMsg>,
-    )> for Handlers
-{
-    fn from(
-        from: (
-            Sender<TuLayerMsg>,
-            Sender<TransactionLayerMsg>,
-            Sender<TransportLayerMsg>,
-        ),
-    ) -> Self {
-        Self {
-            tu: from.0.into(),
-            transaction: from.1.into(),
-            transport: from.2.into(),
-        }
-    }
-}
 /*
 pub struct Receivers {
     pub tu: Receiver<TuLayerMsg>,
@@ -13,14 +13,20 @@ pub use error::Error;
 pub use handlers::Handlers;
 pub use result_ext::ResultExt;
 
-use common::tokio::sync::mpsc::channel;
+use common::{tokio::sync::mpsc::channel, Config};
+use std::sync::Arc;
 
-pub fn channels_builder() -> (Handlers, receivers::Receivers) {
+pub fn channels_builder(config: Config) -> (Handlers, receivers::Receivers) {
     let (tu_tx, tu_rx) = channel(10);
     let (transaction_tx, transaction_rx) = channel(10);
     let (transport_tx, transport_rx) = channel(10);
 
-    let handlers = (tu_tx, transaction_tx, transport_tx).into();
+    let handlers = Handlers {
+        tu: tu_tx.into(),
+        transaction: transaction_tx.into(),
+        transport: transport_tx.into(),
+        config: Arc::new(config),
+    };
 
     let receivers = (tu_rx, transaction_rx, transport_rx).into();
 
@@ -4,6 +4,8 @@ use common::rsip::{self, headers::ToTypedHeader, message::HeadersExt};
 
 pub trait DialogExt {
     fn dialog_id(&self) -> Result<DialogId, Error>;
+    //dialog id as seen from the UAS side, where the To tag is the local tag
+    fn uas_dialog_id(&self) -> Result<DialogId, Error>;
 }
 
 impl DialogExt for rsip::Request {
@@ -19,6 +21,19 @@ impl DialogExt for rsip::Request {
 
         Ok(DialogId::new(call_id, local_tag, remote_tag))
     }
+
+    fn uas_dialog_id(&self) -> Result<DialogId, Error> {
+        let call_id = self.call_id_header()?;
+        let local_tag = self
+            .to_header()?
+            .typed()?
+            .tag()
+            .ok_or_else(|| Error::from("missing to tag"))?
+            .clone();
+        let remote_tag = self.from_header()?.typed()?.tag().cloned();
+
+        Ok(DialogId::new(call_id, local_tag, remote_tag))
+    }
 }
 
 impl DialogExt for rsip::Response {
@@ -34,6 +49,19 @@ impl DialogExt for rsip::Response {
 
         Ok(DialogId::new(call_id, local_tag, remote_tag))
     }
+
+    fn uas_dialog_id(&self) -> Result<DialogId, Error> {
+        let call_id = self.call_id_header()?;
+        let local_tag = self
+            .to_header()?
+            .typed()?
+            .tag()
+            .ok_or_else(|| Error::from("missing to tag"))?
+            .clone();
+        let remote_tag = self.from_header()?.typed()?.tag().cloned();
+
+        Ok(DialogId::new(call_id, local_tag, remote_tag))
+    }
 }
 
 impl DialogExt for rsip::SipMessage {
@@ -43,4 +71,11 @@ impl DialogExt for rsip::SipMessage {
             Self::Response(response) => response.dialog_id(),
         }
     }
+
+    fn uas_dialog_id(&self) -> Result<DialogId, Error> {
+        match self {
+            Self::Request(request) => request.uas_dialog_id(),
+            Self::Response(response) => response.uas_dialog_id(),
+        }
+    }
 }
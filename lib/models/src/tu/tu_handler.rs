@@ -15,6 +15,10 @@ impl TuHandler {
         Ok(self.tx.send(TuLayerMsg::Incoming(msg)).await?)
     }
 
+    pub async fn outgoing(&self, msg: rsip::SipMessage) -> Result<(), Error> {
+        Ok(self.tx.send(TuLayerMsg::Outgoing(msg)).await?)
+    }
+
     pub async fn transport_error(&self, msg: rsip::SipMessage, error: String) -> Result<(), Error> {
         Ok(self.tx.send(TuLayerMsg::TransportError(msg, error)).await?)
     }
@@ -0,0 +1,149 @@
+use crate::Error;
+use std::{fmt, str::FromStr};
+
+//a= line, the ones we make use of are typed, the rest are kept as they are
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attribute {
+    Rtpmap(Rtpmap),
+    Fmtp { format: String, params: String },
+    Direction(Direction),
+    Property(String),
+    Value(String, String),
+}
+
+impl FromStr for Attribute {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let (name, value) = match value.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (value, None),
+        };
+
+        match (name, value) {
+            ("rtpmap", Some(value)) => Ok(Self::Rtpmap(value.parse()?)),
+            ("fmtp", Some(value)) => {
+                let (format, params) = value
+                    .split_once(' ')
+                    .ok_or_else(|| Error::parse(format!("invalid fmtp: {}", value)))?;
+
+                Ok(Self::Fmtp {
+                    format: format.into(),
+                    params: params.trim().into(),
+                })
+            }
+            (name, None) => match name.parse::<Direction>() {
+                Ok(direction) => Ok(Self::Direction(direction)),
+                Err(_) => Ok(Self::Property(name.into())),
+            },
+            (name, Some(value)) => Ok(Self::Value(name.into(), value.into())),
+        }
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Rtpmap(rtpmap) => write!(f, "rtpmap:{}", rtpmap),
+            Self::Fmtp { format, params } => write!(f, "fmtp:{} {}", format, params),
+            Self::Direction(direction) => write!(f, "{}", direction),
+            Self::Property(name) => write!(f, "{}", name),
+            Self::Value(name, value) => write!(f, "{}:{}", name, value),
+        }
+    }
+}
+
+//a=rtpmap:<payload type> <encoding name>/<clock rate>[/<channels>]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rtpmap {
+    pub payload_type: u8,
+    pub encoding: String,
+    pub clock_rate: u32,
+    pub channels: Option<u8>,
+}
+
+impl FromStr for Rtpmap {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let error = || Error::parse(format!("invalid rtpmap: {}", value));
+
+        let (payload_type, encoding) = value.split_once(' ').ok_or_else(error)?;
+        let mut tokens = encoding.trim().split('/');
+
+        Ok(Self {
+            payload_type: payload_type.parse().map_err(|_| error())?,
+            encoding: tokens.next().ok_or_else(error)?.into(),
+            clock_rate: tokens
+                .next()
+                .ok_or_else(error)?
+                .parse()
+                .map_err(|_| error())?,
+            channels: tokens
+                .next()
+                .map(|channels| channels.parse())
+                .transpose()
+                .map_err(|_| error())?,
+        })
+    }
+}
+
+impl fmt::Display for Rtpmap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}/{}",
+            self.payload_type, self.encoding, self.clock_rate
+        )?;
+        match self.channels {
+            Some(channels) => write!(f, "/{}", channels),
+            None => Ok(()),
+        }
+    }
+}
+
+//RFC3264 5.1 and 6.1, sendrecv when nothing is said
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    #[default]
+    SendRecv,
+    SendOnly,
+    RecvOnly,
+    Inactive,
+}
+
+impl Direction {
+    //the direction the answerer has to use for the given offered one
+    pub fn reversed(&self) -> Self {
+        match self {
+            Self::SendOnly => Self::RecvOnly,
+            Self::RecvOnly => Self::SendOnly,
+            direction => *direction,
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        match value {
+            "sendrecv" => Ok(Self::SendRecv),
+            "sendonly" => Ok(Self::SendOnly),
+            "recvonly" => Ok(Self::RecvOnly),
+            "inactive" => Ok(Self::Inactive),
+            _ => Err(Error::parse(format!("unknown direction: {}", value))),
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SendRecv => write!(f, "sendrecv"),
+            Self::SendOnly => write!(f, "sendonly"),
+            Self::RecvOnly => write!(f, "recvonly"),
+            Self::Inactive => write!(f, "inactive"),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::{error::Error as StdError, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    Parse(String),
+    OfferAnswer(String),
+}
+
+impl Error {
+    pub fn parse(reason: impl Into<String>) -> Self {
+        Self {
+            kind: ErrorKind::Parse(reason.into()),
+        }
+    }
+
+    pub fn offer_answer(reason: impl Into<String>) -> Self {
+        Self {
+            kind: ErrorKind::OfferAnswer(reason.into()),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::Parse(ref inner) => write!(f, "sdp parse error: {}", inner),
+            ErrorKind::OfferAnswer(ref inner) => write!(f, "sdp offer/answer error: {}", inner),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl StdError for Error {}
@@ -0,0 +1,11 @@
+mod attribute;
+mod error;
+mod media;
+mod offer_answer;
+mod session;
+
+pub use attribute::{Attribute, Direction, Rtpmap};
+pub use error::{Error, ErrorKind};
+pub use media::{Codec, MediaDescription, MediaType};
+pub use offer_answer::{OfferAnswer, OfferAnswerState};
+pub use session::{Bandwidth, Connection, Origin, SessionDescription, Timing};
@@ -0,0 +1,212 @@
+use crate::{Attribute, Bandwidth, Connection, Direction, Error, Rtpmap};
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaType {
+    Audio,
+    Video,
+    Text,
+    Application,
+    Message,
+    Other(String),
+}
+
+impl FromStr for MediaType {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        Ok(match value {
+            "audio" => Self::Audio,
+            "video" => Self::Video,
+            "text" => Self::Text,
+            "application" => Self::Application,
+            "message" => Self::Message,
+            other => Self::Other(other.into()),
+        })
+    }
+}
+
+impl fmt::Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Audio => write!(f, "audio"),
+            Self::Video => write!(f, "video"),
+            Self::Text => write!(f, "text"),
+            Self::Application => write!(f, "application"),
+            Self::Message => write!(f, "message"),
+            Self::Other(other) => write!(f, "{}", other),
+        }
+    }
+}
+
+//a payload format of a media line, along with its rtpmap and fmtp attributes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Codec {
+    pub payload_type: u8,
+    pub name: String,
+    pub clock_rate: u32,
+    pub channels: Option<u8>,
+    pub fmtp: Option<String>,
+}
+
+impl Codec {
+    //RFC3551 static payload types, used when there is no rtpmap
+    pub fn from_static(payload_type: u8) -> Option<Self> {
+        let (name, clock_rate) = match payload_type {
+            0 => ("PCMU", 8000),
+            3 => ("GSM", 8000),
+            4 => ("G723", 8000),
+            8 => ("PCMA", 8000),
+            9 => ("G722", 8000),
+            18 => ("G729", 8000),
+            _ => return None,
+        };
+
+        Some(Self {
+            payload_type,
+            name: name.into(),
+            clock_rate,
+            channels: None,
+            fmtp: None,
+        })
+    }
+}
+
+//m= line and everything that follows until the next one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaDescription {
+    pub media: MediaType,
+    pub port: u16,
+    pub num_ports: Option<u16>,
+    pub protocol: String,
+    pub formats: Vec<String>,
+    pub information: Option<String>,
+    pub connection: Option<Connection>,
+    pub bandwidths: Vec<Bandwidth>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl MediaDescription {
+    pub fn new(media: MediaType, port: u16, protocol: impl Into<String>) -> Self {
+        Self {
+            media,
+            port,
+            num_ports: None,
+            protocol: protocol.into(),
+            formats: Default::default(),
+            information: None,
+            connection: None,
+            bandwidths: Default::default(),
+            attributes: Default::default(),
+        }
+    }
+
+    //RFC3264 6, a port of zero rejects (or disables) the stream
+    pub fn is_rejected(&self) -> bool {
+        self.port == 0
+    }
+
+    pub fn direction(&self) -> Option<Direction> {
+        self.attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                Attribute::Direction(direction) => Some(*direction),
+                _ => None,
+            })
+    }
+
+    pub fn set_direction(&mut self, direction: Direction) {
+        self.attributes
+            .retain(|attribute| !matches!(attribute, Attribute::Direction(_)));
+        self.attributes.push(Attribute::Direction(direction));
+    }
+
+    pub fn codecs(&self) -> Vec<Codec> {
+        self.formats
+            .iter()
+            .filter_map(|format| {
+                let payload_type: u8 = format.parse().ok()?;
+                let fmtp = self
+                    .attributes
+                    .iter()
+                    .find_map(|attribute| match attribute {
+                        Attribute::Fmtp { format: f, params } if f == format => {
+                            Some(params.clone())
+                        }
+                        _ => None,
+                    });
+
+                let codec = match self.rtpmap(payload_type) {
+                    Some(rtpmap) => Codec {
+                        payload_type,
+                        name: rtpmap.encoding.clone(),
+                        clock_rate: rtpmap.clock_rate,
+                        channels: rtpmap.channels,
+                        fmtp: None,
+                    },
+                    None => Codec::from_static(payload_type)?,
+                };
+
+                Some(Codec { fmtp, ..codec })
+            })
+            .collect()
+    }
+
+    fn rtpmap(&self, payload_type: u8) -> Option<&Rtpmap> {
+        self.attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                Attribute::Rtpmap(rtpmap) if rtpmap.payload_type == payload_type => Some(rtpmap),
+                _ => None,
+            })
+    }
+
+    //the m= line value, the rest of the fields are parsed along with the session
+    pub(crate) fn from_media_line(value: &str) -> Result<Self, Error> {
+        let error = || Error::parse(format!("invalid media line: {}", value));
+        let mut tokens = value.split_whitespace();
+
+        let media = tokens.next().ok_or_else(error)?.parse()?;
+        let ports = tokens.next().ok_or_else(error)?;
+        let (port, num_ports) = match ports.split_once('/') {
+            Some((port, num_ports)) => (port, Some(num_ports.parse().map_err(|_| error())?)),
+            None => (ports, None),
+        };
+        let protocol = tokens.next().ok_or_else(error)?;
+
+        Ok(Self {
+            num_ports,
+            formats: tokens.map(Into::into).collect(),
+            ..Self::new(media, port.parse().map_err(|_| error())?, protocol)
+        })
+    }
+}
+
+impl fmt::Display for MediaDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "m={} {}", self.media, self.port)?;
+        if let Some(num_ports) = self.num_ports {
+            write!(f, "/{}", num_ports)?;
+        }
+        write!(f, " {}", self.protocol)?;
+        for format in &self.formats {
+            write!(f, " {}", format)?;
+        }
+        write!(f, "\r\n")?;
+
+        if let Some(information) = &self.information {
+            write!(f, "i={}\r\n", information)?;
+        }
+        if let Some(connection) = &self.connection {
+            write!(f, "c={}\r\n", connection)?;
+        }
+        for bandwidth in &self.bandwidths {
+            write!(f, "b={}\r\n", bandwidth)?;
+        }
+        for attribute in &self.attributes {
+            write!(f, "a={}\r\n", attribute)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{Error, SessionDescription};
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum OfferAnswerState {
+    #[default]
+    Stable,
+    LocalOffer(SessionDescription),
+    RemoteOffer(SessionDescription),
+}
+
+//RFC3264 offer/answer exchange of a dialog, keeps the last negotiated descriptions of each side
+#[derive(Debug, Clone, Default)]
+pub struct OfferAnswer {
+    pub state: OfferAnswerState,
+    pub local: Option<SessionDescription>,
+    pub remote: Option<SessionDescription>,
+}
+
+impl OfferAnswer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn has_pending_offer(&self) -> bool {
+        !matches!(self.state, OfferAnswerState::Stable)
+    }
+
+    pub fn local_offer(&mut self, offer: SessionDescription) -> Result<(), Error> {
+        self.ensure_stable()?;
+        validate_offer(self.local.as_ref(), &offer)?;
+
+        self.state = OfferAnswerState::LocalOffer(offer);
+        Ok(())
+    }
+
+    pub fn remote_offer(&mut self, offer: SessionDescription) -> Result<(), Error> {
+        self.ensure_stable()?;
+        validate_offer(self.remote.as_ref(), &offer)?;
+
+        self.state = OfferAnswerState::RemoteOffer(offer);
+        Ok(())
+    }
+
+    pub fn local_answer(&mut self, answer: SessionDescription) -> Result<(), Error> {
+        let offer = match &self.state {
+            OfferAnswerState::RemoteOffer(offer) => offer.clone(),
+            _ => return Err(Error::offer_answer("no remote offer to answer")),
+        };
+        validate_answer(&offer, &answer)?;
+        validate_version(self.local.as_ref(), &answer)?;
+
+        self.remote = Some(offer);
+        self.local = Some(answer);
+        self.state = OfferAnswerState::Stable;
+        Ok(())
+    }
+
+    pub fn remote_answer(&mut self, answer: SessionDescription) -> Result<(), Error> {
+        let offer = match &self.state {
+            OfferAnswerState::LocalOffer(offer) => offer.clone(),
+            _ => return Err(Error::offer_answer("no local offer to be answered")),
+        };
+        validate_answer(&offer, &answer)?;
+        validate_version(self.remote.as_ref(), &answer)?;
+
+        self.local = Some(offer);
+        self.remote = Some(answer);
+        self.state = OfferAnswerState::Stable;
+        Ok(())
+    }
+
+    //a description that we send: the answer to an outstanding remote offer, a repetition of
+    //the last one (like the 2xx after an answer in a reliable 1xx) or otherwise a new offer
+    pub fn local_description(&mut self, session: SessionDescription) -> Result<(), Error> {
+        match &self.state {
+            OfferAnswerState::RemoteOffer(_) => self.local_answer(session),
+            OfferAnswerState::LocalOffer(offer) if *offer == session => Ok(()),
+            OfferAnswerState::Stable if self.local.as_ref() == Some(&session) => Ok(()),
+            _ => self.local_offer(session),
+        }
+    }
+
+    //same as local_description, for a description that we receive
+    pub fn remote_description(&mut self, session: SessionDescription) -> Result<(), Error> {
+        match &self.state {
+            OfferAnswerState::LocalOffer(_) => self.remote_answer(session),
+            OfferAnswerState::RemoteOffer(offer) if *offer == session => Ok(()),
+            OfferAnswerState::Stable if self.remote.as_ref() == Some(&session) => Ok(()),
+            _ => self.remote_offer(session),
+        }
+    }
+
+    //the offer was rejected (or the transaction failed), the previous session stays in place
+    pub fn rollback(&mut self) {
+        self.state = OfferAnswerState::Stable;
+    }
+
+    fn ensure_stable(&self) -> Result<(), Error> {
+        match self.state {
+            OfferAnswerState::Stable => Ok(()),
+            _ => Err(Error::offer_answer("an offer is already outstanding")),
+        }
+    }
+}
+
+//RFC3264 8, a new offer keeps the m-lines of the previous session in the same order
+fn validate_offer(
+    previous: Option<&SessionDescription>,
+    offer: &SessionDescription,
+) -> Result<(), Error> {
+    if let Some(previous) = previous {
+        if offer.media.len() < previous.media.len() {
+            return Err(Error::offer_answer("offer removes media lines"));
+        }
+
+        let same_types = previous
+            .media
+            .iter()
+            .zip(offer.media.iter())
+            .all(|(previous, offered)| previous.media == offered.media);
+        if !same_types {
+            return Err(Error::offer_answer("offer changes the type of media lines"));
+        }
+    }
+
+    validate_version(previous, offer)
+}
+
+//RFC3264 6, one m-line per offered m-line, same type, rejected streams stay rejected
+fn validate_answer(offer: &SessionDescription, answer: &SessionDescription) -> Result<(), Error> {
+    if offer.media.len() != answer.media.len() {
+        return Err(Error::offer_answer(format!(
+            "answer has {} media lines, offer has {}",
+            answer.media.len(),
+            offer.media.len()
+        )));
+    }
+
+    for (offered, answered) in offer.media.iter().zip(answer.media.iter()) {
+        if offered.media != answered.media {
+            return Err(Error::offer_answer(format!(
+                "answer has {} media where offer has {}",
+                answered.media, offered.media
+            )));
+        }
+        if offered.is_rejected() && !answered.is_rejected() {
+            return Err(Error::offer_answer("answer accepts a rejected media line"));
+        }
+    }
+
+    Ok(())
+}
+
+//RFC3264 8, the version of the origin is incremented when the description changes
+fn validate_version(
+    previous: Option<&SessionDescription>,
+    current: &SessionDescription,
+) -> Result<(), Error> {
+    let previous = match previous {
+        Some(previous) => previous,
+        None => return Ok(()),
+    };
+
+    let previous_version = previous.origin.session_version;
+    let current_version = current.origin.session_version;
+    match current_version {
+        version if version > previous_version => Ok(()),
+        version if version == previous_version && current == previous => Ok(()),
+        version if version == previous_version => Err(Error::offer_answer(
+            "description changed without incrementing the origin version",
+        )),
+        _ => Err(Error::offer_answer("origin version decreased")),
+    }
+}
@@ -0,0 +1,316 @@
+use crate::{Attribute, Direction, Error, MediaDescription};
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+//RFC4566 session description, lines that we don't model (r=, z=, k=) are dropped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionDescription {
+    pub version: u8,
+    pub origin: Origin,
+    pub session_name: String,
+    pub information: Option<String>,
+    pub uri: Option<String>,
+    pub emails: Vec<String>,
+    pub phones: Vec<String>,
+    pub connection: Option<Connection>,
+    pub bandwidths: Vec<Bandwidth>,
+    pub timing: Timing,
+    pub attributes: Vec<Attribute>,
+    pub media: Vec<MediaDescription>,
+}
+
+impl SessionDescription {
+    pub fn new(origin: Origin) -> Self {
+        Self {
+            version: 0,
+            origin,
+            session_name: "-".into(),
+            information: None,
+            uri: None,
+            emails: Default::default(),
+            phones: Default::default(),
+            connection: None,
+            bandwidths: Default::default(),
+            timing: Default::default(),
+            attributes: Default::default(),
+            media: Default::default(),
+        }
+    }
+
+    //session level direction, sendrecv when nothing is said
+    pub fn direction(&self) -> Direction {
+        self.attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                Attribute::Direction(direction) => Some(*direction),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    //direction of the given media line, falling back to the session level one
+    pub fn media_direction(&self, media: &MediaDescription) -> Direction {
+        media.direction().unwrap_or_else(|| self.direction())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+}
+
+impl FromStr for SessionDescription {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let mut version = None;
+        let mut origin = None;
+        let mut session_name = None;
+        let mut session = Self::new(Origin::default());
+
+        for line in value.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (kind, value) = line
+                .split_once('=')
+                .ok_or_else(|| Error::parse(format!("invalid line: {}", line)))?;
+
+            //everything after the first m= line belongs to the latest media description
+            if let Some(media) = session.media.last_mut() {
+                match kind {
+                    "m" => session
+                        .media
+                        .push(MediaDescription::from_media_line(value)?),
+                    "i" => media.information = Some(value.into()),
+                    "c" => media.connection = Some(value.parse()?),
+                    "b" => media.bandwidths.push(value.parse()?),
+                    "a" => media.attributes.push(value.parse()?),
+                    _ => (),
+                };
+                continue;
+            }
+
+            match kind {
+                "v" => {
+                    version = Some(
+                        value
+                            .parse()
+                            .map_err(|_| Error::parse(format!("invalid version: {}", value)))?,
+                    )
+                }
+                "o" => origin = Some(value.parse()?),
+                "s" => session_name = Some(value.to_string()),
+                "i" => session.information = Some(value.into()),
+                "u" => session.uri = Some(value.into()),
+                "e" => session.emails.push(value.into()),
+                "p" => session.phones.push(value.into()),
+                "c" => session.connection = Some(value.parse()?),
+                "b" => session.bandwidths.push(value.parse()?),
+                "t" => session.timing = value.parse()?,
+                "a" => session.attributes.push(value.parse()?),
+                "m" => session
+                    .media
+                    .push(MediaDescription::from_media_line(value)?),
+                _ => (),
+            };
+        }
+
+        Ok(Self {
+            version: version.ok_or_else(|| Error::parse("missing v= line"))?,
+            origin: origin.ok_or_else(|| Error::parse("missing o= line"))?,
+            session_name: session_name.ok_or_else(|| Error::parse("missing s= line"))?,
+            ..session
+        })
+    }
+}
+
+impl TryFrom<&[u8]> for SessionDescription {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+        std::str::from_utf8(bytes)
+            .map_err(|_| Error::parse("body is not valid utf8"))?
+            .parse()
+    }
+}
+
+impl From<SessionDescription> for Vec<u8> {
+    fn from(session: SessionDescription) -> Vec<u8> {
+        session.to_bytes()
+    }
+}
+
+impl fmt::Display for SessionDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "v={}\r\n", self.version)?;
+        write!(f, "o={}\r\n", self.origin)?;
+        write!(f, "s={}\r\n", self.session_name)?;
+        if let Some(information) = &self.information {
+            write!(f, "i={}\r\n", information)?;
+        }
+        if let Some(uri) = &self.uri {
+            write!(f, "u={}\r\n", uri)?;
+        }
+        for email in &self.emails {
+            write!(f, "e={}\r\n", email)?;
+        }
+        for phone in &self.phones {
+            write!(f, "p={}\r\n", phone)?;
+        }
+        if let Some(connection) = &self.connection {
+            write!(f, "c={}\r\n", connection)?;
+        }
+        for bandwidth in &self.bandwidths {
+            write!(f, "b={}\r\n", bandwidth)?;
+        }
+        write!(f, "t={}\r\n", self.timing)?;
+        for attribute in &self.attributes {
+            write!(f, "a={}\r\n", attribute)?;
+        }
+        for media in &self.media {
+            write!(f, "{}", media)?;
+        }
+
+        Ok(())
+    }
+}
+
+//o=<username> <sess-id> <sess-version> <nettype> <addrtype> <unicast-address>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub username: String,
+    pub session_id: u64,
+    pub session_version: u64,
+    pub net_type: String,
+    pub addr_type: String,
+    pub address: String,
+}
+
+impl Default for Origin {
+    fn default() -> Self {
+        Self {
+            username: "-".into(),
+            session_id: 0,
+            session_version: 0,
+            net_type: "IN".into(),
+            addr_type: "IP4".into(),
+            address: "0.0.0.0".into(),
+        }
+    }
+}
+
+impl FromStr for Origin {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let error = || Error::parse(format!("invalid origin: {}", value));
+        let tokens: Vec<&str> = value.split_whitespace().collect();
+        if tokens.len() != 6 {
+            return Err(error());
+        }
+
+        Ok(Self {
+            username: tokens[0].into(),
+            session_id: tokens[1].parse().map_err(|_| error())?,
+            session_version: tokens[2].parse().map_err(|_| error())?,
+            net_type: tokens[3].into(),
+            addr_type: tokens[4].into(),
+            address: tokens[5].into(),
+        })
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {}",
+            self.username,
+            self.session_id,
+            self.session_version,
+            self.net_type,
+            self.addr_type,
+            self.address
+        )
+    }
+}
+
+//c=<nettype> <addrtype> <connection-address>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    pub net_type: String,
+    pub addr_type: String,
+    pub address: String,
+}
+
+impl FromStr for Connection {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let tokens: Vec<&str> = value.split_whitespace().collect();
+        match tokens.as_slice() {
+            [net_type, addr_type, address] => Ok(Self {
+                net_type: net_type.to_string(),
+                addr_type: addr_type.to_string(),
+                address: address.to_string(),
+            }),
+            _ => Err(Error::parse(format!("invalid connection: {}", value))),
+        }
+    }
+}
+
+impl fmt::Display for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.net_type, self.addr_type, self.address)
+    }
+}
+
+//b=<bwtype>:<bandwidth>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bandwidth {
+    pub kind: String,
+    pub value: u32,
+}
+
+impl FromStr for Bandwidth {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let error = || Error::parse(format!("invalid bandwidth: {}", value));
+        let (kind, bandwidth) = value.split_once(':').ok_or_else(error)?;
+
+        Ok(Self {
+            kind: kind.into(),
+            value: bandwidth.parse().map_err(|_| error())?,
+        })
+    }
+}
+
+impl fmt::Display for Bandwidth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.kind, self.value)
+    }
+}
+
+//t=<start-time> <stop-time>, 0 0 for permanent sessions like calls
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timing {
+    pub start: u64,
+    pub stop: u64,
+}
+
+impl FromStr for Timing {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        let error = || Error::parse(format!("invalid timing: {}", value));
+        let (start, stop) = value.trim().split_once(' ').ok_or_else(error)?;
+
+        Ok(Self {
+            start: start.parse().map_err(|_| error())?,
+            stop: stop.trim().parse().map_err(|_| error())?,
+        })
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.start, self.stop)
+    }
+}
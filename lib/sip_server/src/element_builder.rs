@@ -1,48 +1,199 @@
-use crate::Error;
-use crate::{transaction::Transaction, transport::Transport, tu::elements::UserAgent};
-use models::Handlers;
+use crate::{
+    transaction::sm::Timers,
+    transport::{DefaultProcessor, DnsLookup, Transport, TransportProcessor},
+    Error, Transaction, TuElement,
+};
+use common::{tokio, Config};
+use models::{
+    receivers::{Receivers, TuReceiver},
+    Handlers,
+};
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
 
-pub struct ElementBuilder;
+//64*T1, the longest that a transaction can take (RFC3261 17.1.1.2)
+const DEFAULT_SHUTDOWN_DEADLINE: Duration = Duration::from_secs(32);
 
-impl ElementBuilder {
-    pub fn new() {
-        let (tu_tx, tu_rx) = channel(10);
-        let (transaction_tx, transaction_rx) = channel(10);
-        let (transport_tx, transport_rx) = channel(10);
+//wires the transport, transaction and TU layers of an element through the channels of
+//models::channels_builder, the config reaches all of them through the handlers. Processors
+//that need to send messages, like the Pipeline, are created from the handlers of the builder,
+//the TU element from the TU receiver and the stores
+pub struct ElementBuilder<P: TransportProcessor, D: DnsLookup, S> {
+    handlers: Handlers,
+    receivers: Receivers,
+    processor: P,
+    dns_lookup: D,
+    stores: S,
+    listen_addr: SocketAddr,
+    shutdown_deadline: Duration,
+    timers: Timers,
+}
+
+//the running element, the layers run until shutdown is called
+pub struct Element<P: TransportProcessor, D: DnsLookup, E: TuElement> {
+    handlers: Handlers,
+    transport: Transport<P, D>,
+    transaction: Transaction,
+    tu: E,
+    shutdown_deadline: Duration,
+}
 
-        let handlers: Handlers = (tu_tx, transaction_tx, transport_tx).into();
+impl<D: DnsLookup> ElementBuilder<DefaultProcessor, D, ()> {
+    pub fn new(config: Config, dns_lookup: D) -> Self {
+        let (handlers, receivers) = models::channels_builder(config);
 
+        Self {
+            processor: DefaultProcessor::new(handlers.config.clone()),
+            handlers,
+            receivers,
+            dns_lookup,
+            stores: (),
+            listen_addr: ([0, 0, 0, 0], 5060).into(),
+            shutdown_deadline: DEFAULT_SHUTDOWN_DEADLINE,
+            timers: Default::default(),
+        }
     }
-
 }
 
-#[allow(dead_code)]
-#[derive(Debug)]
-pub struct SipManager {
-    pub tu: Arc<dyn TuLayer>,
-    pub transaction: Arc<dyn TransactionLayer>,
-    pub transport: Arc<dyn TransportLayer>,
-}
+impl<P: TransportProcessor, D: DnsLookup, S> ElementBuilder<P, D, S> {
+    pub fn handlers(&self) -> Handlers {
+        self.handlers.clone()
+    }
+
+    pub fn with_processor<T: TransportProcessor>(self, processor: T) -> ElementBuilder<T, D, S> {
+        ElementBuilder {
+            handlers: self.handlers,
+            receivers: self.receivers,
+            processor,
+            dns_lookup: self.dns_lookup,
+            stores: self.stores,
+            listen_addr: self.listen_addr,
+            shutdown_deadline: self.shutdown_deadline,
+            timers: self.timers,
+        }
+    }
+
+    pub fn with_stores<T>(self, stores: T) -> ElementBuilder<P, D, T> {
+        ElementBuilder {
+            handlers: self.handlers,
+            receivers: self.receivers,
+            processor: self.processor,
+            dns_lookup: self.dns_lookup,
+            stores,
+            listen_addr: self.listen_addr,
+            shutdown_deadline: self.shutdown_deadline,
+            timers: self.timers,
+        }
+    }
+
+    pub fn with_listen_addr(mut self, listen_addr: SocketAddr) -> Self {
+        self.listen_addr = listen_addr;
+        self
+    }
+
+    //how long the transactions and dialogs in flight get to finish on shutdown
+    pub fn with_shutdown_deadline(mut self, shutdown_deadline: Duration) -> Self {
+        self.shutdown_deadline = shutdown_deadline;
+        self
+    }
+
+    pub fn with_timers(mut self, timers: Timers) -> Self {
+        self.timers = timers;
+        self
+    }
 
-impl SipBuilder {
-    pub fn new<C, Trx, T>() -> Result<Self, Error>
+    //starts the layers bottom up, must be called inside a tokio runtime
+    pub fn build<E, F>(self, tu: F) -> Result<Element<P, D, E>, Error>
     where
-        C: TuLayer,
-        Trx: TransactionLayer,
-        T: TransportLayer,
+        E: TuElement,
+        F: FnOnce(Handlers, TuReceiver, S) -> Result<E, Error>,
     {
-        Ok(Self {
-            manager: Arc::new_cyclic(|me| SipManager {
-                tu: Arc::new(C::new(me.clone())),
-                transaction: Arc::new(Trx::new(me.clone())),
-                transport: Arc::new(T::new(me.clone()).expect("could not start transport")),
-            }),
+        let Receivers {
+            tu: tu_rx,
+            transaction: transaction_rx,
+            transport: transport_rx,
+        } = self.receivers;
+
+        let transport = Transport::bind(
+            self.handlers.clone(),
+            self.processor,
+            self.dns_lookup,
+            transport_rx,
+            self.listen_addr,
+        )?;
+        let transaction =
+            match Transaction::with_timers(self.handlers.clone(), transaction_rx, self.timers) {
+                Ok(transaction) => transaction,
+                Err(err) => {
+                    transport.abort();
+                    return Err(err);
+                }
+            };
+        let tu = match tu(self.handlers.clone(), tu_rx, self.stores) {
+            Ok(tu) => tu,
+            Err(err) => {
+                transaction.abort();
+                transport.abort();
+                return Err(err);
+            }
+        };
+        tu.reload_timers(self.timers);
+
+        Ok(Element {
+            handlers: self.handlers,
+            transport,
+            transaction,
+            tu,
+            shutdown_deadline: self.shutdown_deadline,
         })
     }
+}
+
+impl<P: TransportProcessor, D: DnsLookup, E: TuElement> Element<P, D, E> {
+    pub fn handlers(&self) -> Handlers {
+        self.handlers.clone()
+    }
+
+    pub fn tu(&self) -> &E {
+        &self.tu
+    }
 
-    pub async fn run(&self) {
-        use common::futures::join;
+    pub fn local_addr(&self) -> SocketAddr {
+        self.transport.local_addr()
+    }
+
+    //only the transactions and dialogs created from now on use them
+    pub async fn reload_timers(&self, timers: Timers) {
+        self.transaction.reload_timers(timers).await;
+        self.tu.reload_timers(timers);
+    }
+
+    //new requests get a 503 while the transactions and dialogs in flight finish, up to the
+    //deadline. Registrations, publications and offline messages are already in the store, so
+    //there is nothing to save, and nothing is deregistered. The socket is closed last
+    pub async fn shutdown(self) {
+        let started = Instant::now();
+        self.tu.drain(self.shutdown_deadline).await;
+
+        while !self.is_idle().await {
+            if started.elapsed() >= self.shutdown_deadline {
+                common::log::warn!("shutdown deadline reached, dropping what is still in flight");
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        self.transport.close_socket();
+        self.tu.abort();
+        self.transaction.abort();
+        self.transport.abort();
+
+        common::log::debug!("element on {} is shut down", self.local_addr());
+    }
 
-        join!(self.manager.transaction.run(), self.manager.transport.run());
+    async fn is_idle(&self) -> bool {
+        self.tu.is_idle().await && !self.transaction.has_active_transactions().await
     }
 }
@@ -25,6 +25,7 @@ pub enum ErrorKind {
     Dialog(DialogError),
     Channel(String),
     Store(store::Error),
+    Sdp(sdp::Error),
 }
 
 #[derive(Debug)]
@@ -155,3 +156,9 @@ impl From<store::Error> for ErrorKind {
         ErrorKind::Store(e)
     }
 }
+
+impl From<sdp::Error> for ErrorKind {
+    fn from(e: sdp::Error) -> Self {
+        ErrorKind::Sdp(e)
+    }
+}
@@ -1,16 +1,20 @@
 use common::rsip::{self};
+use store::MessageLog;
 
-pub fn trace_sip_message(sip_message: rsip::SipMessage) -> Result<(), crate::Error> {
+pub fn trace_sip_message(
+    messages: &dyn MessageLog,
+    sip_message: rsip::SipMessage,
+) -> Result<(), crate::Error> {
     match sip_message.clone() {
         rsip::SipMessage::Request(request) => {
             let mut request: store::DirtyRequest = request.into();
             request.raw_message = Some(Into::<String>::into(sip_message));
-            store::Request::create(request)?;
+            messages.log_request(request)?;
         }
         rsip::SipMessage::Response(response) => {
             let mut response: store::DirtyResponse = response.into();
             response.raw_message = Some(Into::<String>::into(sip_message));
-            store::Response::create(response)?;
+            messages.log_response(response)?;
         }
     };
 
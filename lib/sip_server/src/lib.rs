@@ -1,13 +1,14 @@
+pub mod element_builder;
 pub mod error;
 //pub mod helpers;
 pub mod presets;
-//pub mod element_builder;
 
 pub mod transaction;
 pub mod transport;
 pub mod tu;
 
+pub use element_builder::{Element, ElementBuilder};
 pub use error::{Error, ErrorKind};
 pub use transaction::Transaction;
 pub use transport::Transport;
-pub use tu::ReqProcessor;
+pub use tu::{CallHandler, ReqProcessor, TuElement};
@@ -1,4 +1,7 @@
-use common::rsip::{self, prelude::*};
+use common::{
+    rsip::{self, prelude::*},
+    Config,
+};
 /*
 pub fn create_unauthorized_from(request: rsip::Request) -> Result<rsip::Response, crate::Error> {
     let mut headers: rsip::Headers = Default::default();
@@ -54,6 +57,115 @@ pub fn response_from(
     })
 }
 
+//replaces the body of the message with the given SDP, updating the relevant headers
+pub fn with_sdp_body(mut response: rsip::Response, sdp: Vec<u8>) -> rsip::Response {
+    push_sdp_headers(&mut response.headers, sdp.len());
+    response.body = sdp;
+
+    response
+}
+
+//initial INVITE for a new outgoing call, from our default address towards the target
+pub fn invite_request(config: &Config, target: rsip::Uri, sdp_offer: Vec<u8>) -> rsip::Request {
+    use crate::tu::dialogs::{reliable, replaces, session_timer};
+    use rsip::headers::*;
+
+    let uri: rsip::Uri = config.default_addr().into();
+
+    let mut headers: rsip::Headers = Default::default();
+    headers.push(typed::Via::from(uri.clone()).into());
+    headers.push(
+        typed::From::from(uri.clone())
+            .with_tag(Default::default())
+            .into(),
+    );
+    headers.push(typed::To::from(target.clone()).into());
+    headers.push(CallId::default().into());
+    headers.push(typed::CSeq::from((1, rsip::Method::Invite)).into());
+    headers.push(typed::Contact::from(uri).into());
+    headers.push(MaxForwards::default().into());
+    headers.push(reliable::supported_header());
+    headers.push(session_timer::supported_header());
+    headers.push(replaces::supported_header());
+    headers.push(
+        session_timer::SessionExpires::new(session_timer::DEFAULT_SESSION_EXPIRES, None).into(),
+    );
+    push_sdp_headers(&mut headers, sdp_offer.len());
+
+    rsip::Request {
+        method: rsip::Method::Invite,
+        uri: target,
+        headers,
+        version: Default::default(),
+        body: sdp_offer,
+    }
+}
+
+//RFC3428 4, a MESSAGE outside of any dialog, sent to one contact of the recipient
+pub fn message_request(
+    config: &Config,
+    from: rsip::Uri,
+    to: rsip::Uri,
+    target: rsip::Uri,
+    content_type: &str,
+    body: Vec<u8>,
+) -> rsip::Request {
+    use rsip::headers::*;
+
+    let uri: rsip::Uri = config.default_addr().into();
+
+    let mut headers: rsip::Headers = Default::default();
+    headers.push(typed::Via::from(uri).into());
+    headers.push(typed::From::from(from).with_tag(Default::default()).into());
+    headers.push(typed::To::from(to).into());
+    headers.push(CallId::default().into());
+    headers.push(typed::CSeq::from((1, rsip::Method::Message)).into());
+    headers.push(MaxForwards::default().into());
+    headers.push(ContentType::new(content_type).into());
+    headers.push(ContentLength::new(body.len().to_string()).into());
+
+    rsip::Request {
+        method: rsip::Method::Message,
+        uri: target,
+        headers,
+        version: Default::default(),
+        body,
+    }
+}
+
+fn push_sdp_headers(headers: &mut rsip::Headers, len: usize) {
+    use rsip::headers::{ContentLength, ContentType, UntypedHeader};
+
+    headers.unique_push(ContentType::new("application/sdp").into());
+    headers.unique_push(ContentLength::new(len.to_string()).into());
+}
+
+//skeleton of a request sent inside a dialog, the dialog fills in
+//From/To/Call-ID/CSeq/Contact and the request uri from its own state
+pub fn in_dialog_request(config: &Config, method: rsip::Method) -> rsip::Request {
+    use rsip::headers::*;
+
+    let uri: rsip::Uri = config.default_addr().into();
+
+    let mut headers: rsip::Headers = Default::default();
+    headers.push(typed::Via::from(uri.clone()).into());
+    headers.push(typed::From::from(uri.clone()).into());
+    headers.push(typed::To::from(uri.clone()).into());
+    headers.push(CallId::default().into());
+    headers.push(typed::CSeq::from((1, method)).into());
+    headers.push(typed::Contact::from(uri.clone()).into());
+    headers.push(MaxForwards::default().into());
+    headers.push(ContentLength::default().into());
+
+    rsip::Request {
+        method,
+        uri,
+        headers,
+        version: Default::default(),
+        body: Default::default(),
+    }
+}
+
 pub fn create_404_from(request: rsip::Request) -> Result<rsip::Response, crate::Error> {
     let mut headers: rsip::Headers = Default::default();
     headers.push(request.via_header()?.clone().into());
@@ -99,6 +211,19 @@ pub fn create_405_from(request: rsip::Request) -> Result<rsip::Response, crate::
         ..Default::default()
     })
 }
+
+//RFC4028 8.1, rejects a session interval that is smaller than the one we accept
+pub fn create_422_from(request: rsip::Request) -> Result<rsip::Response, crate::Error> {
+    use crate::tu::dialogs::session_timer;
+
+    let mut response = response_from(request, 422.into())?;
+    response
+        .headers
+        .push(session_timer::min_se_header(session_timer::MIN_SE));
+
+    Ok(response)
+}
+
 /*
 fn www_authenticate_header_value() -> Result<rsip::headers::WwwAuthenticate, crate::Error> {
     use rsip::headers::auth;
@@ -3,52 +3,80 @@ pub mod sm;
 use crate::{error::TransactionError, Error};
 use common::{
     rsip::{self, message::HeadersExt},
-    tokio::{self, sync::RwLock},
+    tokio::{self, sync::RwLock, task::JoinHandle},
 };
 use models::{
     receivers::TrxReceiver,
     transaction::{TransactionHandler, TransactionId, TransactionLayerMsg},
     Handlers,
 };
-use sm::TrxStateSm;
+use sm::{Timers, TrxStateSm};
 use std::{collections::HashMap, fmt::Debug, sync::Arc};
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct Transaction {
     pub inner: Arc<Inner>,
+    tasks: Vec<JoinHandle<()>>,
 }
 
 #[derive(Debug)]
 pub struct Inner {
     handlers: Handlers,
     pub state: RwLock<HashMap<TransactionId, TrxStateSm>>,
+    timers: RwLock<Timers>,
 }
 
 //TODO: make impl here thinner by moving stuff over to TransactionsSm, like in dialogs
 impl Transaction {
     pub fn new(handlers: Handlers, messages_rx: TrxReceiver) -> Result<Self, Error> {
-        let me = Self {
-            inner: Arc::new(Inner {
-                handlers,
-                state: RwLock::new(Default::default()),
-            }),
-        };
+        Self::with_timers(handlers, messages_rx, Default::default())
+    }
 
-        me.run(messages_rx);
+    pub fn with_timers(
+        handlers: Handlers,
+        messages_rx: TrxReceiver,
+        timers: Timers,
+    ) -> Result<Self, Error> {
+        let inner = Arc::new(Inner {
+            handlers,
+            state: RwLock::new(Default::default()),
+            timers: RwLock::new(timers),
+        });
+        let tasks = Self::run(inner.clone(), messages_rx);
 
-        Ok(me)
+        Ok(Self { inner, tasks })
     }
 
     pub fn handler(&self) -> TransactionHandler {
         self.inner.handlers.transaction.clone()
     }
 
-    fn run(&self, messages: TrxReceiver) {
-        let inner = self.inner.clone();
-        tokio::spawn(async move { inner.run(messages).await });
-        let inner_trx = self.inner.clone();
-        tokio::spawn(async move { inner_trx.run_transactions().await });
+    pub async fn has_active_transactions(&self) -> bool {
+        for transaction in self.inner.state.read().await.values() {
+            if transaction.is_active().await {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    //the transactions in flight keep the timers they were created with
+    pub async fn reload_timers(&self, timers: Timers) {
+        *self.inner.timers.write().await = timers;
+    }
+
+    pub fn abort(&self) {
+        self.tasks.iter().for_each(JoinHandle::abort);
+    }
+
+    fn run(inner: Arc<Inner>, messages: TrxReceiver) -> Vec<JoinHandle<()>> {
+        let inner_trx = inner.clone();
+        vec![
+            tokio::spawn(async move { inner.run(messages).await }),
+            tokio::spawn(async move { inner_trx.run_transactions().await }),
+        ]
     }
 }
 
@@ -108,7 +136,8 @@ impl Inner {
 
     async fn new_uac_invite_transaction(&self, msg: rsip::Request) -> Result<(), Error> {
         self.handlers.transport.send(msg.clone().into()).await?;
-        let transaction_data = sm::uac::TrxStateMachine::new(self.handlers.clone(), msg.clone())?;
+        let transaction_data = sm::uac::TrxStateMachine::new(self.handlers.clone(), msg.clone())?
+            .with_timers(*self.timers.read().await);
         {
             let mut data = self.state.write().await;
             data.insert(transaction_data.id.clone(), transaction_data.into());
@@ -121,9 +150,13 @@ impl Inner {
         request: rsip::Request,
         response: Option<rsip::Response>,
     ) -> Result<(), Error> {
-        self.handlers.transport.send(request.clone().into()).await?;
         let transaction_data =
-            sm::uas::TrxStateMachine::new(self.handlers.clone(), request.clone(), response)?;
+            sm::uas::TrxStateMachine::new(self.handlers.clone(), request.clone(), response)?
+                .with_timers(*self.timers.read().await);
+        self.handlers
+            .transport
+            .send(transaction_data.response.clone().into())
+            .await?;
 
         {
             let mut data = self.state.write().await;
@@ -133,16 +166,22 @@ impl Inner {
         Ok(())
     }
 
-    async fn new_uac_transaction(&self, _: rsip::Request) -> Result<(), Error> {
-        unimplemented!("");
+    //TODO: add non-INVITE state machines, until then messages are simply forwarded
+    async fn new_uac_transaction(&self, request: rsip::Request) -> Result<(), Error> {
+        Ok(self.handlers.transport.send(request.into()).await?)
     }
 
+    //TODO: add non-INVITE state machines, until then messages are simply forwarded
     async fn new_uas_transaction(
         &self,
         _: rsip::Request,
-        _: Option<rsip::Response>,
+        response: Option<rsip::Response>,
     ) -> Result<(), Error> {
-        unimplemented!("");
+        if let Some(response) = response {
+            self.handlers.transport.send(response.into()).await?;
+        }
+
+        Ok(())
     }
 
     async fn process_tu_reply(&self, response: rsip::Response) -> Result<(), Error> {
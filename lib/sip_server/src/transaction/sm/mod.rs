@@ -1,6 +1,9 @@
+pub mod timers;
 pub mod uac;
 pub mod uas;
 
+pub use timers::Timers;
+
 use crate::{error::TransactionError, Error};
 use common::{rsip, tokio::sync::Mutex};
 use std::fmt::Debug;
@@ -0,0 +1,49 @@
+use super::uas::{TIMER_T1, TIMER_T2, TIMER_T4};
+use std::time::Duration;
+
+//RFC3261 17.1.1.1, the rest of the timers derive from these. A transaction keeps the ones
+//it was created with, so changing them only affects the transactions that come after
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timers {
+    pub t1: Duration,
+    pub t2: Duration,
+    pub t4: Duration,
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Self {
+            t1: Duration::from_millis(TIMER_T1),
+            t2: Duration::from_millis(TIMER_T2),
+            t4: Duration::from_millis(TIMER_T4),
+        }
+    }
+}
+
+impl Timers {
+    pub fn b(&self) -> Duration {
+        self.t1 * 64
+    }
+
+    pub fn g(&self) -> Duration {
+        self.t1
+    }
+
+    pub fn h(&self) -> Duration {
+        self.t1 * 64
+    }
+
+    pub fn i(&self) -> Duration {
+        self.t4
+    }
+
+    //RFC6026 8.7
+    pub fn l(&self) -> Duration {
+        self.t1 * 64
+    }
+
+    //RFC6026 8.4
+    pub fn m(&self) -> Duration {
+        self.t1 * 64
+    }
+}
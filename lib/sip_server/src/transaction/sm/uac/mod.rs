@@ -2,6 +2,7 @@ mod states;
 
 pub use states::{Accepted, Calling, Completed, Errored, Proceeding, Terminated};
 
+use super::Timers;
 use crate::Error;
 use common::{
     rsip::{self, message::HeadersExt},
@@ -11,7 +12,7 @@ use models::{rsip_ext::*, transaction::TransactionId, Handlers};
 
 //TODO: add state checks as well for better guarantees, look at dialogs
 
-//should come from config
+//the defaults, see Timers
 pub static TIMER_T1: u64 = 500;
 pub static TIMER_B: u64 = 64 * TIMER_T1;
 pub static TIMER_M: u64 = 64 * TIMER_T1;
@@ -25,6 +26,7 @@ pub struct TrxStateMachine {
     pub state: TrxState,
     pub request: rsip::Request,
     pub created_at: Instant,
+    timers: Timers,
     handlers: Handlers,
 }
 
@@ -58,10 +60,16 @@ impl TrxStateMachine {
             state: TrxState::Calling(Default::default()),
             request,
             created_at: Instant::now(),
+            timers: Default::default(),
             handlers,
         })
     }
 
+    pub fn with_timers(mut self, timers: Timers) -> Self {
+        self.timers = timers;
+        self
+    }
+
     pub async fn next(&mut self, response: Option<rsip::Response>) {
         let result = match response {
             Some(response) => self.next_step_with(response).await,
@@ -86,7 +94,10 @@ impl TrxStateMachine {
     async fn next_step(&mut self) -> Result<(), Error> {
         match &self.state {
             TrxState::Calling(calling) => {
-                match (calling.has_timedout(), calling.should_retransmit()) {
+                match (
+                    calling.has_timedout(&self.timers),
+                    calling.should_retransmit(&self.timers),
+                ) {
                     (true, _) => self.terminate(),
                     (false, true) => {
                         self.handlers
@@ -99,12 +110,12 @@ impl TrxStateMachine {
                 }
             }
             TrxState::Completed(completed) => {
-                if completed.should_terminate() {
+                if completed.should_terminate(&self.timers) {
                     self.terminate();
                 }
             }
             TrxState::Accepted(accepted) => {
-                if accepted.should_terminate() {
+                if accepted.should_terminate(&self.timers) {
                     self.terminate();
                 }
             }
@@ -1,7 +1,6 @@
 use common::{rsip, tokio::time::Instant};
-use std::time::Duration;
 
-use super::super::TIMER_M;
+use super::super::super::Timers;
 
 #[derive(Debug, Clone)]
 pub struct Accepted {
@@ -10,7 +9,7 @@ pub struct Accepted {
 }
 
 impl Accepted {
-    pub fn should_terminate(&self) -> bool {
-        self.entered_at.elapsed() > Duration::from_millis(TIMER_M)
+    pub fn should_terminate(&self, timers: &Timers) -> bool {
+        self.entered_at.elapsed() > timers.m()
     }
 }
@@ -1,7 +1,7 @@
 use common::tokio::time::Instant;
 use std::time::Duration;
 
-use super::super::{TIMER_B, TIMER_T1};
+use super::super::super::Timers;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Calling {
@@ -11,20 +11,20 @@ pub struct Calling {
 }
 
 impl Calling {
-    pub fn next_retrasmission(&self) -> Duration {
+    pub fn next_retrasmission(&self, timers: &Timers) -> Duration {
         use std::iter;
 
-        iter::repeat(Duration::from_millis(TIMER_T1))
+        iter::repeat(timers.t1)
             .take(2_i32.pow(self.retransmissions_count.into()) as usize)
             .fold(Duration::from_secs(0), |acc, x| acc + x)
     }
 
-    pub fn has_timedout(&self) -> bool {
-        self.entered_at.elapsed() >= Duration::from_millis(TIMER_B)
+    pub fn has_timedout(&self, timers: &Timers) -> bool {
+        self.entered_at.elapsed() >= timers.b()
     }
 
-    pub fn should_retransmit(&self) -> bool {
-        self.last_retransmission_at.elapsed() > self.next_retrasmission()
+    pub fn should_retransmit(&self, timers: &Timers) -> bool {
+        self.last_retransmission_at.elapsed() > self.next_retrasmission(timers)
     }
 
     pub fn retransmit(self) -> Self {
@@ -2,6 +2,7 @@ mod states;
 
 pub use states::{Accepted, Completed, Confirmed, Errored, Proceeding, Terminated};
 
+use super::Timers;
 use crate::Error;
 use common::{
     rsip::{self, prelude::*},
@@ -14,7 +15,7 @@ use models::{transaction::TransactionId, Handlers};
 static TIMED_OUT: bool = true;
 static DID_NOT_TIME_OUT: bool = false;
 
-//should come from config
+//the defaults, see Timers
 pub static TIMER_T1: u64 = 500;
 pub static TIMER_T2: u64 = 4000;
 pub static TIMER_G: u64 = TIMER_T1;
@@ -32,6 +33,7 @@ pub struct TrxStateMachine {
     //uas (final) response, uas in this case is us
     pub response: rsip::Response,
     pub created_at: Instant,
+    timers: Timers,
     handlers: Handlers,
 }
 
@@ -72,10 +74,16 @@ impl TrxStateMachine {
             response: response.unwrap_or_else(|| request.provisional_of(100)),
             request,
             created_at: Instant::now(),
+            timers: Default::default(),
             handlers,
         })
     }
 
+    pub fn with_timers(mut self, timers: Timers) -> Self {
+        self.timers = timers;
+        self
+    }
+
     pub fn is_active(&self) -> bool {
         !matches!(self.state, TrxState::Errored(_) | TrxState::Terminated(_))
     }
@@ -106,7 +114,10 @@ impl TrxStateMachine {
     async fn next_step(&mut self) -> Result<(), Error> {
         match &self.state {
             TrxState::Completed(completed) => {
-                match (completed.has_timedout(), completed.should_retransmit()) {
+                match (
+                    completed.has_timedout(&self.timers),
+                    completed.should_retransmit(&self.timers),
+                ) {
                     (true, _) => self.terminate(TIMED_OUT),
                     (false, true) => {
                         self.handlers
@@ -119,12 +130,12 @@ impl TrxStateMachine {
                 }
             }
             TrxState::Accepted(accepted) => {
-                if accepted.should_terminate() {
+                if accepted.should_terminate(&self.timers) {
                     self.terminate(TIMED_OUT);
                 }
             }
             TrxState::Confirmed(confirmed) => {
-                if confirmed.should_terminate() {
+                if confirmed.should_terminate(&self.timers) {
                     self.terminate(DID_NOT_TIME_OUT);
                 }
             }
@@ -172,6 +183,19 @@ impl TrxStateMachine {
             (TrxState::Confirmed(_), Method::Ack) => {
                 //absorb ack
             }
+            //RFC3261 9.2, the CANCEL shares the branch of the INVITE, the TU decides what
+            //to do with the call while it's not answered yet
+            (TrxState::Proceeding(_), Method::Cancel) => {
+                self.handlers.tu.process(request.into()).await?;
+            }
+            //too late to cancel anything, the CANCEL itself is still answered
+            (_, Method::Cancel) => {
+                let mut response = crate::presets::response_from(request, 200.into())?;
+                response
+                    .headers
+                    .unique_push(self.response.to_header()?.clone().into());
+                self.handlers.transport.send(response.into()).await?;
+            }
             _ => self.error(
                 format!(
                     "unknown transition for {} and {}",
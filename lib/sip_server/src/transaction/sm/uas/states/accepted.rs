@@ -1,7 +1,6 @@
 use common::tokio::time::Instant;
-use std::time::Duration;
 
-use super::super::TIMER_L;
+use super::super::super::Timers;
 
 #[derive(Debug)]
 pub struct Accepted {
@@ -9,8 +8,8 @@ pub struct Accepted {
 }
 
 impl Accepted {
-    pub fn should_terminate(&self) -> bool {
-        self.entered_at.elapsed() > Duration::from_millis(TIMER_L)
+    pub fn should_terminate(&self, timers: &Timers) -> bool {
+        self.entered_at.elapsed() > timers.l()
     }
 }
 
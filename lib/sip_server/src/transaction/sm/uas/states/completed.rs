@@ -1,7 +1,7 @@
 use common::tokio::time::Instant;
 use std::time::Duration;
 
-use super::super::{TIMER_G, TIMER_H, TIMER_T2};
+use super::super::super::Timers;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Completed {
@@ -11,23 +11,23 @@ pub struct Completed {
 }
 
 impl Completed {
-    pub fn next_retrasmission(&self) -> Duration {
+    pub fn next_retrasmission(&self, timers: &Timers) -> Duration {
         use std::iter;
 
         std::cmp::min(
-            iter::repeat(Duration::from_millis(TIMER_G))
+            iter::repeat(timers.g())
                 .take(2_i32.pow(self.retransmissions_count.into()) as usize)
                 .fold(Duration::from_secs(0), |acc, x| acc + x),
-            Duration::from_millis(TIMER_T2),
+            timers.t2,
         )
     }
 
-    pub fn has_timedout(&self) -> bool {
-        self.entered_at.elapsed() >= Duration::from_millis(TIMER_H)
+    pub fn has_timedout(&self, timers: &Timers) -> bool {
+        self.entered_at.elapsed() >= timers.h()
     }
 
-    pub fn should_retransmit(&self) -> bool {
-        self.last_retransmission_at.elapsed() > self.next_retrasmission()
+    pub fn should_retransmit(&self, timers: &Timers) -> bool {
+        self.last_retransmission_at.elapsed() > self.next_retrasmission(timers)
     }
 
     pub fn retransmit(self) -> Self {
@@ -1,7 +1,6 @@
 use common::{rsip, tokio::time::Instant};
-use std::time::Duration;
 
-use super::super::TIMER_I;
+use super::super::super::Timers;
 
 #[derive(Debug)]
 pub struct Confirmed {
@@ -10,7 +9,7 @@ pub struct Confirmed {
 }
 
 impl Confirmed {
-    pub fn should_terminate(&self) -> bool {
-        self.entered_at.elapsed() > Duration::from_millis(TIMER_I)
+    pub fn should_terminate(&self, timers: &Timers) -> bool {
+        self.entered_at.elapsed() > timers.i()
     }
 }
@@ -0,0 +1,81 @@
+use super::{Action, Middleware};
+use crate::{presets, Error};
+use common::{async_trait::async_trait, ipnetwork::IpNetwork, rsip};
+use models::transport::{RequestMsg, ResponseMsg};
+use std::net::IpAddr;
+
+//lets through the messages of the peers that the rules allow, the first rule that matches
+//the address of the peer wins. Denied requests are dropped, so that scanners learn nothing,
+//unless a rejection is set
+#[derive(Debug)]
+pub struct Acl {
+    rules: Vec<(IpNetwork, bool)>,
+    allow_by_default: bool,
+    rejection: Option<rsip::StatusCode>,
+}
+
+#[async_trait]
+impl Middleware for Acl {
+    async fn incoming_request(&self, msg: RequestMsg) -> Result<Action, Error> {
+        if self.allows(msg.peer.ip()) {
+            return Ok(Action::Continue(msg));
+        }
+
+        common::log::debug!("dropping {} from {}", msg.sip_request.method, msg.peer);
+        match self.rejection {
+            //RFC3261 17.1.1.3, ACKs are never answered
+            Some(status_code) if msg.sip_request.method != rsip::Method::Ack => Ok(
+                Action::Respond(presets::response_from(msg.sip_request, status_code)?),
+            ),
+            _ => Ok(Action::Drop),
+        }
+    }
+
+    async fn incoming_response(&self, msg: ResponseMsg) -> Result<Option<ResponseMsg>, Error> {
+        match self.allows(msg.peer.ip()) {
+            true => Ok(Some(msg)),
+            false => Ok(None),
+        }
+    }
+}
+
+impl Acl {
+    pub fn allowing_all() -> Self {
+        Self {
+            rules: vec![],
+            allow_by_default: true,
+            rejection: None,
+        }
+    }
+
+    pub fn denying_all() -> Self {
+        Self {
+            allow_by_default: false,
+            ..Self::allowing_all()
+        }
+    }
+
+    pub fn allow(mut self, network: IpNetwork) -> Self {
+        self.rules.push((network, true));
+        self
+    }
+
+    pub fn deny(mut self, network: IpNetwork) -> Self {
+        self.rules.push((network, false));
+        self
+    }
+
+    //usually 403
+    pub fn rejecting_with(mut self, status_code: impl Into<rsip::StatusCode>) -> Self {
+        self.rejection = Some(status_code.into());
+        self
+    }
+
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.rules
+            .iter()
+            .find(|(network, _)| network.contains(ip))
+            .map(|(_, allowed)| *allowed)
+            .unwrap_or(self.allow_by_default)
+    }
+}
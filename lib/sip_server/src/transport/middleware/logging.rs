@@ -0,0 +1,89 @@
+use super::{Action, Middleware};
+use crate::Error;
+use common::{
+    async_trait::async_trait,
+    log::{self, Level},
+    rsip::{self, headers::UntypedHeader},
+};
+use models::transport::{RequestMsg, ResponseMsg};
+
+//a line per message that crosses the pipeline, enough to follow a call by its Call-ID
+#[derive(Debug)]
+pub struct Logging {
+    level: Level,
+}
+
+#[async_trait]
+impl Middleware for Logging {
+    async fn incoming_request(&self, msg: RequestMsg) -> Result<Action, Error> {
+        log::log!(
+            self.level,
+            "<- {} {} from {} ({})",
+            msg.sip_request.method,
+            msg.sip_request.uri,
+            msg.peer,
+            call_id_of(&msg.sip_request.headers)
+        );
+
+        Ok(Action::Continue(msg))
+    }
+
+    async fn outgoing_request(&self, msg: RequestMsg) -> Result<Action, Error> {
+        log::log!(
+            self.level,
+            "-> {} {} to {} ({})",
+            msg.sip_request.method,
+            msg.sip_request.uri,
+            msg.peer,
+            call_id_of(&msg.sip_request.headers)
+        );
+
+        Ok(Action::Continue(msg))
+    }
+
+    async fn incoming_response(&self, msg: ResponseMsg) -> Result<Option<ResponseMsg>, Error> {
+        log::log!(
+            self.level,
+            "<- {} from {} ({})",
+            msg.sip_response.status_code,
+            msg.peer,
+            call_id_of(&msg.sip_response.headers)
+        );
+
+        Ok(Some(msg))
+    }
+
+    async fn outgoing_response(&self, msg: ResponseMsg) -> Result<Option<ResponseMsg>, Error> {
+        log::log!(
+            self.level,
+            "-> {} to {} ({})",
+            msg.sip_response.status_code,
+            msg.peer,
+            call_id_of(&msg.sip_response.headers)
+        );
+
+        Ok(Some(msg))
+    }
+}
+
+impl Logging {
+    pub fn new(level: Level) -> Self {
+        Self { level }
+    }
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Self::new(Level::Info)
+    }
+}
+
+fn call_id_of(headers: &rsip::Headers) -> &str {
+    headers
+        .iter()
+        .find_map(|header| match header {
+            rsip::Header::CallId(call_id) => Some(call_id.value()),
+            _ => None,
+        })
+        .unwrap_or("no Call-ID")
+}
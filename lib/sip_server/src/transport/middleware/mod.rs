@@ -0,0 +1,160 @@
+mod acl;
+mod logging;
+mod normalization;
+mod rate_limit;
+mod reloadable;
+
+pub use acl::Acl;
+pub use logging::Logging;
+pub use normalization::HeaderNormalization;
+pub use rate_limit::RateLimit;
+pub use reloadable::Reloadable;
+
+use super::{DefaultProcessor, TransportProcessor};
+use crate::Error;
+use common::{async_trait::async_trait, rsip};
+use models::{
+    transport::{RequestMsg, ResponseMsg},
+    Handlers,
+};
+use std::fmt::Debug;
+
+//what a middleware decides for a request
+#[derive(Debug)]
+pub enum Action {
+    //hands the request, as changed by the middleware, to the next one
+    Continue(RequestMsg),
+    Drop,
+    //answers the request on the spot, the rest of the pipeline never sees it
+    Respond(rsip::Response),
+}
+
+//a step of the pipeline, by default it lets everything through untouched
+#[async_trait]
+pub trait Middleware: Send + Sync + Debug + 'static {
+    async fn incoming_request(&self, msg: RequestMsg) -> Result<Action, Error> {
+        Ok(Action::Continue(msg))
+    }
+
+    async fn outgoing_request(&self, msg: RequestMsg) -> Result<Action, Error> {
+        Ok(Action::Continue(msg))
+    }
+
+    //responses can only be changed or dropped
+    async fn incoming_response(&self, msg: ResponseMsg) -> Result<Option<ResponseMsg>, Error> {
+        Ok(Some(msg))
+    }
+
+    async fn outgoing_response(&self, msg: ResponseMsg) -> Result<Option<ResponseMsg>, Error> {
+        Ok(Some(msg))
+    }
+}
+
+//an ordered chain of middlewares around a transport processor. Incoming messages go through
+//the processor and then through the middlewares in the order they were added, outgoing ones
+//the other way around, so the processor is always the closest to the network
+#[derive(Debug)]
+pub struct Pipeline<P: TransportProcessor = DefaultProcessor> {
+    processor: P,
+    middlewares: Vec<Box<dyn Middleware>>,
+    handlers: Handlers,
+}
+
+#[async_trait]
+impl<P: TransportProcessor> TransportProcessor for Pipeline<P> {
+    async fn process_outgoing_request(&self, msg: RequestMsg) -> Result<Option<RequestMsg>, Error> {
+        let (peer, transport) = (msg.peer, msg.transport);
+        let mut msg = msg;
+        for middleware in self.middlewares.iter().rev() {
+            msg = match middleware.outgoing_request(msg).await? {
+                Action::Continue(msg) => msg,
+                Action::Drop => return Ok(None),
+                //as if the peer had answered
+                Action::Respond(response) => {
+                    self.handlers
+                        .transport
+                        .process(ResponseMsg::new(response, peer, transport).into())
+                        .await?;
+                    return Ok(None);
+                }
+            };
+        }
+
+        self.processor.process_outgoing_request(msg).await
+    }
+
+    async fn process_incoming_response(
+        &self,
+        msg: ResponseMsg,
+    ) -> Result<Option<ResponseMsg>, Error> {
+        let mut msg = match self.processor.process_incoming_response(msg).await? {
+            Some(msg) => msg,
+            None => return Ok(None),
+        };
+        for middleware in self.middlewares.iter() {
+            msg = match middleware.incoming_response(msg).await? {
+                Some(msg) => msg,
+                None => return Ok(None),
+            };
+        }
+
+        Ok(Some(msg))
+    }
+
+    async fn process_incoming_request(&self, msg: RequestMsg) -> Result<Option<RequestMsg>, Error> {
+        let mut msg = match self.processor.process_incoming_request(msg).await? {
+            Some(msg) => msg,
+            None => return Ok(None),
+        };
+        for middleware in self.middlewares.iter() {
+            msg = match middleware.incoming_request(msg).await? {
+                Action::Continue(msg) => msg,
+                Action::Drop => return Ok(None),
+                //goes out like any other response, through the whole pipeline
+                Action::Respond(response) => {
+                    self.handlers.transport.send(response.into()).await?;
+                    return Ok(None);
+                }
+            };
+        }
+
+        Ok(Some(msg))
+    }
+
+    async fn process_outgoing_response(
+        &self,
+        msg: ResponseMsg,
+    ) -> Result<Option<ResponseMsg>, Error> {
+        let mut msg = msg;
+        for middleware in self.middlewares.iter().rev() {
+            msg = match middleware.outgoing_response(msg).await? {
+                Some(msg) => msg,
+                None => return Ok(None),
+            };
+        }
+
+        self.processor.process_outgoing_response(msg).await
+    }
+}
+
+impl Pipeline<DefaultProcessor> {
+    pub fn new(handlers: Handlers) -> Self {
+        Self::wrapping(DefaultProcessor::new(handlers.config.clone()), handlers)
+    }
+}
+
+impl<P: TransportProcessor> Pipeline<P> {
+    //short-circuited requests are answered through the handlers
+    pub fn wrapping(processor: P, handlers: Handlers) -> Self {
+        Self {
+            processor,
+            middlewares: vec![],
+            handlers,
+        }
+    }
+
+    pub fn with(mut self, middleware: impl Middleware) -> Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+}
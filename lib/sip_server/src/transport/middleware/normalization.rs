@@ -0,0 +1,95 @@
+use super::{Action, Middleware};
+use crate::Error;
+use common::{async_trait::async_trait, rsip};
+use models::transport::{RequestMsg, ResponseMsg};
+
+//RFC3261 7.3.1, header names are case-insensitive and values may be padded, so extension
+//headers are brought to their usual form before anything matches on them. Headers that
+//shouldn't cross the pipeline, like internal X- headers, are removed in both directions
+#[derive(Debug, Default)]
+pub struct HeaderNormalization {
+    removed: Vec<String>,
+}
+
+#[async_trait]
+impl Middleware for HeaderNormalization {
+    async fn incoming_request(&self, mut msg: RequestMsg) -> Result<Action, Error> {
+        self.normalize(&mut msg.sip_request.headers);
+
+        Ok(Action::Continue(msg))
+    }
+
+    async fn outgoing_request(&self, mut msg: RequestMsg) -> Result<Action, Error> {
+        self.normalize(&mut msg.sip_request.headers);
+
+        Ok(Action::Continue(msg))
+    }
+
+    async fn incoming_response(&self, mut msg: ResponseMsg) -> Result<Option<ResponseMsg>, Error> {
+        self.normalize(&mut msg.sip_response.headers);
+
+        Ok(Some(msg))
+    }
+
+    async fn outgoing_response(&self, mut msg: ResponseMsg) -> Result<Option<ResponseMsg>, Error> {
+        self.normalize(&mut msg.sip_response.headers);
+
+        Ok(Some(msg))
+    }
+}
+
+impl HeaderNormalization {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn removing(mut self, name: &str) -> Self {
+        self.removed.push(name.to_ascii_lowercase());
+        self
+    }
+
+    fn normalize(&self, headers: &mut rsip::Headers) {
+        for header in std::mem::take(headers).iter() {
+            if self.removed.contains(&name_of(header).to_ascii_lowercase()) {
+                continue;
+            }
+
+            match header {
+                rsip::Header::Other(name, value) => {
+                    headers.push(rsip::Header::Other(canonical(name), value.trim().into()))
+                }
+                header => headers.push(header.clone()),
+            }
+        }
+    }
+}
+
+fn name_of(header: &rsip::Header) -> String {
+    match header {
+        rsip::Header::Other(name, _) => name.trim().into(),
+        header => header
+            .to_string()
+            .split(':')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .into(),
+    }
+}
+
+//x-account-CODE becomes X-Account-Code
+fn canonical(name: &str) -> String {
+    name.trim()
+        .split('-')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => {
+                    first.to_ascii_uppercase().to_string() + &chars.as_str().to_ascii_lowercase()
+                }
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
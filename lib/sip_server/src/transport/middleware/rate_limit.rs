@@ -0,0 +1,85 @@
+use super::{Action, Middleware};
+use crate::{presets, Error};
+use common::{async_trait::async_trait, rsip, tokio::sync::Mutex};
+use models::transport::RequestMsg;
+use std::{collections::HashMap, net::IpAddr, time::Instant};
+
+//peers that are tracked before the ones that are back to a full bucket are forgotten
+const MAX_TRACKED_PEERS: usize = 10_000;
+
+//a token bucket per peer address for incoming requests, requests over the limit get a 503
+//with Retry-After (RFC3261 21.5.4). ACKs are never limited, they complete transactions that
+//were already let in
+#[derive(Debug)]
+pub struct RateLimit {
+    requests_per_second: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+#[async_trait]
+impl Middleware for RateLimit {
+    async fn incoming_request(&self, msg: RequestMsg) -> Result<Action, Error> {
+        if msg.sip_request.method == rsip::Method::Ack || self.take(msg.peer.ip()).await {
+            return Ok(Action::Continue(msg));
+        }
+
+        common::log::warn!("rate limiting {} from {}", msg.sip_request.method, msg.peer);
+        let mut response = presets::response_from(msg.sip_request, 503.into())?;
+        response.headers.push(rsip::Header::Other(
+            "Retry-After".into(),
+            self.retry_after().to_string(),
+        ));
+
+        Ok(Action::Respond(response))
+    }
+}
+
+impl RateLimit {
+    pub fn new(requests_per_second: u32, burst: u32) -> Self {
+        Self {
+            requests_per_second: requests_per_second.max(1) as f64,
+            burst: burst.max(1) as f64,
+            buckets: Default::default(),
+        }
+    }
+
+    async fn take(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().await;
+
+        if buckets.len() >= MAX_TRACKED_PEERS {
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated_at: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated_at = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+
+        (bucket.tokens + elapsed * self.requests_per_second).min(self.burst)
+    }
+
+    //seconds until the next token
+    fn retry_after(&self) -> u64 {
+        (1.0 / self.requests_per_second).ceil() as u64
+    }
+}
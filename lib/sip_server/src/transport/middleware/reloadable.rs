@@ -0,0 +1,55 @@
+use super::{Action, Middleware};
+use crate::Error;
+use common::{async_trait::async_trait, tokio::sync::RwLock};
+use models::transport::{RequestMsg, ResponseMsg};
+use std::sync::Arc;
+
+//a middleware that can be swapped while the pipeline runs, a clone is kept around to reload it.
+//Messages already inside the old one finish there
+#[derive(Debug)]
+pub struct Reloadable<M: Middleware> {
+    current: Arc<RwLock<Arc<M>>>,
+}
+
+impl<M: Middleware> Clone for Reloadable<M> {
+    fn clone(&self) -> Self {
+        Self {
+            current: self.current.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<M: Middleware> Middleware for Reloadable<M> {
+    async fn incoming_request(&self, msg: RequestMsg) -> Result<Action, Error> {
+        self.current().await.incoming_request(msg).await
+    }
+
+    async fn outgoing_request(&self, msg: RequestMsg) -> Result<Action, Error> {
+        self.current().await.outgoing_request(msg).await
+    }
+
+    async fn incoming_response(&self, msg: ResponseMsg) -> Result<Option<ResponseMsg>, Error> {
+        self.current().await.incoming_response(msg).await
+    }
+
+    async fn outgoing_response(&self, msg: ResponseMsg) -> Result<Option<ResponseMsg>, Error> {
+        self.current().await.outgoing_response(msg).await
+    }
+}
+
+impl<M: Middleware> Reloadable<M> {
+    pub fn new(middleware: M) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(middleware))),
+        }
+    }
+
+    pub async fn reload(&self, middleware: M) {
+        *self.current.write().await = Arc::new(middleware);
+    }
+
+    async fn current(&self) -> Arc<M> {
+        self.current.read().await.clone()
+    }
+}
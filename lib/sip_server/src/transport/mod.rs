@@ -1,11 +1,17 @@
 //pub mod dns_lookup;
+pub mod middleware;
 pub mod processor;
+pub mod system_dns_lookup;
+pub mod topology_hiding;
 #[allow(clippy::module_inception)]
 pub mod transport;
 pub mod uac;
 pub mod uas;
 
+pub use middleware::{Middleware, Pipeline};
 pub use processor::DefaultProcessor;
+pub use system_dns_lookup::SystemDnsLookup;
+pub use topology_hiding::TopologyHiding;
 pub use transport::Transport;
 
 use crate::Error;
@@ -1,13 +1,23 @@
 use super::TransportProcessor;
 use crate::Error;
-use common::async_trait::async_trait;
+use common::{async_trait::async_trait, Config};
 use models::transport::{RequestMsg, ResponseMsg};
+use std::sync::Arc;
 
 //TODO: Processor should return an Option<T>
 //so that if None, transport skips the message
 
-#[derive(Debug, Default)]
-pub struct DefaultProcessor;
+//the outgoing Via and the sent-by of the incoming responses follow the listen addrs of the config
+#[derive(Debug, Clone)]
+pub struct DefaultProcessor {
+    config: Arc<Config>,
+}
+
+impl DefaultProcessor {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self { config }
+    }
+}
 
 #[async_trait]
 impl TransportProcessor for DefaultProcessor {
@@ -19,7 +29,8 @@ impl TransportProcessor for DefaultProcessor {
             transport,
         }: RequestMsg,
     ) -> Result<Option<RequestMsg>, Error> {
-        let sip_request = super::uac::apply_request_defaults(sip_request, peer, transport)?;
+        let sip_request =
+            super::uac::apply_request_defaults(sip_request, peer, transport, &self.config)?;
 
         Ok(Some(RequestMsg {
             sip_request,
@@ -36,7 +47,8 @@ impl TransportProcessor for DefaultProcessor {
             transport,
         }: ResponseMsg,
     ) -> Result<Option<ResponseMsg>, Error> {
-        let sip_response = super::uac::apply_response_defaults(sip_response, peer, transport)?;
+        let sip_response =
+            super::uac::apply_response_defaults(sip_response, peer, transport, &self.config)?;
 
         Ok(Some(ResponseMsg {
             sip_response,
@@ -0,0 +1,70 @@
+use super::DnsLookup;
+use crate::Error;
+use common::{
+    async_trait::async_trait,
+    rsip::{self, headers::ToTypedHeader, message::HeadersExt},
+    tokio,
+};
+use models::transport::{RequestMsg, ResponseMsg};
+use std::net::SocketAddr;
+
+//resolves through the resolver of the OS, so only A/AAAA records are used and
+//the NAPTR/SRV steps of RFC3263 are skipped
+#[derive(Debug, Default)]
+pub struct SystemDnsLookup;
+
+#[async_trait]
+impl DnsLookup for SystemDnsLookup {
+    async fn request_msg_from(&self, request: rsip::Request) -> Result<RequestMsg, Error> {
+        let port: u16 = request
+            .uri
+            .host_with_port
+            .port
+            .clone()
+            .map(Into::into)
+            .unwrap_or(5060);
+        let peer = resolve(&request.uri.host_with_port.host, port).await?;
+
+        Ok(RequestMsg {
+            sip_request: request,
+            peer,
+            transport: rsip::Transport::Udp,
+        })
+    }
+
+    //RFC3261 18.2.2, to the received param if there is one, else to the sent-by
+    async fn response_msg_from(&self, response: rsip::Response) -> Result<ResponseMsg, Error> {
+        let via_header = response.via_header()?.typed()?;
+        let port: u16 = via_header
+            .sent_by()
+            .port()
+            .cloned()
+            .map(Into::into)
+            .unwrap_or(5060);
+
+        let peer = match (
+            via_header.sent_protocol(),
+            via_header.received().ok().flatten(),
+        ) {
+            (rsip::Transport::Udp, Some(received)) => (received, port).into(),
+            (rsip::Transport::Udp, None) => resolve(via_header.sent_by().host(), port).await?,
+            (transport, _) => return Err(format!("not supported transport: {}", transport).into()),
+        };
+
+        Ok(ResponseMsg {
+            sip_response: response,
+            peer,
+            transport: rsip::Transport::Udp,
+        })
+    }
+}
+
+async fn resolve(host: &rsip::Host, port: u16) -> Result<SocketAddr, Error> {
+    match host {
+        rsip::Host::IpAddr(ip_addr) => Ok((*ip_addr, port).into()),
+        rsip::Host::Domain(domain) => tokio::net::lookup_host((domain.to_string(), port))
+            .await?
+            .next()
+            .ok_or_else(|| Error::from(format!("could not resolve {}", domain))),
+    }
+}
@@ -0,0 +1,540 @@
+use super::{DefaultProcessor, TransportProcessor};
+use crate::Error;
+use common::{
+    async_trait::async_trait,
+    rsip::{self, headers::UntypedHeader, prelude::*},
+    tokio::sync::RwLock,
+    uuid::Uuid,
+    Config,
+};
+use models::transport::{RequestMsg, ResponseMsg};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+//RFC3261 17.1.1.2 (64*T1), after that no response comes for the request
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(32);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(7200);
+//between the token and the user part of the hidden Contacts, tokens are plain hex
+const TOKEN_SEPARATOR: char = '-';
+
+//hides the internal topology from the peers on the other side of the edge (RFC5853 3.1.2):
+//internal Vias and Record-Routes are stripped, Contacts point to our public address and the
+//Call-ID can be obfuscated. Everything is restored in what the peers send back, so the
+//internal elements never notice. Runs as the transport processor, or can be called directly
+//by a proxy or a B2BUA on the messages that cross the edge
+#[derive(Debug)]
+pub struct TopologyHiding<P: TransportProcessor = DefaultProcessor> {
+    processor: P,
+    public_addr: rsip::HostWithPort,
+    obfuscate_call_id: bool,
+    idle_timeout: Duration,
+    state: RwLock<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    //Vias below ours in the requests that we sent, by our own Via
+    vias: HashMap<String, (Vec<rsip::headers::Via>, Instant)>,
+    //internal Contacts, by the token of the uri that replaced them
+    contacts: HashMap<String, (rsip::Uri, Instant)>,
+    //tokens, by the internal Contact they stand for
+    tokens: HashMap<String, String>,
+    //by the internal Call-ID
+    dialogs: HashMap<String, Dialog>,
+    //internal Call-IDs, by the external ones
+    call_ids: HashMap<String, String>,
+}
+
+#[derive(Debug)]
+struct Dialog {
+    external_call_id: String,
+    //internal Record-Route entries that our own entry replaced, in the order that the
+    //requests of the peer take them
+    route_set: Vec<String>,
+    //Record-Route entries that came from the peer's side
+    external_record_routes: Vec<String>,
+    updated_at: Instant,
+}
+
+#[async_trait]
+impl<P: TransportProcessor> TransportProcessor for TopologyHiding<P> {
+    async fn process_outgoing_request(&self, msg: RequestMsg) -> Result<Option<RequestMsg>, Error> {
+        let RequestMsg {
+            sip_request,
+            peer,
+            transport,
+        } = match self.processor.process_outgoing_request(msg).await? {
+            Some(msg) => msg,
+            None => return Ok(None),
+        };
+
+        Ok(Some(RequestMsg {
+            sip_request: self.hide_request(sip_request).await?,
+            peer,
+            transport,
+        }))
+    }
+
+    async fn process_incoming_response(
+        &self,
+        ResponseMsg {
+            sip_response,
+            peer,
+            transport,
+        }: ResponseMsg,
+    ) -> Result<Option<ResponseMsg>, Error> {
+        let sip_response = self.restore_response(sip_response).await?;
+
+        self.processor
+            .process_incoming_response(ResponseMsg {
+                sip_response,
+                peer,
+                transport,
+            })
+            .await
+    }
+
+    async fn process_incoming_request(
+        &self,
+        RequestMsg {
+            sip_request,
+            peer,
+            transport,
+        }: RequestMsg,
+    ) -> Result<Option<RequestMsg>, Error> {
+        let sip_request = self.restore_request(sip_request).await?;
+
+        self.processor
+            .process_incoming_request(RequestMsg {
+                sip_request,
+                peer,
+                transport,
+            })
+            .await
+    }
+
+    async fn process_outgoing_response(
+        &self,
+        msg: ResponseMsg,
+    ) -> Result<Option<ResponseMsg>, Error> {
+        let ResponseMsg {
+            sip_response,
+            peer,
+            transport,
+        } = match self.processor.process_outgoing_response(msg).await? {
+            Some(msg) => msg,
+            None => return Ok(None),
+        };
+
+        Ok(Some(ResponseMsg {
+            sip_response: self.hide_response(sip_response).await?,
+            peer,
+            transport,
+        }))
+    }
+}
+
+impl TopologyHiding<DefaultProcessor> {
+    pub fn new(config: Arc<Config>, public_addr: rsip::HostWithPort) -> Self {
+        Self::wrapping(DefaultProcessor::new(config), public_addr)
+    }
+}
+
+impl<P: TransportProcessor> TopologyHiding<P> {
+    //the processor runs on the internal side of the messages
+    pub fn wrapping(processor: P, public_addr: rsip::HostWithPort) -> Self {
+        Self {
+            processor,
+            public_addr,
+            obfuscate_call_id: false,
+            idle_timeout: IDLE_TIMEOUT,
+            state: Default::default(),
+        }
+    }
+
+    pub fn with_call_id_obfuscation(mut self) -> Self {
+        self.obfuscate_call_id = true;
+        self
+    }
+
+    //what we remember about a call is dropped when nothing crossed the edge for that long,
+    //it should be longer than the session refreshes
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    //a request that leaves towards an external peer
+    pub async fn hide_request(&self, mut request: rsip::Request) -> Result<rsip::Request, Error> {
+        let mut state = self.state.write().await;
+        state.purge(self.idle_timeout);
+
+        let internal_vias = take_lower_vias(&mut request.headers);
+        if !internal_vias.is_empty() {
+            state.vias.insert(
+                request.via_header()?.value().to_string(),
+                (internal_vias, Instant::now()),
+            );
+        }
+        self.hide_contact(&mut state, &mut request.headers);
+
+        let dialog = state.outgoing(request.call_id_header()?.value(), self.obfuscate_call_id);
+        //RFC3261 12.2.1.1, the peer keeps the Record-Route order for its requests
+        let record_routes = record_routes_of(&request.headers);
+        if !record_routes.is_empty() {
+            dialog.route_set = record_routes;
+            set_record_routes(&mut request.headers, vec![self.record_route()]);
+        }
+        let external_call_id = dialog.external_call_id.clone();
+        request
+            .headers
+            .unique_push(rsip::headers::CallId::new(external_call_id).into());
+
+        Ok(request)
+    }
+
+    //a response of an external peer to a request that we hid
+    pub async fn restore_response(
+        &self,
+        mut response: rsip::Response,
+    ) -> Result<rsip::Response, Error> {
+        let mut state = self.state.write().await;
+
+        if let Some((vias, _)) = state.vias.get(response.via_header()?.value()) {
+            restore_lower_vias(&mut response.headers, vias.clone());
+        }
+
+        let internal_call_id = state.internal_call_id(response.call_id_header()?.value());
+        response
+            .headers
+            .unique_push(rsip::headers::CallId::new(internal_call_id.clone()).into());
+        if let Some(dialog) = state.dialogs.get_mut(&internal_call_id) {
+            dialog.updated_at = Instant::now();
+            let record_routes = record_routes_of(&response.headers);
+            if !dialog.route_set.is_empty() && record_routes.contains(&self.record_route()) {
+                let route_set = dialog.route_set.clone();
+                set_record_routes(
+                    &mut response.headers,
+                    self.replace_ours(record_routes, route_set),
+                );
+            }
+        }
+
+        if is_bye_accepted(&response) {
+            state.forget(&internal_call_id);
+        }
+
+        Ok(response)
+    }
+
+    //a request of an external peer, new or inside a call that we hid
+    pub async fn restore_request(
+        &self,
+        mut request: rsip::Request,
+    ) -> Result<rsip::Request, Error> {
+        let mut state = self.state.write().await;
+        state.purge(self.idle_timeout);
+
+        if let Some((contact, _)) = token_of(&request.uri).and_then(|t| state.contacts.get(t)) {
+            request.uri = contact.clone();
+        }
+
+        let dialog = state.incoming(request.call_id_header()?.value());
+        for record_route in record_routes_of(&request.headers) {
+            if !dialog.external_record_routes.contains(&record_route) {
+                dialog.external_record_routes.push(record_route);
+            }
+        }
+        let routes = routes_of(&request.headers);
+        if !dialog.route_set.is_empty() && routes.contains(&self.record_route()) {
+            let routes = self.replace_ours(routes, dialog.route_set.clone());
+            request
+                .headers
+                .retain(|header| !matches!(header, rsip::Header::Route(_)));
+            for route in routes {
+                request
+                    .headers
+                    .push(rsip::headers::Route::new(route).into());
+            }
+        }
+
+        let internal_call_id = state.internal_call_id(request.call_id_header()?.value());
+        request
+            .headers
+            .unique_push(rsip::headers::CallId::new(internal_call_id).into());
+
+        Ok(request)
+    }
+
+    //our response to a request of an external peer
+    pub async fn hide_response(
+        &self,
+        mut response: rsip::Response,
+    ) -> Result<rsip::Response, Error> {
+        let mut state = self.state.write().await;
+
+        self.hide_contact(&mut state, &mut response.headers);
+
+        let internal_call_id = response.call_id_header()?.value().to_string();
+        let dialog = state.outgoing(&internal_call_id, self.obfuscate_call_id);
+        //entries that the request didn't have were added on our side, RFC3261 12.1.2 has
+        //the peer reverse them for its requests
+        let record_routes = record_routes_of(&response.headers);
+        let internal: Vec<String> = record_routes
+            .iter()
+            .filter(|record_route| !dialog.external_record_routes.contains(record_route))
+            .cloned()
+            .collect();
+        if let Some(first) = internal.first().cloned() {
+            let ours = self.record_route();
+            let record_routes = record_routes
+                .into_iter()
+                .filter_map(|record_route| match record_route {
+                    record_route if record_route == first => Some(ours.clone()),
+                    record_route if internal.contains(&record_route) => None,
+                    record_route => Some(record_route),
+                })
+                .collect();
+            dialog.route_set = internal.into_iter().rev().collect();
+            set_record_routes(&mut response.headers, record_routes);
+        }
+        let external_call_id = dialog.external_call_id.clone();
+        response
+            .headers
+            .unique_push(rsip::headers::CallId::new(external_call_id).into());
+
+        if is_bye_accepted(&response) {
+            state.forget(&internal_call_id);
+        }
+
+        Ok(response)
+    }
+
+    //every internal Contact gets its own token as the user part, so that the ones of different
+    //UAs don't collide on our address. The user part is kept after it, since it often
+    //identifies the device
+    fn hide_contact(&self, state: &mut State, headers: &mut rsip::Headers) {
+        let contact = headers.iter().find_map(|header| match header {
+            rsip::Header::Contact(contact) => contact.typed().ok(),
+            _ => None,
+        });
+
+        if let Some(contact) = contact {
+            let token = state.token_for(&contact.uri);
+            let user = match &contact.uri.auth {
+                Some(auth) => format!("{}{}{}", token, TOKEN_SEPARATOR, auth.user),
+                None => token.clone(),
+            };
+            let hidden = rsip::Uri {
+                auth: Some(rsip::Auth {
+                    user,
+                    password: None,
+                }),
+                host_with_port: self.public_addr.clone(),
+                params: vec![],
+                headers: vec![],
+                ..contact.uri.clone()
+            };
+            state
+                .contacts
+                .insert(token, (contact.uri.clone(), Instant::now()));
+            headers.unique_push(
+                rsip::typed::Contact {
+                    uri: hidden,
+                    ..contact
+                }
+                .into(),
+            );
+        }
+    }
+
+    fn record_route(&self) -> String {
+        format!("<sip:{};lr>", self.public_addr)
+    }
+
+    fn replace_ours(&self, entries: Vec<String>, route_set: Vec<String>) -> Vec<String> {
+        let ours = self.record_route();
+
+        entries
+            .into_iter()
+            .flat_map(|entry| match entry == ours {
+                true => route_set.clone(),
+                false => vec![entry],
+            })
+            .collect()
+    }
+}
+
+impl State {
+    //the call of a message that we send, by its internal Call-ID
+    fn outgoing(&mut self, call_id: &str, obfuscate: bool) -> &mut Dialog {
+        if !self.dialogs.contains_key(call_id) {
+            let external_call_id = match obfuscate {
+                true => Uuid::new_v4().simple().to_string(),
+                false => call_id.to_string(),
+            };
+            self.call_ids
+                .insert(external_call_id.clone(), call_id.to_string());
+            self.dialogs
+                .insert(call_id.to_string(), Dialog::new(external_call_id));
+        }
+
+        let dialog = self
+            .dialogs
+            .get_mut(call_id)
+            .expect("dialog was just added");
+        dialog.updated_at = Instant::now();
+        dialog
+    }
+
+    //the call of a message that we receive, calls started by the peer keep their Call-ID
+    fn incoming(&mut self, call_id: &str) -> &mut Dialog {
+        let internal_call_id = self.internal_call_id(call_id);
+        self.call_ids
+            .insert(call_id.to_string(), internal_call_id.clone());
+
+        let dialog = self
+            .dialogs
+            .entry(internal_call_id)
+            .or_insert_with(|| Dialog::new(call_id.to_string()));
+        dialog.updated_at = Instant::now();
+        dialog
+    }
+
+    fn internal_call_id(&self, call_id: &str) -> String {
+        self.call_ids
+            .get(call_id)
+            .cloned()
+            .unwrap_or_else(|| call_id.to_string())
+    }
+
+    //the same Contact keeps its token, the peers might still use the uri that we gave them
+    fn token_for(&mut self, contact: &rsip::Uri) -> String {
+        self.tokens
+            .entry(contact.to_string())
+            .or_insert_with(|| Uuid::new_v4().simple().to_string())
+            .clone()
+    }
+
+    fn forget(&mut self, internal_call_id: &str) {
+        if let Some(dialog) = self.dialogs.remove(internal_call_id) {
+            self.call_ids.remove(&dialog.external_call_id);
+        }
+    }
+
+    fn purge(&mut self, idle_timeout: Duration) {
+        let now = Instant::now();
+
+        self.vias
+            .retain(|_, (_, created_at)| now.duration_since(*created_at) < TRANSACTION_TIMEOUT);
+        self.contacts
+            .retain(|_, (_, updated_at)| now.duration_since(*updated_at) < idle_timeout);
+        let contacts = &self.contacts;
+        self.tokens.retain(|_, token| contacts.contains_key(token));
+
+        let call_ids = &mut self.call_ids;
+        self.dialogs.retain(|_, dialog| {
+            let active = now.duration_since(dialog.updated_at) < idle_timeout;
+            if !active {
+                call_ids.remove(&dialog.external_call_id);
+            }
+            active
+        });
+    }
+}
+
+impl Dialog {
+    fn new(external_call_id: String) -> Self {
+        Self {
+            external_call_id,
+            route_set: vec![],
+            external_record_routes: vec![],
+            updated_at: Instant::now(),
+        }
+    }
+}
+
+fn take_lower_vias(headers: &mut rsip::Headers) -> Vec<rsip::headers::Via> {
+    let mut vias = vec![];
+    let mut top = true;
+
+    headers.retain(|header| match header {
+        rsip::Header::Via(_) if top => {
+            top = false;
+            true
+        }
+        rsip::Header::Via(via) => {
+            vias.push(via.clone());
+            false
+        }
+        _ => true,
+    });
+
+    vias
+}
+
+fn restore_lower_vias(headers: &mut rsip::Headers, vias: Vec<rsip::headers::Via>) {
+    let mut restored: rsip::Headers = Default::default();
+    let mut vias = Some(vias);
+
+    for header in std::mem::take(headers).iter().cloned() {
+        let is_via = matches!(header, rsip::Header::Via(_));
+        restored.push(header);
+        if is_via {
+            for via in vias.take().unwrap_or_default() {
+                restored.push(via.into());
+            }
+        }
+    }
+
+    *headers = restored;
+}
+
+//the token of a uri that we gave to the peers, whatever they added to it
+fn token_of(uri: &rsip::Uri) -> Option<&str> {
+    uri.auth
+        .as_ref()
+        .and_then(|auth| auth.user.split(TOKEN_SEPARATOR).next())
+}
+
+//the entries of all the header lines, so that they can be matched regardless of how they were
+//grouped
+fn record_routes_of(headers: &rsip::Headers) -> Vec<String> {
+    entries_of(headers.iter().filter_map(|header| match header {
+        rsip::Header::RecordRoute(record_route) => Some(record_route.value()),
+        _ => None,
+    }))
+}
+
+fn routes_of(headers: &rsip::Headers) -> Vec<String> {
+    entries_of(headers.iter().filter_map(|header| match header {
+        rsip::Header::Route(route) => Some(route.value()),
+        _ => None,
+    }))
+}
+
+fn entries_of<'a>(values: impl Iterator<Item = &'a str>) -> Vec<String> {
+    values
+        .flat_map(|value| value.split(','))
+        .map(|entry| entry.trim().to_string())
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+fn set_record_routes(headers: &mut rsip::Headers, record_routes: Vec<String>) {
+    headers.retain(|header| !matches!(header, rsip::Header::RecordRoute(_)));
+    for record_route in record_routes {
+        headers.push(rsip::headers::RecordRoute::new(record_route).into());
+    }
+}
+
+fn is_bye_accepted(response: &rsip::Response) -> bool {
+    response.status_code.kind() == rsip::StatusCodeKind::Successful
+        && matches!(
+            response.cseq_header().and_then(|cseq| cseq.typed()),
+            Ok(cseq) if cseq.method == rsip::Method::Bye
+        )
+}
@@ -11,7 +11,7 @@ use common::{
     },
     futures_util::stream::StreamExt,
     rsip,
-    tokio::{self, net::UdpSocket, sync::Mutex},
+    tokio::{self, net::UdpSocket, sync::Mutex, task::JoinHandle},
     tokio_util::codec::BytesCodec,
     tokio_util::udp::UdpFramed,
 };
@@ -28,6 +28,9 @@ type UdpStream = SplitStream<UdpFramed<BytesCodec>>;
 #[derive(Debug)]
 pub struct Transport<P: TransportProcessor, D: DnsLookup> {
     inner: Arc<Inner<P, D>>,
+    local_addr: SocketAddr,
+    messages_task: JoinHandle<()>,
+    socket_task: JoinHandle<()>,
 }
 
 #[derive(Debug)]
@@ -45,27 +48,56 @@ impl<P: TransportProcessor, D: DnsLookup> Transport<P, D> {
         dns_lookup: D,
         messages_rx: TrReceiver,
     ) -> Result<Self, Error> {
-        let (udp_sink, udp_stream) = create_socket()?;
-
-        let me = Self {
-            inner: Arc::new(Inner {
-                processor,
-                dns_lookup,
-                udp_sink: Mutex::new(udp_sink),
-                handlers,
-            }),
-        };
+        Self::bind(
+            handlers,
+            processor,
+            dns_lookup,
+            messages_rx,
+            ([0, 0, 0, 0], 5060).into(),
+        )
+    }
+
+    pub fn bind(
+        handlers: Handlers,
+        processor: P,
+        dns_lookup: D,
+        messages_rx: TrReceiver,
+        addr: SocketAddr,
+    ) -> Result<Self, Error> {
+        let (udp_sink, udp_stream, local_addr) = create_socket(addr)?;
+
+        let inner = Arc::new(Inner {
+            processor,
+            dns_lookup,
+            udp_sink: Mutex::new(udp_sink),
+            handlers,
+        });
+
+        let messages_inner = inner.clone();
+        let messages_task = tokio::spawn(async move { messages_inner.run(messages_rx).await });
+        let socket_inner = inner.clone();
+        let socket_task = tokio::spawn(async move { socket_inner.run_socket(udp_stream).await });
+
+        Ok(Self {
+            inner,
+            local_addr,
+            messages_task,
+            socket_task,
+        })
+    }
 
-        me.run(messages_rx, udp_stream);
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
 
-        Ok(me)
+    //stops reading from the socket, messages of the upper layers are still sent
+    pub fn close_socket(&self) {
+        self.socket_task.abort();
     }
 
-    fn run(&self, messages: TrReceiver, udp_stream: UdpStream) {
-        let inner = self.inner.clone();
-        tokio::spawn(async move { inner.run(messages).await });
-        let socket_inner = self.inner.clone();
-        tokio::spawn(async move { socket_inner.run_socket(udp_stream).await });
+    pub fn abort(&self) {
+        self.close_socket();
+        self.messages_task.abort();
     }
 }
 
@@ -189,8 +221,32 @@ impl<P: TransportProcessor, D: DnsLookup> Inner<P, D> {
         Ok(())
     }
 
+    //retransmissions (and ACKs of non-2xx responses) belong to an existing transaction
     async fn process_incoming_request(&self, request: RequestMsg) -> Result<(), Error> {
-        Ok(self.handlers.tu.process(request.sip_request.into()).await?)
+        let transaction_id = request.transaction_id()?;
+
+        match transaction_id {
+            Some(transaction_id)
+                if self
+                    .handlers
+                    .transaction
+                    .has_transaction_for(transaction_id)
+                    .await? =>
+            {
+                self.handlers
+                    .transaction
+                    .process(request.sip_request.into())
+                    .await?;
+            }
+            _ => {
+                self.handlers
+                    .tu
+                    .process(request.sip_request.into())
+                    .await?;
+            }
+        };
+
+        Ok(())
     }
 
     async fn process_incoming_response(&self, response: ResponseMsg) -> Result<(), Error> {
@@ -249,11 +305,14 @@ impl<P: TransportProcessor, D: DnsLookup> Inner<P, D> {
     }
 }
 
-fn create_socket() -> Result<(UdpSink, UdpStream), crate::Error> {
-    let socket = UdpSocket::from_std(std::net::UdpSocket::bind("0.0.0.0:5060")?)?;
-    common::log::debug!("starting udp server listening in port 5060");
-    let socket = UdpFramed::new(socket, BytesCodec::new());
-    Ok(socket.split())
+fn create_socket(addr: SocketAddr) -> Result<(UdpSink, UdpStream, SocketAddr), crate::Error> {
+    let socket = std::net::UdpSocket::bind(addr)?;
+    socket.set_nonblocking(true)?;
+    let socket = UdpSocket::from_std(socket)?;
+    let local_addr = socket.local_addr()?;
+    common::log::debug!("starting udp server listening in {}", local_addr);
+    let (udp_sink, udp_stream) = UdpFramed::new(socket, BytesCodec::new()).split();
+    Ok((udp_sink, udp_stream, local_addr))
 }
 
 #[allow(dead_code)]
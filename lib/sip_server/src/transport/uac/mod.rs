@@ -1,5 +1,8 @@
 use crate::Error;
-use common::rsip::{self, prelude::*};
+use common::{
+    rsip::{self, prelude::*},
+    Config,
+};
 use std::net::SocketAddr;
 
 //outgoing
@@ -7,6 +10,7 @@ pub fn apply_request_defaults(
     mut request: rsip::Request,
     peer: SocketAddr,
     _transport: rsip::Transport,
+    config: &Config,
 ) -> Result<rsip::Request, Error> {
     apply_via_maddr_address(
         request.via_header_mut().expect("via header is missing!"),
@@ -16,7 +20,10 @@ pub fn apply_request_defaults(
         request.via_header_mut().expect("via header is missing!"),
         &peer,
     )?;
-    apply_via_sent_by(request.via_header_mut().expect("via header is missing!"))?;
+    apply_via_sent_by(
+        request.via_header_mut().expect("via header is missing!"),
+        config,
+    )?;
 
     Ok(request)
 }
@@ -26,8 +33,9 @@ pub fn apply_response_defaults(
     response: rsip::Response,
     _peer: SocketAddr,
     _transport: rsip::Transport,
+    config: &Config,
 ) -> Result<rsip::Response, Error> {
-    assert_sent_by_value(response.via_header().expect("via header missing"))?;
+    assert_sent_by_value(response.via_header().expect("via header missing"), config)?;
     Ok(response)
 }
 
@@ -58,20 +66,23 @@ pub fn apply_via_ttl(via_header: &mut rsip::headers::Via, peer: &SocketAddr) ->
     Ok(())
 }
 
-pub fn apply_via_sent_by(via_header: &mut rsip::headers::Via) -> Result<(), Error> {
+pub fn apply_via_sent_by(
+    via_header: &mut rsip::headers::Via,
+    config: &Config,
+) -> Result<(), Error> {
     let typed_via_header = via_header.typed()?;
 
     let mut uri = typed_via_header.uri.clone();
-    uri.host_with_port = common::CONFIG.default_addr();
+    uri.host_with_port = config.default_addr();
     via_header.replace(typed_via_header.to_string());
 
     Ok(())
 }
 
-pub fn assert_sent_by_value(via_header: &rsip::headers::Via) -> Result<(), Error> {
+pub fn assert_sent_by_value(via_header: &rsip::headers::Via, config: &Config) -> Result<(), Error> {
     let typed_via_header = via_header.typed()?;
 
-    if common::CONFIG.contains_addr(&typed_via_header.uri.host_with_port) {
+    if config.contains_addr(&typed_via_header.uri.host_with_port) {
         Ok(())
     } else {
         Err(Error::custom(format!(
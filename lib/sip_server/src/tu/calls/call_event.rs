@@ -0,0 +1,38 @@
+use common::rsip;
+
+//progress of a call, as observed by its dialog
+//Failed and Terminated are always the last event of a call
+#[derive(Debug, Clone)]
+pub enum CallEvent {
+    Ringing(rsip::Response),
+    EarlyMedia(rsip::Response), //provisional response carrying SDP
+    Answered(rsip::Response),
+    //PRACK of the peer to one of our reliable provisionals, it carries the SDP answer
+    //when the provisional carried the offer
+    Pracked(rsip::Request),
+    //re-INVITE or UPDATE of the peer, to be answered through the session
+    ModificationRequested(rsip::Request),
+    //our re-INVITE or UPDATE got accepted or rejected, the latter keeps the session as it was
+    Modified(rsip::Response),
+    ModificationRejected(rsip::Response),
+    //REFER of the peer, to be accepted or rejected through the session
+    TransferRequested(rsip::Request),
+    //our REFER got accepted or rejected by the peer
+    TransferAccepted(rsip::Response),
+    TransferRejected(rsip::Response),
+    //status of the referred request, as reported by the NOTIFYs of the peer
+    TransferProgress(rsip::StatusCode),
+    //INFO of the peer, already answered with 200
+    InfoReceived(rsip::Request),
+    Failed {
+        status_code: Option<rsip::StatusCode>,
+        reason: String,
+    },
+    Terminated,
+}
+
+impl CallEvent {
+    pub fn is_final(&self) -> bool {
+        matches!(self, Self::Failed { .. } | Self::Terminated)
+    }
+}
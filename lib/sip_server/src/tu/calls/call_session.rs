@@ -0,0 +1,201 @@
+use super::CallEvent;
+use crate::{
+    presets,
+    tu::dialogs::{negotiation, refer},
+    Error,
+};
+use common::{
+    rsip::{self, headers::UntypedHeader, prelude::*},
+    tokio::sync::mpsc::UnboundedReceiver,
+};
+use models::{rsip_ext::*, tu::DialogId, Handlers};
+
+//handle of an outgoing call, requests go through the TU so that they are
+//processed by the UAC dialog, which in turn reports back through the events
+#[derive(Debug)]
+pub struct CallSession {
+    pub request: rsip::Request,
+    events: UnboundedReceiver<CallEvent>,
+    handlers: Handlers,
+}
+
+impl CallSession {
+    pub fn new(
+        handlers: Handlers,
+        request: rsip::Request,
+        events: UnboundedReceiver<CallEvent>,
+    ) -> Self {
+        Self {
+            request,
+            events,
+            handlers,
+        }
+    }
+
+    pub fn dialog_id(&self) -> Result<DialogId, Error> {
+        Ok(self.request.dialog_id()?)
+    }
+
+    //returns None once the dialog is gone
+    pub async fn next_event(&mut self) -> Option<CallEvent> {
+        self.events.recv().await
+    }
+
+    pub async fn hangup(&self) -> Result<(), Error> {
+        self.send(self.in_dialog_request(rsip::Method::Bye)?).await
+    }
+
+    //the outcome comes through CallEvent::Modified or ModificationRejected, the latter with
+    //a 491 of our own when another modification is still in progress
+    pub async fn reinvite(&self, sdp_offer: Vec<u8>) -> Result<(), Error> {
+        let mut request = self.in_dialog_request(rsip::Method::Invite)?;
+        request
+            .headers
+            .unique_push(rsip::headers::ContentType::new("application/sdp").into());
+        request
+            .headers
+            .unique_push(rsip::headers::ContentLength::new(sdp_offer.len().to_string()).into());
+        request.body = sdp_offer;
+
+        self.send(request).await
+    }
+
+    pub async fn update(&self, sdp_offer: Vec<u8>) -> Result<(), Error> {
+        let mut request = self.in_dialog_request(rsip::Method::Update)?;
+        request
+            .headers
+            .unique_push(rsip::headers::ContentType::new("application/sdp").into());
+        request
+            .headers
+            .unique_push(rsip::headers::ContentLength::new(sdp_offer.len().to_string()).into());
+        request.body = sdp_offer;
+
+        self.send(request).await
+    }
+
+    //answers an offer received in a reliable provisional, through CallEvent::EarlyMedia
+    pub async fn prack(&self, sdp_answer: Vec<u8>) -> Result<(), Error> {
+        let mut request = self.in_dialog_request(rsip::Method::PRack)?;
+        request
+            .headers
+            .unique_push(rsip::headers::ContentType::new("application/sdp").into());
+        request
+            .headers
+            .unique_push(rsip::headers::ContentLength::new(sdp_answer.len().to_string()).into());
+        request.body = sdp_answer;
+
+        self.send(request).await
+    }
+
+    //answers a re-INVITE or UPDATE received through CallEvent::ModificationRequested
+    pub async fn accept_modification(
+        &self,
+        request: &rsip::Request,
+        sdp_answer: Vec<u8>,
+    ) -> Result<(), Error> {
+        negotiation::check_answer(&request.body, &sdp_answer)?;
+        let response = presets::response_from(request.clone(), 200.into())?;
+
+        self.send_response(presets::with_sdp_body(response, sdp_answer))
+            .await
+    }
+
+    //the session stays as it was before the modification, 488 is the usual choice
+    pub async fn reject_modification(
+        &self,
+        request: &rsip::Request,
+        status_code: impl Into<rsip::StatusCode>,
+    ) -> Result<(), Error> {
+        let status_code = status_code.into();
+        if status_code.kind() < rsip::StatusCodeKind::RequestFailure {
+            return Err(Error::custom(format!(
+                "{} can't be used to reject a modification",
+                status_code
+            )));
+        }
+
+        self.send_response(presets::response_from(request.clone(), status_code)?)
+            .await
+    }
+
+    //asks the peer to call the target, the outcome comes through CallEvent::TransferProgress
+    pub async fn transfer(&self, target: rsip::Uri) -> Result<(), Error> {
+        let mut request = self.in_dialog_request(rsip::Method::Refer)?;
+        request.headers.push(refer::refer_to_header(&target));
+
+        self.send(request).await
+    }
+
+    //answers a REFER received through CallEvent::TransferRequested, the dialog then
+    //reports that the referred call is being tried
+    pub async fn accept_transfer(&self, request: &rsip::Request) -> Result<(), Error> {
+        self.send_response(presets::response_from(request.clone(), 202.into())?)
+            .await
+    }
+
+    pub async fn reject_transfer(
+        &self,
+        request: &rsip::Request,
+        status_code: impl Into<rsip::StatusCode>,
+    ) -> Result<(), Error> {
+        let status_code = status_code.into();
+        if status_code.kind() < rsip::StatusCodeKind::RequestFailure {
+            return Err(Error::custom(format!(
+                "{} can't be used to reject a transfer",
+                status_code
+            )));
+        }
+
+        self.send_response(presets::response_from(request.clone(), status_code)?)
+            .await
+    }
+
+    //reports the status of the referred call to the peer, a final one ends the transfer
+    pub async fn transfer_progress(
+        &self,
+        status_code: impl Into<rsip::StatusCode>,
+    ) -> Result<(), Error> {
+        let mut request = self.in_dialog_request(rsip::Method::Notify)?;
+        request.body = refer::sipfrag(&status_code.into());
+
+        self.send(request).await
+    }
+
+    pub async fn send_info(&self, content_type: &str, body: Vec<u8>) -> Result<(), Error> {
+        let mut request = self.in_dialog_request(rsip::Method::Info)?;
+        request
+            .headers
+            .unique_push(rsip::headers::ContentType::new(content_type).into());
+        request
+            .headers
+            .unique_push(rsip::headers::ContentLength::new(body.len().to_string()).into());
+        request.body = body;
+
+        self.send(request).await
+    }
+
+    //the dialog is found through the headers of the initial INVITE, the rest is filled in
+    //by the dialog itself
+    fn in_dialog_request(&self, method: rsip::Method) -> Result<rsip::Request, Error> {
+        let mut request = presets::in_dialog_request(&self.handlers.config, method);
+        request
+            .headers
+            .unique_push(self.request.from_header()?.clone().into());
+        request
+            .headers
+            .unique_push(self.request.to_header()?.clone().into());
+        request
+            .headers
+            .unique_push(self.request.call_id_header()?.clone().into());
+
+        Ok(request)
+    }
+
+    async fn send(&self, request: rsip::Request) -> Result<(), Error> {
+        Ok(self.handlers.tu.outgoing(request.into()).await?)
+    }
+
+    async fn send_response(&self, response: rsip::Response) -> Result<(), Error> {
+        Ok(self.handlers.tu.outgoing(response.into()).await?)
+    }
+}
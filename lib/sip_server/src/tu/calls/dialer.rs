@@ -0,0 +1,39 @@
+use super::CallSession;
+use crate::{presets, tu::dialogs::Dialogs, Error};
+use common::{rsip, tokio};
+use models::Handlers;
+use std::sync::Arc;
+
+//starts outgoing calls on the dialogs of the UA, handed to the application so that it can
+//originate calls from a call handler, like the outgoing leg of a B2BUA
+#[derive(Debug, Clone)]
+pub struct Dialer {
+    handlers: Handlers,
+    dialogs: Arc<Dialogs>,
+}
+
+impl Dialer {
+    pub fn new(handlers: Handlers, dialogs: Arc<Dialogs>) -> Self {
+        Self { handlers, dialogs }
+    }
+
+    pub async fn call(&self, target: rsip::Uri, sdp_offer: Vec<u8>) -> Result<CallSession, Error> {
+        self.call_with(self.invite_request(target, sdp_offer)).await
+    }
+
+    //the INVITE that call would send, for the application to adjust before call_with
+    pub fn invite_request(&self, target: rsip::Uri, sdp_offer: Vec<u8>) -> rsip::Request {
+        presets::invite_request(&self.handlers.config, target, sdp_offer)
+    }
+
+    //like call, for an INVITE that the application has already adjusted
+    pub async fn call_with(&self, request: rsip::Request) -> Result<CallSession, Error> {
+        let (events_tx, events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        self.dialogs
+            .new_uac_call(request.clone(), events_tx)
+            .await?;
+
+        Ok(CallSession::new(self.handlers.clone(), request, events_rx))
+    }
+}
@@ -0,0 +1,159 @@
+use super::{CallEvent, CallSession, Dialer};
+use crate::{
+    presets,
+    tu::dialogs::{negotiation, replaces::Replaces},
+    Error,
+};
+use common::{
+    rsip::{self, prelude::*},
+    tokio::sync::mpsc::UnboundedReceiver,
+};
+use models::Handlers;
+
+//handle given to the application for a new incoming INVITE, responses go through the TU
+//so that the UAS dialog can follow them
+#[derive(Debug)]
+pub struct IncomingCall {
+    pub request: rsip::Request,
+    events: UnboundedReceiver<CallEvent>,
+    handlers: Handlers,
+    dialer: Option<Dialer>,
+}
+
+impl IncomingCall {
+    pub fn new(
+        handlers: Handlers,
+        request: rsip::Request,
+        events: UnboundedReceiver<CallEvent>,
+    ) -> Self {
+        Self {
+            request,
+            events,
+            handlers,
+            dialer: None,
+        }
+    }
+
+    pub fn with_dialer(mut self, dialer: Dialer) -> Self {
+        self.dialer = Some(dialer);
+        self
+    }
+
+    //originates calls on the same UA, set for the calls that the UA hands to the application
+    pub fn dialer(&self) -> Option<&Dialer> {
+        self.dialer.as_ref()
+    }
+
+    pub fn caller(&self) -> Result<rsip::Uri, Error> {
+        Ok(self.request.from_header()?.uri()?)
+    }
+
+    pub fn callee(&self) -> Result<rsip::Uri, Error> {
+        Ok(self.request.to_header()?.uri()?)
+    }
+
+    //events before the call is accepted, like the PRACKs of reliable provisionals
+    pub async fn next_event(&mut self) -> Option<CallEvent> {
+        self.events.recv().await
+    }
+
+    //the dialog that this call takes the place of, which is already being hung up,
+    //for instance when completing an attended transfer
+    pub fn replaces(&self) -> Option<Replaces> {
+        Replaces::from_headers(&self.request.headers).ok().flatten()
+    }
+
+    pub fn sdp_offer(&self) -> Option<&[u8]> {
+        match self.request.body.is_empty() {
+            true => None,
+            false => Some(&self.request.body),
+        }
+    }
+
+    //any 1xx apart from 100, which is already sent by the dialog
+    pub async fn provisional(
+        &self,
+        status_code: impl Into<rsip::StatusCode>,
+        sdp: Option<Vec<u8>>,
+    ) -> Result<(), Error> {
+        let status_code = status_code.into();
+        if status_code.kind() != rsip::StatusCodeKind::Provisional {
+            return Err(Error::custom(format!(
+                "{} is not a provisional response",
+                status_code
+            )));
+        }
+
+        self.respond(status_code, sdp).await
+    }
+
+    pub async fn ringing(&self) -> Result<(), Error> {
+        self.provisional(rsip::StatusCode::Ringing, None).await
+    }
+
+    //the returned session follows the call from now on, like for outgoing calls
+    pub async fn accept(self, sdp_answer: Vec<u8>) -> Result<CallSession, Error> {
+        self.respond(200, Some(sdp_answer)).await?;
+
+        Ok(CallSession::new(self.handlers, self.request, self.events))
+    }
+
+    pub async fn reject(self, status_code: impl Into<rsip::StatusCode>) -> Result<(), Error> {
+        let status_code = status_code.into();
+        if status_code.kind() < rsip::StatusCodeKind::RequestFailure {
+            return Err(Error::custom(format!(
+                "{} can't be used to reject a call",
+                status_code
+            )));
+        }
+
+        self.respond(status_code, None).await
+    }
+
+    pub async fn redirect(
+        self,
+        status_code: impl Into<rsip::StatusCode>,
+        targets: Vec<rsip::Uri>,
+    ) -> Result<(), Error> {
+        let status_code = status_code.into();
+        if status_code.kind() != rsip::StatusCodeKind::Redirection {
+            return Err(Error::custom(format!(
+                "{} is not a redirection response",
+                status_code
+            )));
+        }
+        if targets.is_empty() {
+            return Err(Error::from("redirecting a call needs at least one target"));
+        }
+
+        let mut response = presets::response_from(self.request.clone(), status_code)?;
+        for target in targets {
+            response
+                .headers
+                .push(rsip::typed::Contact::from(target).into());
+        }
+
+        self.send(response).await
+    }
+
+    async fn respond(
+        &self,
+        status_code: impl Into<rsip::StatusCode>,
+        sdp: Option<Vec<u8>>,
+    ) -> Result<(), Error> {
+        let response = presets::response_from(self.request.clone(), status_code.into())?;
+        let response = match sdp {
+            Some(sdp) => {
+                negotiation::check_answer(&self.request.body, &sdp)?;
+                presets::with_sdp_body(response, sdp)
+            }
+            None => response,
+        };
+
+        self.send(response).await
+    }
+
+    async fn send(&self, response: rsip::Response) -> Result<(), Error> {
+        Ok(self.handlers.tu.outgoing(response.into()).await?)
+    }
+}
@@ -0,0 +1,9 @@
+mod call_event;
+mod call_session;
+mod dialer;
+mod incoming_call;
+
+pub use call_event::CallEvent;
+pub use call_session::CallSession;
+pub use dialer::Dialer;
+pub use incoming_call::IncomingCall;
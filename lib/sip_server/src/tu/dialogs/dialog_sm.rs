@@ -1,34 +1,90 @@
-//use super::{uac, uas};
-use super::uac;
-//use models::transport::ResponseMsg;
-use crate::Error;
+use super::{
+    modification::Modification,
+    refer::Transfer,
+    replaces::{Replaces, Target},
+    session_timer::SessionTimer,
+    uac, uas,
+};
+use crate::{presets, transaction::sm::Timers, tu::calls::CallEvent, Error};
 use common::rsip;
+use models::{tu::DialogId, Handlers};
+use sdp::OfferAnswer;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum DialogSm {
     Uac(uac::MultiDialog),
-    //Uas(uas::MultiDialog),
+    Uas(uas::MultiDialog),
 }
 
 impl DialogSm {
+    pub async fn is_active(&self) -> bool {
+        match self {
+            Self::Uac(uac) => uac.is_active().await,
+            Self::Uas(uas) => uas.is_active().await,
+        }
+    }
+
+    pub async fn has_ended_for(&self, linger: Duration) -> bool {
+        match self {
+            Self::Uac(uac) => uac.has_ended_for(linger).await,
+            Self::Uas(uas) => uas.has_ended_for(linger).await,
+        }
+    }
+
     pub async fn process_incoming_request(&self, msg: rsip::Request) -> Result<(), Error> {
         match self {
             Self::Uac(uac) => uac.process_incoming_request(msg).await,
-            //Self::Uas(uas) => uas.process_response(msg).await?,
+            Self::Uas(uas) => uas.process_incoming_request(msg).await,
         }
     }
 
     pub async fn process_incoming_response(&self, msg: rsip::Response) -> Result<(), Error> {
         match self {
             Self::Uac(uac) => uac.process_incoming_response(msg).await,
-            //Self::Uas(uas) => uas.process_response(msg).await?,
+            Self::Uas(uas) => uas.process_incoming_response(msg).await,
+        }
+    }
+
+    pub async fn process_outgoing_request(&self, msg: rsip::Request) -> Result<(), Error> {
+        match self {
+            Self::Uac(uac) => uac.process_outgoing_request(msg).await,
+            Self::Uas(uas) => uas.process_outgoing_request(msg).await,
+        }
+    }
+
+    pub async fn process_outgoing_response(&self, msg: rsip::Response) -> Result<(), Error> {
+        match self {
+            Self::Uac(uac) => uac.process_outgoing_response(msg).await,
+            Self::Uas(uas) => uas.process_outgoing_response(msg).await,
+        }
+    }
+
+    pub async fn replaced_by(&self, replaces: &Replaces) -> Option<Target> {
+        match self {
+            Self::Uac(uac) => uac.replaced_by(replaces).await,
+            Self::Uas(uas) => uas.replaced_by(replaces).await,
+        }
+    }
+
+    pub async fn replace(&self, replaces: &Replaces) {
+        match self {
+            Self::Uac(uac) => uac.replace(replaces).await,
+            Self::Uas(uas) => uas.replace(replaces).await,
+        }
+    }
+
+    pub async fn next(&self) {
+        match self {
+            Self::Uac(uac) => uac.next().await,
+            Self::Uas(uas) => uas.next().await,
         }
     }
 
     pub async fn transport_error(&self, reason: String, msg: rsip::SipMessage) {
         match self {
             Self::Uac(uac) => uac.transport_error(reason, msg).await,
-            //Self::Uas(uas) => uas.process_response(msg).await?,
+            Self::Uas(uas) => uas.transport_error(reason, msg).await,
         }
     }
 }
@@ -38,9 +94,65 @@ impl From<uac::MultiDialog> for DialogSm {
         Self::Uac(from)
     }
 }
-/*
+
 impl From<uas::MultiDialog> for DialogSm {
     fn from(from: uas::MultiDialog) -> Self {
         Self::Uas(from)
     }
-}*/
+}
+
+//what the UAC and UAS dialogs have in common once confirmed, so that the requests that both
+//of them handle the same way are written once, in the module of each extension
+pub trait Dialog: Send + Sync {
+    fn id(&self) -> &DialogId;
+    fn handlers(&self) -> &Handlers;
+    //the 2xx of a re-INVITE is retransmitted with them until the ACK arrives
+    fn timers(&self) -> Timers;
+    //RFC3261 14.1, whether we sent the initial INVITE, which decides the glare retry timer
+    fn owns_call_id(&self) -> bool;
+    fn modification(&mut self) -> &mut Option<Modification>;
+    fn session_timer(&mut self) -> &mut Option<SessionTimer>;
+    fn offer_answer(&mut self) -> &mut OfferAnswer;
+    fn transfer(&mut self) -> &mut Option<Transfer>;
+    //RFC3261 12.2, target refresh requests and their responses update it
+    fn set_remote_target(&mut self, remote_target: rsip::Uri);
+    fn emit(&self, event: CallEvent);
+    fn terminate(&mut self, msg: rsip::SipMessage);
+    fn set_outgoing_request_defaults_for(
+        &mut self,
+        request: rsip::Request,
+    ) -> Result<rsip::Request, Error>;
+    fn set_outgoing_response_defaults_for(
+        &mut self,
+        response: rsip::Response,
+    ) -> Result<rsip::Response, Error>;
+}
+
+//RFC6086 4.2.2, INFO carries application data inside the dialog, the application gets it
+//once the transaction is answered
+pub async fn process_incoming_info<D: Dialog>(
+    dialog: &mut D,
+    request: rsip::Request,
+) -> Result<(), Error> {
+    let response = presets::response_from(request.clone(), 200.into())?;
+    dialog
+        .handlers()
+        .transaction
+        .new_uas(request.clone(), Some(response))
+        .await?;
+    dialog.emit(CallEvent::InfoReceived(request));
+
+    Ok(())
+}
+
+//a BYE that ends the dialog on our side, like when the session timer expires
+pub async fn hang_up<D: Dialog>(dialog: &mut D) -> Result<(), Error> {
+    let bye = dialog.set_outgoing_request_defaults_for(presets::in_dialog_request(
+        &dialog.handlers().config,
+        rsip::Method::Bye,
+    ))?;
+    dialog.terminate(bye.clone().into());
+    dialog.handlers().transaction.new_uac(bye).await?;
+
+    Ok(())
+}
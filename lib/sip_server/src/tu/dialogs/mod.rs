@@ -1,11 +1,23 @@
-pub mod uac;
-//pub mod uas;
 pub mod dialog_sm;
+pub mod modification;
+pub mod negotiation;
+pub mod refer;
+pub mod reliable;
+pub mod replaces;
+pub mod route_set;
+pub mod session_timer;
+pub mod uac;
+pub mod uas;
 
 pub use crate::error::{DialogError, Error};
-use common::{rsip, tokio::sync::RwLock};
+use crate::{transaction::sm::Timers, tu::calls::CallEvent};
+use common::{
+    rsip,
+    tokio::sync::{mpsc::UnboundedSender, RwLock},
+};
 use dialog_sm::DialogSm;
 use models::{rsip_ext::*, tu::DialogId, Handlers};
+use replaces::Replaces;
 use std::collections::HashMap;
 
 #[derive(Debug)]
@@ -13,6 +25,8 @@ pub struct Dialogs {
     handlers: Handlers,
     //TODO: convert to message passing
     data: RwLock<HashMap<DialogId, DialogSm>>,
+    //read without awaiting, so that the element can hand them over while it's built
+    timers: std::sync::RwLock<Timers>,
 }
 
 impl Dialogs {
@@ -20,24 +34,141 @@ impl Dialogs {
         Self {
             handlers,
             data: Default::default(),
+            timers: Default::default(),
         }
     }
 
+    //the dialogs in flight keep retransmitting with the timers they were created with
+    pub fn reload_timers(&self, timers: Timers) {
+        *self.timers.write().unwrap_or_else(|err| err.into_inner()) = timers;
+    }
+
+    fn timers(&self) -> Timers {
+        *self.timers.read().unwrap_or_else(|err| err.into_inner())
+    }
+
     //TODO: add proper dialog id type
     pub async fn exists(&self, dialog_id: DialogId) -> bool {
-        self.data.read().await.get(&dialog_id).is_some()
+        self.data.read().await.get(&dialog_id.prefixed()).is_some()
+    }
+
+    pub async fn has_dialog_for(&self, msg: &impl DialogExt) -> bool {
+        self.key_for(msg).await.is_ok()
+    }
+
+    //ended dialogs linger for a while, only the ones still going count
+    pub async fn has_active_dialogs(&self) -> bool {
+        for dialog in self.data.read().await.values() {
+            if dialog.is_active().await {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    //false once the dialog of the message is over, or when there is none
+    pub async fn is_active_for(&self, msg: &impl DialogExt) -> bool {
+        let dialog_id = match self.key_for(msg).await {
+            Ok(dialog_id) => dialog_id,
+            Err(_) => return false,
+        };
+
+        match self.data.read().await.get(&dialog_id) {
+            Some(dialog) => dialog.is_active().await,
+            None => false,
+        }
     }
 
     pub async fn new_uac_session(&self, request: rsip::Request) -> Result<(), Error> {
-        let dialog_data = uac::MultiDialog::new(self.handlers.clone(), request).await?;
+        let dialog_data =
+            uac::MultiDialog::new(self.handlers.clone(), request, None, self.timers()).await?;
         let mut data = self.data.write().await;
         data.insert(dialog_data.id.clone(), dialog_data.into());
 
         Ok(())
     }
 
+    //same as new_uac_session, but the progress of the call is reported to the given channel
+    pub async fn new_uac_call(
+        &self,
+        request: rsip::Request,
+        events: UnboundedSender<CallEvent>,
+    ) -> Result<(), Error> {
+        let dialog_data =
+            uac::MultiDialog::new(self.handlers.clone(), request, Some(events), self.timers())
+                .await?;
+        let mut data = self.data.write().await;
+        data.insert(dialog_data.id.clone(), dialog_data.into());
+
+        Ok(())
+    }
+
+    pub async fn new_uas_session(&self, request: rsip::Request) -> Result<(), Error> {
+        let dialog_data =
+            uas::MultiDialog::new(self.handlers.clone(), request, None, self.timers()).await?;
+        let mut data = self.data.write().await;
+        data.insert(dialog_data.id.clone(), dialog_data.into());
+
+        Ok(())
+    }
+
+    //same as new_uas_session, but the progress of the call is reported to the given channel
+    pub async fn new_uas_call(
+        &self,
+        request: rsip::Request,
+        events: UnboundedSender<CallEvent>,
+    ) -> Result<(), Error> {
+        let dialog_data =
+            uas::MultiDialog::new(self.handlers.clone(), request, Some(events), self.timers())
+                .await?;
+        let mut data = self.data.write().await;
+        data.insert(dialog_data.id.clone(), dialog_data.into());
+
+        Ok(())
+    }
+
+    //RFC3891 3, the call takes the place of the dialog that its Replaces matches. Both
+    //happen under the write lock, so nothing reaches the replaced dialog in between.
+    //Returns the status code to reject the INVITE with when there is nothing to replace
+    pub async fn replace_with_uas_call(
+        &self,
+        request: rsip::Request,
+        replaces: &Replaces,
+        events: UnboundedSender<CallEvent>,
+    ) -> Result<Option<rsip::StatusCode>, Error> {
+        let mut data = self.data.write().await;
+
+        //stored under our local tag when we placed the call, under the peer's one otherwise
+        let mut replaced = None;
+        for local_tag in [&replaces.to_tag, &replaces.from_tag] {
+            let key = DialogId::new(&replaces.call_id, local_tag, None::<String>);
+            if let Some(sm) = data.get(&key) {
+                if let Some(target) = sm.replaced_by(replaces).await {
+                    replaced = Some((key, target));
+                    break;
+                }
+            }
+        }
+
+        if let Some(status_code) = replaces.rejection(replaced.as_ref().map(|(_, t)| *t)) {
+            return Ok(Some(status_code));
+        }
+
+        let dialog_data =
+            uas::MultiDialog::new(self.handlers.clone(), request, Some(events), self.timers())
+                .await?;
+        data.insert(dialog_data.id.clone(), dialog_data.into());
+
+        if let Some(sm) = replaced.and_then(|(key, _)| data.get(&key)) {
+            sm.replace(replaces).await;
+        }
+
+        Ok(None)
+    }
+
     pub async fn process_incoming_response(&self, response: rsip::Response) -> Result<(), Error> {
-        let dialog_id = response.dialog_id()?;
+        let dialog_id = self.key_for(&response).await?;
 
         if let Some(sm) = self.data.read().await.get(&dialog_id) {
             sm.process_incoming_response(response).await
@@ -47,7 +178,7 @@ impl Dialogs {
     }
 
     pub async fn process_incoming_request(&self, request: rsip::Request) -> Result<(), Error> {
-        let dialog_id = request.dialog_id()?;
+        let dialog_id = self.key_for(&request).await?;
 
         if let Some(sm) = self.data.read().await.get(&dialog_id) {
             sm.process_incoming_request(request).await
@@ -56,17 +187,89 @@ impl Dialogs {
         }
     }
 
+    pub async fn process_outgoing_request(&self, request: rsip::Request) -> Result<(), Error> {
+        let dialog_id = self.key_for(&request).await?;
+
+        if let Some(sm) = self.data.read().await.get(&dialog_id) {
+            sm.process_outgoing_request(request).await
+        } else {
+            Err(Error::from(DialogError::NotFound))
+        }
+    }
+
+    pub async fn process_outgoing_response(&self, response: rsip::Response) -> Result<(), Error> {
+        let dialog_id = self.key_for(&response).await?;
+
+        if let Some(sm) = self.data.read().await.get(&dialog_id) {
+            sm.process_outgoing_response(response).await
+        } else {
+            Err(Error::from(DialogError::NotFound))
+        }
+    }
+
     //TODO: maybe take a dialog_id here ?
     pub async fn transport_error(
         &self,
         msg: rsip::SipMessage,
         reason: String,
     ) -> Result<(), Error> {
-        if let Some(sm) = self.data.read().await.get(&msg.dialog_id()?) {
+        let dialog_id = self.key_for(&msg).await?;
+
+        if let Some(sm) = self.data.read().await.get(&dialog_id) {
             sm.transport_error(reason, msg).await;
             Ok(())
         } else {
             Err(Error::from(DialogError::NotFound))
         }
     }
+
+    pub async fn run_dialogs(&self) {
+        use common::tokio::time;
+
+        let mut ticker = time::interval(time::Duration::from_millis(100));
+        loop {
+            ticker.tick().await;
+
+            self.check_dialogs().await
+        }
+    }
+
+    //moves every dialog forward and drops the ones that ended long enough ago,
+    //run_dialogs calls it every 100ms
+    pub async fn check_dialogs(&self) {
+        //RFC3261 17.2.1, retransmissions of the peer keep arriving for 64*T1 after a dialog
+        //ended, so it stays around that long to absorb them
+        let linger = self.timers().h();
+
+        let mut ended = vec![];
+        {
+            let data = self.data.read().await;
+            for (dialog_id, dialog_data) in data.iter() {
+                dialog_data.next().await;
+                if dialog_data.has_ended_for(linger).await {
+                    ended.push(dialog_id.clone());
+                }
+            }
+        }
+
+        if !ended.is_empty() {
+            let mut data = self.data.write().await;
+            for dialog_id in ended {
+                data.remove(&dialog_id);
+            }
+        }
+    }
+
+    //dialogs are stored under the tag of the side that sent the initial INVITE, which
+    //is found either in the From or in the To header, depending on who sends the message
+    async fn key_for(&self, msg: &impl DialogExt) -> Result<DialogId, Error> {
+        let data = self.data.read().await;
+
+        [msg.dialog_id(), msg.uas_dialog_id()]
+            .into_iter()
+            .flatten()
+            .map(|dialog_id| dialog_id.prefixed())
+            .find(|dialog_id| data.contains_key(dialog_id))
+            .ok_or_else(|| Error::from(DialogError::NotFound))
+    }
 }
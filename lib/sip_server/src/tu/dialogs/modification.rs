@@ -0,0 +1,368 @@
+use super::{
+    dialog_sm::{self, Dialog},
+    negotiation, session_timer,
+    uas::states::UnAcked,
+};
+use crate::{presets, tu::calls::CallEvent, Error};
+use common::{
+    rand::{self, Rng},
+    rsip::{self, headers::UntypedHeader, prelude::*},
+    tokio::time::{Duration, Instant},
+};
+use models::rsip_ext::*;
+
+//a re-INVITE or UPDATE (RFC3311) in progress inside a confirmed dialog,
+//only one can be in progress at any time in each direction
+#[derive(Debug, Clone)]
+pub enum Modification {
+    //we sent it and we wait for a final response
+    Outgoing(rsip::Request),
+    //we received 491 and we will send it again after the retry timer
+    Retrying {
+        request: rsip::Request,
+        retry_at: Instant,
+    },
+    //the peer sent it and waits for the application to respond
+    Incoming(rsip::Request),
+    //we accepted the peer's re-INVITE and retransmit the 2xx until the ACK arrives
+    UnAcked {
+        request: rsip::Request,
+        un_acked: UnAcked,
+    },
+}
+
+impl Modification {
+    pub fn retrying(request: rsip::Request, owns_call_id: bool) -> Self {
+        Self::Retrying {
+            request,
+            retry_at: Instant::now() + glare_retry_after(owns_call_id),
+        }
+    }
+
+    pub fn request(&self) -> &rsip::Request {
+        match self {
+            Self::Outgoing(request) => request,
+            Self::Retrying { request, .. } => request,
+            Self::Incoming(request) => request,
+            Self::UnAcked { request, .. } => request,
+        }
+    }
+
+    pub fn is_outgoing(&self) -> bool {
+        matches!(self, Self::Outgoing(_))
+    }
+
+    pub fn is_incoming(&self) -> bool {
+        matches!(self, Self::Incoming(_) | Self::UnAcked { .. })
+    }
+
+    pub fn is_unacked(&self) -> bool {
+        matches!(self, Self::UnAcked { .. })
+    }
+
+    pub fn should_retry(&self) -> bool {
+        matches!(self, Self::Retrying { retry_at, .. } if *retry_at <= Instant::now())
+    }
+
+    //whether the given message refers to the request of this modification
+    pub fn matches(&self, cseq: &rsip::typed::CSeq) -> bool {
+        let request = self.request();
+
+        request.method == cseq.method
+            && request
+                .cseq_header()
+                .and_then(|h| h.seq())
+                .map(|seq| seq == cseq.seq)
+                .unwrap_or(false)
+    }
+}
+
+pub fn is_modification(method: &rsip::Method) -> bool {
+    matches!(method, rsip::Method::Invite | rsip::Method::Update)
+}
+
+//our re-INVITE or UPDATE, the dialog has already filled in its headers
+pub async fn send<D: Dialog>(dialog: &mut D, request: rsip::Request) -> Result<(), Error> {
+    if let Err(err) = negotiation::local_offer(dialog.offer_answer(), &request.body) {
+        common::log::warn!("({}): {}", dialog.id(), err);
+    }
+    *dialog.modification() = Some(Modification::Outgoing(request.clone()));
+
+    match request.method {
+        rsip::Method::Invite => {
+            dialog
+                .handlers()
+                .transaction
+                .new_uac_invite(request)
+                .await?
+        }
+        _ => dialog.handlers().transaction.new_uac(request).await?,
+    };
+
+    Ok(())
+}
+
+//RFC3261 14.1, ours can't be sent while another modification is in progress. The request
+//never leaves, the application learns about it like from a 491 of the peer
+pub fn reject_pending<D: Dialog>(dialog: &mut D, request: rsip::Request) -> Result<(), Error> {
+    common::log::warn!(
+        "({}): a session modification is in progress, not sending {}",
+        dialog.id(),
+        request.method
+    );
+    let response = presets::response_from(request, 491.into())?;
+    dialog.emit(CallEvent::ModificationRejected(response));
+
+    Ok(())
+}
+
+//sends again the request that met a 491, once its retry timer fired
+pub async fn retry<D: Dialog>(dialog: &mut D) -> Result<(), Error> {
+    let request = match dialog.modification() {
+        Some(modification) => modification.request().clone(),
+        None => return Ok(()),
+    };
+
+    //a new transaction needs a new branch and a new CSeq
+    let mut request = dialog.set_outgoing_request_defaults_for(request)?;
+    request.headers.unique_push(
+        presets::in_dialog_request(&dialog.handlers().config, request.method)
+            .via_header()?
+            .clone()
+            .into(),
+    );
+
+    send(dialog, request).await
+}
+
+pub async fn process_incoming<D: Dialog>(
+    dialog: &mut D,
+    request: rsip::Request,
+) -> Result<(), Error> {
+    //RFC3261 14.2, glare with our own request or a request of the peer still pending
+    let status_code: Option<rsip::StatusCode> = match dialog.modification() {
+        Some(Modification::Outgoing(_)) => Some(491.into()),
+        Some(modification) if modification.is_incoming() => Some(500.into()),
+        _ => None,
+    };
+    //RFC4028 8.1, a refresh can't shrink the session interval below our Min-SE either
+    let status_code = status_code
+        .or_else(|| session_timer::is_too_small(&request.headers).then(|| 422.into()))
+        .or_else(|| negotiation::check_remote_offer(dialog, &request.body));
+
+    if let Some(status_code) = status_code {
+        let mut response = match status_code == 422.into() {
+            true => presets::create_422_from(request.clone())?,
+            false => presets::response_from(request.clone(), status_code.clone())?,
+        };
+        if status_code == 500.into() {
+            response
+                .headers
+                .push(rsip::headers::RetryAfter::new("1").into());
+        }
+
+        match request.method {
+            rsip::Method::Invite => {
+                dialog
+                    .handlers()
+                    .transaction
+                    .new_uas_invite(request, Some(response))
+                    .await?
+            }
+            _ => {
+                dialog
+                    .handlers()
+                    .transaction
+                    .new_uas(request, Some(response))
+                    .await?
+            }
+        };
+
+        return Ok(());
+    }
+
+    //target refresh, RFC3261 12.2.2
+    if let Ok(contact) = request.contact_header() {
+        dialog.set_remote_target(contact.typed()?.uri);
+    }
+
+    if request.method == rsip::Method::Invite {
+        let trying = presets::response_from(request.clone(), rsip::StatusCode::Trying)?;
+        dialog
+            .handlers()
+            .transaction
+            .new_uas_invite(request.clone(), Some(trying))
+            .await?;
+    }
+
+    *dialog.modification() = Some(Modification::Incoming(request.clone()));
+    dialog.emit(CallEvent::ModificationRequested(request));
+
+    Ok(())
+}
+
+//the response of the application to the re-INVITE or UPDATE of the peer
+pub async fn process_answer<D: Dialog>(
+    dialog: &mut D,
+    response: rsip::Response,
+) -> Result<(), Error> {
+    let request = match dialog.modification() {
+        Some(modification) => modification.request().clone(),
+        None => return Ok(()),
+    };
+    let response = dialog.set_outgoing_response_defaults_for(response)?;
+    let response = match response.status_code.kind() {
+        rsip::StatusCodeKind::Successful => {
+            session_timer::refresh_answered(dialog, &request, response)
+        }
+        _ => response,
+    };
+    match response.status_code.kind() {
+        rsip::StatusCodeKind::Provisional => (),
+        rsip::StatusCodeKind::Successful => negotiation::report_local(dialog, &response.body),
+        _ => dialog.offer_answer().rollback(),
+    };
+
+    let is_invite = request.method == rsip::Method::Invite;
+    match (is_invite, response.status_code.kind()) {
+        (true, rsip::StatusCodeKind::Provisional) => {
+            dialog.handlers().transaction.reply(response).await?
+        }
+        //TODO: non-INVITE transactions don't support provisional responses yet
+        (false, rsip::StatusCodeKind::Provisional) => (),
+        (true, rsip::StatusCodeKind::Successful) => {
+            *dialog.modification() = Some(Modification::UnAcked {
+                request,
+                un_acked: UnAcked::new(response.clone(), dialog.timers()),
+            });
+            dialog.handlers().transaction.reply(response).await?
+        }
+        (true, _) => {
+            *dialog.modification() = None;
+            dialog.handlers().transaction.reply(response).await?
+        }
+        (false, _) => {
+            *dialog.modification() = None;
+            dialog
+                .handlers()
+                .transaction
+                .new_uas(request, Some(response))
+                .await?
+        }
+    };
+
+    Ok(())
+}
+
+//the response of the peer to our re-INVITE or UPDATE
+pub async fn process_response<D: Dialog>(
+    dialog: &mut D,
+    response: rsip::Response,
+) -> Result<(), Error> {
+    let request = match dialog.modification() {
+        Some(modification) => modification.request().clone(),
+        None => return Ok(()),
+    };
+
+    match response.status_code.kind() {
+        rsip::StatusCodeKind::Provisional => (),
+        rsip::StatusCodeKind::Successful => {
+            //target refresh, RFC3261 12.2.1.2
+            if let Ok(contact) = response.contact_header() {
+                dialog.set_remote_target(contact.typed()?.uri);
+            }
+            session_timer::refresh_acknowledged(dialog, &request, &response);
+            negotiation::report_remote(dialog, &response.body);
+            if request.method == rsip::Method::Invite {
+                dialog
+                    .handlers()
+                    .transport
+                    .send(request.ack_request_from(response.clone()).into())
+                    .await?;
+            }
+
+            *dialog.modification() = None;
+            dialog.emit(CallEvent::Modified(response));
+        }
+        _ if response.status_code == 491.into() => {
+            dialog.offer_answer().rollback();
+            let owns_call_id = dialog.owns_call_id();
+            *dialog.modification() = Some(Modification::retrying(request, owns_call_id));
+        }
+        _ if response.status_code == 408.into() || response.status_code == 481.into() => {
+            *dialog.modification() = None;
+            dialog.terminate(response.into());
+        }
+        //the session stays as it was before the modification
+        _ => {
+            dialog.offer_answer().rollback();
+            *dialog.modification() = None;
+            dialog.emit(CallEvent::ModificationRejected(response));
+        }
+    };
+
+    Ok(())
+}
+
+//the ACK of our 2xx to a re-INVITE of the peer
+pub fn acked<D: Dialog>(dialog: &mut D, request: rsip::Request) -> Result<(), Error> {
+    let cseq = request.cseq_header()?.typed()?;
+    let matched = match dialog.modification() {
+        Some(modification) if modification.is_unacked() => {
+            modification.request().cseq_header()?.seq()? == cseq.seq
+        }
+        _ => false,
+    };
+
+    match matched {
+        true => {
+            //the answer to the offer of our 2xx, when the re-INVITE had none
+            negotiation::report_remote(dialog, &request.body);
+            *dialog.modification() = None
+        }
+        false => common::log::warn!("({}): received ACK that matches nothing", dialog.id()),
+    };
+
+    Ok(())
+}
+
+//RFC3261 14.2, like the 2xx to the initial INVITE, the one to a re-INVITE is retransmitted
+//by us until the ACK arrives. When it never does, the dialog is hung up
+pub async fn retransmit<D: Dialog>(dialog: &mut D) -> Result<(), Error> {
+    let (request, un_acked) = match dialog.modification() {
+        Some(Modification::UnAcked { request, un_acked }) => (request.clone(), un_acked.clone()),
+        _ => return Ok(()),
+    };
+
+    match (un_acked.has_timedout(), un_acked.should_retransmit()) {
+        (true, _) => {
+            *dialog.modification() = None;
+            dialog_sm::hang_up(dialog).await?;
+        }
+        (false, true) => {
+            dialog
+                .handlers()
+                .transport
+                .send(un_acked.response.clone().into())
+                .await?;
+            *dialog.modification() = Some(Modification::UnAcked {
+                request,
+                un_acked: un_acked.retransmit(),
+            });
+        }
+        (false, false) => (),
+    };
+
+    Ok(())
+}
+
+//RFC3261 14.1, the owner of the Call-ID waits 2.1-4s, the other side 0-2s, in units of 10ms
+pub fn glare_retry_after(owns_call_id: bool) -> Duration {
+    let mut rng = rand::thread_rng();
+    let units: u64 = match owns_call_id {
+        true => rng.gen_range(210..=400),
+        false => rng.gen_range(0..=200),
+    };
+
+    Duration::from_millis(units * 10)
+}
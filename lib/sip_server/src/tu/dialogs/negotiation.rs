@@ -0,0 +1,72 @@
+use super::dialog_sm::Dialog;
+use common::rsip;
+use sdp::{OfferAnswer, SessionDescription};
+use std::convert::TryFrom;
+
+//RFC3264 offer/answer of a dialog, fed with the bodies that it sends and receives,
+//empty bodies take no part in it
+
+//bodies of requests that can only carry an offer, like a re-INVITE or an UPDATE
+pub fn local_offer(offer_answer: &mut OfferAnswer, body: &[u8]) -> Result<(), sdp::Error> {
+    match body.is_empty() {
+        true => Ok(()),
+        false => offer_answer.local_offer(SessionDescription::try_from(body)?),
+    }
+}
+
+pub fn remote_offer(offer_answer: &mut OfferAnswer, body: &[u8]) -> Result<(), sdp::Error> {
+    match body.is_empty() {
+        true => Ok(()),
+        false => offer_answer.remote_offer(SessionDescription::try_from(body)?),
+    }
+}
+
+//bodies of responses, PRACKs and ACKs, which can be an answer, a repetition or an offer
+pub fn local(offer_answer: &mut OfferAnswer, body: &[u8]) -> Result<(), sdp::Error> {
+    match body.is_empty() {
+        true => Ok(()),
+        false => offer_answer.local_description(SessionDescription::try_from(body)?),
+    }
+}
+
+pub fn remote(offer_answer: &mut OfferAnswer, body: &[u8]) -> Result<(), sdp::Error> {
+    match body.is_empty() {
+        true => Ok(()),
+        false => offer_answer.remote_description(SessionDescription::try_from(body)?),
+    }
+}
+
+//RFC3264 6, checked before the answer goes out, an empty offer means the body is an offer
+pub fn check_answer(offer: &[u8], answer: &[u8]) -> Result<(), sdp::Error> {
+    if offer.is_empty() || answer.is_empty() {
+        return Ok(());
+    }
+
+    let mut offer_answer = OfferAnswer::new();
+    offer_answer.remote_offer(SessionDescription::try_from(offer)?)?;
+    offer_answer.local_answer(SessionDescription::try_from(answer)?)
+}
+
+//RFC3264 8, a new offer that breaks the rules of the session is not acceptable
+pub fn check_remote_offer<D: Dialog>(dialog: &mut D, body: &[u8]) -> Option<rsip::StatusCode> {
+    match remote_offer(dialog.offer_answer(), body) {
+        Ok(()) => None,
+        Err(err) => {
+            common::log::warn!("({}): {}", dialog.id(), err);
+            Some(488.into())
+        }
+    }
+}
+
+//violations in what we send or get as an answer are only reported, the session goes on
+pub fn report_local<D: Dialog>(dialog: &mut D, body: &[u8]) {
+    if let Err(err) = local(dialog.offer_answer(), body) {
+        common::log::warn!("({}): {}", dialog.id(), err);
+    }
+}
+
+pub fn report_remote<D: Dialog>(dialog: &mut D, body: &[u8]) {
+    if let Err(err) = remote(dialog.offer_answer(), body) {
+        common::log::warn!("({}): {}", dialog.id(), err);
+    }
+}
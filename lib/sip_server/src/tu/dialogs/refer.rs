@@ -0,0 +1,294 @@
+use super::dialog_sm::Dialog;
+use crate::{presets, tu::calls::CallEvent, Error};
+use common::rsip::{self, headers::UntypedHeader, prelude::*};
+use std::convert::TryFrom;
+
+//event package of the implicit subscription that a REFER creates, RFC3515
+pub const EVENT: &str = "refer";
+pub const SIPFRAG_CONTENT_TYPE: &str = "message/sipfrag;version=2.0";
+//RFC3515 2.4.4, the implicit subscription lasts as long as the notifier says
+pub const SUBSCRIPTION_EXPIRES: u32 = 60;
+
+//a REFER inside a confirmed dialog, only one can be in progress at any time
+#[derive(Debug, Clone)]
+pub enum Transfer {
+    //we sent it, the peer reports the progress of the referred request with NOTIFYs
+    Outgoing(rsip::Request),
+    //the peer sent it and waits for the application to accept or reject it
+    Incoming(rsip::Request),
+    //we accepted it, the application reports the progress through NOTIFYs
+    Accepted(rsip::Request),
+}
+
+impl Transfer {
+    pub fn request(&self) -> &rsip::Request {
+        match self {
+            Self::Outgoing(request) => request,
+            Self::Incoming(request) => request,
+            Self::Accepted(request) => request,
+        }
+    }
+
+    pub fn matches(&self, cseq: &rsip::typed::CSeq) -> bool {
+        let request = self.request();
+
+        request.method == cseq.method
+            && request
+                .cseq_header()
+                .and_then(|h| h.seq())
+                .map(|seq| seq == cseq.seq)
+                .unwrap_or(false)
+    }
+}
+
+pub async fn process_incoming_refer<D: Dialog>(
+    dialog: &mut D,
+    request: rsip::Request,
+) -> Result<(), Error> {
+    //RFC3515 2.4.1, exactly one Refer-To, and we handle one transfer at a time
+    let status_code: Option<rsip::StatusCode> =
+        match (dialog.transfer().is_some(), refer_to(&request.headers)) {
+            (_, None) => Some(400.into()),
+            (true, _) => Some(491.into()),
+            (false, Some(_)) => None,
+        };
+
+    if let Some(status_code) = status_code {
+        let response = presets::response_from(request.clone(), status_code)?;
+        dialog
+            .handlers()
+            .transaction
+            .new_uas(request, Some(response))
+            .await?;
+
+        return Ok(());
+    }
+
+    *dialog.transfer() = Some(Transfer::Incoming(request.clone()));
+    dialog.emit(CallEvent::TransferRequested(request));
+
+    Ok(())
+}
+
+//the response of the application to the REFER of the peer
+pub async fn process_transfer_answer<D: Dialog>(
+    dialog: &mut D,
+    response: rsip::Response,
+) -> Result<(), Error> {
+    let request = match dialog.transfer() {
+        Some(transfer) => transfer.request().clone(),
+        None => return Ok(()),
+    };
+
+    match response.status_code.kind() {
+        //TODO: non-INVITE transactions don't support provisional responses yet
+        rsip::StatusCodeKind::Provisional => (),
+        rsip::StatusCodeKind::Successful => {
+            *dialog.transfer() = Some(Transfer::Accepted(request.clone()));
+            dialog
+                .handlers()
+                .transaction
+                .new_uas(request, Some(response))
+                .await?;
+
+            //RFC3515 2.4.4, the subscription starts with a NOTIFY of its initial state
+            let mut notify = dialog.set_outgoing_request_defaults_for(
+                presets::in_dialog_request(&dialog.handlers().config, rsip::Method::Notify),
+            )?;
+            notify.body = sipfrag(&rsip::StatusCode::Trying);
+            notify_transfer(dialog, notify).await?;
+        }
+        _ => {
+            *dialog.transfer() = None;
+            dialog
+                .handlers()
+                .transaction
+                .new_uas(request, Some(response))
+                .await?;
+        }
+    };
+
+    Ok(())
+}
+
+//reports the progress of the referred request to the peer
+pub async fn notify_transfer<D: Dialog>(
+    dialog: &mut D,
+    mut notify: rsip::Request,
+) -> Result<(), Error> {
+    let accepted = matches!(dialog.transfer(), Some(Transfer::Accepted(_)));
+    let status_code = match (accepted, parse_sipfrag(&notify.body)) {
+        (true, Some(status_code)) => status_code,
+        _ => {
+            common::log::warn!(
+                "({}): no accepted transfer to report on, dropping NOTIFY",
+                dialog.id()
+            );
+            return Ok(());
+        }
+    };
+
+    //RFC3515 2.4.5, a final response to the referred request ends the subscription
+    let terminated = status_code.kind() != rsip::StatusCodeKind::Provisional;
+    if terminated {
+        *dialog.transfer() = None;
+    }
+
+    notify.headers.push(event_header());
+    notify.headers.push(subscription_state_header(terminated));
+    notify
+        .headers
+        .unique_push(rsip::headers::ContentType::new(SIPFRAG_CONTENT_TYPE).into());
+    notify
+        .headers
+        .unique_push(rsip::headers::ContentLength::new(notify.body.len().to_string()).into());
+
+    dialog.handlers().transaction.new_uac(notify).await?;
+
+    Ok(())
+}
+
+//the NOTIFYs of the peer about the REFER that we sent
+pub async fn process_incoming_notify<D: Dialog>(
+    dialog: &mut D,
+    request: rsip::Request,
+) -> Result<(), Error> {
+    let is_ours = is_refer_event(&request.headers)
+        && matches!(dialog.transfer(), Some(Transfer::Outgoing(_)));
+
+    //RFC6665 4.1.3, a NOTIFY that matches no subscription
+    let status_code: rsip::StatusCode = match is_ours {
+        true => 200.into(),
+        false => 481.into(),
+    };
+    let response = presets::response_from(request.clone(), status_code)?;
+    dialog
+        .handlers()
+        .transaction
+        .new_uas(request.clone(), Some(response))
+        .await?;
+    if !is_ours {
+        return Ok(());
+    }
+
+    if let Some(status_code) = parse_sipfrag(&request.body) {
+        dialog.emit(CallEvent::TransferProgress(status_code));
+    }
+    if is_terminated(&request.headers) {
+        *dialog.transfer() = None;
+    }
+
+    Ok(())
+}
+
+//we handle one transfer at a time, the REFER never leaves and the application learns
+//about it like from a 491 of the peer
+pub fn reject_pending<D: Dialog>(dialog: &mut D, request: rsip::Request) -> Result<(), Error> {
+    common::log::warn!(
+        "({}): a transfer is in progress, not sending REFER",
+        dialog.id()
+    );
+    let response = presets::response_from(request, 491.into())?;
+    dialog.emit(CallEvent::TransferRejected(response));
+
+    Ok(())
+}
+
+//the response of the peer to the REFER that we sent
+pub fn process_refer_response<D: Dialog>(dialog: &mut D, response: rsip::Response) {
+    match response.status_code.kind() {
+        rsip::StatusCodeKind::Provisional => (),
+        rsip::StatusCodeKind::Successful => dialog.emit(CallEvent::TransferAccepted(response)),
+        _ => {
+            *dialog.transfer() = None;
+            dialog.emit(CallEvent::TransferRejected(response));
+        }
+    }
+}
+
+//Refer-To of a REFER, r is its compact form
+pub fn refer_to(headers: &rsip::Headers) -> Option<rsip::Uri> {
+    let mut values = headers.iter().filter_map(|header| match header {
+        rsip::Header::Other(key, value)
+            if key.eq_ignore_ascii_case("Refer-To") || key.eq_ignore_ascii_case("r") =>
+        {
+            Some(value.as_str())
+        }
+        _ => None,
+    });
+
+    //RFC3515 2.4.1, exactly one Refer-To
+    let value = values.next()?;
+    if values.next().is_some() {
+        return None;
+    }
+
+    let value = value.trim();
+    let uri = match (value.find('<'), value.find('>')) {
+        (Some(start), Some(end)) if start < end => &value[start + 1..end],
+        _ => value.split(';').next()?,
+    };
+
+    rsip::Uri::try_from(uri.trim()).ok()
+}
+
+pub fn refer_to_header(target: &rsip::Uri) -> rsip::Header {
+    rsip::Header::Other("Refer-To".into(), format!("<{}>", target))
+}
+
+pub fn event_header() -> rsip::Header {
+    rsip::headers::Event::new(EVENT).into()
+}
+
+pub fn is_refer_event(headers: &rsip::Headers) -> bool {
+    headers.iter().any(|header| match header {
+        rsip::Header::Event(event) => is_refer_package(event.value()),
+        //o is the compact form of Event
+        rsip::Header::Other(key, value) if key.eq_ignore_ascii_case("o") => is_refer_package(value),
+        _ => false,
+    })
+}
+
+pub fn subscription_state_header(terminated: bool) -> rsip::Header {
+    let value = match terminated {
+        true => "terminated;reason=noresource".to_string(),
+        false => format!("active;expires={}", SUBSCRIPTION_EXPIRES),
+    };
+
+    rsip::headers::SubscriptionState::new(value).into()
+}
+
+pub fn is_terminated(headers: &rsip::Headers) -> bool {
+    headers.iter().any(|header| match header {
+        rsip::Header::SubscriptionState(state) => state
+            .value()
+            .trim()
+            .to_ascii_lowercase()
+            .starts_with("terminated"),
+        _ => false,
+    })
+}
+
+//RFC3515 2.4.5, the NOTIFY body is the status line of the referred request's response
+pub fn sipfrag(status_code: &rsip::StatusCode) -> Vec<u8> {
+    format!("SIP/2.0 {}\r\n", status_code).into_bytes()
+}
+
+pub fn parse_sipfrag(body: &[u8]) -> Option<rsip::StatusCode> {
+    let status_line = std::str::from_utf8(body).ok()?.lines().next()?;
+    let mut tokens = status_line.split_whitespace();
+    if !tokens.next()?.eq_ignore_ascii_case("SIP/2.0") {
+        return None;
+    }
+
+    let code: u16 = tokens.next()?.parse().ok()?;
+    Some(code.into())
+}
+
+fn is_refer_package(value: &str) -> bool {
+    value
+        .split(';')
+        .next()
+        .map(|package| package.trim().eq_ignore_ascii_case(EVENT))
+        .unwrap_or(false)
+}
@@ -0,0 +1,140 @@
+use crate::transaction::sm::Timers;
+use common::{
+    rand::{self, Rng},
+    rsip::{self, headers::UntypedHeader},
+    tokio::time::{Duration, Instant},
+};
+
+//option tag of reliable provisional responses, RFC3262
+pub const OPTION_TAG: &str = "100rel";
+
+//a reliable provisional response that we sent and is not PRACKed yet
+#[derive(Debug, Clone)]
+pub struct Unpracked {
+    pub entered_at: Instant,
+    pub response: rsip::Response,
+    pub rseq: u32,
+    pub retransmissions_count: u8,
+    pub last_retransmission_at: Instant,
+    pub timers: Timers,
+}
+
+impl Unpracked {
+    pub fn new(response: rsip::Response, rseq: u32, timers: Timers) -> Self {
+        Self {
+            entered_at: Instant::now(),
+            response,
+            rseq,
+            retransmissions_count: 0,
+            last_retransmission_at: Instant::now(),
+            timers,
+        }
+    }
+
+    //RFC3262 3, starts at T1 and doubles, without a T2 cap
+    pub fn next_retrasmission(&self) -> Duration {
+        self.timers.t1 * 2_u32.pow(self.retransmissions_count.into())
+    }
+
+    pub fn has_timedout(&self) -> bool {
+        self.entered_at.elapsed() >= self.timers.t1 * 64
+    }
+
+    pub fn should_retransmit(&self) -> bool {
+        self.last_retransmission_at.elapsed() > self.next_retrasmission()
+    }
+
+    pub fn retransmit(self) -> Self {
+        Self {
+            retransmissions_count: self.retransmissions_count + 1,
+            last_retransmission_at: Instant::now(),
+            ..self
+        }
+    }
+
+    pub fn matches(&self, rack: &RAck) -> bool {
+        rack.rseq == self.rseq
+    }
+}
+
+//RAck header of a PRACK: the RSeq of the provisional and the CSeq of the INVITE
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RAck {
+    pub rseq: u32,
+    pub cseq: u32,
+    pub method: rsip::Method,
+}
+
+impl RAck {
+    pub fn from_headers(headers: &rsip::Headers) -> Option<Self> {
+        let value = other_header(headers, "RAck")?;
+        let mut tokens = value.split_whitespace();
+
+        let rseq = tokens.next()?.parse().ok()?;
+        let cseq = tokens.next()?.parse().ok()?;
+        let method = match tokens.next()? {
+            method if method.eq_ignore_ascii_case("INVITE") => rsip::Method::Invite,
+            _ => return None,
+        };
+
+        Some(Self { rseq, cseq, method })
+    }
+}
+
+impl From<RAck> for rsip::Header {
+    fn from(rack: RAck) -> rsip::Header {
+        rsip::Header::Other(
+            "RAck".into(),
+            format!("{} {} {}", rack.rseq, rack.cseq, rack.method),
+        )
+    }
+}
+
+pub fn requires_100rel(headers: &rsip::Headers) -> bool {
+    headers.iter().any(|header| match header {
+        rsip::Header::Require(require) => has_option_tag(require.value()),
+        _ => false,
+    })
+}
+
+pub fn supports_100rel(headers: &rsip::Headers) -> bool {
+    requires_100rel(headers)
+        || headers.iter().any(|header| match header {
+            rsip::Header::Supported(supported) => has_option_tag(supported.value()),
+            _ => false,
+        })
+}
+
+pub fn rseq(headers: &rsip::Headers) -> Option<u32> {
+    other_header(headers, "RSeq")?.trim().parse().ok()
+}
+
+pub fn rseq_header(rseq: u32) -> rsip::Header {
+    rsip::Header::Other("RSeq".into(), rseq.to_string())
+}
+
+pub fn require_header() -> rsip::Header {
+    rsip::headers::Require::new(OPTION_TAG).into()
+}
+
+pub fn supported_header() -> rsip::Header {
+    rsip::headers::Supported::new(OPTION_TAG).into()
+}
+
+//RFC3262 3, the first RSeq is chosen uniformly between 1 and 2**31 - 1
+pub fn initial_rseq() -> u32 {
+    rand::thread_rng().gen_range(1..(1 << 31))
+}
+
+fn has_option_tag(value: &str) -> bool {
+    value
+        .split(',')
+        .any(|tag| tag.trim().eq_ignore_ascii_case(OPTION_TAG))
+}
+
+fn other_header<'a>(headers: &'a rsip::Headers, name: &str) -> Option<&'a str> {
+    headers.iter().find_map(|header| match header {
+        rsip::Header::Other(key, value) if key.eq_ignore_ascii_case(name) => Some(value.as_str()),
+        _ => None,
+    })
+}
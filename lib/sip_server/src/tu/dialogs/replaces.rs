@@ -0,0 +1,123 @@
+use crate::Error;
+use common::rsip::{self, headers::UntypedHeader};
+
+//option tag of the Replaces header, RFC3891
+pub const OPTION_TAG: &str = "replaces";
+
+//Replaces of an INVITE: the dialog it takes the place of, with the to-tag being our
+//local tag and the from-tag the tag of the peer of that dialog
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replaces {
+    pub call_id: String,
+    pub to_tag: String,
+    pub from_tag: String,
+    pub early_only: bool,
+}
+
+//state of the dialog that a Replaces matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    //an early dialog of a call that we placed
+    EarlyUac,
+    //an early dialog of a call that we received, like when a call is picked up
+    EarlyUas,
+    Confirmed,
+    Terminated,
+}
+
+impl Replaces {
+    //RFC3891 3, more than one Replaces is answered with 400, like an unparseable one
+    pub fn from_headers(headers: &rsip::Headers) -> Result<Option<Self>, Error> {
+        let mut values = headers.iter().filter_map(|header| match header {
+            rsip::Header::Other(key, value) if key.eq_ignore_ascii_case("Replaces") => {
+                Some(value.as_str())
+            }
+            _ => None,
+        });
+
+        let value = match values.next() {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        if values.next().is_some() {
+            return Err(Error::custom("more than one Replaces header"));
+        }
+
+        value
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::custom(format!("invalid Replaces header: {}", value)))
+    }
+
+    pub fn matches(
+        &self,
+        call_id: &rsip::headers::CallId,
+        local_tag: &str,
+        remote_tag: &str,
+    ) -> bool {
+        call_id.value() == self.call_id && local_tag == self.to_tag && remote_tag == self.from_tag
+    }
+
+    //RFC3891 3, the answer to the INVITE when the replacement can't take place
+    pub fn rejection(&self, target: Option<Target>) -> Option<rsip::StatusCode> {
+        match target {
+            None | Some(Target::EarlyUac) => Some(481.into()),
+            Some(Target::Terminated) => Some(603.into()),
+            Some(Target::Confirmed) if self.early_only => Some(486.into()),
+            Some(Target::Confirmed) | Some(Target::EarlyUas) => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Replaces {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, ()> {
+        let mut tokens = value.split(';').map(str::trim);
+
+        let call_id = tokens
+            .next()
+            .filter(|call_id| !call_id.is_empty())
+            .ok_or(())?;
+        let mut to_tag = None;
+        let mut from_tag = None;
+        let mut early_only = false;
+        for token in tokens {
+            match token.split_once('=') {
+                Some((key, tag)) if key.trim().eq_ignore_ascii_case("to-tag") => {
+                    to_tag = Some(tag.trim().to_string())
+                }
+                Some((key, tag)) if key.trim().eq_ignore_ascii_case("from-tag") => {
+                    from_tag = Some(tag.trim().to_string())
+                }
+                None if token.eq_ignore_ascii_case("early-only") => early_only = true,
+                _ => (),
+            }
+        }
+
+        Ok(Self {
+            call_id: call_id.into(),
+            to_tag: to_tag.ok_or(())?,
+            from_tag: from_tag.ok_or(())?,
+            early_only,
+        })
+    }
+}
+
+impl From<Replaces> for rsip::Header {
+    fn from(replaces: Replaces) -> rsip::Header {
+        let mut value = format!(
+            "{};to-tag={};from-tag={}",
+            replaces.call_id, replaces.to_tag, replaces.from_tag
+        );
+        if replaces.early_only {
+            value.push_str(";early-only");
+        }
+
+        rsip::Header::Other("Replaces".into(), value)
+    }
+}
+
+pub fn supported_header() -> rsip::Header {
+    rsip::headers::Supported::new(OPTION_TAG).into()
+}
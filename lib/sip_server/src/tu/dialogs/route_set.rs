@@ -0,0 +1,53 @@
+use crate::Error;
+use common::rsip::{self, headers::UntypedHeader, prelude::*, uri::UriWithParams};
+
+//RFC3261 12.1, the Record-Route entries of the message that establishes the dialog, in the
+//order they appear, the UAC reverses them
+pub fn from_record_routes(headers: &rsip::Headers) -> Result<Vec<UriWithParams>, Error> {
+    let mut route_set = vec![];
+    for header in headers.iter() {
+        if let rsip::Header::RecordRoute(record_route) = header {
+            route_set.extend(record_route.typed()?.uris().to_owned());
+        }
+    }
+
+    Ok(route_set)
+}
+
+//RFC3261 12.2.1.1, the Request-URI and the Route headers of a request inside the dialog
+pub fn apply(request: &mut rsip::Request, route_set: &[UriWithParams], remote_target: rsip::Uri) {
+    request
+        .headers
+        .retain(|header| !matches!(header, rsip::Header::Route(_)));
+
+    let routes = match route_set.first() {
+        //a strict router wants itself as the Request-URI and the remote target as the
+        //last Route
+        Some(first) if !is_loose(first) => {
+            request.uri = first.uri.clone();
+            route_set[1..]
+                .iter()
+                .map(|route| route.to_string())
+                .chain(std::iter::once(format!("<{}>", remote_target)))
+                .collect::<Vec<_>>()
+        }
+        _ => {
+            request.uri = remote_target;
+            route_set.iter().map(|route| route.to_string()).collect()
+        }
+    };
+
+    for route in routes {
+        request
+            .headers
+            .push(rsip::headers::Route::new(route).into());
+    }
+}
+
+fn is_loose(route: &UriWithParams) -> bool {
+    route
+        .uri
+        .params
+        .iter()
+        .any(|param| matches!(param, rsip::Param::Lr))
+}
@@ -0,0 +1,314 @@
+use super::{
+    dialog_sm::{self, Dialog},
+    modification,
+};
+use crate::{presets, Error};
+use common::{
+    rsip::{self, headers::UntypedHeader},
+    tokio::time::{Duration, Instant},
+};
+
+//option tag of session timers, RFC4028
+pub const OPTION_TAG: &str = "timer";
+//RFC4028 4, recommended session interval and the smallest one we accept, in seconds
+pub const DEFAULT_SESSION_EXPIRES: u32 = 1800;
+pub const MIN_SE: u32 = 90;
+
+//refresher param of Session-Expires, relative to the transaction that carries it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refresher {
+    Uac,
+    Uas,
+}
+
+impl std::fmt::Display for Refresher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Uac => write!(f, "uac"),
+            Self::Uas => write!(f, "uas"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionExpires {
+    pub delta: u32,
+    pub refresher: Option<Refresher>,
+}
+
+impl SessionExpires {
+    pub fn new(delta: u32, refresher: Option<Refresher>) -> Self {
+        Self { delta, refresher }
+    }
+
+    pub fn from_headers(headers: &rsip::Headers) -> Option<Self> {
+        //x is the compact form of Session-Expires
+        let value =
+            other_header(headers, "Session-Expires").or_else(|| other_header(headers, "x"))?;
+        let mut tokens = value.split(';');
+
+        let delta = tokens.next()?.trim().parse().ok()?;
+        let refresher = tokens.find_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            match (key.trim(), value.trim()) {
+                (key, "uac") if key.eq_ignore_ascii_case("refresher") => Some(Refresher::Uac),
+                (key, "uas") if key.eq_ignore_ascii_case("refresher") => Some(Refresher::Uas),
+                _ => None,
+            }
+        });
+
+        Some(Self { delta, refresher })
+    }
+}
+
+impl From<SessionExpires> for rsip::Header {
+    fn from(session_expires: SessionExpires) -> rsip::Header {
+        let value = match session_expires.refresher {
+            Some(refresher) => format!("{};refresher={}", session_expires.delta, refresher),
+            None => session_expires.delta.to_string(),
+        };
+
+        rsip::Header::Other("Session-Expires".into(), value)
+    }
+}
+
+//a negotiated session timer of a dialog, restarted by every successful refresh
+#[derive(Debug, Clone)]
+pub struct SessionTimer {
+    pub interval: u32,
+    pub local_refresher: bool,
+    //refreshes use UPDATE when the peer allows it, otherwise a re-INVITE with our last SDP
+    pub peer_allows_update: bool,
+    pub local_sdp: Vec<u8>,
+    pub refreshed_at: Instant,
+    pub refresh_sent: bool,
+}
+
+impl SessionTimer {
+    //local_is_uac refers to the transaction that negotiated the given Session-Expires
+    pub fn new(session_expires: &SessionExpires, local_is_uac: bool) -> Self {
+        Self {
+            interval: interval_of(session_expires),
+            local_refresher: is_local_refresher(session_expires, local_is_uac),
+            peer_allows_update: false,
+            local_sdp: Default::default(),
+            refreshed_at: Instant::now(),
+            refresh_sent: false,
+        }
+    }
+
+    pub fn with_peer_allow(mut self, headers: &rsip::Headers) -> Self {
+        self.peer_allows_update = allows_update(headers);
+        self
+    }
+
+    pub fn with_local_sdp(mut self, local_sdp: Vec<u8>) -> Self {
+        self.local_sdp = local_sdp;
+        self
+    }
+
+    //RFC4028 10, the refresher refreshes at half the interval
+    pub fn should_refresh(&self) -> bool {
+        self.local_refresher
+            && !self.refresh_sent
+            && self.refreshed_at.elapsed() >= Duration::from_secs((self.interval / 2).into())
+    }
+
+    //RFC4028 10, BYE is sent min(32, interval/3) seconds before the session expires
+    pub fn has_expired(&self) -> bool {
+        let margin = std::cmp::min(32, self.interval / 3);
+
+        self.refreshed_at.elapsed() >= Duration::from_secs((self.interval - margin).into())
+    }
+
+    pub fn refresh_sent(self) -> Self {
+        Self {
+            refresh_sent: true,
+            ..self
+        }
+    }
+
+    pub fn refreshed(self, session_expires: &SessionExpires, local_is_uac: bool) -> Self {
+        Self {
+            interval: interval_of(session_expires),
+            local_refresher: is_local_refresher(session_expires, local_is_uac),
+            refreshed_at: Instant::now(),
+            refresh_sent: false,
+            ..self
+        }
+    }
+
+    //Session-Expires of a refresh request that we send
+    pub fn session_expires(&self) -> SessionExpires {
+        SessionExpires::new(self.interval, Some(Refresher::Uac))
+    }
+}
+
+//Session-Expires of our 2xx to a request: the requester refreshes if it supports session
+//timers and doesn't say otherwise, we refresh if it doesn't support them at all
+pub fn answer(request_headers: &rsip::Headers) -> SessionExpires {
+    let default_refresher = match supports_timer(request_headers) {
+        true => Refresher::Uac,
+        false => Refresher::Uas,
+    };
+
+    match SessionExpires::from_headers(request_headers) {
+        Some(SessionExpires { delta, refresher }) => {
+            SessionExpires::new(delta, Some(refresher.unwrap_or(default_refresher)))
+        }
+        None => SessionExpires::new(DEFAULT_SESSION_EXPIRES, Some(default_refresher)),
+    }
+}
+
+//a successful re-INVITE or UPDATE of the peer refreshes the session
+pub fn refresh_answered<D: Dialog>(
+    dialog: &mut D,
+    request: &rsip::Request,
+    mut response: rsip::Response,
+) -> rsip::Response {
+    let session_timer = match dialog.session_timer().take() {
+        Some(session_timer) => session_timer,
+        None => return response,
+    };
+
+    let session_expires = answer(&request.headers);
+    if supports_timer(&request.headers) {
+        response.headers.push(require_header());
+    }
+    response.headers.push(session_expires.clone().into());
+
+    let session_timer = session_timer.refreshed(&session_expires, false);
+    *dialog.session_timer() = match response.body.is_empty() {
+        true => Some(session_timer),
+        false => Some(session_timer.with_local_sdp(response.body.clone())),
+    };
+
+    response
+}
+
+//the peer accepted our re-INVITE or UPDATE
+pub fn refresh_acknowledged<D: Dialog>(
+    dialog: &mut D,
+    request: &rsip::Request,
+    response: &rsip::Response,
+) {
+    let session_timer = match dialog.session_timer().take() {
+        Some(session_timer) => session_timer,
+        None => return,
+    };
+
+    let session_expires = SessionExpires::from_headers(&response.headers)
+        .unwrap_or_else(|| session_timer.session_expires());
+    let session_timer = session_timer.refreshed(&session_expires, true);
+    *dialog.session_timer() = match request.body.is_empty() {
+        true => Some(session_timer),
+        false => Some(session_timer.with_local_sdp(request.body.clone())),
+    };
+}
+
+//refreshes the session when it's up to us, or hangs up when no refresh arrived in time
+pub async fn check<D: Dialog>(dialog: &mut D) -> Result<(), Error> {
+    let session_timer = match dialog.session_timer() {
+        Some(session_timer) => session_timer.clone(),
+        None => return Ok(()),
+    };
+
+    if session_timer.has_expired() {
+        //RFC4028 10, the peer is probably gone
+        dialog_sm::hang_up(dialog).await?;
+    } else if session_timer.should_refresh() && dialog.modification().is_none() {
+        let refresh = refresh_request(dialog, &session_timer)?;
+        *dialog.session_timer() = Some(session_timer.refresh_sent());
+        modification::send(dialog, refresh).await?;
+    }
+
+    Ok(())
+}
+
+fn refresh_request<D: Dialog>(
+    dialog: &mut D,
+    session_timer: &SessionTimer,
+) -> Result<rsip::Request, Error> {
+    let method = match session_timer.peer_allows_update {
+        true => rsip::Method::Update,
+        false => rsip::Method::Invite,
+    };
+
+    let mut request = presets::in_dialog_request(&dialog.handlers().config, method);
+    request.headers.push(session_timer.session_expires().into());
+    request.headers.push(min_se_header(MIN_SE));
+    request.headers.push(supported_header());
+    if method == rsip::Method::Invite && !session_timer.local_sdp.is_empty() {
+        request
+            .headers
+            .unique_push(rsip::headers::ContentType::new("application/sdp").into());
+        request.headers.unique_push(
+            rsip::headers::ContentLength::new(session_timer.local_sdp.len().to_string()).into(),
+        );
+        request.body = session_timer.local_sdp.clone();
+    }
+
+    dialog.set_outgoing_request_defaults_for(request)
+}
+
+//RFC4028 8.1, a session interval below our Min-SE is rejected with 422
+pub fn is_too_small(headers: &rsip::Headers) -> bool {
+    matches!(SessionExpires::from_headers(headers), Some(se) if se.delta < MIN_SE)
+}
+
+pub fn min_se(headers: &rsip::Headers) -> Option<u32> {
+    other_header(headers, "Min-SE")?.trim().parse().ok()
+}
+
+pub fn min_se_header(delta: u32) -> rsip::Header {
+    rsip::Header::Other("Min-SE".into(), delta.to_string())
+}
+
+pub fn supports_timer(headers: &rsip::Headers) -> bool {
+    headers.iter().any(|header| match header {
+        rsip::Header::Supported(supported) => has_option_tag(supported.value()),
+        rsip::Header::Require(require) => has_option_tag(require.value()),
+        _ => false,
+    })
+}
+
+pub fn require_header() -> rsip::Header {
+    rsip::headers::Require::new(OPTION_TAG).into()
+}
+
+pub fn supported_header() -> rsip::Header {
+    rsip::headers::Supported::new(OPTION_TAG).into()
+}
+
+//requests below our Min-SE are rejected, but a 2xx could still carry a smaller interval,
+//which would expire the session right away
+fn interval_of(session_expires: &SessionExpires) -> u32 {
+    std::cmp::max(session_expires.delta, MIN_SE)
+}
+
+fn is_local_refresher(session_expires: &SessionExpires, local_is_uac: bool) -> bool {
+    (session_expires.refresher.unwrap_or(Refresher::Uac) == Refresher::Uac) == local_is_uac
+}
+
+fn allows_update(headers: &rsip::Headers) -> bool {
+    headers.iter().any(|header| match header {
+        rsip::Header::Allow(allow) => allow
+            .value()
+            .split(',')
+            .any(|method| method.trim().eq_ignore_ascii_case("UPDATE")),
+        _ => false,
+    })
+}
+
+fn has_option_tag(value: &str) -> bool {
+    value
+        .split(',')
+        .any(|tag| tag.trim().eq_ignore_ascii_case(OPTION_TAG))
+}
+
+fn other_header<'a>(headers: &'a rsip::Headers, name: &str) -> Option<&'a str> {
+    headers.iter().find_map(|header| match header {
+        rsip::Header::Other(key, value) if key.eq_ignore_ascii_case(name) => Some(value.as_str()),
+        _ => None,
+    })
+}
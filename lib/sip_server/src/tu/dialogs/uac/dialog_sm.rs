@@ -3,10 +3,28 @@ use super::{
     validations,
 };
 
-use crate::{presets, Error};
-use common::rsip::{self, prelude::*, uri::UriWithParams};
-use common::tokio::time::Instant;
+use crate::{
+    presets,
+    transaction::sm::Timers,
+    tu::{
+        calls::CallEvent,
+        dialogs::{
+            dialog_sm::{self, Dialog},
+            modification::{self, Modification},
+            negotiation,
+            refer::{self, Transfer},
+            reliable,
+            replaces::{Replaces, Target},
+            route_set,
+            session_timer::{self, SessionExpires, SessionTimer},
+        },
+    },
+    Error,
+};
+use common::rsip::{self, headers::UntypedHeader, prelude::*, uri::UriWithParams};
+use common::tokio::{sync::mpsc::UnboundedSender, time::Instant};
 use models::{rsip_ext::*, tu::DialogId, Handlers};
+use sdp::OfferAnswer;
 
 #[derive(Debug)]
 pub struct DialogSm {
@@ -28,9 +46,19 @@ pub struct DialogSm {
     pub state: DialogState,
     pub created_at: Instant,
     pub handlers: Handlers,
+    pub events: Option<UnboundedSender<CallEvent>>,
+    pub modification: Option<Modification>,
+    pub remote_rseq: Option<u32>,
+    //reliable provisional carrying an offer, the application answers it in the PRACK
+    pub pending_prack: Option<rsip::Response>,
+    pub session_timer: Option<SessionTimer>,
+    pub offer_answer: OfferAnswer,
+    pub transfer: Option<Transfer>,
+    //the T1 and T2 of the transaction layer when the dialog was created
+    pub timers: Timers,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SessionType {
     UacOffer,
     UasOffer,
@@ -48,25 +76,17 @@ pub enum DialogState {
 }
 
 //TODO: remove unused async in private functions
-//TODO: need to apply strict or loose routing (probably in a helper/module)
 #[allow(dead_code)]
 impl DialogSm {
     pub async fn new(handlers: Handlers, request: rsip::Request) -> Result<Self, Error> {
         validations::run(&request)?;
 
-        let mut route_set: Vec<UriWithParams> = request
-            .record_route_header()
-            .map(|h| h.typed().map(|h| h.uris().to_owned()))
-            .transpose()?
-            .unwrap_or_default();
-        route_set.reverse();
-
         //TODO: probably it is a good idea to save local_from and remote_to
         //and expose some attributes as fns on top of that
-        let me = Self {
+        let mut me = Self {
             id: request.dialog_id()?,
             call_id: request.call_id_header()?.clone(),
-            transaction_id: request.transaction_id().expect("transaction id").into(),
+            transaction_id: request.transaction_id()?.into(),
             local_tag: request
                 .from_header()?
                 .tag()?
@@ -77,7 +97,8 @@ impl DialogSm {
             remote_seqn: None,
             remote_uri: request.to_header()?.uri()?,
             remote_target: None,
-            route_set,
+            //comes with the responses that establish the dialog
+            route_set: vec![],
             session_type: session_type(&request)?,
             //TODO: need to check transport as well (arrived from TLS?)
             secure: request.uri.is_sips()?,
@@ -86,13 +107,170 @@ impl DialogSm {
             state: DialogState::Unconfirmed(Default::default()),
             created_at: Instant::now(),
             handlers: handlers.clone(),
+            events: None,
+            modification: None,
+            remote_rseq: None,
+            pending_prack: None,
+            session_timer: None,
+            offer_answer: Default::default(),
+            transfer: None,
+            timers: Default::default(),
         };
+        negotiation::report_local(&mut me, &request.body);
 
         handlers.transaction.new_uac_invite(request).await?;
 
         Ok(me)
     }
 
+    pub fn with_events(mut self, events: UnboundedSender<CallEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn with_timers(mut self, timers: Timers) -> Self {
+        self.timers = timers;
+        self
+    }
+
+    //RFC3261 12.2.1.2, another early dialog created by the same INVITE, after a downstream
+    //proxy forked it, the INVITE is not sent again
+    pub fn fork(&self) -> Self {
+        let mut forked = Self {
+            id: self.id.prefixed(),
+            call_id: self.call_id.clone(),
+            transaction_id: self.transaction_id.clone(),
+            local_tag: self.local_tag.clone(),
+            local_seqn: self.local_seqn,
+            local_uri: self.local_uri.clone(),
+            remote_tag: None,
+            remote_seqn: None,
+            remote_uri: self.remote_uri.clone(),
+            remote_target: None,
+            route_set: vec![],
+            session_type: self.session_type.clone(),
+            secure: self.secure,
+            contact_header: self.contact_header.clone(),
+            request: self.request.clone(),
+            state: DialogState::Unconfirmed(Default::default()),
+            created_at: Instant::now(),
+            handlers: self.handlers.clone(),
+            events: self.events.clone(),
+            modification: None,
+            remote_rseq: None,
+            pending_prack: None,
+            session_timer: None,
+            offer_answer: Default::default(),
+            transfer: None,
+            timers: self.timers,
+        };
+        negotiation::report_local(&mut forked, &self.request.body);
+
+        forked
+    }
+
+    pub fn is_confirmed(&self) -> bool {
+        matches!(self.state, DialogState::Confirmed(_))
+    }
+
+    //RFC3261 13.2.2.4, a 2xx of this fork after the call has been answered by another one,
+    //the dialog it creates is acked and hung up without telling the application
+    pub async fn release_fork(&mut self, response: rsip::Response) -> Result<(), Error> {
+        self.handlers
+            .transport
+            .send(self.request.ack_request_from(response.clone()).into())
+            .await?;
+
+        self.bind(&response)?;
+        self.remote_target = Some(response.contact_header()?.typed()?.uri);
+        self.pending_prack = None;
+        let bye = self.set_outgoing_request_defaults_for(presets::in_dialog_request(
+            &self.handlers.config,
+            rsip::Method::Bye,
+        ))?;
+        self.state = DialogState::Terminated(Terminated {
+            entered_at: Instant::now(),
+        });
+
+        self.handlers.transaction.new_uac(bye).await?;
+
+        Ok(())
+    }
+
+    //another fork answered the call, so this early dialog ends without telling the application
+    pub fn abandon(&mut self) {
+        if !matches!(
+            self.state,
+            DialogState::Unconfirmed(_) | DialogState::Early(_)
+        ) {
+            return;
+        }
+
+        self.pending_prack = None;
+        self.state = DialogState::Terminated(Terminated {
+            entered_at: Instant::now(),
+        });
+    }
+
+    //RFC3891 3, the state of this dialog when the given Replaces matches it
+    pub fn replaced_by(&self, replaces: &Replaces) -> Option<Target> {
+        let remote_tag = self.remote_tag.as_ref()?;
+        if !replaces.matches(
+            &self.call_id,
+            &self.local_tag.to_string(),
+            &remote_tag.to_string(),
+        ) {
+            return None;
+        }
+
+        Some(match self.state {
+            DialogState::Unconfirmed(_) | DialogState::Early(_) => Target::EarlyUac,
+            DialogState::Confirmed(_) => Target::Confirmed,
+            _ => Target::Terminated,
+        })
+    }
+
+    //another dialog took the place of this one, only a confirmed one can be replaced
+    pub async fn replace(&mut self) {
+        if let Err(err) = self._replace().await {
+            self.error(
+                format!("Dialog {} failed to be replaced: {}", self.id, err),
+                None,
+            );
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !matches!(
+            self.state,
+            DialogState::Terminated(_) | DialogState::Errored(_)
+        )
+    }
+
+    //when the dialog terminated or errored, None while it's still going
+    pub fn ended_at(&self) -> Option<Instant> {
+        match &self.state {
+            DialogState::Terminated(terminated) => Some(terminated.entered_at),
+            DialogState::Errored(errored) => Some(errored.entered_at),
+            _ => None,
+        }
+    }
+
+    async fn _replace(&mut self) -> Result<(), Error> {
+        if !self.is_confirmed() {
+            return Ok(());
+        }
+
+        let bye = self.set_outgoing_request_defaults_for(presets::in_dialog_request(
+            &self.handlers.config,
+            rsip::Method::Bye,
+        ))?;
+        self.terminate(bye.clone().into());
+        self.handlers.transaction.new_uac(bye).await?;
+
+        Ok(())
+    }
+
     async fn _process_incoming_request(&mut self, request: rsip::Request) -> Result<(), Error> {
         if !matches!(self.state, DialogState::Confirmed(_)) {
             return Err(Error::custom(format!(
@@ -101,15 +279,18 @@ impl DialogSm {
             )));
         }
 
+        if request.method == rsip::Method::Ack {
+            return modification::acked(self, request);
+        }
+
         self.validate_incoming_request(&request)?;
         match request.method {
-            /*
-            rsip::Method::Invite => {
-                self.handlers
-                    .transaction
-                    .new_uas_invite(response_msg_from(request).await, None)
-                    .await?
-            }*/
+            rsip::Method::Invite | rsip::Method::Update => {
+                modification::process_incoming(self, request).await?
+            }
+            rsip::Method::Refer => refer::process_incoming_refer(self, request).await?,
+            rsip::Method::Notify => refer::process_incoming_notify(self, request).await?,
+            rsip::Method::Info => dialog_sm::process_incoming_info(self, request).await?,
             rsip::Method::Bye => {
                 self.terminate(request.clone().into());
                 self.handlers
@@ -133,8 +314,45 @@ impl DialogSm {
     }
 
     async fn _process_incoming_response(&mut self, response: rsip::Response) -> Result<(), Error> {
+        let cseq = response.cseq_header()?.typed()?;
+        if matches!(&self.modification, Some(m) if m.is_outgoing() && m.matches(&cseq)) {
+            return modification::process_response(self, response).await;
+        }
+        if matches!(&self.transfer, Some(t @ Transfer::Outgoing(_)) if t.matches(&cseq)) {
+            refer::process_refer_response(self, response);
+            return Ok(());
+        }
+
+        //responses to requests sent inside the dialog (like BYE) or to a CANCEL
+        if cseq.method != rsip::Method::Invite || cseq.seq != self.request.cseq_header()?.seq()? {
+            return Ok(());
+        }
+
+        //RFC3261 12.1.2, a tagged 1xx or 2xx binds the dialog to the remote tag
+        if self.remote_tag.is_none()
+            && response.status_code.kind() <= rsip::StatusCodeKind::Successful
+            && response.to_header()?.typed()?.tag().is_some()
+        {
+            self.bind(&response)?;
+        }
+
+        //an answer to our offer, or an offer when the INVITE had none
+        if response.status_code.kind() <= rsip::StatusCodeKind::Successful {
+            negotiation::report_remote(self, &response.body);
+        }
+
         match response.status_code().kind() {
+            rsip::StatusCodeKind::Provisional if reliable::requires_100rel(&response.headers) => {
+                self.reliable_early(response).await?
+            }
             rsip::StatusCodeKind::Provisional => self.early(response).await,
+            //retransmitted 2xx, the ACK was lost
+            rsip::StatusCodeKind::Successful if matches!(self.state, DialogState::Confirmed(_)) => {
+                self.handlers
+                    .transport
+                    .send(self.request.ack_request_from(response).into())
+                    .await?;
+            }
             rsip::StatusCodeKind::Successful => {
                 self.confirm(response.clone()).await?;
                 self.handlers
@@ -142,6 +360,12 @@ impl DialogSm {
                     .send(self.request.ack_request_from(response).into())
                     .await?;
             }
+            rsip::StatusCodeKind::RequestFailure if response.status_code == 422.into() => {
+                self.retry_with_min_se(response).await?
+            }
+            rsip::StatusCodeKind::RequestFailure
+            | rsip::StatusCodeKind::ServerFailure
+            | rsip::StatusCodeKind::GlobalFailure => self.reject(response),
             rsip::StatusCodeKind::Redirection => self.error(
                 format!(
                     "({}): received status {}, peer wants redirection to {}",
@@ -167,6 +391,26 @@ impl DialogSm {
     }
 
     async fn _process_outgoing_request(&mut self, request: rsip::Request) -> Result<(), Error> {
+        if request.method == rsip::Method::PRack {
+            return match self.pending_prack.take() {
+                Some(response) => self.send_prack(response, request.body).await,
+                None => {
+                    common::log::warn!("({}): no provisional waits for a PRACK", self.id);
+                    Ok(())
+                }
+            };
+        }
+
+        //hanging up before the call is answered means cancelling the INVITE
+        if matches!(request.method, rsip::Method::Bye | rsip::Method::Cancel)
+            && matches!(
+                self.state,
+                DialogState::Unconfirmed(_) | DialogState::Early(_)
+            )
+        {
+            return self.cancel().await;
+        }
+
         if !matches!(self.state, DialogState::Confirmed(_)) {
             return Err(Error::custom(format!(
                 "cannot process a request while UAC dialog state is in {}",
@@ -174,14 +418,30 @@ impl DialogSm {
             )));
         }
 
+        //RFC3261 14.1, only one re-INVITE (or UPDATE) can be in progress
+        if modification::is_modification(&request.method) && self.modification.is_some() {
+            return modification::reject_pending(self, request);
+        }
+        if request.method == rsip::Method::Refer && self.transfer.is_some() {
+            return refer::reject_pending(self, request);
+        }
+
         let request = self.set_outgoing_request_defaults_for(request)?;
 
         match request.method {
-            rsip::Method::Invite => self.handlers.transaction.new_uac_invite(request).await?,
+            rsip::Method::Invite | rsip::Method::Update => {
+                modification::send(self, request).await?
+            }
             rsip::Method::Bye => {
                 self.terminate(request.clone().into());
                 self.handlers.transaction.new_uac(request).await?
             }
+            rsip::Method::Info => self.handlers.transaction.new_uac(request).await?,
+            rsip::Method::Refer => {
+                self.transfer = Some(Transfer::Outgoing(request.clone()));
+                self.handlers.transaction.new_uac(request).await?
+            }
+            rsip::Method::Notify => refer::notify_transfer(self, request).await?,
             _ => self.error(
                 format!(
                     "({}): don't know how to handle method {} inside a dialog",
@@ -194,7 +454,38 @@ impl DialogSm {
         Ok(())
     }
 
-    async fn _process_outgoing_response(&mut self, _: rsip::Response) -> Result<(), Error> {
+    //only responses to a re-INVITE or UPDATE of the peer, everything else is answered
+    //by the dialog itself
+    async fn _process_outgoing_response(&mut self, response: rsip::Response) -> Result<(), Error> {
+        let cseq = response.cseq_header()?.typed()?;
+        if matches!(&self.transfer, Some(t @ Transfer::Incoming(_)) if t.matches(&cseq)) {
+            return refer::process_transfer_answer(self, response).await;
+        }
+
+        if !matches!(&self.modification, Some(m @ Modification::Incoming(_)) if m.matches(&cseq)) {
+            common::log::warn!(
+                "({}): no pending request for outgoing response {}, dropping it",
+                self.id,
+                response.status_code
+            );
+            return Ok(());
+        }
+
+        modification::process_answer(self, response).await
+    }
+
+    async fn next_step(&mut self) -> Result<(), Error> {
+        if matches!(&self.modification, Some(m) if m.should_retry()) {
+            return modification::retry(self).await;
+        }
+        if matches!(&self.modification, Some(m) if m.is_unacked()) {
+            return modification::retransmit(self).await;
+        }
+
+        if matches!(self.state, DialogState::Confirmed(_)) {
+            return session_timer::check(self).await;
+        }
+
         Ok(())
     }
 
@@ -202,11 +493,163 @@ impl DialogSm {
         self.error(reason, Some(msg));
     }
 
+    async fn reliable_early(&mut self, response: rsip::Response) -> Result<(), Error> {
+        let rseq = match reliable::rseq(&response.headers) {
+            Some(rseq) => rseq,
+            None => {
+                common::log::warn!("({}): reliable provisional without RSeq", self.id);
+                self.early(response).await;
+                return Ok(());
+            }
+        };
+
+        //RFC3262 4, retransmissions and out of order provisionals are discarded
+        if matches!(self.remote_rseq, Some(remote_rseq) if rseq != remote_rseq + 1) {
+            return Ok(());
+        }
+        self.remote_rseq = Some(rseq);
+        self.early(response.clone()).await;
+
+        //an offer in the provisional is answered in the PRACK, that's up to the application
+        if !response.body.is_empty() && self.request.body.is_empty() {
+            self.pending_prack = Some(response);
+            return Ok(());
+        }
+
+        self.send_prack(response, Default::default()).await
+    }
+
+    async fn send_prack(&mut self, response: rsip::Response, body: Vec<u8>) -> Result<(), Error> {
+        let rack = reliable::RAck {
+            rseq: reliable::rseq(&response.headers)
+                .ok_or_else(|| Error::from("missing RSeq header"))?,
+            cseq: self.request.cseq_header()?.seq()?,
+            method: rsip::Method::Invite,
+        };
+
+        //the dialog is still early, so the remote tag and target come from the provisional
+        let mut request = presets::in_dialog_request(&self.handlers.config, rsip::Method::PRack);
+        request
+            .headers
+            .unique_push(self.request.from_header()?.clone().into());
+        request
+            .headers
+            .unique_push(response.to_header()?.clone().into());
+        request.headers.unique_push(self.call_id.clone().into());
+        request.headers.unique_push(
+            rsip::typed::CSeq::from((self.increased_seqn(), rsip::Method::PRack)).into(),
+        );
+        request
+            .headers
+            .retain(|h| !matches!(h, rsip::Header::Contact(_)));
+        request.headers.push(rack.into());
+        //RFC3262 7.1, the PRACK goes through the route set of the early dialog, like any
+        //other request inside it
+        let remote_target = match response.contact_header() {
+            Ok(contact) => contact.typed()?.uri,
+            Err(_) => self.request.uri.clone(),
+        };
+        route_set::apply(&mut request, &self.route_set, remote_target);
+
+        if !body.is_empty() {
+            negotiation::report_local(self, &body);
+            request
+                .headers
+                .unique_push(rsip::headers::ContentType::new("application/sdp").into());
+            request
+                .headers
+                .unique_push(rsip::headers::ContentLength::new(body.len().to_string()).into());
+            request.body = body;
+        }
+
+        self.handlers.transaction.new_uac(request).await?;
+
+        Ok(())
+    }
+
+    //RFC4028 7.4, the INVITE is sent again with the Min-SE of the 422 as session interval
+    async fn retry_with_min_se(&mut self, response: rsip::Response) -> Result<(), Error> {
+        let min_se = match session_timer::min_se(&response.headers) {
+            Some(min_se) => min_se,
+            None => {
+                self.reject(response);
+                return Ok(());
+            }
+        };
+        if matches!(SessionExpires::from_headers(&self.request.headers), Some(se) if se.delta >= min_se)
+        {
+            self.reject(response);
+            return Ok(());
+        }
+
+        let mut request = self.request.clone();
+        request.headers.retain(|h| match h {
+            rsip::Header::Other(key, _) => !["Session-Expires", "x", "Min-SE"]
+                .iter()
+                .any(|name| key.eq_ignore_ascii_case(name)),
+            _ => true,
+        });
+        request
+            .headers
+            .push(SessionExpires::new(min_se, None).into());
+        request.headers.push(session_timer::min_se_header(min_se));
+        request.headers.unique_push(
+            rsip::typed::CSeq::from((self.increased_seqn(), rsip::Method::Invite)).into(),
+        );
+        //a new transaction needs a new branch
+        request.headers.unique_push(
+            presets::in_dialog_request(&self.handlers.config, rsip::Method::Invite)
+                .via_header()?
+                .clone()
+                .into(),
+        );
+
+        self.transaction_id = request.transaction_id()?.into();
+        self.request = request.clone();
+        self.handlers.transaction.new_uac_invite(request).await?;
+
+        Ok(())
+    }
+
+    async fn cancel(&mut self) -> Result<(), Error> {
+        //RFC3261 9.1: same Request-URI, Call-ID, To, From, Via and CSeq number as the INVITE
+        let mut request = self.request.clone();
+        request.method = rsip::Method::Cancel;
+        request.headers.unique_push(
+            rsip::typed::CSeq::from((self.request.cseq_header()?.seq()?, rsip::Method::Cancel))
+                .into(),
+        );
+        request.headers.retain(|h| {
+            !matches!(
+                h,
+                rsip::Header::ContentType(_) | rsip::Header::ContentDisposition(_)
+            )
+        });
+        request
+            .headers
+            .unique_push(rsip::Header::ContentLength(Default::default()));
+        request.body = Default::default();
+
+        self.handlers.transaction.new_uac(request).await?;
+
+        Ok(())
+    }
+
     async fn early(&mut self, response: rsip::Response) {
+        //retransmitted or additional provisional responses
+        if matches!(self.state, DialogState::Early(_)) {
+            self.emit_provisional(response.clone());
+            if let DialogState::Early(early) = &mut self.state {
+                early.response = response;
+            }
+            return;
+        }
+
         if !matches!(self.state, DialogState::Unconfirmed(_)) {
             return self.wrong_transition("early", response.into());
         }
 
+        self.emit_provisional(response.clone());
         self.state = DialogState::Early(Early {
             response,
             entered_at: Instant::now(),
@@ -234,7 +677,17 @@ impl DialogSm {
         self.remote_seqn = Some(response.cseq_header()?.typed()?.seq);
 
         self.remote_target = Some(response.contact_header()?.typed()?.uri);
+        //RFC3261 13.2.2.4, the 2xx recomputes the route set of the early dialog
+        self.route_set = route_set_of(&response)?;
+        self.pending_prack = None;
+        //RFC4028 7.2, without Session-Expires in the 2xx the session doesn't expire
+        self.session_timer = SessionExpires::from_headers(&response.headers).map(|se| {
+            SessionTimer::new(&se, true)
+                .with_peer_allow(&response.headers)
+                .with_local_sdp(self.request.body.clone())
+        });
 
+        self.emit(CallEvent::Answered(response.clone()));
         self.state = DialogState::Confirmed(Confirmed {
             response,
             entered_at: Instant::now(),
@@ -243,12 +696,21 @@ impl DialogSm {
         Ok(())
     }
 
-    //TODO: I suspect msg here should be an option
-    fn terminate(&mut self, msg: rsip::SipMessage) {
-        if matches!(self.state, DialogState::Errored(_)) {
-            return self.wrong_transition("terminate", msg);
-        }
+    //the remote tag and the route set of the dialog, from its first tagged response
+    fn bind(&mut self, response: &rsip::Response) -> Result<(), Error> {
+        self.remote_tag = response.to_header()?.typed()?.tag().cloned();
+        self.id = response.dialog_id()?;
+        self.route_set = route_set_of(response)?;
 
+        Ok(())
+    }
+
+    //final non 2xx response, the transaction takes care of the ACK
+    fn reject(&mut self, response: rsip::Response) {
+        self.emit(CallEvent::Failed {
+            status_code: Some(response.status_code.clone()),
+            reason: format!("call rejected with {}", response.status_code),
+        });
         self.state = DialogState::Terminated(Terminated {
             entered_at: Instant::now(),
         });
@@ -256,6 +718,10 @@ impl DialogSm {
 
     fn error(&mut self, error: String, sip_message: Option<rsip::SipMessage>) {
         common::log::error!("Dialog {} errored: {}", self.id, error);
+        self.emit(CallEvent::Failed {
+            status_code: None,
+            reason: error.clone(),
+        });
         self.state = DialogState::Errored(Errored {
             entered_at: Instant::now(),
             sip_message,
@@ -263,6 +729,17 @@ impl DialogSm {
         });
     }
 
+    fn emit_provisional(&self, response: rsip::Response) {
+        match (
+            response.status_code == rsip::StatusCode::Trying,
+            response.body.is_empty(),
+        ) {
+            (true, _) => (),
+            (false, true) => self.emit(CallEvent::Ringing(response)),
+            (false, false) => self.emit(CallEvent::EarlyMedia(response)),
+        }
+    }
+
     fn wrong_transition(&mut self, desired_state: &'static str, msg: rsip::SipMessage) {
         self.error(
             format!(
@@ -278,32 +755,6 @@ impl DialogSm {
         self.local_seqn
     }
 
-    fn set_outgoing_request_defaults_for(
-        &mut self,
-        mut request: rsip::Request,
-    ) -> Result<rsip::Request, Error> {
-        request.from_header_mut()?.mut_tag(self.local_tag.clone())?;
-        request.from_header_mut()?.mut_uri(self.local_uri.clone())?;
-
-        request
-            .to_header_mut()?
-            .mut_tag(self.remote_tag.clone().expect("remote tag"))?;
-        request.to_header_mut()?.mut_uri(self.remote_uri.clone())?;
-
-        request.call_id_header_mut()?.replace(self.call_id.clone());
-        if !matches!(request.method, rsip::Method::Ack | rsip::Method::Cancel) {
-            request.cseq_header_mut()?.mut_seq(self.increased_seqn())?;
-        }
-        request.uri = self.remote_target.clone().expect("remote target");
-        if !matches!(request.method, rsip::Method::Invite) {
-            request
-                .contact_header_mut()?
-                .replace(self.contact_header.clone());
-        }
-
-        Ok(request)
-    }
-
     fn validate_incoming_request(&mut self, request: &rsip::Request) -> Result<(), Error> {
         let req_seqn = request.cseq_header()?.seq()?;
         if let (Some(remote_seqn), req_seqn) = (self.remote_seqn, req_seqn) {
@@ -368,6 +819,124 @@ impl DialogSm {
             );
         }
     }
+
+    pub async fn next(&mut self) {
+        if let Err(err) = self.next_step().await {
+            self.error(
+                format!("Dialog {} failed to move forward: {}", self.id, err),
+                None,
+            );
+        }
+    }
+}
+
+//RFC3261 12.1.2, the UAC takes the Record-Route entries in reverse order
+impl Dialog for DialogSm {
+    fn id(&self) -> &DialogId {
+        &self.id
+    }
+
+    fn handlers(&self) -> &Handlers {
+        &self.handlers
+    }
+
+    fn timers(&self) -> Timers {
+        self.timers
+    }
+
+    //we sent the initial INVITE
+    fn owns_call_id(&self) -> bool {
+        true
+    }
+
+    fn modification(&mut self) -> &mut Option<Modification> {
+        &mut self.modification
+    }
+
+    fn session_timer(&mut self) -> &mut Option<SessionTimer> {
+        &mut self.session_timer
+    }
+
+    fn offer_answer(&mut self) -> &mut OfferAnswer {
+        &mut self.offer_answer
+    }
+
+    fn transfer(&mut self) -> &mut Option<Transfer> {
+        &mut self.transfer
+    }
+
+    fn set_remote_target(&mut self, remote_target: rsip::Uri) {
+        self.remote_target = Some(remote_target);
+    }
+
+    fn emit(&self, event: CallEvent) {
+        if let Some(events) = &self.events {
+            //the application might have dropped its session handle, that's fine
+            let _ = events.send(event);
+        }
+    }
+
+    //TODO: I suspect msg here should be an option
+    fn terminate(&mut self, msg: rsip::SipMessage) {
+        if matches!(self.state, DialogState::Errored(_)) {
+            return self.wrong_transition("terminate", msg);
+        }
+
+        self.emit(CallEvent::Terminated);
+        self.state = DialogState::Terminated(Terminated {
+            entered_at: Instant::now(),
+        });
+    }
+
+    fn set_outgoing_request_defaults_for(
+        &mut self,
+        mut request: rsip::Request,
+    ) -> Result<rsip::Request, Error> {
+        request.from_header_mut()?.mut_tag(self.local_tag.clone())?;
+        request.from_header_mut()?.mut_uri(self.local_uri.clone())?;
+
+        request
+            .to_header_mut()?
+            .mut_tag(self.remote_tag.clone().expect("remote tag"))?;
+        request.to_header_mut()?.mut_uri(self.remote_uri.clone())?;
+
+        request.call_id_header_mut()?.replace(self.call_id.clone());
+        if !matches!(request.method, rsip::Method::Ack | rsip::Method::Cancel) {
+            request.cseq_header_mut()?.mut_seq(self.increased_seqn())?;
+        }
+        route_set::apply(
+            &mut request,
+            &self.route_set,
+            self.remote_target.clone().expect("remote target"),
+        );
+        if !matches!(request.method, rsip::Method::Invite) {
+            request
+                .contact_header_mut()?
+                .replace(self.contact_header.clone());
+        }
+
+        Ok(request)
+    }
+
+    fn set_outgoing_response_defaults_for(
+        &mut self,
+        mut response: rsip::Response,
+    ) -> Result<rsip::Response, Error> {
+        if response.status_code.kind() == rsip::StatusCodeKind::Successful
+            && response.contact_header().is_err()
+        {
+            response.headers.push(self.contact_header.clone().into());
+        }
+
+        Ok(response)
+    }
+}
+
+fn route_set_of(response: &rsip::Response) -> Result<Vec<UriWithParams>, Error> {
+    let mut route_set = route_set::from_record_routes(&response.headers)?;
+    route_set.reverse();
+
+    Ok(route_set)
 }
 
 pub fn is_secure(request: &rsip::Request) -> Result<bool, Error> {
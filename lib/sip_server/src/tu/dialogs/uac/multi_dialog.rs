@@ -1,35 +1,69 @@
-use crate::Error;
-use common::{rsip, tokio::sync::Mutex};
+use crate::{
+    transaction::sm::Timers,
+    tu::{
+        calls::CallEvent,
+        dialogs::replaces::{Replaces, Target},
+    },
+    Error,
+};
+use common::{
+    rsip::{self, prelude::*},
+    tokio::sync::{mpsc::UnboundedSender, Mutex},
+};
 use models::{rsip_ext::*, tu::DialogId, Handlers};
+use std::time::Duration;
 
+//the early dialogs that an INVITE creates, one per remote tag when a downstream proxy forks it
 #[derive(Debug)]
 pub struct MultiDialog {
     pub id: DialogId,
     dialogs: Mutex<Vec<super::DialogSm>>,
+    //forks whose 2xx we acked and hung up, their retransmissions only get acked again
+    released: Mutex<Vec<DialogId>>,
 }
 
 impl MultiDialog {
-    pub async fn new(handlers: Handlers, msg: rsip::Request) -> Result<Self, Error> {
+    pub async fn new(
+        handlers: Handlers,
+        msg: rsip::Request,
+        events: Option<UnboundedSender<CallEvent>>,
+        timers: Timers,
+    ) -> Result<Self, Error> {
+        let dialog = super::DialogSm::new(handlers, msg.clone()).await?;
+        let dialog = dialog.with_timers(timers);
+        let dialog = match events {
+            Some(events) => dialog.with_events(events),
+            None => dialog,
+        };
+
         Ok(Self {
             id: msg.dialog_id()?,
-            dialogs: Mutex::new(vec![super::DialogSm::new(handlers, msg).await?]),
+            dialogs: Mutex::new(vec![dialog]),
+            released: Default::default(),
         })
     }
 
+    pub async fn is_active(&self) -> bool {
+        self.dialogs.lock().await.iter().any(|d| d.is_active())
+    }
+
+    //all of its dialogs ended, at least that long ago
+    pub async fn has_ended_for(&self, linger: Duration) -> bool {
+        self.dialogs
+            .lock()
+            .await
+            .iter()
+            .all(|d| matches!(d.ended_at(), Some(ended_at) if ended_at.elapsed() >= linger))
+    }
+
     pub async fn process_incoming_request(&self, msg: rsip::Request) -> Result<(), Error> {
-        let dialog_id = msg.dialog_id()?;
+        //requests of the peer, so the local tag is in the To header
+        let dialog_id = msg.uas_dialog_id()?;
 
-        //TODO: decouple the find part, will be needed all over the place
         let mut dialogs = self.dialogs.lock().await;
+        let index = position_for(&dialogs, &dialog_id);
 
-        let dialog = match dialogs.iter_mut().find(|d| d.id == dialog_id) {
-            Some(dialog) => dialog,
-            None => dialogs
-                .first_mut()
-                .expect("No dialog inside MultiDialog Vec ??"),
-        };
-
-        dialog.process_incoming_request(msg).await;
+        dialogs[index].process_incoming_request(msg).await;
 
         Ok(())
     }
@@ -37,34 +71,151 @@ impl MultiDialog {
     pub async fn process_incoming_response(&self, msg: rsip::Response) -> Result<(), Error> {
         let dialog_id = msg.dialog_id()?;
 
-        //TODO: decouple the find part, will be needed all over the place
         let mut dialogs = self.dialogs.lock().await;
+        let is_new_fork = !dialog_id.is_unconfirmed() && !dialogs.iter().any(|d| d.id == dialog_id);
+
+        //once a fork answered the call, a 2xx of any other one, new, early or abandoned, is
+        //acked and hung up
+        let is_late_answer = !dialog_id.is_unconfirmed()
+            && msg.status_code.kind() == rsip::StatusCodeKind::Successful
+            && msg.cseq_header()?.typed()?.method == rsip::Method::Invite;
+        if is_late_answer
+            && dialogs
+                .iter()
+                .any(|d| d.is_confirmed() && d.id != dialog_id)
+        {
+            let mut released = self.released.lock().await;
+            if released.contains(&dialog_id) {
+                let dialog = &dialogs[0];
+                dialog
+                    .handlers
+                    .transport
+                    .send(dialog.request.ack_request_from(msg).into())
+                    .await?;
+                return Ok(());
+            }
+
+            //the BYE follows the route set of the fork, from its own dialog
+            match dialogs.iter().position(|d| d.id == dialog_id) {
+                Some(index) => dialogs[index].release_fork(msg).await?,
+                None => {
+                    let mut forked = dialogs[0].fork();
+                    forked.release_fork(msg).await?;
+                    dialogs.push(forked);
+                }
+            };
+            released.push(dialog_id);
+
+            return Ok(());
+        }
+
+        //the first tagged response binds the dialog of the INVITE, any other tag forks it
+        let index = match is_new_fork {
+            true => match dialogs
+                .iter()
+                .position(|d| d.remote_tag.is_none() && d.is_active())
+            {
+                Some(index) => index,
+                None => {
+                    let forked = dialogs[0].fork();
+                    dialogs.push(forked);
+                    dialogs.len() - 1
+                }
+            },
+            false => position_for(&dialogs, &dialog_id),
+        };
 
-        let dialog = match dialogs.iter_mut().find(|d| d.id == dialog_id) {
-            Some(dialog) => dialog,
-            None => dialogs
-                .first_mut()
-                .expect("No dialog inside MultiDialog Vec ??"),
+        let cseq = msg.cseq_header()?.typed()?;
+        let is_final_to_invite = cseq.method == rsip::Method::Invite
+            && cseq.seq == dialogs[index].request.cseq_header()?.seq()?
+            && msg.status_code.kind() != rsip::StatusCodeKind::Provisional
+            && !dialogs[index].is_confirmed();
+
+        dialogs[index].process_incoming_response(msg).await;
+
+        //the INVITE transaction is over, the early dialogs of the other forks with it
+        if is_final_to_invite {
+            dialogs
+                .iter_mut()
+                .enumerate()
+                .filter(|(position, _)| *position != index)
+                .for_each(|(_, dialog)| dialog.abandon());
+        }
+
+        Ok(())
+    }
+
+    pub async fn process_outgoing_request(&self, msg: rsip::Request) -> Result<(), Error> {
+        let dialog_id = msg.dialog_id()?;
+
+        let mut dialogs = self.dialogs.lock().await;
+        //the application doesn't know the remote tag, a PRACK goes to the fork that waits for it
+        let index = match msg.method {
+            rsip::Method::PRack if dialog_id.is_unconfirmed() => dialogs
+                .iter()
+                .position(|d| d.pending_prack.is_some())
+                .unwrap_or_else(|| position_for(&dialogs, &dialog_id)),
+            _ => position_for(&dialogs, &dialog_id),
         };
 
-        dialog.process_incoming_response(msg).await;
+        dialogs[index].process_outgoing_request(msg).await;
 
         Ok(())
     }
 
+    pub async fn process_outgoing_response(&self, msg: rsip::Response) -> Result<(), Error> {
+        //responses to requests of the peer, so the local tag is in the To header
+        let dialog_id = msg.uas_dialog_id()?;
+
+        let mut dialogs = self.dialogs.lock().await;
+        let index = position_for(&dialogs, &dialog_id);
+
+        dialogs[index].process_outgoing_response(msg).await;
+
+        Ok(())
+    }
+
+    pub async fn replaced_by(&self, replaces: &Replaces) -> Option<Target> {
+        self.dialogs
+            .lock()
+            .await
+            .iter()
+            .find_map(|d| d.replaced_by(replaces))
+    }
+
+    pub async fn replace(&self, replaces: &Replaces) {
+        let mut dialogs = self.dialogs.lock().await;
+        if let Some(dialog) = dialogs
+            .iter_mut()
+            .find(|d| d.replaced_by(replaces).is_some())
+        {
+            dialog.replace().await;
+        }
+    }
+
+    pub async fn next(&self) {
+        for dialog in self.dialogs.lock().await.iter_mut() {
+            dialog.next().await;
+        }
+    }
+
     pub async fn transport_error(&self, reason: String, msg: rsip::SipMessage) {
         let dialog_id = msg.dialog_id().expect("missing dialog_id to report error");
 
-        //TODO: decouple the find part, will be needed all over the place
         let mut dialogs = self.dialogs.lock().await;
+        let index = position_for(&dialogs, &dialog_id);
 
-        let dialog = match dialogs.iter_mut().find(|d| d.id == dialog_id) {
-            Some(dialog) => dialog,
-            None => dialogs
-                .first_mut()
-                .expect("No dialog inside MultiDialog Vec ??"),
-        };
-
-        dialog.transport_error(reason, msg).await;
+        dialogs[index].transport_error(reason, msg).await;
     }
 }
+
+//the dialog with the given id, otherwise (like for requests of the application that don't
+//know the remote tag) the one that answered the call, or the first one still active
+fn position_for(dialogs: &[super::DialogSm], dialog_id: &DialogId) -> usize {
+    dialogs
+        .iter()
+        .position(|d| d.id == *dialog_id)
+        .or_else(|| dialogs.iter().position(|d| d.is_confirmed()))
+        .or_else(|| dialogs.iter().position(|d| d.is_active()))
+        .unwrap_or(0)
+}
@@ -1,9 +1,31 @@
-use super::states::{Confirmed, Early, Errored, Terminated};
-
-use crate::Error;
-use common::rsip::{self, prelude::*, uri::UriWithParams};
-use common::tokio::time::Instant;
-use models::{rsip_ext::*, transport::RequestMsg, tu::DialogId, Handlers};
+use super::{
+    states::{Confirmed, Early, Errored, Terminated, UnAcked, Unestablished},
+    validations,
+};
+
+use crate::{
+    presets,
+    transaction::sm::Timers,
+    tu::{
+        calls::CallEvent,
+        dialogs::{
+            dialog_sm::{self, Dialog},
+            modification::{self, Modification},
+            negotiation,
+            refer::{self, Transfer},
+            reliable::{self, Unpracked},
+            replaces::{Replaces, Target},
+            route_set,
+            session_timer::{self, SessionTimer},
+        },
+    },
+    Error,
+};
+use common::rsip::{self, headers::UntypedHeader, prelude::*, uri::UriWithParams};
+use common::tokio::{sync::mpsc::UnboundedSender, time::Instant};
+use models::{tu::DialogId, Handlers};
+use sdp::OfferAnswer;
+use std::collections::VecDeque;
 
 #[derive(Debug)]
 pub struct DialogSm {
@@ -11,7 +33,7 @@ pub struct DialogSm {
     pub call_id: rsip::headers::CallId,
     pub transaction_id: String,
     pub local_tag: rsip::common::param::Tag,
-    pub local_seqn: u32,
+    pub local_seqn: Option<u32>,
     pub local_uri: rsip::Uri,
     pub remote_tag: rsip::common::param::Tag,
     pub remote_seqn: u32,
@@ -21,10 +43,22 @@ pub struct DialogSm {
     pub route_set: Vec<UriWithParams>,
     pub session_type: SessionType,
     pub contact_header: rsip::headers::Contact,
-    pub msg: rsip::Request,
+    pub request: rsip::Request,
     pub state: DialogState,
     pub created_at: Instant,
     pub handlers: Handlers,
+    pub events: Option<UnboundedSender<CallEvent>>,
+    pub modification: Option<Modification>,
+    pub local_rseq: Option<u32>,
+    pub unpracked: Option<Unpracked>,
+    pub queued_provisionals: VecDeque<rsip::Response>,
+    pub session_timer: Option<SessionTimer>,
+    pub offer_answer: OfferAnswer,
+    pub transfer: Option<Transfer>,
+    //the application hung up before the ACK of our 2xx arrived
+    pub hangup_on_ack: bool,
+    //the T1 and T2 of the transaction layer when the dialog was created
+    pub timers: Timers,
 }
 
 #[derive(Debug)]
@@ -37,9 +71,10 @@ pub enum SessionType {
 #[allow(dead_code)]
 #[derive(Debug)]
 pub enum DialogState {
-    Early(Early),         //just created
-    UnAcked(Confirmed),   //received Ack
-    Confirmed(Confirmed), //received Ack
+    Unestablished(Unestablished), //just created
+    Early(Early),                 //sent 1xx
+    UnAcked(UnAcked),             //sent 2xx
+    Confirmed(Confirmed),         //received Ack
     Terminated(Terminated),
     Errored(Errored),
 }
@@ -47,75 +82,259 @@ pub enum DialogState {
 //TODO: remove unused async in private functions
 #[allow(dead_code)]
 impl DialogSm {
-    pub async fn new(handlers: Handlers, mut msg: rsip::Response) -> Result<Self, Error> {
-        //TODO: need to make sure that it is a success or early
-        //TODO: need to make sure that a contact address is added
-        //TODO: need to check SIP or SIPS url
-        //validations::run(&msg)?;
-
-        let route_set: Vec<UriWithParams> = msg
-            .record_route_header()
-            .map(|h| h.typed().map(|h| h.uris().to_owned()))
-            .transpose()?
-            .unwrap_or_else(|| vec![]);
-        msg.to_header_mut().mut_tag(Default::default())?;
-
-        let me = Self {
-            id: msg.dialog_id()?,
-            call_id: msg.call_id_header()?.clone(),
-            transaction_id: msg.transaction_id()?.expect("transaction id").into(),
-            local_tag: msg
-                .to_header()?
-                .tag()?,
-            local_seqn: msg.cseq_header()?.seq()?,
-            local_uri: msg.to_header()?.uri()?,
-            remote_tag: msg.from_header()?.tag()?,
-            remote_seqn: msg.cseq_header()?.seq()?,
-            remote_uri: msg.from_header()?.uri()?,
-            remote_target: None,
+    pub async fn new(handlers: Handlers, request: rsip::Request) -> Result<Self, Error> {
+        validations::run(&request)?;
+
+        //as opposed to the UAC, the UAS keeps the Record-Route order
+        let route_set = route_set::from_record_routes(&request.headers)?;
+
+        let local_tag = rsip::common::param::Tag::default();
+        let remote_tag = request
+            .from_header()?
+            .tag()?
+            .ok_or_else(|| Error::from("missing from tag"))?;
+        let contact_uri: rsip::Uri = handlers.config.default_addr().into();
+
+        let mut me = Self {
+            //the id follows the From/To tags of the INVITE, so that the application can refer
+            //to the dialog through the original request, before it learns our local tag
+            id: DialogId::new(request.call_id_header()?, &remote_tag, Some(&local_tag)),
+            call_id: request.call_id_header()?.clone(),
+            transaction_id: request.transaction_id()?.into(),
+            local_tag,
+            local_seqn: None,
+            local_uri: request.to_header()?.uri()?,
+            remote_tag,
+            remote_seqn: request.cseq_header()?.seq()?,
+            remote_uri: request.from_header()?.uri()?,
+            remote_target: request.contact_header()?.typed()?.uri,
             route_set,
-            session_type: session_type(&msg)?,
+            session_type: session_type(&request)?,
             //TODO: need to check transport as well (arrived from TLS?)
-            secure: msg.uri.is_sips()?,
-            contact_header: msg.contact_header()?.clone(),
-            msg: msg.clone(),
-            state: DialogState::Unconfirmed(Default::default()),
+            secure: request.uri.is_sips()?,
+            contact_header: rsip::typed::Contact::from(contact_uri).into(),
+            request: request.clone(),
+            state: DialogState::Unestablished(Default::default()),
             created_at: Instant::now(),
             handlers: handlers.clone(),
+            events: None,
+            modification: None,
+            local_rseq: None,
+            unpracked: None,
+            queued_provisionals: Default::default(),
+            session_timer: None,
+            offer_answer: Default::default(),
+            transfer: None,
+            hangup_on_ack: false,
+            timers: Default::default(),
         };
 
+        //RFC3264 6, an offer we can't make sense of is rejected right away
+        let response = match negotiation::check_remote_offer(&mut me, &request.body) {
+            Some(status_code) => {
+                me.state = DialogState::Terminated(Terminated {
+                    entered_at: Instant::now(),
+                });
+                presets::response_from(request.clone(), status_code)?
+            }
+            None => presets::response_from(request.clone(), 100.into())?,
+        };
+        let response = me.with_local_tag(response)?;
         handlers
             .transaction
-            .new_uac_invite(request_msg_from(msg).await)
+            .new_uas_invite(request, Some(response))
             .await?;
 
         Ok(me)
     }
 
-    async fn _process_incoming_request(&mut self, request: rsip::Request) -> Result<(), Error> {
-        if !matches!(self.state, DialogState::Confirmed(_)) {
-            return Err(Error::custom(format!(
-                "cannot process a request while UAC dialog state is in {}",
-                self.state
-            )));
+    pub fn with_events(mut self, events: UnboundedSender<CallEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn with_timers(mut self, timers: Timers) -> Self {
+        self.timers = timers;
+        self
+    }
+
+    pub fn is_active(&self) -> bool {
+        !matches!(
+            self.state,
+            DialogState::Terminated(_) | DialogState::Errored(_)
+        )
+    }
+
+    //when the dialog terminated or errored, None while it's still going
+    pub fn ended_at(&self) -> Option<Instant> {
+        match &self.state {
+            DialogState::Terminated(terminated) => Some(terminated.entered_at),
+            DialogState::Errored(errored) => Some(errored.entered_at),
+            _ => None,
         }
+    }
 
-        let request = self.set_dialog_defaults_for(request)?;
+    //RFC3891 3, the state of this dialog when the given Replaces matches it
+    pub fn replaced_by(&self, replaces: &Replaces) -> Option<Target> {
+        if !replaces.matches(
+            &self.call_id,
+            &self.local_tag.to_string(),
+            &self.remote_tag.to_string(),
+        ) {
+            return None;
+        }
 
-        match request.method {
-            rsip::Method::Invite => {
+        Some(match self.state {
+            DialogState::Unestablished(_) | DialogState::Early(_) => Target::EarlyUas,
+            DialogState::UnAcked(_) | DialogState::Confirmed(_) => Target::Confirmed,
+            _ => Target::Terminated,
+        })
+    }
+
+    //another dialog took the place of this one: a call that is still ringing is
+    //answered with 487, like a CANCEL would do, an answered one is hung up
+    pub async fn replace(&mut self) {
+        if let Err(err) = self._replace().await {
+            self.error(
+                format!("Dialog {} failed to be replaced: {}", self.id, err),
+                None,
+            );
+        }
+    }
+
+    async fn _replace(&mut self) -> Result<(), Error> {
+        match self.state {
+            DialogState::Unestablished(_) | DialogState::Early(_) => {
+                self.stop_reliable_provisionals();
+                let response = presets::response_from(self.request.clone(), 487.into())?;
+                self.terminate(response.clone().into());
                 self.handlers
                     .transaction
-                    .new_uac_invite(request_msg_from(request).await)
-                    .await?
+                    .reply(self.with_local_tag(response)?)
+                    .await?;
+
+                Ok(())
+            }
+            DialogState::UnAcked(_) | DialogState::Confirmed(_) => {
+                let bye = self.set_outgoing_request_defaults_for(presets::in_dialog_request(
+                    &self.handlers.config,
+                    rsip::Method::Bye,
+                ))?;
+                self.terminate(bye.clone().into());
+                self.handlers.transaction.new_uac(bye).await?;
+
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn _process_incoming_request(&mut self, request: rsip::Request) -> Result<(), Error> {
+        match request.method {
+            rsip::Method::Ack => match &self.modification {
+                Some(Modification::UnAcked { .. }) => modification::acked(self, request)?,
+                _ => {
+                    //the answer to the offer of our 2xx, when the INVITE had none
+                    negotiation::report_remote(self, &request.body);
+                    self.confirm(request);
+                    if std::mem::take(&mut self.hangup_on_ack) {
+                        dialog_sm::hang_up(self).await?;
+                    }
+                }
+            },
+            rsip::Method::Invite | rsip::Method::Update => {
+                if !matches!(self.state, DialogState::Confirmed(_)) {
+                    return Err(Error::custom(format!(
+                        "cannot process a {} while UAS dialog state is in {}",
+                        request.method, self.state
+                    )));
+                }
+
+                self.validate_incoming_request(&request)?;
+                modification::process_incoming(self, request).await?
+            }
+            rsip::Method::Refer | rsip::Method::Notify | rsip::Method::Info => {
+                if !matches!(self.state, DialogState::Confirmed(_)) {
+                    return Err(Error::custom(format!(
+                        "cannot process a {} while UAS dialog state is in {}",
+                        request.method, self.state
+                    )));
+                }
+
+                self.validate_incoming_request(&request)?;
+                match request.method {
+                    rsip::Method::Refer => refer::process_incoming_refer(self, request).await?,
+                    rsip::Method::Info => dialog_sm::process_incoming_info(self, request).await?,
+                    _ => refer::process_incoming_notify(self, request).await?,
+                }
+            }
+            rsip::Method::PRack => {
+                if !matches!(
+                    self.state,
+                    DialogState::Early(_) | DialogState::UnAcked(_) | DialogState::Confirmed(_)
+                ) {
+                    return Err(Error::custom(format!(
+                        "cannot process a PRACK while UAS dialog state is in {}",
+                        self.state
+                    )));
+                }
+
+                self.validate_incoming_request(&request)?;
+                self.process_incoming_prack(request).await?
             }
             rsip::Method::Bye => {
+                if !matches!(
+                    self.state,
+                    DialogState::Early(_) | DialogState::UnAcked(_) | DialogState::Confirmed(_)
+                ) {
+                    return Err(Error::custom(format!(
+                        "cannot process a BYE while UAS dialog state is in {}",
+                        self.state
+                    )));
+                }
+
+                self.validate_incoming_request(&request)?;
+                if matches!(self.state, DialogState::Early(_)) {
+                    self.stop_reliable_provisionals();
+                    //RFC3261 15.1.2, the pending INVITE still needs a final response
+                    let response = presets::response_from(self.request.clone(), 487.into())?;
+                    self.handlers
+                        .transaction
+                        .reply(self.with_local_tag(response)?)
+                        .await?;
+                }
+
                 self.terminate(request.clone().into());
                 self.handlers
                     .transaction
-                    .new_uac(request_msg_from(request).await)
+                    .new_uas(
+                        request.clone(),
+                        Some(presets::response_from(request, 200.into())?),
+                    )
                     .await?
             }
+            //RFC3261 9.2, the caller gave up on a call that is still ringing. Once answered
+            //the CANCEL has no effect, but it's answered anyway
+            rsip::Method::Cancel => {
+                let response = presets::response_from(request.clone(), 200.into())?;
+                self.handlers
+                    .transaction
+                    .new_uas(request.clone(), Some(self.with_local_tag(response)?))
+                    .await?;
+
+                if matches!(
+                    self.state,
+                    DialogState::Unestablished(_) | DialogState::Early(_)
+                ) {
+                    self.stop_reliable_provisionals();
+                    let response = presets::response_from(self.request.clone(), 487.into())?;
+                    self.terminate(request.into());
+                    self.handlers
+                        .transaction
+                        .reply(self.with_local_tag(response)?)
+                        .await?;
+                }
+            }
             _ => self.error(
                 format!(
                     "({}): don't know how to handle method {} inside a dialog",
@@ -128,48 +347,175 @@ impl DialogSm {
         Ok(())
     }
 
-    async fn _process_incoming_response(&mut self, msg: rsip::Response) -> Result<(), Error> {
-        match msg.status_code().kind() {
-            rsip::StatusCodeKind::Provisional => self.early(msg).await,
-            rsip::StatusCodeKind::Successful => {
-                self.confirm(msg.clone()).await?;
-                self.handlers
-                    .transport
-                    .send(
-                        request_msg_from(self.msg.ack_request_from(msg))
-                            .await
-                            .into(),
-                    )
-                    .await?;
-            }
-            rsip::StatusCodeKind::Redirection => self.error(
+    //only responses to requests that we sent inside the dialog end up here (like BYE)
+    async fn _process_incoming_response(&mut self, response: rsip::Response) -> Result<(), Error> {
+        let cseq = response.cseq_header()?.typed()?;
+        if matches!(&self.modification, Some(m) if m.is_outgoing() && m.matches(&cseq)) {
+            return modification::process_response(self, response).await;
+        }
+        if matches!(&self.transfer, Some(t @ Transfer::Outgoing(_)) if t.matches(&cseq)) {
+            refer::process_refer_response(self, response);
+            return Ok(());
+        }
+
+        if !matches!(
+            self.state,
+            DialogState::Confirmed(_) | DialogState::Terminated(_)
+        ) {
+            self.error(
                 format!(
-                    "({}): received status {}, peer wants redirection to {}",
-                    self.id,
-                    msg.status_code,
-                    msg.contact_header()
-                        .map(|h| h.to_string())
-                        .unwrap_or_else(|_| "no contact header".into()),
+                    "({}): unexpected response {} while in {}",
+                    self.id, response.status_code, self.state
                 ),
-                Some(msg.into()),
-            ),
+                Some(response.into()),
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn _process_outgoing_request(&mut self, request: rsip::Request) -> Result<(), Error> {
+        //RFC3261 15: the callee must not send a BYE before the 2xx has been acked, so it's
+        //sent once the ACK arrives, or when it never does
+        if request.method == rsip::Method::Bye && matches!(self.state, DialogState::UnAcked(_)) {
+            self.hangup_on_ack = true;
+            return Ok(());
+        }
+        if !matches!(self.state, DialogState::Confirmed(_)) {
+            return Err(Error::custom(format!(
+                "cannot process a request while UAS dialog state is in {}",
+                self.state
+            )));
+        }
+
+        //RFC3261 14.1, only one re-INVITE (or UPDATE) can be in progress
+        if modification::is_modification(&request.method) && self.modification.is_some() {
+            return modification::reject_pending(self, request);
+        }
+        if request.method == rsip::Method::Refer && self.transfer.is_some() {
+            return refer::reject_pending(self, request);
+        }
+
+        let request = self.set_outgoing_request_defaults_for(request)?;
+
+        match request.method {
+            rsip::Method::Invite | rsip::Method::Update => {
+                modification::send(self, request).await?
+            }
+            rsip::Method::Bye => {
+                self.terminate(request.clone().into());
+                self.handlers.transaction.new_uac(request).await?
+            }
+            rsip::Method::Info => self.handlers.transaction.new_uac(request).await?,
+            rsip::Method::Refer => {
+                self.transfer = Some(Transfer::Outgoing(request.clone()));
+                self.handlers.transaction.new_uac(request).await?
+            }
+            rsip::Method::Notify => refer::notify_transfer(self, request).await?,
             _ => self.error(
                 format!(
-                    "({}): unknown match: {}, {}",
-                    self.id, msg.status_code, self.state,
+                    "({}): don't know how to handle method {} inside a dialog",
+                    self.id, request.method,
                 ),
-                Some(msg.into()),
+                Some(request.into()),
             ),
-        };
+        }
 
         Ok(())
     }
 
-    async fn _process_outgoing_request(&mut self, _: rsip::Request) -> Result<(), Error> {
+    async fn _process_outgoing_response(&mut self, response: rsip::Response) -> Result<(), Error> {
+        let cseq = response.cseq_header()?.typed()?;
+        if matches!(&self.modification, Some(m) if m.is_incoming() && m.matches(&cseq)) {
+            return modification::process_answer(self, response).await;
+        }
+        if matches!(&self.transfer, Some(t @ Transfer::Incoming(_)) if t.matches(&cseq)) {
+            return refer::process_transfer_answer(self, response).await;
+        }
+
+        if cseq.seq != self.invite_seqn()? {
+            return Err(Error::custom(format!(
+                "({}): only responses to the initial INVITE are supported",
+                self.id
+            )));
+        }
+        //the caller cancelled or hung up while the application was still deciding
+        if matches!(self.state, DialogState::Terminated(_)) {
+            common::log::warn!(
+                "({}): the call is over, dropping {}",
+                self.id,
+                response.status_code
+            );
+            return Ok(());
+        }
+
+        let response = self.set_outgoing_response_defaults_for(response)?;
+        let response = match response.status_code.kind() {
+            rsip::StatusCodeKind::Successful => self.start_session_timer(response),
+            _ => response,
+        };
+        //the answer to the offer of the INVITE, or our offer when it had none
+        if response.status_code.kind() <= rsip::StatusCodeKind::Successful {
+            negotiation::report_local(self, &response.body);
+        }
+        if response.status_code.kind() != rsip::StatusCodeKind::Provisional {
+            self.stop_reliable_provisionals();
+        }
+
+        match response.status_code.kind() {
+            rsip::StatusCodeKind::Provisional if self.is_reliable(&response) => {
+                return self.send_reliable_provisional(response).await
+            }
+            rsip::StatusCodeKind::Provisional => {
+                if response.status_code != rsip::StatusCode::Trying {
+                    self.early(response.clone());
+                }
+            }
+            rsip::StatusCodeKind::Successful => self.unack(response.clone()),
+            _ => self.terminate(response.clone().into()),
+        };
+
+        self.handlers.transaction.reply(response).await?;
+
         Ok(())
     }
 
-    async fn _process_outgoing_response(&mut self, _: rsip::Response) -> Result<(), Error> {
+    async fn next_step(&mut self) -> Result<(), Error> {
+        if matches!(&self.modification, Some(m) if m.should_retry()) {
+            return modification::retry(self).await;
+        }
+        if matches!(&self.modification, Some(m) if m.is_unacked()) {
+            return modification::retransmit(self).await;
+        }
+
+        if let Some(unpracked) = self.unpracked.clone() {
+            return self.retransmit_unpracked(unpracked).await;
+        }
+
+        if matches!(self.state, DialogState::Confirmed(_)) {
+            return session_timer::check(self).await;
+        }
+
+        let un_acked = match &self.state {
+            DialogState::UnAcked(un_acked) => un_acked.clone(),
+            _ => return Ok(()),
+        };
+
+        match (un_acked.has_timedout(), un_acked.should_retransmit()) {
+            (true, _) => {
+                //RFC3261 13.3.1.4: the ACK never arrived, the session has to be closed
+                dialog_sm::hang_up(self).await?;
+            }
+            (false, true) => {
+                self.handlers
+                    .transport
+                    .send(un_acked.response.clone().into())
+                    .await?;
+                self.state = DialogState::UnAcked(un_acked.retransmit());
+            }
+            (false, false) => (),
+        };
+
         Ok(())
     }
 
@@ -177,59 +523,168 @@ impl DialogSm {
         self.error(reason, Some(msg));
     }
 
-    async fn early(&mut self, response: rsip::Response) {
-        if !matches!(self.state, DialogState::Unconfirmed(_)) {
-            return self.wrong_transition("early", response.into());
+    //RFC3262 3, only when the peer supports 100rel and either requires it, the application
+    //asked for it or the provisional carries early media
+    fn is_reliable(&self, response: &rsip::Response) -> bool {
+        response.status_code != rsip::StatusCode::Trying
+            && reliable::supports_100rel(&self.request.headers)
+            && (reliable::requires_100rel(&self.request.headers)
+                || reliable::requires_100rel(&response.headers)
+                || !response.body.is_empty())
+    }
+
+    async fn send_reliable_provisional(&mut self, response: rsip::Response) -> Result<(), Error> {
+        //the next reliable provisional waits for the PRACK of the previous one
+        if self.unpracked.is_some() {
+            self.queued_provisionals.push_back(response);
+            return Ok(());
         }
 
-        self.state = DialogState::Early(Early {
-            response,
-            entered_at: Instant::now(),
-        });
+        let rseq = self
+            .local_rseq
+            .map(|rseq| rseq + 1)
+            .unwrap_or_else(reliable::initial_rseq);
+        self.local_rseq = Some(rseq);
+
+        let mut response = response;
+        if !reliable::requires_100rel(&response.headers) {
+            response.headers.push(reliable::require_header());
+        }
+        response.headers.push(reliable::rseq_header(rseq));
+
+        self.early(response.clone());
+        self.unpracked = Some(Unpracked::new(response.clone(), rseq, self.timers));
+        self.handlers.transaction.reply(response).await?;
+
+        Ok(())
     }
 
-    async fn confirm(&mut self, response: rsip::Response) -> Result<(), Error> {
-        if !matches!(
-            self.state,
-            DialogState::Early(_) | DialogState::Unconfirmed(_)
+    async fn process_incoming_prack(&mut self, request: rsip::Request) -> Result<(), Error> {
+        let invite_seqn = self.invite_seqn()?;
+        let matched = match (
+            &self.unpracked,
+            reliable::RAck::from_headers(&request.headers),
         ) {
-            return Ok(self.wrong_transition("confirm", response.into()));
+            (Some(unpracked), Some(rack)) => unpracked.matches(&rack) && rack.cseq == invite_seqn,
+            _ => false,
+        };
+
+        if !matched {
+            //RFC3262 3, a PRACK that matches no unacknowledged provisional
+            let response = presets::response_from(request.clone(), 481.into())?;
+            self.handlers
+                .transaction
+                .new_uas(request, Some(response))
+                .await?;
+
+            return Ok(());
         }
 
-        self.remote_tag = Some(
-            response
-                .to_header()?
-                .typed()?
-                .tag()
-                .ok_or_else(|| Error::from("missing from tag"))?
-                .clone(),
+        self.unpracked = None;
+        negotiation::report_remote(self, &request.body);
+        let response = presets::response_from(request.clone(), 200.into())?;
+        self.handlers
+            .transaction
+            .new_uas(request.clone(), Some(response))
+            .await?;
+        self.emit(CallEvent::Pracked(request));
+
+        match self.queued_provisionals.pop_front() {
+            Some(next) => self.send_reliable_provisional(next).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn retransmit_unpracked(&mut self, unpracked: Unpracked) -> Result<(), Error> {
+        match (unpracked.has_timedout(), unpracked.should_retransmit()) {
+            (true, _) => {
+                //RFC3262 3, the PRACK never arrived, the INVITE is rejected with a 5xx
+                self.stop_reliable_provisionals();
+                let response = presets::response_from(self.request.clone(), 500.into())?;
+                let response = self.with_local_tag(response)?;
+                self.terminate(response.clone().into());
+                self.handlers.transaction.reply(response).await?;
+            }
+            (false, true) => {
+                self.handlers
+                    .transport
+                    .send(unpracked.response.clone().into())
+                    .await?;
+                self.unpracked = Some(unpracked.retransmit());
+            }
+            (false, false) => (),
+        };
+
+        Ok(())
+    }
+
+    //RFC4028 9, the 2xx carries the Session-Expires we agree on, even if the INVITE had none
+    fn start_session_timer(&mut self, mut response: rsip::Response) -> rsip::Response {
+        let session_expires = session_timer::answer(&self.request.headers);
+        if session_timer::supports_timer(&self.request.headers) {
+            response.headers.push(session_timer::require_header());
+        }
+        response.headers.push(session_expires.clone().into());
+
+        self.session_timer = Some(
+            SessionTimer::new(&session_expires, false)
+                .with_peer_allow(&self.request.headers)
+                .with_local_sdp(response.body.clone()),
         );
 
-        self.remote_seqn = Some(response.cseq_header()?.typed()?.seq);
+        response
+    }
 
-        self.remote_target = Some(response.contact_header()?.typed()?.uri);
+    fn stop_reliable_provisionals(&mut self) {
+        self.unpracked = None;
+        self.queued_provisionals.clear();
+    }
 
-        self.state = DialogState::Confirmed(Confirmed {
+    fn early(&mut self, response: rsip::Response) {
+        if !matches!(
+            self.state,
+            DialogState::Unestablished(_) | DialogState::Early(_)
+        ) {
+            return self.wrong_transition("early", response.into());
+        }
+
+        self.state = DialogState::Early(Early {
             response,
             entered_at: Instant::now(),
         });
-
-        Ok(())
     }
 
-    //TODO: I suspect msg here should be an option
-    fn terminate(&mut self, msg: rsip::SipMessage) {
-        if matches!(self.state, DialogState::Errored(_)) {
-            return self.wrong_transition("terminate", msg);
+    fn unack(&mut self, response: rsip::Response) {
+        if !matches!(
+            self.state,
+            DialogState::Unestablished(_) | DialogState::Early(_)
+        ) {
+            return self.wrong_transition("unacked", response.into());
         }
 
-        self.state = DialogState::Terminated(Terminated {
-            entered_at: Instant::now(),
-        });
+        self.state = DialogState::UnAcked(UnAcked::new(response, self.timers));
+    }
+
+    fn confirm(&mut self, request: rsip::Request) {
+        match &self.state {
+            DialogState::UnAcked(un_acked) => {
+                self.state = DialogState::Confirmed(Confirmed {
+                    response: un_acked.response.clone(),
+                    entered_at: Instant::now(),
+                });
+            }
+            //retransmitted ACK, due to our own 2xx retransmissions
+            DialogState::Confirmed(_) => (),
+            _ => self.wrong_transition("confirm", request.into()),
+        }
     }
 
     fn error(&mut self, error: String, sip_message: Option<rsip::SipMessage>) {
         common::log::error!("Dialog {} errored: {}", self.id, error);
+        self.emit(CallEvent::Failed {
+            status_code: None,
+            reason: error.clone(),
+        });
         self.state = DialogState::Errored(Errored {
             entered_at: Instant::now(),
             sip_message,
@@ -248,38 +703,36 @@ impl DialogSm {
     }
 
     fn increased_seqn(&mut self) -> u32 {
-        self.local_seqn = self.local_seqn + 1;
-        self.local_seqn
+        let seqn = self.local_seqn.map(|seqn| seqn + 1).unwrap_or(1);
+        self.local_seqn = Some(seqn);
+        seqn
     }
 
-    fn set_dialog_defaults_for(
-        &mut self,
-        mut request: rsip::Request,
-    ) -> Result<rsip::Request, Error> {
-        request.from_header_mut()?.mut_tag(self.local_tag.clone())?;
-        request.from_header_mut()?.mut_uri(self.local_uri.clone())?;
+    fn invite_seqn(&self) -> Result<u32, Error> {
+        Ok(self.request.cseq_header()?.seq()?)
+    }
 
-        request
-            .to_header_mut()?
-            .mut_tag(self.remote_tag.clone().expect("remote tag"))?;
-        request.to_header_mut()?.mut_uri(self.remote_uri.clone())?;
+    fn with_local_tag(&self, mut response: rsip::Response) -> Result<rsip::Response, Error> {
+        response.to_header_mut()?.mut_tag(self.local_tag.clone())?;
 
-        request.call_id_header_mut()?.replace(self.call_id.clone());
-        if !matches!(request.method, rsip::Method::Ack | rsip::Method::Cancel) {
-            request.cseq_header_mut()?.mut_seq(self.increased_seqn())?;
-        }
-        request.uri = self.remote_target.clone().expect("remote target");
-        if !matches!(request.method, rsip::Method::Invite) {
-            request
-                .contact_header_mut()?
-                .replace(self.contact_header.clone());
+        Ok(response)
+    }
+
+    fn validate_incoming_request(&mut self, request: &rsip::Request) -> Result<(), Error> {
+        let req_seqn = request.cseq_header()?.seq()?;
+        if self.remote_seqn > req_seqn {
+            return Err(Error::from(format!(
+                "request remote seqn is lower than {}",
+                self.remote_seqn
+            )));
         }
+        self.remote_seqn = req_seqn;
 
-        Ok(request)
+        Ok(())
     }
 
-    pub async fn process_incoming_request(&mut self, msg: rsip::Request) {
-        if let Err(err) = self._process_incoming_request(msg).await {
+    pub async fn process_incoming_request(&mut self, request: rsip::Request) {
+        if let Err(err) = self._process_incoming_request(request).await {
             self.error(
                 format!(
                     "Dialog {} failed to process incoming request: {}",
@@ -290,8 +743,8 @@ impl DialogSm {
         }
     }
 
-    pub async fn process_incoming_response(&mut self, msg: rsip::Response) {
-        if let Err(err) = self._process_incoming_response(msg).await {
+    pub async fn process_incoming_response(&mut self, response: rsip::Response) {
+        if let Err(err) = self._process_incoming_response(response).await {
             self.error(
                 format!(
                     "Dialog {} failed to process incoming response: {}",
@@ -302,8 +755,8 @@ impl DialogSm {
         }
     }
 
-    pub async fn process_outgoing_request(&mut self, msg: rsip::Request) {
-        if let Err(err) = self._process_outgoing_request(msg).await {
+    pub async fn process_outgoing_request(&mut self, request: rsip::Request) {
+        if let Err(err) = self._process_outgoing_request(request).await {
             self.error(
                 format!(
                     "Dialog {} failed to process outgoing request: {}",
@@ -314,8 +767,8 @@ impl DialogSm {
         }
     }
 
-    pub async fn process_outgoing_response(&mut self, msg: rsip::Response) {
-        if let Err(err) = self._process_outgoing_response(msg).await {
+    pub async fn process_outgoing_response(&mut self, response: rsip::Response) {
+        if let Err(err) = self._process_outgoing_response(response).await {
             self.error(
                 format!(
                     "Dialog {} failed to process outgoing response: {}",
@@ -325,6 +778,113 @@ impl DialogSm {
             );
         }
     }
+
+    pub async fn next(&mut self) {
+        if let Err(err) = self.next_step().await {
+            self.error(
+                format!("Dialog {} failed to move forward: {}", self.id, err),
+                None,
+            );
+        }
+    }
+}
+
+impl Dialog for DialogSm {
+    fn id(&self) -> &DialogId {
+        &self.id
+    }
+
+    fn handlers(&self) -> &Handlers {
+        &self.handlers
+    }
+
+    fn timers(&self) -> Timers {
+        self.timers
+    }
+
+    //the peer sent the initial INVITE
+    fn owns_call_id(&self) -> bool {
+        false
+    }
+
+    fn modification(&mut self) -> &mut Option<Modification> {
+        &mut self.modification
+    }
+
+    fn session_timer(&mut self) -> &mut Option<SessionTimer> {
+        &mut self.session_timer
+    }
+
+    fn offer_answer(&mut self) -> &mut OfferAnswer {
+        &mut self.offer_answer
+    }
+
+    fn transfer(&mut self) -> &mut Option<Transfer> {
+        &mut self.transfer
+    }
+
+    fn set_remote_target(&mut self, remote_target: rsip::Uri) {
+        self.remote_target = remote_target;
+    }
+
+    fn emit(&self, event: CallEvent) {
+        if let Some(events) = &self.events {
+            //the application might have dropped its session handle, that's fine
+            let _ = events.send(event);
+        }
+    }
+
+    //TODO: I suspect msg here should be an option
+    fn terminate(&mut self, msg: rsip::SipMessage) {
+        if matches!(self.state, DialogState::Errored(_)) {
+            return self.wrong_transition("terminate", msg);
+        }
+
+        self.emit(CallEvent::Terminated);
+        self.state = DialogState::Terminated(Terminated {
+            entered_at: Instant::now(),
+        });
+    }
+
+    fn set_outgoing_request_defaults_for(
+        &mut self,
+        mut request: rsip::Request,
+    ) -> Result<rsip::Request, Error> {
+        request.from_header_mut()?.mut_tag(self.local_tag.clone())?;
+        request.from_header_mut()?.mut_uri(self.local_uri.clone())?;
+
+        request.to_header_mut()?.mut_tag(self.remote_tag.clone())?;
+        request.to_header_mut()?.mut_uri(self.remote_uri.clone())?;
+
+        request.call_id_header_mut()?.replace(self.call_id.clone());
+        if !matches!(request.method, rsip::Method::Ack | rsip::Method::Cancel) {
+            request.cseq_header_mut()?.mut_seq(self.increased_seqn())?;
+        }
+        route_set::apply(&mut request, &self.route_set, self.remote_target.clone());
+        if !matches!(request.method, rsip::Method::Invite) {
+            request
+                .contact_header_mut()?
+                .replace(self.contact_header.clone());
+        }
+
+        Ok(request)
+    }
+
+    fn set_outgoing_response_defaults_for(
+        &mut self,
+        response: rsip::Response,
+    ) -> Result<rsip::Response, Error> {
+        let mut response = self.with_local_tag(response)?;
+
+        //RFC3261 12.1.1, dialog creating responses must carry our Contact
+        if response.status_code.kind() <= rsip::StatusCodeKind::Successful
+            && response.contact_header().is_err()
+        {
+            response.headers.push(self.contact_header.clone().into());
+        }
+
+        Ok(response)
+    }
 }
 
 pub fn is_secure(request: &rsip::Request) -> Result<bool, Error> {
@@ -351,41 +911,12 @@ pub fn session_type(request: &rsip::Request) -> Result<SessionType, Error> {
     }
 }
 
-async fn request_msg_from(request: rsip::Request) -> RequestMsg {
-    let target = resolve_address_for(request.uri.clone()).await;
-
-    RequestMsg {
-        sip_request: request,
-        peer: target.socket_addr(),
-        transport: rsip::Transport::Udp,
-    }
-}
-
-async fn resolve_address_for(uri: rsip::Uri) -> common::rsip_dns::Target {
-    use common::rsip_dns::{self, trust_dns_resolver::TokioAsyncResolver, ResolvableExt};
-
-    let context = rsip_dns::Context::initialize_from(
-        uri,
-        rsip_dns::AsyncTrustDnsClient::new(
-            TokioAsyncResolver::tokio(Default::default(), Default::default()).unwrap(),
-        ),
-        rsip_dns::SupportedTransports::only(vec![rsip::Transport::Udp]),
-    )
-    .unwrap();
-
-    let mut lookup = rsip_dns::Lookup::from(context);
-
-    lookup
-        .resolve_next()
-        .await
-        .expect("next Target in dns lookup")
-}
-
 impl std::fmt::Display for DialogState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Unconfirmed(_) => write!(f, "DialogState::Unconfirmed"),
+            Self::Unestablished(_) => write!(f, "DialogState::Unestablished"),
             Self::Early(_) => write!(f, "DialogState::Early"),
+            Self::UnAcked(_) => write!(f, "DialogState::UnAcked"),
             Self::Confirmed(_) => write!(f, "DialogState::Confirmed"),
             Self::Terminated(_) => write!(f, "DialogState::Terminated"),
             Self::Errored(_) => write!(f, "DialogState::Errored"),
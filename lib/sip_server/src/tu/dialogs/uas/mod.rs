@@ -1,7 +1,7 @@
-pub mod states;
-//pub mod validations;
 pub mod dialog_sm;
-//pub mod multi_dialog;
+pub mod multi_dialog;
+pub mod states;
+pub mod validations;
 
 pub use dialog_sm::DialogSm;
-//pub use multi_dialog::MultiDialog;
+pub use multi_dialog::MultiDialog;
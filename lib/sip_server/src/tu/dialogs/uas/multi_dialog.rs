@@ -1,43 +1,170 @@
-use crate::Error;
-use common::{rsip, tokio::sync::Mutex};
-use models::Handlers;
+use crate::{
+    transaction::sm::Timers,
+    tu::{
+        calls::CallEvent,
+        dialogs::replaces::{Replaces, Target},
+    },
+    Error,
+};
+use common::{
+    rsip,
+    tokio::sync::{mpsc::UnboundedSender, Mutex},
+};
+use models::{rsip_ext::*, tu::DialogId, Handlers};
+use std::time::Duration;
 
+//TODO: a UAS creates a single dialog per INVITE, unless we fork the responses ourselves
 #[derive(Debug)]
 pub struct MultiDialog {
+    pub id: DialogId,
     dialogs: Mutex<Vec<super::DialogSm>>,
 }
 
 impl MultiDialog {
-    pub async fn new(handlers: Handlers, msg: rsip::Request) -> Result<Self, Error> {
+    pub async fn new(
+        handlers: Handlers,
+        msg: rsip::Request,
+        events: Option<UnboundedSender<CallEvent>>,
+        timers: Timers,
+    ) -> Result<Self, Error> {
+        let dialog = super::DialogSm::new(handlers, msg).await?;
+        let dialog = dialog.with_timers(timers);
+        let dialog = match events {
+            Some(events) => dialog.with_events(events),
+            None => dialog,
+        };
+
         Ok(Self {
-            dialogs: Mutex::new(vec![super::DialogSm::new(handlers, msg).await?]),
+            id: dialog.id.prefixed(),
+            dialogs: Mutex::new(vec![dialog]),
         })
     }
 
-    pub async fn process_response(&self, msg: rsip::Response) -> Result<(), Error> {
-        /*
+    pub async fn is_active(&self) -> bool {
+        self.dialogs.lock().await.iter().any(|d| d.is_active())
+    }
+
+    //all of its dialogs ended, at least that long ago
+    pub async fn has_ended_for(&self, linger: Duration) -> bool {
+        self.dialogs
+            .lock()
+            .await
+            .iter()
+            .all(|d| matches!(d.ended_at(), Some(ended_at) if ended_at.elapsed() >= linger))
+    }
+
+    pub async fn process_incoming_request(&self, msg: rsip::Request) -> Result<(), Error> {
+        let dialog_id = msg.dialog_id()?;
+
+        //TODO: decouple the find part, will be needed all over the place
+        let mut dialogs = self.dialogs.lock().await;
+
+        let dialog = match dialogs.iter_mut().find(|d| d.id == dialog_id) {
+            Some(dialog) => dialog,
+            None => dialogs
+                .first_mut()
+                .expect("No dialog inside MultiDialog Vec ??"),
+        };
+
+        dialog.process_incoming_request(msg).await;
+
+        Ok(())
+    }
+
+    pub async fn process_incoming_response(&self, msg: rsip::Response) -> Result<(), Error> {
+        let dialog_id = msg.uas_dialog_id()?;
+
+        let mut dialogs = self.dialogs.lock().await;
+
+        let dialog = match dialogs.iter_mut().find(|d| d.id == dialog_id) {
+            Some(dialog) => dialog,
+            None => dialogs
+                .first_mut()
+                .expect("No dialog inside MultiDialog Vec ??"),
+        };
+
+        dialog.process_incoming_response(msg).await;
+
+        Ok(())
+    }
+
+    pub async fn process_outgoing_request(&self, msg: rsip::Request) -> Result<(), Error> {
+        //the application builds it from the INVITE, so like for responses the To tag could
+        //be missing, the dialog fills in the tags itself
         let dialog_id = msg.dialog_id()?;
-        if dialog_id.is_unconfirmed() {
-            //TODO: this is not compatible with rfc2543
-            return Err(Error::custom(
-                "SIP Response in a uas dialog without a tag in TO tag",
-            ));
-        }
 
         let mut dialogs = self.dialogs.lock().await;
 
-        let dialog = match dialogs.iter_mut().find(|d| *d == dialog_id) {
+        let dialog = match dialogs.iter_mut().find(|d| d.id == dialog_id) {
             Some(dialog) => dialog,
             None => dialogs
                 .first_mut()
                 .expect("No dialog inside MultiDialog Vec ??"),
         };
 
-        dialog.process_response(msg.sip_response).await;
-        */
+        dialog.process_outgoing_request(msg).await;
 
         Ok(())
     }
 
-    pub async fn transport_error(&mut self, reason: String, msg: Option<rsip::SipMessage>) {}
+    pub async fn process_outgoing_response(&self, msg: rsip::Response) -> Result<(), Error> {
+        //the application might not know our local tag yet, so To tag could be missing
+        let dialog_id = msg.dialog_id()?;
+
+        let mut dialogs = self.dialogs.lock().await;
+
+        let dialog = match dialogs.iter_mut().find(|d| d.id == dialog_id) {
+            Some(dialog) => dialog,
+            None => dialogs
+                .first_mut()
+                .expect("No dialog inside MultiDialog Vec ??"),
+        };
+
+        dialog.process_outgoing_response(msg).await;
+
+        Ok(())
+    }
+
+    pub async fn replaced_by(&self, replaces: &Replaces) -> Option<Target> {
+        self.dialogs
+            .lock()
+            .await
+            .iter()
+            .find_map(|d| d.replaced_by(replaces))
+    }
+
+    pub async fn replace(&self, replaces: &Replaces) {
+        let mut dialogs = self.dialogs.lock().await;
+        if let Some(dialog) = dialogs
+            .iter_mut()
+            .find(|d| d.replaced_by(replaces).is_some())
+        {
+            dialog.replace().await;
+        }
+    }
+
+    pub async fn next(&self) {
+        for dialog in self.dialogs.lock().await.iter_mut() {
+            dialog.next().await;
+        }
+    }
+
+    pub async fn transport_error(&self, reason: String, msg: rsip::SipMessage) {
+        let dialog_id = match &msg {
+            rsip::SipMessage::Request(_) => msg.uas_dialog_id(),
+            rsip::SipMessage::Response(_) => msg.dialog_id(),
+        }
+        .expect("missing dialog_id to report error");
+
+        let mut dialogs = self.dialogs.lock().await;
+
+        let dialog = match dialogs.iter_mut().find(|d| d.id == dialog_id) {
+            Some(dialog) => dialog,
+            None => dialogs
+                .first_mut()
+                .expect("No dialog inside MultiDialog Vec ??"),
+        };
+
+        dialog.transport_error(reason, msg).await;
+    }
 }
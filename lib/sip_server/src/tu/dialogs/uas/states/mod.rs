@@ -1,12 +1,14 @@
 mod confirmed;
-//TODO: rename that to unconfirmed, and rename uac unconfirmed to something like unstablished?
-mod un_acked;
 mod early;
 mod errored;
 mod terminated;
+//TODO: rename that to unconfirmed, and rename uac unconfirmed to something like unstablished?
+mod un_acked;
+mod unestablished;
 
 pub use confirmed::Confirmed;
 pub use early::Early;
 pub use errored::Errored;
 pub use terminated::Terminated;
 pub use un_acked::UnAcked;
+pub use unestablished::Unestablished;
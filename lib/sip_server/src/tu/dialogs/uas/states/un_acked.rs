@@ -1,7 +1,53 @@
 use common::{rsip, tokio::time::Instant};
+use std::time::Duration;
 
-#[derive(Debug)]
+use crate::transaction::sm::Timers;
+
+//RFC6026: the 2xx is retransmitted by the TU (and not the transaction) until the ACK arrives
+#[derive(Debug, Clone)]
 pub struct UnAcked {
     pub entered_at: Instant,
     pub response: rsip::Response,
+    pub retransmissions_count: u8,
+    pub last_retransmission_at: Instant,
+    pub timers: Timers,
+}
+
+impl UnAcked {
+    pub fn new(response: rsip::Response, timers: Timers) -> Self {
+        Self {
+            entered_at: Instant::now(),
+            response,
+            retransmissions_count: 0,
+            last_retransmission_at: Instant::now(),
+            timers,
+        }
+    }
+
+    pub fn next_retrasmission(&self) -> Duration {
+        use std::iter;
+
+        std::cmp::min(
+            iter::repeat(self.timers.t1)
+                .take(2_i32.pow(self.retransmissions_count.into()) as usize)
+                .fold(Duration::from_secs(0), |acc, x| acc + x),
+            self.timers.t2,
+        )
+    }
+
+    pub fn has_timedout(&self) -> bool {
+        self.entered_at.elapsed() >= self.timers.h()
+    }
+
+    pub fn should_retransmit(&self) -> bool {
+        self.last_retransmission_at.elapsed() > self.next_retrasmission()
+    }
+
+    pub fn retransmit(self) -> Self {
+        Self {
+            retransmissions_count: self.retransmissions_count + 1,
+            last_retransmission_at: Instant::now(),
+            ..self
+        }
+    }
 }
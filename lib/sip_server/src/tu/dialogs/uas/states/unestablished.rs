@@ -0,0 +1,14 @@
+use common::tokio::time::Instant;
+
+#[derive(Debug)]
+pub struct Unestablished {
+    pub entered_at: Instant,
+}
+
+impl Default for Unestablished {
+    fn default() -> Self {
+        Self {
+            entered_at: Instant::now(),
+        }
+    }
+}
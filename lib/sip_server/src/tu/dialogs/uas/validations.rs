@@ -0,0 +1,24 @@
+use crate::{
+    tu::dialogs::uac::validations::{contact_header_is_secure, contact_header_is_unique},
+    Error,
+};
+use common::rsip;
+
+pub fn run(request: &rsip::Request) -> Result<(), Error> {
+    if request.method != rsip::Method::Invite {
+        return Err(Error::custom(format!(
+            "can't create a UAS dialog from a {} request",
+            request.method
+        )));
+    }
+
+    if !contact_header_is_unique(request)? {
+        return Err(Error::from("more than one or zero Contact headers found"));
+    }
+
+    if !contact_header_is_secure(request)? {
+        return Err(Error::from("Contact header not secure"));
+    }
+
+    Ok(())
+}
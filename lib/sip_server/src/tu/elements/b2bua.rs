@@ -0,0 +1,293 @@
+use crate::{
+    tu::calls::{CallEvent, CallSession, Dialer, IncomingCall},
+    CallHandler, Error,
+};
+use common::{
+    async_trait::async_trait,
+    rsip::{self, headers::UntypedHeader, prelude::*},
+    tokio,
+};
+use std::{fmt::Debug, sync::Arc};
+
+//the two legs of a bridged call, incoming is the one of the caller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Leg {
+    Incoming,
+    Outgoing,
+}
+
+//what a service changes in the call while it's relayed from one leg to the other
+#[async_trait]
+pub trait B2buaHooks: Send + Sync + Debug + 'static {
+    //target of the outgoing leg, None rejects the call with 404
+    async fn route(&self, request: &rsip::Request) -> Result<Option<rsip::Uri>, Error> {
+        Ok(Some(request.to_header()?.uri()?))
+    }
+
+    //the INVITE of the outgoing leg, like the From and To for number translation
+    fn rewrite_request(&self, _incoming: &rsip::Request, _outgoing: &mut rsip::Request) {}
+
+    //SDP that arrived on the given leg before it's sent on the other one, like for media
+    //anchoring
+    fn rewrite_sdp(&self, _from: Leg, sdp: Vec<u8>) -> Vec<u8> {
+        sdp
+    }
+}
+
+//how the outgoing leg ended up before being answered
+enum Early {
+    Answered(Vec<u8>),
+    Failed(rsip::StatusCode),
+    //the caller gave up, the incoming leg is already over
+    Cancelled,
+}
+
+//relays everything as it is
+#[derive(Debug, Default)]
+pub struct Passthrough;
+
+impl B2buaHooks for Passthrough {}
+
+//terminates the incoming call and originates a new one to the target of the hooks, the
+//two dialogs are kept in sync until one of them ends
+#[derive(Debug)]
+pub struct B2bua<H: B2buaHooks = Passthrough> {
+    hooks: Arc<H>,
+}
+
+#[async_trait]
+impl<H: B2buaHooks> CallHandler for B2bua<H> {
+    async fn incoming_call(&self, call: IncomingCall) -> Result<(), Error> {
+        self.bridge(call).await
+    }
+}
+
+impl<H: B2buaHooks> B2bua<H> {
+    pub fn new(hooks: H) -> Self {
+        Self {
+            hooks: Arc::new(hooks),
+        }
+    }
+
+    pub async fn bridge(&self, mut call: IncomingCall) -> Result<(), Error> {
+        let dialer = match call.dialer() {
+            Some(dialer) => dialer.clone(),
+            None => {
+                call.reject(500).await?;
+                return Err(Error::from(
+                    "incoming call has no dialer for the outgoing leg",
+                ));
+            }
+        };
+        let target = match self.hooks.route(&call.request).await? {
+            Some(target) => target,
+            None => return call.reject(404).await,
+        };
+
+        let outgoing = self.outgoing_request(&dialer, &call.request, target);
+        let mut outgoing = match dialer.call_with(outgoing).await {
+            Ok(outgoing) => outgoing,
+            Err(err) => {
+                call.reject(500).await?;
+                return Err(err);
+            }
+        };
+
+        let incoming = match self.early(&mut call, &mut outgoing).await {
+            Ok(Early::Answered(sdp_answer)) => call.accept(sdp_answer).await,
+            Ok(Early::Failed(status_code)) => return call.reject(status_code).await,
+            Ok(Early::Cancelled) => return Ok(()),
+            Err(err) => Err(err),
+        };
+        let mut incoming = match incoming {
+            Ok(incoming) => incoming,
+            Err(err) => {
+                let _ = outgoing.hangup().await;
+                return Err(err);
+            }
+        };
+
+        if let Err(err) = self.confirmed(&mut incoming, &mut outgoing).await {
+            let _ = incoming.hangup().await;
+            let _ = outgoing.hangup().await;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    //caller id is kept, the rest of the INVITE is ours
+    fn outgoing_request(
+        &self,
+        dialer: &Dialer,
+        incoming: &rsip::Request,
+        target: rsip::Uri,
+    ) -> rsip::Request {
+        let sdp_offer = self.hooks.rewrite_sdp(Leg::Incoming, incoming.body.clone());
+        let mut outgoing = dialer.invite_request(target, sdp_offer);
+        if let Ok(from) = incoming.from_header().and_then(|from| from.typed()) {
+            outgoing.headers.unique_push(
+                rsip::typed::From {
+                    params: vec![],
+                    ..from
+                }
+                .with_tag(Default::default())
+                .into(),
+            );
+        }
+        self.hooks.rewrite_request(incoming, &mut outgoing);
+
+        outgoing
+    }
+
+    //relays the progress of the outgoing leg until it's answered or fails
+    async fn early(
+        &self,
+        call: &mut IncomingCall,
+        outgoing: &mut CallSession,
+    ) -> Result<Early, Error> {
+        loop {
+            tokio::select! {
+                event = call.next_event() => match event {
+                    //the caller gave up
+                    Some(event) if event.is_final() => {
+                        outgoing.hangup().await?;
+                        return Ok(Early::Cancelled);
+                    }
+                    None => {
+                        outgoing.hangup().await?;
+                        return Ok(Early::Cancelled);
+                    }
+                    Some(_) => (),
+                },
+                event = outgoing.next_event() => match event {
+                    Some(CallEvent::Ringing(response)) => {
+                        call.provisional(response.status_code, None).await?
+                    }
+                    Some(CallEvent::EarlyMedia(response)) => {
+                        let sdp = self.hooks.rewrite_sdp(Leg::Outgoing, response.body);
+                        call.provisional(response.status_code, Some(sdp)).await?
+                    }
+                    Some(CallEvent::Answered(response)) => {
+                        let sdp = self.hooks.rewrite_sdp(Leg::Outgoing, response.body);
+                        return Ok(Early::Answered(sdp));
+                    }
+                    Some(CallEvent::Failed { status_code, .. }) => {
+                        return Ok(Early::Failed(failure_status_code(status_code)));
+                    }
+                    Some(CallEvent::Terminated) | None => return Ok(Early::Failed(480.into())),
+                    Some(_) => (),
+                },
+            }
+        }
+    }
+
+    //relays BYE, session modifications and INFO between the legs
+    async fn confirmed(
+        &self,
+        incoming: &mut CallSession,
+        outgoing: &mut CallSession,
+    ) -> Result<(), Error> {
+        //a modification relayed to the other leg, waiting for its outcome
+        let mut pending: Option<(Leg, rsip::Request)> = None;
+
+        loop {
+            let (leg, event) = tokio::select! {
+                event = incoming.next_event() => (Leg::Incoming, event),
+                event = outgoing.next_event() => (Leg::Outgoing, event),
+            };
+            let (from, to) = match leg {
+                Leg::Incoming => (&*incoming, &*outgoing),
+                Leg::Outgoing => (&*outgoing, &*incoming),
+            };
+
+            match event {
+                Some(event) if event.is_final() => {
+                    to.hangup().await?;
+                    return Ok(());
+                }
+                None => {
+                    to.hangup().await?;
+                    return Ok(());
+                }
+                Some(CallEvent::ModificationRequested(request)) => {
+                    //RFC3261 14.2, the other leg is busy with a modification of the first one
+                    if pending.is_some() {
+                        from.reject_modification(&request, 491).await?;
+                        continue;
+                    }
+
+                    let sdp = self.hooks.rewrite_sdp(leg, request.body.clone());
+                    match request.method {
+                        rsip::Method::Update => to.update(sdp).await?,
+                        _ => to.reinvite(sdp).await?,
+                    }
+                    pending = Some((leg, request));
+                }
+                //the outcome of a modification is relayed back to the leg that requested it
+                Some(CallEvent::Modified(response)) => {
+                    if let Some((_, request)) = take_pending(&mut pending, leg) {
+                        let sdp = self.hooks.rewrite_sdp(leg, response.body);
+                        to.accept_modification(&request, sdp).await?;
+                    }
+                }
+                Some(CallEvent::ModificationRejected(response)) => {
+                    if let Some((_, request)) = take_pending(&mut pending, leg) {
+                        let status_code = match response.status_code.kind() {
+                            kind if kind >= rsip::StatusCodeKind::RequestFailure => {
+                                response.status_code
+                            }
+                            _ => 488.into(),
+                        };
+                        to.reject_modification(&request, status_code).await?;
+                    }
+                }
+                Some(CallEvent::InfoReceived(request)) => {
+                    let content_type = request
+                        .headers
+                        .iter()
+                        .find_map(|header| match header {
+                            rsip::Header::ContentType(content_type) => {
+                                Some(content_type.value().to_string())
+                            }
+                            _ => None,
+                        })
+                        .unwrap_or_else(|| "application/octet-stream".into());
+                    to.send_info(&content_type, request.body).await?;
+                }
+                //transfers would need both legs to take part, which we don't do
+                Some(CallEvent::TransferRequested(request)) => {
+                    from.reject_transfer(&request, 501).await?
+                }
+                Some(_) => (),
+            }
+        }
+    }
+}
+
+impl Default for B2bua<Passthrough> {
+    fn default() -> Self {
+        Self::new(Passthrough)
+    }
+}
+
+//the failure of the outgoing leg as it's relayed to the caller
+fn failure_status_code(status_code: Option<rsip::StatusCode>) -> rsip::StatusCode {
+    match status_code {
+        Some(status_code) if status_code.kind() >= rsip::StatusCodeKind::RequestFailure => {
+            status_code
+        }
+        _ => 502.into(),
+    }
+}
+
+//a modification requested on one leg is answered by the other one
+fn take_pending(
+    pending: &mut Option<(Leg, rsip::Request)>,
+    answered_by: Leg,
+) -> Option<(Leg, rsip::Request)> {
+    match pending {
+        Some((requested_by, _)) if *requested_by != answered_by => pending.take(),
+        _ => None,
+    }
+}
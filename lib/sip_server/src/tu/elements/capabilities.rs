@@ -2,6 +2,7 @@ pub use crate::{Error, ReqProcessor};
 use common::{
     async_trait::async_trait,
     rsip::{self, prelude::*},
+    Config,
 };
 use models::Handlers;
 
@@ -19,7 +20,7 @@ impl Capabilities {
 #[async_trait]
 impl ReqProcessor for Capabilities {
     async fn process_incoming_request(&self, msg: rsip::Request) -> Result<(), Error> {
-        apply_default_checks(&msg)?;
+        apply_default_checks(&self.handlers.config, &msg)?;
 
         let response = create_busy_here_from(msg.clone())?;
 
@@ -27,14 +28,14 @@ impl ReqProcessor for Capabilities {
     }
 }
 
-fn apply_default_checks(request: &rsip::Request) -> Result<(), Error> {
-    has_correct_request_uri(&request.uri)?;
+fn apply_default_checks(config: &Config, request: &rsip::Request) -> Result<(), Error> {
+    has_correct_request_uri(config, &request.uri)?;
 
     Ok(())
 }
 
-fn has_correct_request_uri(request_uri: &rsip::Uri) -> Result<(), Error> {
-    if common::CONFIG.contains_addr(&request_uri.host_with_port) {
+fn has_correct_request_uri(config: &Config, request_uri: &rsip::Uri) -> Result<(), Error> {
+    if config.contains_addr(&request_uri.host_with_port) {
         Ok(())
     } else {
         Err(Error::from("invalid request uri"))
@@ -0,0 +1,296 @@
+use super::registrar::aor_of;
+use crate::{presets, Error, ReqProcessor};
+use common::{
+    async_trait::async_trait,
+    rsip::{self, headers::UntypedHeader, message::HeadersExt, prelude::*},
+    tokio::{
+        sync::RwLock,
+        time::{Duration, Instant},
+    },
+    uuid::Uuid,
+};
+use models::Handlers;
+use std::collections::HashMap;
+use store::Stores;
+
+//Timer F (RFC3261 17.1.2.2), 64*T1, a fork without a final response by then has failed
+const FORK_TIMEOUT: Duration = Duration::from_secs(32);
+//the Call-ID of a stored message sent to a contact that just registered starts with this
+//and the id of the message, so that it's removed once the contact takes it
+const OFFLINE_CALL_ID_PREFIX: &str = "offline-";
+
+//instant messages (RFC3428) to the registered contacts of the recipient, or kept in the
+//store until the recipient registers again
+#[derive(Debug)]
+pub struct Messenger {
+    handlers: Handlers,
+    stores: Stores,
+    in_flight: RwLock<InFlight>,
+}
+
+#[derive(Debug, Default)]
+struct InFlight {
+    //by the Call-ID of each forwarded request
+    forks: HashMap<String, Fork>,
+    //by an id of our own, messages of the same sender may share a Call-ID
+    deliveries: HashMap<String, Delivery>,
+}
+
+#[derive(Debug)]
+struct Fork {
+    delivery: String,
+    sent_at: Instant,
+}
+
+//a message forwarded to every contact of the recipient, it's kept if none of them takes it
+#[derive(Debug)]
+struct Delivery {
+    pending: usize,
+    delivered: bool,
+    message: store::DirtyOfflineMessage,
+}
+
+#[async_trait]
+impl ReqProcessor for Messenger {
+    async fn process_incoming_request(&self, request: rsip::Request) -> Result<(), Error> {
+        match request.method {
+            rsip::Method::Message => self.process_message(request).await,
+            _ => {
+                let response = presets::create_405_from(request.clone())?;
+                self.reply(request, response).await
+            }
+        }
+    }
+}
+
+impl Messenger {
+    pub fn new(handlers: Handlers) -> Self {
+        Self {
+            handlers,
+            stores: Default::default(),
+            in_flight: Default::default(),
+        }
+    }
+
+    pub fn with_stores(mut self, stores: Stores) -> Self {
+        self.stores = stores;
+        self
+    }
+
+    //the responses and transport errors of the requests that we forwarded
+    pub async fn has_message_for(&self, msg: &impl HeadersExt) -> bool {
+        let call_id = match msg.call_id_header() {
+            Ok(call_id) => call_id.value().to_string(),
+            Err(_) => return false,
+        };
+
+        offline_message_id(&call_id).is_some()
+            || self.in_flight.read().await.forks.contains_key(&call_id)
+    }
+
+    pub async fn process_incoming_response(&self, response: rsip::Response) -> Result<(), Error> {
+        if response.status_code.kind() == rsip::StatusCodeKind::Provisional {
+            return Ok(());
+        }
+
+        let delivered = response.status_code.kind() == rsip::StatusCodeKind::Successful;
+        self.resolve(response.call_id_header()?.value(), delivered)
+            .await
+    }
+
+    //the contact can't be reached, like any other failure of the fork
+    pub async fn transport_error(&self, msg: rsip::SipMessage) -> Result<(), Error> {
+        self.resolve(msg.call_id_header()?.value(), false).await
+    }
+
+    pub async fn run_deliveries(&self) {
+        use common::tokio::time;
+
+        let mut ticker = time::interval(time::Duration::from_millis(1000));
+        loop {
+            ticker.tick().await;
+
+            if let Err(err) = self.check_deliveries().await {
+                common::log::error!("Error checking message deliveries: {}", err)
+            }
+        }
+    }
+
+    //non-INVITE transactions have no state machine yet, so nothing else reports the forks
+    //that never got a final response
+    pub async fn check_deliveries(&self) -> Result<(), Error> {
+        let timed_out = self
+            .in_flight
+            .read()
+            .await
+            .forks
+            .iter()
+            .filter(|(_, fork)| fork.sent_at.elapsed() >= FORK_TIMEOUT)
+            .map(|(call_id, _)| call_id.clone())
+            .collect::<Vec<_>>();
+
+        for call_id in timed_out {
+            self.resolve(&call_id, false).await?;
+        }
+
+        Ok(())
+    }
+
+    //the final outcome of a fork, the message is stored once all of them failed
+    async fn resolve(&self, call_id: &str, delivered: bool) -> Result<(), Error> {
+        if let Some(id) = offline_message_id(call_id) {
+            if delivered {
+                self.stores.offline_messages.delete(id)?;
+            }
+            return Ok(());
+        }
+
+        let mut in_flight = self.in_flight.write().await;
+        let id = match in_flight.forks.remove(call_id) {
+            Some(fork) => fork.delivery,
+            None => return Ok(()),
+        };
+        let delivery = match in_flight.deliveries.get_mut(&id) {
+            Some(delivery) => delivery,
+            None => return Ok(()),
+        };
+
+        delivery.pending -= 1;
+        delivery.delivered |= delivered;
+        if delivery.pending == 0 {
+            if let Some(delivery) = in_flight.deliveries.remove(&id) {
+                if !delivery.delivered {
+                    self.stores.offline_messages.create(delivery.message)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    //RFC3428 7, we take over the delivery so the sender gets a 202
+    async fn process_message(&self, request: rsip::Request) -> Result<(), Error> {
+        let recipient = aor_of(&request.uri);
+        let sender = request.from_header()?.typed()?.uri;
+        let content_type = request
+            .headers
+            .iter()
+            .find_map(|header| match header {
+                rsip::Header::ContentType(content_type) => Some(content_type.value().to_string()),
+                _ => None,
+            })
+            .unwrap_or_else(|| "text/plain".into());
+        let message = store::DirtyOfflineMessage {
+            recipient: Some(recipient.to_string()),
+            sender: Some(sender.to_string()),
+            content_type: Some(content_type.clone()),
+            body: Some(String::from_utf8_lossy(&request.body).into()),
+        };
+
+        let contacts = match &recipient.auth {
+            Some(auth) => self
+                .stores
+                .locations
+                .for_aor(&auth.user, &recipient.host_with_port.host.to_string())?,
+            None => {
+                let response = presets::create_404_from(request.clone())?;
+                return self.reply(request, response).await;
+            }
+        };
+
+        if contacts.is_empty() {
+            self.stores.offline_messages.create(message)?;
+        } else {
+            let id = Uuid::new_v4().simple().to_string();
+            let forks = contacts
+                .into_iter()
+                .map(|contact| {
+                    Ok(presets::message_request(
+                        &self.handlers.config,
+                        sender.clone(),
+                        recipient.clone(),
+                        rsip::Uri::try_from(contact.contact_uri.as_str())?,
+                        &content_type,
+                        request.body.clone(),
+                    ))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+
+            {
+                let mut in_flight = self.in_flight.write().await;
+                for fork in forks.iter() {
+                    in_flight.forks.insert(
+                        fork.call_id_header()?.value().to_string(),
+                        Fork {
+                            delivery: id.clone(),
+                            sent_at: Instant::now(),
+                        },
+                    );
+                }
+                in_flight.deliveries.insert(
+                    id,
+                    Delivery {
+                        pending: forks.len(),
+                        delivered: false,
+                        message,
+                    },
+                );
+            }
+            for fork in forks {
+                self.handlers.transaction.new_uac(fork).await?;
+            }
+        }
+
+        let response = presets::response_from(request.clone(), 202.into())?;
+        self.reply(request, response).await
+    }
+
+    async fn reply(&self, request: rsip::Request, response: rsip::Response) -> Result<(), Error> {
+        Ok(self
+            .handlers
+            .transaction
+            .new_uas(request, Some(response))
+            .await?)
+    }
+}
+
+//sends the messages that were kept for the address of record to a contact that was just
+//registered, the messenger removes each of them once the contact answers with a 2xx
+pub async fn deliver_offline_messages(
+    handlers: &Handlers,
+    stores: &Stores,
+    aor: &rsip::Uri,
+    contact: &rsip::Uri,
+) -> Result<(), Error> {
+    for message in stores.offline_messages.for_recipient(&aor.to_string())? {
+        let mut request = presets::message_request(
+            &handlers.config,
+            rsip::Uri::try_from(message.sender.as_str())?,
+            aor.clone(),
+            contact.clone(),
+            &message.content_type,
+            message.body.into_bytes(),
+        );
+        let call_id = format!(
+            "{}{}-{}",
+            OFFLINE_CALL_ID_PREFIX,
+            message.id,
+            request.call_id_header()?.value()
+        );
+        request
+            .headers
+            .unique_push(rsip::headers::CallId::new(call_id).into());
+        handlers.transaction.new_uac(request).await?;
+    }
+
+    Ok(())
+}
+
+fn offline_message_id(call_id: &str) -> Option<i64> {
+    call_id
+        .strip_prefix(OFFLINE_CALL_ID_PREFIX)?
+        .split_once('-')?
+        .0
+        .parse()
+        .ok()
+}
@@ -1,9 +1,13 @@
+mod b2bua;
 mod capabilities;
+mod messenger;
 mod registrar;
-//mod ua;
+mod ua;
 //mod proxy;
 
+pub use b2bua::{B2bua, B2buaHooks, Leg, Passthrough};
 pub use capabilities::Capabilities;
-pub use registrar::Registrar;
-//pub use ua::UserAgent;
+pub use messenger::Messenger;
+pub use registrar::{ContactEvent, Registrar, RegistrationEvent};
+pub use ua::UserAgent;
 //pub use proxy::{Proxy, ProxyProcessor};
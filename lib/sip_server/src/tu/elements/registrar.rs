@@ -1,19 +1,53 @@
+use super::messenger::deliver_offline_messages;
 use crate::{Error, ReqProcessor};
 use common::{
     async_trait::async_trait,
+    chrono::Utc,
     rsip::{self, prelude::*},
+    tokio::sync::broadcast,
+    Config,
 };
 use models::Handlers;
+use std::fmt;
+use store::{LocationStore, Stores};
+
+//how many state changes a slow listener of the registrar may fall behind
+const EVENTS_CAPACITY: usize = 100;
 
 #[derive(Debug)]
 pub struct Registrar {
     handlers: Handlers,
+    stores: Stores,
+    events: broadcast::Sender<RegistrationEvent>,
+}
+
+//what happened to the binding of a contact, RFC3680 5.1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContactEvent {
+    //already bound before anyone started watching
+    Registered,
+    Created,
+    Refreshed,
+    Shortened,
+    Expired,
+    //removed by the registrar, the user agent is expected to register again
+    Deactivated,
+    Unregistered,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistrationEvent {
+    pub aor: rsip::Uri,
+    pub contact: rsip::Uri,
+    pub event: ContactEvent,
+    //seconds left on the binding, 0 once it's gone
+    pub expires: u32,
 }
 
 #[async_trait]
 impl ReqProcessor for Registrar {
     async fn process_incoming_request(&self, msg: rsip::Request) -> Result<(), Error> {
-        apply_default_checks(&msg)?;
+        apply_default_checks(&self.handlers.config, &msg)?;
 
         match msg.contact_header() {
             Ok(_) => self.handle_update(msg).await,
@@ -24,33 +58,148 @@ impl ReqProcessor for Registrar {
 
 impl Registrar {
     pub fn new(handlers: Handlers) -> Self {
-        Self { handlers }
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+
+        Self {
+            handlers,
+            stores: Default::default(),
+            events,
+        }
+    }
+
+    //the messenger has to get the same stores, for the messages kept for the contacts
+    pub fn with_stores(mut self, stores: Stores) -> Self {
+        self.stores = stores;
+        self
+    }
+
+    //state changes of the bindings, like for the reg event package
+    pub fn events(&self) -> broadcast::Receiver<RegistrationEvent> {
+        self.events.subscribe()
+    }
+
+    //removes a binding on behalf of the administrator
+    pub fn deactivate(&self, contact: &rsip::Uri) -> Result<(), Error> {
+        let registration = self.stores.locations.delete_by_uri(&contact.to_string())?;
+        self.emit(registration_event_from(
+            &registration,
+            ContactEvent::Deactivated,
+        )?);
+
+        Ok(())
+    }
+
+    pub async fn run_registrations(&self) {
+        use common::tokio::time;
+
+        let mut ticker = time::interval(time::Duration::from_millis(1000));
+        loop {
+            ticker.tick().await;
+
+            if let Err(err) = self.check_registrations() {
+                common::log::error!("Error checking registrations: {}", err)
+            }
+        }
+    }
+
+    //bindings that weren't refreshed in time are removed
+    pub fn check_registrations(&self) -> Result<(), Error> {
+        for registration in self.stores.locations.delete_expired()? {
+            self.emit(registration_event_from(
+                &registration,
+                ContactEvent::Expired,
+            )?);
+        }
+
+        Ok(())
     }
 
     async fn handle_update(&self, msg: rsip::Request) -> Result<(), Error> {
         use std::convert::TryFrom;
 
+        let aor = aor_of(&msg.to_header()?.typed()?.uri);
+        let mut created = vec![];
         for contact_header in msg.contact_headers() {
             let typed_contact_header = contact_header.typed()?;
+            let contact = typed_contact_header.uri.clone();
 
             match expires_value_for(contact_header, msg.expires_header())? {
                 0 => {
-                    store::Registration::delete_by_uri(typed_contact_header.uri.to_string())?;
+                    self.stores
+                        .locations
+                        .delete_by_uri(&typed_contact_header.uri.to_string())?;
+                    self.emit(RegistrationEvent {
+                        aor: aor.clone(),
+                        contact,
+                        event: ContactEvent::Unregistered,
+                        expires: 0,
+                    });
                 }
                 _ => {
                     println!("{:?}", typed_contact_header);
-                    store::Registration::upsert(store::DirtyRegistration::try_from(msg.clone())?)?;
+                    let bindings = bindings_of(self.stores.locations.as_ref(), &aor)?;
+                    let (existing, replaced): (Vec<_>, Vec<_>) = bindings
+                        .into_iter()
+                        .partition(|binding| binding.contact_uri == contact.to_string());
+                    let registration = self
+                        .stores
+                        .locations
+                        .upsert(store::DirtyRegistration::try_from(msg.clone())?)?;
+
+                    //the store keeps one binding per address of record, so the upsert
+                    //took the place of any other contact, which is over for its watchers
+                    for binding in replaced.iter() {
+                        self.emit(registration_event_from(
+                            binding,
+                            ContactEvent::Unregistered,
+                        )?);
+                    }
+                    let event = match existing.first() {
+                        None => ContactEvent::Created,
+                        Some(existing) if registration.expires < existing.expires => {
+                            ContactEvent::Shortened
+                        }
+                        Some(_) => ContactEvent::Refreshed,
+                    };
+                    if event == ContactEvent::Created {
+                        created.push(contact.clone());
+                    }
+                    self.emit(RegistrationEvent {
+                        aor: aor.clone(),
+                        contact,
+                        event,
+                        expires: (registration.expires - Utc::now()).num_seconds().max(0) as u32,
+                    });
                 }
             }
         }
 
-        self.handle_query(msg).await
+        self.handle_query(msg).await?;
+
+        //only new contacts get the kept messages, a refresh would send again the ones that
+        //are still in flight. The REGISTER succeeded already, even if the messages can't go
+        for contact in created {
+            if let Err(err) =
+                deliver_offline_messages(&self.handlers, &self.stores, &aor, &contact).await
+            {
+                common::log::warn!("could not deliver offline messages: {}", err);
+            }
+        }
+
+        Ok(())
+    }
+
+    //nobody listening for the events is fine
+    fn emit(&self, event: RegistrationEvent) {
+        let _ = self.events.send(event);
     }
 
     async fn handle_query(&self, msg: rsip::Request) -> Result<(), Error> {
         let response = create_registration_ok_from(
             msg.clone(),
-            store::Registration::search(Default::default())?
+            self.stores
+                .locations
+                .all()?
                 .into_iter()
                 .map(Into::into)
                 .collect::<Vec<rsip::headers::Contact>>(),
@@ -59,13 +208,75 @@ impl Registrar {
     }
 }
 
-fn apply_default_checks(request: &rsip::Request) -> Result<(), Error> {
+impl ContactEvent {
+    pub fn is_terminated(&self) -> bool {
+        matches!(self, Self::Expired | Self::Deactivated | Self::Unregistered)
+    }
+}
+
+impl fmt::Display for ContactEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Registered => write!(f, "registered"),
+            Self::Created => write!(f, "created"),
+            Self::Refreshed => write!(f, "refreshed"),
+            Self::Shortened => write!(f, "shortened"),
+            Self::Expired => write!(f, "expired"),
+            Self::Deactivated => write!(f, "deactivated"),
+            Self::Unregistered => write!(f, "unregistered"),
+        }
+    }
+}
+
+//the address of record without the port or params of the uri it was registered with
+pub fn aor_of(uri: &rsip::Uri) -> rsip::Uri {
+    rsip::Uri {
+        scheme: uri.scheme.clone(),
+        auth: uri.auth.clone(),
+        host_with_port: rsip::HostWithPort {
+            host: uri.host_with_port.host.clone(),
+            port: None,
+        },
+        ..Default::default()
+    }
+}
+
+fn bindings_of(
+    locations: &dyn LocationStore,
+    aor: &rsip::Uri,
+) -> Result<Vec<store::Registration>, Error> {
+    let username = match &aor.auth {
+        Some(auth) => auth.user.clone(),
+        None => return Ok(vec![]),
+    };
+
+    Ok(locations.for_aor(&username, &aor.host_with_port.host.to_string())?)
+}
+
+fn registration_event_from(
+    registration: &store::Registration,
+    event: ContactEvent,
+) -> Result<RegistrationEvent, Error> {
+    let aor = match &registration.domain {
+        Some(domain) => format!("sip:{}@{}", registration.username, domain),
+        None => format!("sip:{}", registration.username),
+    };
+
+    Ok(RegistrationEvent {
+        aor: rsip::Uri::try_from(aor.as_str())?,
+        contact: rsip::Uri::try_from(registration.contact_uri.as_str())?,
+        event,
+        expires: 0,
+    })
+}
+
+fn apply_default_checks(config: &Config, request: &rsip::Request) -> Result<(), Error> {
     let to_header = request.to_header()?;
     let from_header = request.from_header()?;
 
-    has_correct_request_uri(&request.uri)?;
+    has_correct_request_uri(config, &request.uri)?;
     extensions_are_supported()?;
-    has_correct_to_request_uri(to_header)?;
+    has_correct_to_request_uri(config, to_header)?;
     has_same_from_to_header_uris(from_header, to_header)?;
 
     Ok(())
@@ -91,18 +302,18 @@ fn has_same_from_to_header_uris(
     Ok(())
 }
 
-fn has_correct_request_uri(request_uri: &rsip::Uri) -> Result<(), Error> {
-    if common::CONFIG.contains_addr(&request_uri.host_with_port) {
+fn has_correct_request_uri(config: &Config, request_uri: &rsip::Uri) -> Result<(), Error> {
+    if config.contains_addr(&request_uri.host_with_port) {
         Ok(())
     } else {
         Err(Error::from("invalid request uri"))
     }
 }
 
-fn has_correct_to_request_uri(to_header: &rsip::headers::To) -> Result<(), Error> {
+fn has_correct_to_request_uri(config: &Config, to_header: &rsip::headers::To) -> Result<(), Error> {
     let typed_to_header = to_header.typed()?;
 
-    if common::CONFIG.contains_addr(&typed_to_header.uri.host_with_port) {
+    if config.contains_addr(&typed_to_header.uri.host_with_port) {
         Ok(())
     } else {
         Err(Error::from("record not found!"))
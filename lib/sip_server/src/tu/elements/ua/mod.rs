@@ -1,54 +1,165 @@
 //mod processor;
 
-use crate::{presets, tu::dialogs::Dialogs, Error, ReqProcessor};
-use common::{rsip, tokio};
-use std::sync::Arc;
+use crate::{
+    presets,
+    transaction::sm::Timers,
+    tu::{
+        calls::{CallSession, Dialer, IncomingCall},
+        dialogs::{replaces::Replaces, session_timer, Dialogs},
+        elements::Messenger,
+        router::Router,
+        subscriptions::{EventPackage, Subscriptions},
+    },
+    CallHandler, Error, ReqProcessor, TuElement,
+};
+use common::{
+    async_trait::async_trait,
+    rsip,
+    tokio::{self, sync::RwLock, task::JoinHandle},
+};
+use std::{sync::Arc, time::Duration};
+use store::Stores;
 
 use models::{receivers::TuReceiver, tu::TuLayerMsg, Handlers};
 
+//methods that the UA handles itself, in or out of dialogs
+const HANDLED_METHODS: &[rsip::Method] = &[
+    rsip::Method::Invite,
+    rsip::Method::Ack,
+    rsip::Method::Cancel,
+    rsip::Method::Bye,
+    rsip::Method::Update,
+    rsip::Method::PRack,
+    rsip::Method::Info,
+    rsip::Method::Refer,
+    rsip::Method::Notify,
+    rsip::Method::Subscribe,
+    rsip::Method::Publish,
+    rsip::Method::Message,
+];
+
 //TODO: rename this to something else like ProxyTu etc
 #[derive(Debug)]
-pub struct UserAgent<R: ReqProcessor, C: ReqProcessor> {
-    inner: Arc<Inner<R, C>>,
+pub struct UserAgent<H: CallHandler> {
+    inner: Arc<Inner<H>>,
+    tasks: Vec<JoinHandle<()>>,
 }
 
-impl<R: ReqProcessor, C: ReqProcessor> UserAgent<R, C> {
+impl<H: CallHandler> UserAgent<H> {
+    //out of dialog requests that the UA doesn't handle itself, like REGISTER and OPTIONS,
+    //go through the router
     pub fn new(
         handlers: Handlers,
         messages_rx: TuReceiver,
-        registrar: R,
-        capabilities: C,
+        router: Router,
+        call_handler: H,
     ) -> Result<Self, Error> {
-        let me = Self {
-            inner: Arc::new(Inner {
-                registrar,
-                capabilities,
-                dialogs: Dialogs::new(handlers.clone()),
-                handlers,
-            }),
-        };
+        Self::with_stores(
+            handlers,
+            messages_rx,
+            router,
+            call_handler,
+            Default::default(),
+        )
+    }
+
+    //the publications and the offline messages are kept in the stores, the registrar of the
+    //router should get the same ones
+    pub fn with_stores(
+        handlers: Handlers,
+        messages_rx: TuReceiver,
+        router: Router,
+        call_handler: H,
+        stores: Stores,
+    ) -> Result<Self, Error> {
+        let inner = Arc::new(Inner {
+            router: RwLock::new(Arc::new(router.allowing(HANDLED_METHODS))),
+            call_handler: Arc::new(call_handler),
+            dialogs: Arc::new(Dialogs::new(handlers.clone())),
+            subscriptions: Arc::new(
+                Subscriptions::new(handlers.clone()).with_stores(stores.clone()),
+            ),
+            messenger: Messenger::new(handlers.clone()).with_stores(stores),
+            draining: Default::default(),
+            handlers,
+        });
+        let tasks = Self::run(inner.clone(), messages_rx);
+
+        Ok(Self { inner, tasks })
+    }
+
+    //requests already handed to the old router finish there
+    pub async fn reload_router(&self, router: Router) {
+        *self.inner.router.write().await = Arc::new(router.allowing(HANDLED_METHODS));
+    }
+
+    //starts a new outgoing call, its progress can be followed through the returned session
+    pub async fn call(&self, target: rsip::Uri, sdp_offer: Vec<u8>) -> Result<CallSession, Error> {
+        self.dialer().call(target, sdp_offer).await
+    }
+
+    pub fn dialer(&self) -> Dialer {
+        self.inner.dialer()
+    }
+
+    //event packages that the UA serves as a notifier, selected by the Event of a SUBSCRIBE
+    pub async fn register_event_package(&self, package: impl EventPackage) {
+        self.inner.subscriptions.register(package).await
+    }
+
+    //packages report changes of their resources through this one
+    pub fn subscriptions(&self) -> Arc<Subscriptions> {
+        self.inner.subscriptions.clone()
+    }
+
+    fn run(inner: Arc<Inner<H>>, messages: TuReceiver) -> Vec<JoinHandle<()>> {
+        let inner_dialogs = inner.clone();
+        let inner_subscriptions = inner.clone();
+        let inner_messenger = inner.clone();
+        vec![
+            tokio::spawn(async move { inner.run(messages).await }),
+            tokio::spawn(async move { inner_dialogs.dialogs.run_dialogs().await }),
+            tokio::spawn(
+                async move { inner_subscriptions.subscriptions.run_subscriptions().await },
+            ),
+            tokio::spawn(async move { inner_messenger.messenger.run_deliveries().await }),
+        ]
+    }
+}
 
-        me.run(messages_rx);
+#[async_trait]
+impl<H: CallHandler> TuElement for UserAgent<H> {
+    async fn drain(&self, retry_after: Duration) {
+        *self.inner.draining.write().await = Some(retry_after);
+    }
+
+    //subscriptions are not waited for, subscribers refresh them elsewhere after the 503
+    async fn is_idle(&self) -> bool {
+        !self.inner.dialogs.has_active_dialogs().await
+    }
 
-        Ok(me)
+    fn reload_timers(&self, timers: Timers) {
+        self.inner.dialogs.reload_timers(timers)
     }
 
-    fn run(&self, messages: TuReceiver) {
-        let inner = self.inner.clone();
-        tokio::spawn(async move { inner.run(messages).await });
+    fn abort(&self) {
+        self.tasks.iter().for_each(JoinHandle::abort);
     }
 }
 
 #[derive(Debug)]
-struct Inner<R: ReqProcessor, C: ReqProcessor> {
-    registrar: R,
-    capabilities: C,
-    #[allow(dead_code)]
-    dialogs: Dialogs,
+struct Inner<H: CallHandler> {
+    router: RwLock<Arc<Router>>,
+    call_handler: Arc<H>,
+    dialogs: Arc<Dialogs>,
+    subscriptions: Arc<Subscriptions>,
+    messenger: Messenger,
+    //the Retry-After of the 503s while the element goes down
+    draining: RwLock<Option<Duration>>,
     handlers: Handlers,
 }
 
-impl<R: ReqProcessor, C: ReqProcessor> Inner<R, C> {
+impl<H: CallHandler> Inner<H> {
     async fn run(&self, mut messages: TuReceiver) {
         while let Some(request) = messages.recv().await {
             if let Err(err) = self.receive(request).await {
@@ -75,6 +186,11 @@ impl<R: ReqProcessor, C: ReqProcessor> Inner<R, C> {
         msg: rsip::SipMessage,
         error: String,
     ) -> Result<(), Error> {
+        if self.messenger.has_message_for(&msg).await {
+            common::log::warn!("could not forward MESSAGE: {}", error);
+            return self.messenger.transport_error(msg).await;
+        }
+
         Ok(self.dialogs.transport_error(msg, error).await?)
     }
 
@@ -107,13 +223,127 @@ impl<R: ReqProcessor, C: ReqProcessor> Inner<R, C> {
     async fn handle_incoming_request(&self, request: rsip::Request) -> Result<(), Error> {
         use rsip::Method;
 
+        if self.dialogs.has_dialog_for(&request).await {
+            return self.dialogs.process_incoming_request(request).await;
+        }
+        if self.subscriptions.has_subscription_for(&request).await {
+            return self.subscriptions.process_incoming_request(request).await;
+        }
+
+        if let Some(retry_after) = *self.draining.read().await {
+            if request.method != Method::Ack {
+                return self.reject_while_draining(request, retry_after).await;
+            }
+        }
+        let router = self.router.read().await.clone();
+        if let Some(processor) = router.processor_for(&request) {
+            return processor.process_incoming_request(request).await;
+        }
+
         match request.method {
-            Method::Register => self.registrar.process_incoming_request(request).await?,
-            Method::Options => self.capabilities.process_incoming_request(request).await?,
+            Method::Subscribe | Method::Publish => {
+                self.subscriptions.process_incoming_request(request).await?
+            }
+            Method::Message => self.messenger.process_incoming_request(request).await?,
+            Method::Invite => self.handle_incoming_call(request).await?,
+            Method::Ack => common::log::warn!("received ACK but no dialog exists for that msg"),
+            _ => router.process_incoming_request(request).await?,
+        };
+
+        Ok(())
+    }
+
+    async fn handle_incoming_call(&self, request: rsip::Request) -> Result<(), Error> {
+        if session_timer::is_too_small(&request.headers) {
+            let response = presets::create_422_from(request.clone())?;
+            self.handlers
+                .transaction
+                .new_uas_invite(request, Some(response))
+                .await?;
+            return Ok(());
+        }
+
+        let replaces = match Replaces::from_headers(&request.headers) {
+            Ok(replaces) => replaces,
+            Err(err) => {
+                common::log::warn!("rejecting INVITE: {}", err);
+                return self.reject_incoming_call(request, 400.into()).await;
+            }
+        };
+
+        let (events_tx, events_rx) = tokio::sync::mpsc::unbounded_channel();
+        match replaces {
+            Some(replaces) => {
+                if let Some(status_code) = self
+                    .dialogs
+                    .replace_with_uas_call(request.clone(), &replaces, events_tx)
+                    .await?
+                {
+                    return self.reject_incoming_call(request, status_code).await;
+                }
+            }
+            None => {
+                self.dialogs
+                    .new_uas_call(request.clone(), events_tx)
+                    .await?
+            }
+        };
+
+        //the dialog already rejected the INVITE, like when its offer is unacceptable
+        if !self.dialogs.is_active_for(&request).await {
+            return Ok(());
+        }
+
+        //the application answers through the TU channel, so it can't run inside our loop
+        let call_handler = self.call_handler.clone();
+        let call =
+            IncomingCall::new(self.handlers.clone(), request, events_rx).with_dialer(self.dialer());
+        tokio::spawn(async move {
+            if let Err(err) = call_handler.incoming_call(call).await {
+                common::log::error!("Error handling incoming call: {}", err)
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn reject_incoming_call(
+        &self,
+        request: rsip::Request,
+        status_code: rsip::StatusCode,
+    ) -> Result<(), Error> {
+        let response = presets::response_from(request.clone(), status_code)?;
+        self.handlers
+            .transaction
+            .new_uas_invite(request, Some(response))
+            .await?;
+
+        Ok(())
+    }
+
+    //RFC3261 21.5.4, the element is going down so the request is better retried elsewhere
+    async fn reject_while_draining(
+        &self,
+        request: rsip::Request,
+        retry_after: Duration,
+    ) -> Result<(), Error> {
+        let mut response = presets::response_from(request.clone(), 503.into())?;
+        response.headers.push(rsip::Header::Other(
+            "Retry-After".into(),
+            retry_after.as_secs().max(1).to_string(),
+        ));
+
+        match request.method {
+            rsip::Method::Invite => {
+                self.handlers
+                    .transaction
+                    .new_uas_invite(request, Some(response))
+                    .await?
+            }
             _ => {
                 self.handlers
-                    .transport
-                    .send(presets::create_405_from(request)?.into())
+                    .transaction
+                    .new_uas(request, Some(response))
                     .await?
             }
         };
@@ -121,15 +351,22 @@ impl<R: ReqProcessor, C: ReqProcessor> Inner<R, C> {
         Ok(())
     }
 
+    fn dialer(&self) -> Dialer {
+        Dialer::new(self.handlers.clone(), self.dialogs.clone())
+    }
+
     async fn handle_incoming_response(&self, response: rsip::Response) -> Result<(), Error> {
-        if let Ok(dialog_id) = response.dialog_id() {
-            if self.dialogs.exists(dialog_id).await {
-                //TODO: this is wrong, uac dialogs can process requests as well
-                self.dialogs.uac_process_incoming_response(response).await?
-            } else {
-                common::log::warn!("received response msg but no dialog exists for that msg");
-            };
-        }
+        if self.dialogs.has_dialog_for(&response).await {
+            self.dialogs.process_incoming_response(response).await?
+        } else if self.subscriptions.has_subscription_for(&response).await {
+            self.subscriptions
+                .process_incoming_response(response)
+                .await?
+        } else if self.messenger.has_message_for(&response).await {
+            self.messenger.process_incoming_response(response).await?
+        } else {
+            common::log::warn!("received response msg but no dialog exists for that msg");
+        };
 
         Ok(())
     }
@@ -137,6 +374,10 @@ impl<R: ReqProcessor, C: ReqProcessor> Inner<R, C> {
     async fn handle_outgoing_request(&self, request: rsip::Request) -> Result<(), Error> {
         use rsip::Method;
 
+        if self.dialogs.has_dialog_for(&request).await {
+            return self.dialogs.process_outgoing_request(request).await;
+        }
+
         match request.method {
             Method::Invite => {
                 //TODO: consider letting the dialog handle the transaction creation ?
@@ -149,6 +390,10 @@ impl<R: ReqProcessor, C: ReqProcessor> Inner<R, C> {
     }
 
     async fn handle_outgoing_response(&self, response: rsip::Response) -> Result<(), Error> {
+        if self.dialogs.has_dialog_for(&response).await {
+            return self.dialogs.process_outgoing_response(response).await;
+        }
+
         self.handlers.transport.send(response.into()).await?;
 
         Ok(())
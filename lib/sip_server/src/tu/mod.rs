@@ -1,8 +1,11 @@
+pub mod calls;
 pub mod dialogs;
 pub mod elements;
+pub mod router;
+pub mod subscriptions;
 
 use common::{async_trait::async_trait, rsip};
-use std::fmt::Debug;
+use std::{fmt::Debug, time::Duration};
 
 #[async_trait]
 pub trait ReqProcessor: Send + Sync + Debug + 'static {
@@ -14,6 +17,27 @@ pub trait RespProcessor: Send + Sync + Debug + 'static {
     async fn process_incoming_response(&self, msg: rsip::Response) -> Result<(), crate::Error>;
 }
 
+//invoked for every new incoming INVITE, the call is answered through the given handle
+#[async_trait]
+pub trait CallHandler: Send + Sync + Debug + 'static {
+    async fn incoming_call(&self, call: calls::IncomingCall) -> Result<(), crate::Error>;
+}
+
+//the element that consumes the TU channel, like the UserAgent
+#[async_trait]
+pub trait TuElement: Send + Sync + 'static {
+    //from now on new requests are turned away, the ones of existing dialogs go on
+    async fn drain(&self, _retry_after: Duration) {}
+    //nothing is in flight that would be dropped by aborting
+    async fn is_idle(&self) -> bool {
+        true
+    }
+    //the timers of the transaction layer, for what the TU retransmits itself
+    fn reload_timers(&self, _timers: crate::transaction::sm::Timers) {}
+    //stops the tasks of the element, called when the whole element is shut down
+    fn abort(&self);
+}
+
 /*
 #[async_trait]
 pub trait DialogsProcessor: Send + Sync + Any + Debug {
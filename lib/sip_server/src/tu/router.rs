@@ -0,0 +1,274 @@
+use crate::{presets, tu::subscriptions, Error, ReqProcessor};
+use common::{
+    async_trait::async_trait,
+    rsip::{self, headers::UntypedHeader},
+};
+use models::Handlers;
+use std::{fmt, sync::Arc};
+
+type Predicate = Box<dyn Fn(&rsip::Request) -> bool + Send + Sync>;
+
+//what a request needs to match for its processor to get it, every condition that is set
+//has to hold
+pub struct Route {
+    processor: Arc<dyn ReqProcessor>,
+    methods: Vec<rsip::Method>,
+    user: Option<String>,
+    domain: Option<String>,
+    event: Option<String>,
+    predicates: Vec<Predicate>,
+}
+
+//picks the processor of an out of dialog request from the routes, in the order they were
+//added. Requests that no route takes go to the fallback, or get a 405 that lists the
+//methods of the routes (RFC3261 8.2.1)
+#[derive(Debug)]
+pub struct Router {
+    handlers: Handlers,
+    routes: Vec<Route>,
+    fallback: Option<Arc<dyn ReqProcessor>>,
+    //handled outside of the routes, like by the element that runs the router
+    allowed: Vec<rsip::Method>,
+}
+
+#[derive(Debug)]
+pub struct RouterBuilder {
+    router: Router,
+}
+
+#[async_trait]
+impl ReqProcessor for Router {
+    async fn process_incoming_request(&self, request: rsip::Request) -> Result<(), Error> {
+        match self
+            .processor_for(&request)
+            .or_else(|| self.fallback.clone())
+        {
+            Some(processor) => processor.process_incoming_request(request).await,
+            None => {
+                let mut response = presets::create_405_from(request)?;
+                response.headers.push(self.allow_header());
+
+                Ok(self.handlers.transport.send(response.into()).await?)
+            }
+        }
+    }
+}
+
+impl Router {
+    pub fn builder(handlers: Handlers) -> RouterBuilder {
+        RouterBuilder {
+            router: Self {
+                handlers,
+                routes: vec![],
+                fallback: None,
+                allowed: vec![],
+            },
+        }
+    }
+
+    pub fn processor_for(&self, request: &rsip::Request) -> Option<Arc<dyn ReqProcessor>> {
+        self.routes
+            .iter()
+            .find(|route| route.matches(request))
+            .map(|route| route.processor.clone())
+    }
+
+    //adds methods that are handled before the router is asked, to be listed in the 405s
+    pub fn allowing(mut self, methods: &[rsip::Method]) -> Self {
+        for method in methods {
+            if !self.allowed.contains(method) {
+                self.allowed.push(method.clone());
+            }
+        }
+        self
+    }
+
+    pub fn allowed_methods(&self) -> Vec<rsip::Method> {
+        let mut methods: Vec<rsip::Method> = vec![];
+        for method in self
+            .routes
+            .iter()
+            .flat_map(|route| route.methods.iter())
+            .chain(self.allowed.iter())
+        {
+            if !methods.contains(method) {
+                methods.push(method.clone());
+            }
+        }
+
+        methods
+    }
+
+    fn allow_header(&self) -> rsip::Header {
+        let methods = self
+            .allowed_methods()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        rsip::headers::Allow::new(methods).into()
+    }
+}
+
+impl RouterBuilder {
+    pub fn route(mut self, route: Route) -> Self {
+        self.router.routes.push(route);
+        self
+    }
+
+    pub fn method(self, method: rsip::Method, processor: impl ReqProcessor) -> Self {
+        self.route(Route::new(processor).method(method))
+    }
+
+    //requests of an event package, like the SUBSCRIBEs and PUBLISHes of presence
+    pub fn event(self, event: &str, processor: impl ReqProcessor) -> Self {
+        self.route(Route::new(processor).event(event))
+    }
+
+    pub fn fallback(mut self, processor: impl ReqProcessor) -> Self {
+        self.router.fallback = Some(Arc::new(processor));
+        self
+    }
+
+    pub fn build(self) -> Router {
+        self.router
+    }
+}
+
+impl Route {
+    pub fn new(processor: impl ReqProcessor) -> Self {
+        Self::shared(Arc::new(processor))
+    }
+
+    //for a processor that serves more than one route
+    pub fn shared(processor: Arc<dyn ReqProcessor>) -> Self {
+        Self {
+            processor,
+            methods: vec![],
+            user: None,
+            domain: None,
+            event: None,
+            predicates: vec![],
+        }
+    }
+
+    pub fn method(mut self, method: rsip::Method) -> Self {
+        self.methods.push(method);
+        self
+    }
+
+    //the user of the Request-URI, * matches any sequence, like 1800* for toll free numbers
+    pub fn user(mut self, pattern: &str) -> Self {
+        self.user = Some(pattern.into());
+        self
+    }
+
+    //the host of the Request-URI, case-insensitive, * matches any sequence
+    pub fn domain(mut self, pattern: &str) -> Self {
+        self.domain = Some(pattern.to_ascii_lowercase());
+        self
+    }
+
+    pub fn event(mut self, event: &str) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    //the value of a header, * matches any sequence
+    pub fn header(self, name: &str, pattern: &str) -> Self {
+        let (name, pattern) = (name.to_string(), pattern.to_string());
+
+        self.when(move |request| {
+            request
+                .headers
+                .iter()
+                .any(|header| match header_value(header, &name) {
+                    Some(value) => glob(&pattern, value.trim()),
+                    None => false,
+                })
+        })
+    }
+
+    pub fn when(
+        mut self,
+        predicate: impl Fn(&rsip::Request) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+
+    pub fn matches(&self, request: &rsip::Request) -> bool {
+        if !self.methods.is_empty() && !self.methods.contains(&request.method) {
+            return false;
+        }
+        if let Some(pattern) = &self.user {
+            match &request.uri.auth {
+                Some(auth) if glob(pattern, &auth.user) => (),
+                _ => return false,
+            }
+        }
+        if let Some(pattern) = &self.domain {
+            let host = request.uri.host_with_port.host.to_string();
+            if !glob(pattern, &host.to_ascii_lowercase()) {
+                return false;
+            }
+        }
+        if let Some(event) = &self.event {
+            match subscriptions::event_package(&request.headers) {
+                Some(package) if package.eq_ignore_ascii_case(event) => (),
+                _ => return false,
+            }
+        }
+
+        self.predicates.iter().all(|predicate| predicate(request))
+    }
+}
+
+impl fmt::Debug for Route {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Route")
+            .field("processor", &self.processor)
+            .field("methods", &self.methods)
+            .field("user", &self.user)
+            .field("domain", &self.domain)
+            .field("event", &self.event)
+            .field("predicates", &self.predicates.len())
+            .finish()
+    }
+}
+
+//header names are case-insensitive (RFC3261 7.3.1)
+fn header_value<'a>(header: &'a rsip::Header, name: &str) -> Option<&'a str> {
+    use rsip::Header;
+
+    let (header_name, value) = match header {
+        Header::Other(header_name, value) => (header_name.as_str(), value.as_str()),
+        Header::From(header) => ("From", header.value()),
+        Header::To(header) => ("To", header.value()),
+        Header::Contact(header) => ("Contact", header.value()),
+        Header::UserAgent(header) => ("User-Agent", header.value()),
+        Header::Subject(header) => ("Subject", header.value()),
+        Header::Priority(header) => ("Priority", header.value()),
+        _ => return None,
+    };
+
+    match header_name.eq_ignore_ascii_case(name) {
+        true => Some(value),
+        false => None,
+    }
+}
+
+fn glob(pattern: &str, value: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == value,
+        Some((prefix, rest)) => match value.strip_prefix(prefix) {
+            Some(value) => value
+                .char_indices()
+                .map(|(index, _)| index)
+                .chain(std::iter::once(value.len()))
+                .any(|index| glob(rest, &value[index..])),
+            None => false,
+        },
+    }
+}
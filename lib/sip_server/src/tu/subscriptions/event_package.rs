@@ -0,0 +1,34 @@
+use super::Subscription;
+use crate::Error;
+use common::async_trait::async_trait;
+use std::fmt::Debug;
+
+//an event package served by the notifier, like presence or message-summary, registered
+//under the name that subscribers put in their Event header
+#[async_trait]
+pub trait EventPackage: Send + Sync + Debug + 'static {
+    fn name(&self) -> &str;
+
+    //content type of the NOTIFY bodies, which is what subscribers must accept
+    fn content_type(&self) -> &str;
+
+    //RFC6665 4.2.1.1, used when the SUBSCRIBE has no Expires, and the longest we grant
+    fn default_expires(&self) -> u32 {
+        3600
+    }
+
+    //RFC3903 4, whether the state of the resources can be published with PUBLISH, in which
+    //case the package composes it from the stored publications
+    fn accepts_publications(&self) -> bool {
+        false
+    }
+
+    //RFC6665 4.2.1.2, whether the subscriber may watch the resource, a rejected
+    //subscription is answered with 403
+    async fn authorize(&self, _subscription: &Subscription) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    //the current state of the subscribed resource, sent as the body of the NOTIFY
+    async fn state(&self, subscription: &Subscription) -> Result<Vec<u8>, Error>;
+}
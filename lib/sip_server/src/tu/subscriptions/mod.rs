@@ -0,0 +1,470 @@
+pub mod event_package;
+pub mod packages;
+pub mod publication;
+pub mod subscription;
+
+pub use event_package::EventPackage;
+pub use publication::Outcome;
+pub use subscription::{Subscription, SubscriptionState, TerminationReason};
+
+use crate::{error::DialogError, presets, Error};
+use common::{
+    rsip::{self, headers::UntypedHeader, prelude::*},
+    tokio::sync::RwLock,
+};
+use models::{rsip_ext::*, tu::DialogId, Handlers};
+use std::{collections::HashMap, sync::Arc};
+use store::Stores;
+
+//RFC6665 4.2.1.1, shorter subscriptions are answered with 423
+pub const MIN_EXPIRES: u32 = 60;
+
+//the notifier side of SIP events (RFC6665): subscriptions of the registered event
+//packages and the NOTIFYs that report the state of their resources
+#[derive(Debug)]
+pub struct Subscriptions {
+    handlers: Handlers,
+    stores: Stores,
+    packages: RwLock<HashMap<String, Arc<dyn EventPackage>>>,
+    data: RwLock<HashMap<DialogId, Subscription>>,
+}
+
+impl Subscriptions {
+    pub fn new(handlers: Handlers) -> Self {
+        Self {
+            handlers,
+            stores: Default::default(),
+            packages: Default::default(),
+            data: Default::default(),
+        }
+    }
+
+    //where the publications are kept, the packages that compose them, like Presence, have to
+    //read them from the same stores
+    pub fn with_stores(mut self, stores: Stores) -> Self {
+        self.stores = stores;
+        self
+    }
+
+    pub async fn register(&self, package: impl EventPackage) {
+        self.packages
+            .write()
+            .await
+            .insert(package.name().to_ascii_lowercase(), Arc::new(package));
+    }
+
+    //names of the registered packages, for the Allow-Events header
+    pub async fn allowed_events(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.packages.read().await.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub async fn exists(&self, dialog_id: &DialogId) -> bool {
+        self.data.read().await.contains_key(dialog_id)
+    }
+
+    pub async fn has_subscription_for(&self, msg: &impl DialogExt) -> bool {
+        self.key_for(msg).await.is_ok()
+    }
+
+    pub async fn process_incoming_request(&self, request: rsip::Request) -> Result<(), Error> {
+        match request.method {
+            rsip::Method::Subscribe => self.process_subscribe(request).await,
+            rsip::Method::Publish => self.process_publish(request).await,
+            _ => {
+                let response = presets::create_405_from(request.clone())?;
+                self.reply(request, response).await
+            }
+        }
+    }
+
+    //RFC6665 4.2.2, a NOTIFY that fails means that the subscriber is gone
+    pub async fn process_incoming_response(&self, response: rsip::Response) -> Result<(), Error> {
+        let dialog_id = self.key_for(&response).await?;
+
+        if response.status_code.kind() > rsip::StatusCodeKind::Successful {
+            common::log::warn!(
+                "({}): NOTIFY failed with {}, removing subscription",
+                dialog_id,
+                response.status_code
+            );
+            self.data.write().await.remove(&dialog_id);
+        }
+
+        Ok(())
+    }
+
+    //sends the current state of the resource to every subscriber of the given event package,
+    //to be called by the package whenever the resource changes
+    pub async fn notify(&self, event: &str, resource: &rsip::Uri) -> Result<(), Error> {
+        let package = match self.package(event).await {
+            Some(package) => package,
+            None => return Err(Error::custom(format!("unknown event package {}", event))),
+        };
+
+        let mut data = self.data.write().await;
+        for subscription in data.values_mut().filter(|subscription| {
+            subscription.event.eq_ignore_ascii_case(event)
+                && matches_resource(subscription.resource(), resource)
+        }) {
+            self.try_notify(package.as_ref(), subscription).await;
+        }
+
+        Ok(())
+    }
+
+    //ends the subscriptions to the resource, like when it goes away
+    pub async fn terminate(
+        &self,
+        event: &str,
+        resource: &rsip::Uri,
+        reason: TerminationReason,
+    ) -> Result<(), Error> {
+        let package = match self.package(event).await {
+            Some(package) => package,
+            None => return Err(Error::custom(format!("unknown event package {}", event))),
+        };
+
+        let mut data = self.data.write().await;
+        for subscription in data.values_mut().filter(|subscription| {
+            subscription.event.eq_ignore_ascii_case(event)
+                && matches_resource(subscription.resource(), resource)
+        }) {
+            subscription.terminate(reason);
+            self.try_notify(package.as_ref(), subscription).await;
+        }
+        data.retain(|_, subscription| !subscription.is_terminated());
+
+        Ok(())
+    }
+
+    pub async fn run_subscriptions(&self) {
+        use common::tokio::time;
+
+        let mut ticker = time::interval(time::Duration::from_millis(1000));
+        loop {
+            ticker.tick().await;
+
+            if let Err(err) = self.check_subscriptions().await {
+                common::log::error!("Error checking subscriptions: {}", err)
+            }
+            if let Err(err) = self.check_publications().await {
+                common::log::error!("Error checking publications: {}", err)
+            }
+        }
+    }
+
+    //RFC6665 4.2.2, subscriptions that weren't refreshed in time end with a final NOTIFY
+    pub async fn check_subscriptions(&self) -> Result<(), Error> {
+        let mut data = self.data.write().await;
+        for subscription in data.values_mut().filter(|s| s.has_expired()) {
+            subscription.terminate(TerminationReason::Timeout);
+            let package = match self.package(&subscription.event).await {
+                Some(package) => package,
+                None => continue,
+            };
+            self.try_notify(package.as_ref(), subscription).await;
+        }
+        data.retain(|_, subscription| !subscription.is_terminated());
+
+        Ok(())
+    }
+
+    //RFC3903 6, publications that weren't refreshed in time are removed, which changes
+    //the composed state of their presentities
+    pub async fn check_publications(&self) -> Result<(), Error> {
+        if !self.accepts_publications().await {
+            return Ok(());
+        }
+
+        for publication in self.stores.publications.delete_expired()? {
+            let resource = rsip::Uri::try_from(publication.presentity.as_str())?;
+            self.notify(&publication.event, &resource).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn accepts_publications(&self) -> bool {
+        self.packages
+            .read()
+            .await
+            .values()
+            .any(|package| package.accepts_publications())
+    }
+
+    //RFC3903 6, the event state compositor of the packages that accept publications
+    async fn process_publish(&self, request: rsip::Request) -> Result<(), Error> {
+        let package = match event_package(&request.headers) {
+            Some(name) => self.package(&name).await,
+            None => None,
+        }
+        .filter(|package| package.accepts_publications());
+        let package = match package {
+            Some(package) => package,
+            None => {
+                let response = self.bad_event_response(request.clone()).await?;
+                return self.reply(request, response).await;
+            }
+        };
+
+        let expires = match request.expires_header().map(|h| h.seconds()).transpose()? {
+            Some(expires) if expires > 0 && expires < MIN_EXPIRES => {
+                let mut response = presets::response_from(request.clone(), 423.into())?;
+                response.headers.push(min_expires_header(MIN_EXPIRES));
+                return self.reply(request, response).await;
+            }
+            Some(expires) => expires.min(package.default_expires()),
+            None => package.default_expires(),
+        };
+
+        let outcome = publication::publish(
+            self.stores.publications.as_ref(),
+            package.name(),
+            package.content_type(),
+            &request,
+            expires,
+        )?;
+        let response = match &outcome {
+            Outcome::Published(publication) => {
+                let mut response = presets::response_from(request.clone(), 200.into())?;
+                response
+                    .headers
+                    .push(publication::entity_tag_header(&publication.entity_tag));
+                response
+                    .headers
+                    .unique_push(rsip::headers::Expires::new(expires.to_string()).into());
+                response
+            }
+            Outcome::Removed(_) => {
+                let mut response = presets::response_from(request.clone(), 200.into())?;
+                response
+                    .headers
+                    .unique_push(rsip::headers::Expires::new("0").into());
+                response
+            }
+            Outcome::Failed(status_code) => {
+                let mut response = presets::response_from(request.clone(), status_code.clone())?;
+                if *status_code == rsip::StatusCode::from(415) {
+                    response
+                        .headers
+                        .push(rsip::headers::Accept::new(package.content_type()).into());
+                }
+                response
+            }
+        };
+        self.reply(request.clone(), response).await?;
+
+        match outcome {
+            Outcome::Failed(_) => Ok(()),
+            _ => self.notify(package.name(), &request.uri).await,
+        }
+    }
+
+    async fn process_subscribe(&self, request: rsip::Request) -> Result<(), Error> {
+        let package = match event_package(&request.headers) {
+            Some(name) => self.package(&name).await,
+            None => None,
+        };
+        let package = match package {
+            Some(package) => package,
+            None => {
+                let response = self.bad_event_response(request.clone()).await?;
+                return self.reply(request, response).await;
+            }
+        };
+
+        let expires = match request.expires_header().map(|h| h.seconds()).transpose()? {
+            Some(expires) if expires > 0 && expires < MIN_EXPIRES => {
+                let mut response = presets::response_from(request.clone(), 423.into())?;
+                response.headers.push(min_expires_header(MIN_EXPIRES));
+                return self.reply(request, response).await;
+            }
+            Some(expires) => expires.min(package.default_expires()),
+            None => package.default_expires(),
+        };
+
+        match request.to_header()?.tag()? {
+            Some(_) => self.refresh(package.as_ref(), request, expires).await,
+            None => self.subscribe(package.as_ref(), request, expires).await,
+        }
+    }
+
+    async fn subscribe(
+        &self,
+        package: &dyn EventPackage,
+        request: rsip::Request,
+        expires: u32,
+    ) -> Result<(), Error> {
+        let mut subscription = Subscription::new(
+            &self.handlers.config,
+            request.clone(),
+            package.name().to_string(),
+            expires,
+        )?;
+
+        if !package.authorize(&subscription).await? {
+            let response = presets::response_from(request.clone(), 403.into())?;
+            return self.reply(request, response).await;
+        }
+
+        let response = subscription.response_to(request.clone())?;
+        self.reply(request, response).await?;
+
+        //RFC6665 4.2.1.2, a SUBSCRIBE with Expires: 0 is a one time fetch of the state
+        if expires == 0 {
+            subscription.terminate(TerminationReason::Timeout);
+        }
+        self.send_notify(package, &mut subscription).await?;
+
+        if !subscription.is_terminated() {
+            self.data
+                .write()
+                .await
+                .insert(subscription.id.clone(), subscription);
+        }
+
+        Ok(())
+    }
+
+    async fn refresh(
+        &self,
+        package: &dyn EventPackage,
+        request: rsip::Request,
+        expires: u32,
+    ) -> Result<(), Error> {
+        let dialog_id = request.uas_dialog_id()?;
+
+        let mut data = self.data.write().await;
+        let subscription = match data.get_mut(&dialog_id) {
+            Some(subscription) => subscription,
+            None => {
+                let response = presets::response_from(request.clone(), 481.into())?;
+                return self.reply(request, response).await;
+            }
+        };
+
+        //RFC3261 12.2.2, a request out of order is still answered
+        if let Err(err) = subscription.refresh(&request, expires) {
+            common::log::warn!("({}): {}", dialog_id, err);
+            let response = presets::response_from(request.clone(), 500.into())?;
+            return self.reply(request, response).await;
+        }
+        let response = subscription.response_to(request.clone())?;
+        self.reply(request, response).await?;
+
+        //RFC6665 4.2.1.2, every accepted SUBSCRIBE is followed by a NOTIFY
+        self.send_notify(package, subscription).await?;
+        if subscription.is_terminated() {
+            data.remove(&dialog_id);
+        }
+
+        Ok(())
+    }
+
+    //one subscriber that can't be reached doesn't keep the others from being notified, nor
+    //the terminated subscriptions from being removed
+    async fn try_notify(&self, package: &dyn EventPackage, subscription: &mut Subscription) {
+        if let Err(err) = self.send_notify(package, subscription).await {
+            common::log::warn!("({}): failed to send NOTIFY: {}", subscription.id, err);
+        }
+    }
+
+    async fn send_notify(
+        &self,
+        package: &dyn EventPackage,
+        subscription: &mut Subscription,
+    ) -> Result<(), Error> {
+        //the state isn't given away before the subscription is authorized or once rejected
+        let body = match subscription.state {
+            SubscriptionState::Active
+            | SubscriptionState::Terminated(TerminationReason::Timeout) => {
+                package.state(subscription).await?
+            }
+            _ => vec![],
+        };
+        let notify =
+            subscription.notify_request(&self.handlers.config, package.content_type(), body)?;
+
+        Ok(self.handlers.transaction.new_uac(notify).await?)
+    }
+
+    async fn reply(&self, request: rsip::Request, response: rsip::Response) -> Result<(), Error> {
+        Ok(self
+            .handlers
+            .transaction
+            .new_uas(request, Some(response))
+            .await?)
+    }
+
+    //RFC6665 8.3.2, an unknown package gets a 489 listing the ones we serve
+    async fn bad_event_response(&self, request: rsip::Request) -> Result<rsip::Response, Error> {
+        let mut response = presets::response_from(request, 489.into())?;
+        response.headers.push(rsip::Header::Other(
+            "Allow-Events".into(),
+            self.allowed_events().await.join(", "),
+        ));
+
+        Ok(response)
+    }
+
+    async fn package(&self, name: &str) -> Option<Arc<dyn EventPackage>> {
+        self.packages
+            .read()
+            .await
+            .get(&name.to_ascii_lowercase())
+            .cloned()
+    }
+
+    //requests of the subscriber carry our tag in the To header, responses to our NOTIFYs
+    //in the From header
+    async fn key_for(&self, msg: &impl DialogExt) -> Result<DialogId, Error> {
+        let data = self.data.read().await;
+
+        [msg.uas_dialog_id(), msg.dialog_id()]
+            .into_iter()
+            .flatten()
+            .find(|dialog_id| data.contains_key(dialog_id))
+            .ok_or_else(|| Error::from(DialogError::NotFound))
+    }
+}
+
+//package of the Event header, o is its compact form
+pub fn event_package(headers: &rsip::Headers) -> Option<String> {
+    event_value(headers)?
+        .split(';')
+        .next()
+        .map(|package| package.trim().to_string())
+        .filter(|package| !package.is_empty())
+}
+
+pub fn event_id(headers: &rsip::Headers) -> Option<String> {
+    event_value(headers)?
+        .split(';')
+        .skip(1)
+        .find_map(|param| match param.split_once('=') {
+            Some((key, value)) if key.trim().eq_ignore_ascii_case("id") => {
+                Some(value.trim().to_string())
+            }
+            _ => None,
+        })
+}
+
+pub fn min_expires_header(seconds: u32) -> rsip::Header {
+    rsip::Header::Other("Min-Expires".into(), seconds.to_string())
+}
+
+fn event_value(headers: &rsip::Headers) -> Option<&str> {
+    headers.iter().find_map(|header| match header {
+        rsip::Header::Event(event) => Some(event.value()),
+        rsip::Header::Other(key, value) if key.eq_ignore_ascii_case("o") => Some(value.as_str()),
+        _ => None,
+    })
+}
+
+//resources are compared without their params, like a transport or a tag
+fn matches_resource(resource: &rsip::Uri, other: &rsip::Uri) -> bool {
+    resource.auth.as_ref().map(|auth| &auth.user) == other.auth.as_ref().map(|auth| &auth.user)
+        && resource.host_with_port == other.host_with_port
+}
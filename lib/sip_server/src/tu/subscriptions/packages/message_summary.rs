@@ -0,0 +1,203 @@
+use crate::{
+    presets,
+    tu::subscriptions::{publication, EventPackage, Subscription, Subscriptions},
+    Error,
+};
+use common::{
+    async_trait::async_trait,
+    rsip::{self, headers::UntypedHeader},
+    tokio::sync::RwLock,
+    Config,
+};
+use models::Handlers;
+use std::{collections::HashMap, fmt, sync::Arc};
+use store::Stores;
+
+pub const EVENT: &str = "message-summary";
+pub const CONTENT_TYPE: &str = "application/simple-message-summary";
+
+//message counts of a mailbox, RFC3842 5.2
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub new: u32,
+    pub old: u32,
+    pub new_urgent: u32,
+    pub old_urgent: u32,
+}
+
+//the message waiting indication package (RFC3842), the application updates the counts of
+//the mailboxes and subscribers get them in a NOTIFY. It's cheap to clone, so the
+//application can keep a clone around after registering it
+#[derive(Debug, Clone, Default)]
+pub struct MessageSummary {
+    mailboxes: Arc<RwLock<HashMap<String, Summary>>>,
+    //sends the counts to the registered contacts of the account even without a subscription
+    unsolicited: Option<Handlers>,
+    stores: Stores,
+}
+
+#[async_trait]
+impl EventPackage for MessageSummary {
+    fn name(&self) -> &str {
+        EVENT
+    }
+
+    fn content_type(&self) -> &str {
+        CONTENT_TYPE
+    }
+
+    //only the registered devices of the account may watch its mailbox
+    async fn authorize(&self, subscription: &Subscription) -> Result<bool, Error> {
+        let subscriber = subscription.subscriber();
+        let resource = subscription.resource();
+        if subscriber.auth != resource.auth
+            || subscriber.host_with_port.host != resource.host_with_port.host
+        {
+            return Ok(false);
+        }
+
+        Ok(!registrations_of(&self.stores, subscriber)?.is_empty())
+    }
+
+    async fn state(&self, subscription: &Subscription) -> Result<Vec<u8>, Error> {
+        let summary = self.summary(subscription.resource()).await;
+
+        Ok(body(subscription.resource(), &summary).into_bytes())
+    }
+}
+
+impl MessageSummary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //RFC3842 doesn't define unsolicited NOTIFYs but many devices expect them instead of
+    //subscribing
+    pub fn with_unsolicited_notify(mut self, handlers: Handlers) -> Self {
+        self.unsolicited = Some(handlers);
+        self
+    }
+
+    //has to be the stores of the registrar, only registered devices may subscribe
+    pub fn with_stores(mut self, stores: Stores) -> Self {
+        self.stores = stores;
+        self
+    }
+
+    pub async fn summary(&self, account: &rsip::Uri) -> Summary {
+        self.mailboxes
+            .read()
+            .await
+            .get(&publication::presentity(account))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    //stores the new counts of the mailbox and notifies its subscribers
+    pub async fn update(
+        &self,
+        subscriptions: &Subscriptions,
+        account: &rsip::Uri,
+        summary: Summary,
+    ) -> Result<(), Error> {
+        self.mailboxes
+            .write()
+            .await
+            .insert(publication::presentity(account), summary);
+
+        subscriptions.notify(EVENT, account).await?;
+        if let Some(handlers) = &self.unsolicited {
+            for registration in registrations_of(&self.stores, account)? {
+                let target = rsip::Uri::try_from(registration.contact_uri.as_str())?;
+                handlers
+                    .transaction
+                    .new_uac(unsolicited_notify(
+                        &handlers.config,
+                        account,
+                        target,
+                        &summary,
+                    ))
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Summary {
+    pub fn messages_waiting(&self) -> bool {
+        self.new > 0
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} ({}/{})",
+            self.new, self.old, self.new_urgent, self.old_urgent
+        )
+    }
+}
+
+//RFC3842 5.2, only voice messages are reported
+pub fn body(account: &rsip::Uri, summary: &Summary) -> String {
+    format!(
+        "Messages-Waiting: {}\r\nMessage-Account: {}\r\nVoice-Message: {}\r\n",
+        if summary.messages_waiting() {
+            "yes"
+        } else {
+            "no"
+        },
+        publication::presentity(account),
+        summary
+    )
+}
+
+fn unsolicited_notify(
+    config: &Config,
+    account: &rsip::Uri,
+    target: rsip::Uri,
+    summary: &Summary,
+) -> rsip::Request {
+    use rsip::headers::{ContentLength, ContentType, Event, SubscriptionState};
+
+    let body = body(account, summary).into_bytes();
+
+    let mut request = presets::in_dialog_request(config, rsip::Method::Notify);
+    request.headers.unique_push(
+        rsip::typed::From::from(account.clone())
+            .with_tag(Default::default())
+            .into(),
+    );
+    request
+        .headers
+        .unique_push(rsip::typed::To::from(account.clone()).into());
+    request.headers.push(Event::new(EVENT).into());
+    request
+        .headers
+        .push(SubscriptionState::new("active").into());
+    request
+        .headers
+        .unique_push(ContentType::new(CONTENT_TYPE).into());
+    request
+        .headers
+        .unique_push(ContentLength::new(body.len().to_string()).into());
+    request.uri = target;
+    request.body = body;
+
+    request
+}
+
+fn registrations_of(
+    stores: &Stores,
+    account: &rsip::Uri,
+) -> Result<Vec<store::Registration>, Error> {
+    match &account.auth {
+        Some(auth) => Ok(stores
+            .locations
+            .for_aor(&auth.user, &account.host_with_port.host.to_string())?),
+        None => Ok(vec![]),
+    }
+}
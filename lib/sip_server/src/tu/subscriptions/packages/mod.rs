@@ -0,0 +1,7 @@
+pub mod message_summary;
+pub mod presence;
+pub mod reg;
+
+pub use message_summary::MessageSummary;
+pub use presence::Presence;
+pub use reg::Reg;
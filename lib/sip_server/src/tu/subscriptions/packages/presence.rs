@@ -0,0 +1,136 @@
+use crate::{
+    tu::subscriptions::{publication, EventPackage, Subscription},
+    Error,
+};
+use common::{async_trait::async_trait, rsip};
+use store::Stores;
+
+pub const EVENT: &str = "presence";
+pub const CONTENT_TYPE: &str = "application/pidf+xml";
+
+//the presence event package (RFC3856), the state of a presentity is composed of the PIDF
+//documents that its devices PUBLISH
+#[derive(Debug, Default)]
+pub struct Presence {
+    stores: Stores,
+}
+
+#[async_trait]
+impl EventPackage for Presence {
+    fn name(&self) -> &str {
+        EVENT
+    }
+
+    fn content_type(&self) -> &str {
+        CONTENT_TYPE
+    }
+
+    fn accepts_publications(&self) -> bool {
+        true
+    }
+
+    //users of the same domain may watch each other
+    async fn authorize(&self, subscription: &Subscription) -> Result<bool, Error> {
+        Ok(subscription.subscriber().host_with_port.host
+            == subscription.resource().host_with_port.host)
+    }
+
+    async fn state(&self, subscription: &Subscription) -> Result<Vec<u8>, Error> {
+        let publications = self
+            .stores
+            .publications
+            .active_for(&publication::presentity(subscription.resource()), EVENT)?;
+        let documents = publications
+            .iter()
+            .map(|publication| publication.document.as_str())
+            .collect::<Vec<_>>();
+
+        Ok(compose(&entity(subscription.resource()), &documents).into_bytes())
+    }
+}
+
+impl Presence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //has to be the stores of the subscriptions, which keep what is published
+    pub fn with_stores(mut self, stores: Stores) -> Self {
+        self.stores = stores;
+        self
+    }
+}
+
+//RFC3863 4.1, the pres: uri of the presentity
+pub fn entity(resource: &rsip::Uri) -> String {
+    match &resource.auth {
+        Some(auth) => format!("pres:{}@{}", auth.user, resource.host_with_port.host),
+        None => format!("pres:{}", resource.host_with_port.host),
+    }
+}
+
+//merges the tuples of the published documents into a single PIDF document, a tuple that
+//is published again under the same id replaces the earlier one
+pub fn compose(entity: &str, documents: &[&str]) -> String {
+    let mut tuples: Vec<(Option<&str>, &str)> = vec![];
+    for tuple in documents.iter().flat_map(|document| tuples_of(document)) {
+        let id = tuple_id(tuple);
+        match tuples
+            .iter_mut()
+            .find(|(existing, _)| existing.is_some() && *existing == id)
+        {
+            Some(existing) => existing.1 = tuple,
+            None => tuples.push((id, tuple)),
+        }
+    }
+
+    let mut document = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\r\n<presence xmlns=\"urn:ietf:params:xml:ns:pidf\" entity=\"{}\">\r\n",
+        entity
+    );
+    for (_, tuple) in tuples {
+        document.push_str(tuple);
+        document.push_str("\r\n");
+    }
+    document.push_str("</presence>\r\n");
+
+    document
+}
+
+fn tuples_of(document: &str) -> Vec<&str> {
+    let mut tuples = vec![];
+    let mut rest = document;
+    while let Some(start) = find_tuple_start(rest) {
+        let end = match rest[start..].find("</tuple>") {
+            Some(end) => start + end + "</tuple>".len(),
+            None => break,
+        };
+        tuples.push(&rest[start..end]);
+        rest = &rest[end..];
+    }
+
+    tuples
+}
+
+//an opening <tuple, but not a <tuples or similar element
+fn find_tuple_start(document: &str) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(start) = document[offset..].find("<tuple") {
+        let start = offset + start;
+        match document[start + "<tuple".len()..].chars().next() {
+            Some(c) if c.is_whitespace() || c == '>' => return Some(start),
+            _ => offset = start + "<tuple".len(),
+        }
+    }
+
+    None
+}
+
+fn tuple_id(tuple: &str) -> Option<&str> {
+    let opening_tag = &tuple[..tuple.find('>')?];
+    let value = &opening_tag[opening_tag.find(" id=")? + " id=".len()..];
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &value[1..];
+
+    Some(&value[..value.find(quote)?])
+}
@@ -0,0 +1,211 @@
+use crate::{
+    tu::{
+        elements::{ContactEvent, RegistrationEvent},
+        subscriptions::{EventPackage, Subscription, Subscriptions},
+    },
+    Error,
+};
+use common::{
+    async_trait::async_trait,
+    chrono::Utc,
+    rsip,
+    tokio::sync::{broadcast, RwLock},
+};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+use store::Stores;
+
+pub const EVENT: &str = "reg";
+pub const CONTENT_TYPE: &str = "application/reginfo+xml";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContactState {
+    pub uri: rsip::Uri,
+    pub event: ContactEvent,
+    pub expires: u32,
+}
+
+//the registration event package (RFC3680), fed by the state changes of the registrar.
+//Cheap to clone, one clone is registered and another one runs the registrar events
+#[derive(Debug, Clone, Default)]
+pub struct Reg {
+    //contacts per address of record, terminated ones are reported once and then dropped
+    registrations: Arc<RwLock<HashMap<String, Vec<ContactState>>>>,
+    stores: Stores,
+}
+
+#[async_trait]
+impl EventPackage for Reg {
+    fn name(&self) -> &str {
+        EVENT
+    }
+
+    fn content_type(&self) -> &str {
+        CONTENT_TYPE
+    }
+
+    //users of the same domain, like the application servers of it, may watch registrations
+    async fn authorize(&self, subscription: &Subscription) -> Result<bool, Error> {
+        Ok(subscription.subscriber().host_with_port.host
+            == subscription.resource().host_with_port.host)
+    }
+
+    //RFC3680 5.3, the version increases with every NOTIFY of the subscription
+    async fn state(&self, subscription: &Subscription) -> Result<Vec<u8>, Error> {
+        let contacts = self.contacts(subscription.resource()).await?;
+
+        Ok(reginfo(subscription.resource(), subscription.local_seqn, &contacts).into_bytes())
+    }
+}
+
+impl Reg {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //has to be the stores of the registrar, for the bindings that didn't change since
+    pub fn with_stores(mut self, stores: Stores) -> Self {
+        self.stores = stores;
+        self
+    }
+
+    //to be spawned with the events of the registrar
+    pub async fn run(
+        &self,
+        subscriptions: Arc<Subscriptions>,
+        mut events: broadcast::Receiver<RegistrationEvent>,
+    ) {
+        use broadcast::error::RecvError;
+
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Err(err) = self.process(&subscriptions, event).await {
+                        common::log::error!("Error processing registration event: {}", err)
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    common::log::warn!("missed {} registration events", skipped)
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    pub async fn process(
+        &self,
+        subscriptions: &Subscriptions,
+        event: RegistrationEvent,
+    ) -> Result<(), Error> {
+        {
+            let mut registrations = self.registrations.write().await;
+            let contacts = registrations.entry(key_for(&event.aor)).or_default();
+            contacts
+                .retain(|contact| !contact.event.is_terminated() && contact.uri != event.contact);
+            contacts.push(ContactState {
+                uri: event.contact,
+                event: event.event,
+                expires: event.expires,
+            });
+        }
+
+        subscriptions.notify(EVENT, &event.aor).await
+    }
+
+    //addresses of record that didn't change since we started are loaded from the store
+    pub async fn contacts(&self, aor: &rsip::Uri) -> Result<Vec<ContactState>, Error> {
+        if let Some(contacts) = self.registrations.read().await.get(&key_for(aor)) {
+            return Ok(contacts.clone());
+        }
+
+        let username = match &aor.auth {
+            Some(auth) => auth.user.clone(),
+            None => return Ok(vec![]),
+        };
+        self.stores
+            .locations
+            .for_aor(&username, &aor.host_with_port.host.to_string())?
+            .into_iter()
+            .map(|registration| {
+                Ok(ContactState {
+                    uri: rsip::Uri::try_from(registration.contact_uri.as_str())?,
+                    event: ContactEvent::Registered,
+                    expires: (registration.expires - Utc::now()).num_seconds().max(0) as u32,
+                })
+            })
+            .collect()
+    }
+}
+
+//RFC3680 5.3, a full state document of a single address of record
+pub fn reginfo(aor: &rsip::Uri, version: u32, contacts: &[ContactState]) -> String {
+    let aor = key_for(aor);
+    let state = if contacts.is_empty() {
+        "init"
+    } else if contacts
+        .iter()
+        .any(|contact| !contact.event.is_terminated())
+    {
+        "active"
+    } else {
+        "terminated"
+    };
+
+    let mut document = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\r\n<reginfo xmlns=\"urn:ietf:params:xml:ns:reginfo\" version=\"{}\" state=\"full\">\r\n",
+        version
+    );
+    document.push_str(&format!(
+        "  <registration aor=\"{}\" id=\"{}\" state=\"{}\">\r\n",
+        escape(&aor),
+        id_for(&aor),
+        state
+    ));
+    for contact in contacts {
+        let uri = contact.uri.to_string();
+        match contact.event.is_terminated() {
+            true => document.push_str(&format!(
+                "    <contact id=\"{}\" state=\"terminated\" event=\"{}\">\r\n",
+                id_for(&uri),
+                contact.event
+            )),
+            false => document.push_str(&format!(
+                "    <contact id=\"{}\" state=\"active\" event=\"{}\" expires=\"{}\">\r\n",
+                id_for(&uri),
+                contact.event,
+                contact.expires
+            )),
+        }
+        document.push_str(&format!("      <uri>{}</uri>\r\n", escape(&uri)));
+        document.push_str("    </contact>\r\n");
+    }
+    document.push_str("  </registration>\r\n</reginfo>\r\n");
+
+    document
+}
+
+//registrations are kept per address of record, regardless of the port or params of the uri
+fn key_for(aor: &rsip::Uri) -> String {
+    match &aor.auth {
+        Some(auth) => format!("sip:{}@{}", auth.user, aor.host_with_port.host),
+        None => format!("sip:{}", aor.host_with_port.host),
+    }
+}
+
+//ids have to stay the same across NOTIFYs, so they are derived from what they identify
+fn id_for(value: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
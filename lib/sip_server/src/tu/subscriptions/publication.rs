@@ -0,0 +1,109 @@
+use crate::Error;
+use common::{
+    chrono::{Duration, Utc},
+    rsip::{self, headers::UntypedHeader},
+    uuid::Uuid,
+};
+use store::PublicationStore;
+
+//outcome of a PUBLISH on the event state compositor, RFC3903 6
+#[derive(Debug)]
+pub enum Outcome {
+    //the publication was created, refreshed or modified and carries its new entity tag
+    Published(store::Publication),
+    //a PUBLISH with Expires: 0 removed the publication
+    Removed(store::Publication),
+    Failed(rsip::StatusCode),
+}
+
+pub fn publish(
+    publications: &dyn PublicationStore,
+    event: &str,
+    content_type: &str,
+    request: &rsip::Request,
+    expires: u32,
+) -> Result<Outcome, Error> {
+    let presentity = presentity(&request.uri);
+    let has_body = !request.body.is_empty();
+
+    if has_body && !has_content_type(request, content_type) {
+        return Ok(Outcome::Failed(415.into()));
+    }
+
+    let existing = match if_match(&request.headers) {
+        Some(entity_tag) => {
+            match publications.find_by_entity_tag(&presentity, event, &entity_tag)? {
+                Some(publication) => Some(publication),
+                None => return Ok(Outcome::Failed(412.into())),
+            }
+        }
+        None => None,
+    };
+
+    let expires_at = Utc::now() + Duration::seconds(expires.into());
+    match existing {
+        //RFC3903 6 step 5, an initial PUBLISH must carry the event state
+        None if !has_body || expires == 0 => Ok(Outcome::Failed(400.into())),
+        None => Ok(Outcome::Published(publications.create(
+            store::DirtyPublication {
+                presentity: Some(presentity),
+                event: Some(event.into()),
+                entity_tag: Some(new_entity_tag()),
+                expires: Some(expires_at),
+                content_type: Some(content_type.into()),
+                document: Some(String::from_utf8_lossy(&request.body).into()),
+            },
+        )?)),
+        Some(publication) if expires == 0 => {
+            Ok(Outcome::Removed(publications.delete(publication.id)?))
+        }
+        //a refresh has no body and keeps the document, a modification replaces it, both
+        //get a new entity tag
+        Some(publication) => Ok(Outcome::Published(publications.update(
+            store::DirtyPublication {
+                entity_tag: Some(new_entity_tag()),
+                expires: Some(expires_at),
+                document: has_body.then(|| String::from_utf8_lossy(&request.body).into()),
+                ..Default::default()
+            },
+            publication.id,
+        )?)),
+    }
+}
+
+//publications are stored per presentity, without the params of the request uri
+pub fn presentity(uri: &rsip::Uri) -> String {
+    match &uri.auth {
+        Some(auth) => format!("sip:{}@{}", auth.user, uri.host_with_port),
+        None => format!("sip:{}", uri.host_with_port),
+    }
+}
+
+pub fn if_match(headers: &rsip::Headers) -> Option<String> {
+    headers.iter().find_map(|header| match header {
+        rsip::Header::Other(key, value) if key.eq_ignore_ascii_case("SIP-If-Match") => {
+            Some(value.trim().to_string())
+        }
+        _ => None,
+    })
+}
+
+pub fn entity_tag_header(entity_tag: &str) -> rsip::Header {
+    rsip::Header::Other("SIP-ETag".into(), entity_tag.into())
+}
+
+fn new_entity_tag() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+fn has_content_type(request: &rsip::Request, content_type: &str) -> bool {
+    request.headers.iter().any(|header| match header {
+        rsip::Header::ContentType(header) => header
+            .value()
+            .split(';')
+            .next()
+            .map(|value| value.trim().eq_ignore_ascii_case(content_type))
+            .unwrap_or(false),
+        _ => false,
+    })
+}
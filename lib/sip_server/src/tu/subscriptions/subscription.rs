@@ -0,0 +1,222 @@
+use crate::{presets, tu::dialogs::route_set, Error};
+use common::{
+    rsip::{self, headers::UntypedHeader, prelude::*, uri::UriWithParams},
+    tokio::time::{Duration, Instant},
+    Config,
+};
+use models::tu::DialogId;
+
+//state of a subscription as reported in the Subscription-State of its NOTIFYs, RFC6665 4.1.3
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscriptionState {
+    Active,
+    //waits for the authorization of the resource owner
+    Pending,
+    Terminated(TerminationReason),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminationReason {
+    //the subscriber let it expire or unsubscribed with Expires: 0
+    Timeout,
+    //the resource owner doesn't allow the subscription anymore
+    Rejected,
+    //the resource is gone
+    NoResource,
+    //the notifier goes away, the subscriber may try again later
+    Deactivated,
+}
+
+//the notifier side of a subscription dialog, created by an initial SUBSCRIBE
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub id: DialogId,
+    pub call_id: rsip::headers::CallId,
+    pub event: String,
+    //id param of the Event header, RFC6665 8.2.1
+    pub event_id: Option<String>,
+    pub local_tag: rsip::common::param::Tag,
+    pub local_seqn: u32,
+    pub local_uri: rsip::Uri,
+    pub remote_tag: rsip::common::param::Tag,
+    pub remote_seqn: u32,
+    pub remote_uri: rsip::Uri,
+    pub remote_target: rsip::Uri,
+    //like any UAS of a dialog, in the order of the Record-Route of the SUBSCRIBE
+    pub route_set: Vec<UriWithParams>,
+    pub contact_header: rsip::headers::Contact,
+    pub state: SubscriptionState,
+    pub expires_at: Instant,
+    pub request: rsip::Request,
+}
+
+impl Subscription {
+    pub fn new(
+        config: &Config,
+        request: rsip::Request,
+        event: String,
+        expires: u32,
+    ) -> Result<Self, Error> {
+        let local_tag = rsip::common::param::Tag::default();
+        let remote_tag = request
+            .from_header()?
+            .tag()?
+            .ok_or_else(|| Error::from("missing from tag"))?;
+        let contact_uri: rsip::Uri = config.default_addr().into();
+
+        Ok(Self {
+            id: DialogId::new(request.call_id_header()?, &local_tag, Some(&remote_tag)),
+            call_id: request.call_id_header()?.clone(),
+            event,
+            event_id: super::event_id(&request.headers),
+            local_tag,
+            local_seqn: 0,
+            local_uri: request.to_header()?.uri()?,
+            remote_tag,
+            remote_seqn: request.cseq_header()?.seq()?,
+            remote_uri: request.from_header()?.uri()?,
+            remote_target: request.contact_header()?.typed()?.uri,
+            route_set: route_set::from_record_routes(&request.headers)?,
+            contact_header: rsip::typed::Contact::from(contact_uri).into(),
+            state: SubscriptionState::Active,
+            expires_at: Instant::now() + Duration::from_secs(expires.into()),
+            request,
+        })
+    }
+
+    //the resource that is subscribed to, like the AOR of a presentity
+    pub fn resource(&self) -> &rsip::Uri {
+        &self.local_uri
+    }
+
+    pub fn subscriber(&self) -> &rsip::Uri {
+        &self.remote_uri
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        matches!(self.state, SubscriptionState::Terminated(_))
+    }
+
+    pub fn has_expired(&self) -> bool {
+        Instant::now() >= self.expires_at
+    }
+
+    pub fn expires(&self) -> u32 {
+        self.expires_at
+            .saturating_duration_since(Instant::now())
+            .as_secs() as u32
+    }
+
+    //RFC6665 4.2.1.2, a refresh with Expires: 0 ends the subscription
+    pub fn refresh(&mut self, request: &rsip::Request, expires: u32) -> Result<(), Error> {
+        let remote_seqn = request.cseq_header()?.seq()?;
+        if remote_seqn <= self.remote_seqn {
+            return Err(Error::from(format!(
+                "request remote seqn is lower than {}",
+                self.remote_seqn
+            )));
+        }
+        self.remote_seqn = remote_seqn;
+
+        if let Ok(contact) = request.contact_header() {
+            self.remote_target = contact.typed()?.uri;
+        }
+        self.expires_at = Instant::now() + Duration::from_secs(expires.into());
+        if expires == 0 {
+            self.state = SubscriptionState::Terminated(TerminationReason::Timeout);
+        }
+
+        Ok(())
+    }
+
+    pub fn terminate(&mut self, reason: TerminationReason) {
+        self.state = SubscriptionState::Terminated(reason);
+    }
+
+    //the 2xx to the SUBSCRIBE, carrying our tag and the Expires we settled on
+    pub fn response_to(&self, request: rsip::Request) -> Result<rsip::Response, Error> {
+        let mut response = presets::response_from(request, 200.into())?;
+        response.to_header_mut()?.mut_tag(self.local_tag.clone())?;
+        response
+            .headers
+            .unique_push(rsip::headers::Expires::new(self.expires().to_string()).into());
+        response
+            .headers
+            .unique_push(self.contact_header.clone().into());
+
+        Ok(response)
+    }
+
+    //RFC6665 4.2.2, every NOTIFY carries the Event and Subscription-State of the subscription
+    pub fn notify_request(
+        &mut self,
+        config: &Config,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<rsip::Request, Error> {
+        use rsip::headers::{ContentLength, ContentType};
+
+        self.local_seqn += 1;
+
+        let mut request = presets::in_dialog_request(config, rsip::Method::Notify);
+        request.headers.unique_push(
+            rsip::typed::From::from(self.local_uri.clone())
+                .with_tag(self.local_tag.clone())
+                .into(),
+        );
+        request.headers.unique_push(
+            rsip::typed::To::from(self.remote_uri.clone())
+                .with_tag(self.remote_tag.clone())
+                .into(),
+        );
+        request.headers.unique_push(self.call_id.clone().into());
+        request
+            .headers
+            .unique_push(rsip::typed::CSeq::from((self.local_seqn, rsip::Method::Notify)).into());
+        request
+            .headers
+            .unique_push(self.contact_header.clone().into());
+        request.headers.push(self.event_header());
+        request.headers.push(self.subscription_state_header());
+        if !body.is_empty() {
+            request
+                .headers
+                .unique_push(ContentType::new(content_type).into());
+        }
+        request
+            .headers
+            .unique_push(ContentLength::new(body.len().to_string()).into());
+        route_set::apply(&mut request, &self.route_set, self.remote_target.clone());
+        request.body = body;
+
+        Ok(request)
+    }
+
+    fn event_header(&self) -> rsip::Header {
+        match &self.event_id {
+            Some(id) => rsip::headers::Event::new(format!("{};id={}", self.event, id)).into(),
+            None => rsip::headers::Event::new(self.event.clone()).into(),
+        }
+    }
+
+    fn subscription_state_header(&self) -> rsip::Header {
+        let value = match &self.state {
+            SubscriptionState::Active => format!("active;expires={}", self.expires()),
+            SubscriptionState::Pending => format!("pending;expires={}", self.expires()),
+            SubscriptionState::Terminated(reason) => format!("terminated;reason={}", reason),
+        };
+
+        rsip::headers::SubscriptionState::new(value).into()
+    }
+}
+
+impl std::fmt::Display for TerminationReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "timeout"),
+            Self::Rejected => write!(f, "rejected"),
+            Self::NoResource => write!(f, "noresource"),
+            Self::Deactivated => write!(f, "deactivated"),
+        }
+    }
+}
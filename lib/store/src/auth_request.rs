@@ -1,5 +1,5 @@
 use crate::schema::auth_requests;
-use crate::{db_conn, Error};
+use crate::{DbConn, Error};
 use common::{
     chrono::{DateTime, Utc},
     uuid::Uuid,
@@ -60,18 +60,18 @@ impl LazyQuery {
         self
     }
 
-    pub fn load(self) -> Result<Vec<AuthRequest>, Error> {
-        Ok(self.query.get_results(&db_conn()?)?)
+    pub fn load(self, conn: &mut DbConn) -> Result<Vec<AuthRequest>, Error> {
+        Ok(self.query.get_results(conn)?)
     }
 
-    pub fn first(self) -> Result<Option<AuthRequest>, Error> {
-        Ok(self.query.first(&db_conn()?).optional()?)
+    pub fn first(self, conn: &mut DbConn) -> Result<Option<AuthRequest>, Error> {
+        Ok(self.query.first(conn).optional()?)
     }
 
-    pub fn exists(self) -> Result<bool, Error> {
+    pub fn exists(self, conn: &mut DbConn) -> Result<bool, Error> {
         use diesel::dsl::{exists, select};
 
-        Ok(select(exists(self.query)).get_result(&db_conn()?)?)
+        Ok(select(exists(self.query)).get_result(conn)?)
     }
 }
 
@@ -80,38 +80,42 @@ impl AuthRequest {
         LazyQuery::new(auth_requests::table.into_boxed())
     }
 
-    pub fn find(id: i64) -> Result<Self, Error> {
-        Ok(auth_requests::table.find(id).first::<Self>(&db_conn()?)?)
+    pub fn find(conn: &mut DbConn, id: i64) -> Result<Self, Error> {
+        Ok(auth_requests::table.find(id).first::<Self>(conn)?)
     }
 
-    pub fn create(record: impl Into<DirtyAuthRequest>) -> Result<Self, Error> {
+    pub fn create(conn: &mut DbConn, record: impl Into<DirtyAuthRequest>) -> Result<Self, Error> {
         use diesel::insert_into;
 
         Ok(insert_into(auth_requests::table)
             .values(record.into())
-            .get_result(&db_conn()?)?)
+            .get_result(conn)?)
     }
 
-    pub fn update(record: impl Into<DirtyAuthRequest>, id: i64) -> Result<Self, Error> {
+    pub fn update(
+        conn: &mut DbConn,
+        record: impl Into<DirtyAuthRequest>,
+        id: i64,
+    ) -> Result<Self, Error> {
         Ok(
             diesel::update(auth_requests::table.filter(auth_requests::id.eq(id)))
                 .set(&record.into())
-                .get_result(&db_conn()?)?,
+                .get_result(conn)?,
         )
     }
 
-    pub fn consumed(nonce: String) -> Result<Self, Error> {
+    pub fn consumed(conn: &mut DbConn, nonce: String) -> Result<Self, Error> {
         Ok(
             diesel::update(auth_requests::table.filter(auth_requests::nonce.eq(nonce)))
                 .set(auth_requests::consumed_at.eq(Utc::now()))
-                .get_result(&db_conn()?)?,
+                .get_result(conn)?,
         )
     }
 
-    pub fn delete(id: i64) -> Result<Self, Error> {
+    pub fn delete(conn: &mut DbConn, id: i64) -> Result<Self, Error> {
         Ok(
             diesel::delete(auth_requests::table.filter(auth_requests::id.eq(id)))
-                .get_result(&db_conn()?)?,
+                .get_result(conn)?,
         )
     }
 }
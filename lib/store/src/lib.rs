@@ -3,16 +3,17 @@ pub extern crate diesel;
 pub mod schema;
 
 use diesel::pg::PgConnection;
-use diesel::r2d2::{ConnectionManager, Pool};
-use once_cell::sync::Lazy;
-use std::sync::Arc;
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
 
 mod auth_request;
 //mod dialog;
 mod error;
+mod offline_message;
+mod publication;
 mod registration;
 mod request;
 mod response;
+mod stores;
 mod transaction;
 
 pub use auth_request::{AuthRequest, DirtyAuthRequest};
@@ -20,27 +21,18 @@ pub use auth_request::{AuthRequest, DirtyAuthRequest};
 //    Dialog, DialogFlow, DialogWithTransaction, DirtyDialog, DirtyDialogWithTransaction,
 //};
 pub use error::Error;
+pub use offline_message::{DirtyOfflineMessage, OfflineMessage};
+pub use publication::{DirtyPublication, Publication};
 pub use registration::{DirtyRegistration, Registration, Transport};
 pub use request::{DirtyRequest, Request};
 pub use response::{DirtyResponse, Response};
+pub use stores::{
+    InMemory, LocationStore, MessageLog, NonceStore, OfflineMessageStore, Postgres,
+    PublicationStore, Sqlite, Stores,
+};
 pub use transaction::{DirtyTransaction, Transaction, TransactionState};
 
 //type PgConn = diesel_logger::LoggingConnection<PgConnection>;
 type PgConn = PgConnection;
-pub type DbConn = diesel::r2d2::PooledConnection<diesel::r2d2::ConnectionManager<PgConn>>;
-
-static DB_POOL: Lazy<Arc<Pool<diesel::r2d2::ConnectionManager<PgConn>>>> = Lazy::new(|| {
-    let config = common::Config::default();
-    let manager = ConnectionManager::<PgConn>::new(config.database_url);
-
-    Arc::new(
-        Pool::builder()
-            .max_size(20)
-            .build(manager)
-            .expect("failed to build database pool"),
-    )
-});
-
-pub fn db_conn() -> Result<DbConn, r2d2::Error> {
-    DB_POOL.get()
-}
+pub type DbPool = Pool<ConnectionManager<PgConn>>;
+pub type DbConn = PooledConnection<ConnectionManager<PgConn>>;
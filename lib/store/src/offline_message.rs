@@ -0,0 +1,58 @@
+use crate::schema::offline_messages;
+use crate::{DbConn, Error};
+use common::chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+//a MESSAGE (RFC3428) kept until the recipient registers a contact
+#[derive(Queryable, AsChangeset, Insertable, Debug, Clone)]
+#[table_name = "offline_messages"]
+pub struct OfflineMessage {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub recipient: String,
+    pub sender: String,
+    pub content_type: String,
+    pub body: String,
+}
+
+#[derive(AsChangeset, Insertable, Debug, Default, Clone)]
+#[table_name = "offline_messages"]
+pub struct DirtyOfflineMessage {
+    pub recipient: Option<String>,
+    pub sender: Option<String>,
+    pub content_type: Option<String>,
+    pub body: Option<String>,
+}
+
+impl OfflineMessage {
+    //the messages waiting for the recipient, oldest first
+    pub fn for_recipient(conn: &mut DbConn, recipient: &str) -> Result<Vec<OfflineMessage>, Error> {
+        Ok(offline_messages::table
+            .filter(offline_messages::recipient.eq(recipient))
+            .order(offline_messages::created_at.asc())
+            .load::<OfflineMessage>(conn)?)
+    }
+
+    pub fn count(conn: &mut DbConn) -> Result<i64, Error> {
+        Ok(offline_messages::table.count().get_result(conn)?)
+    }
+
+    pub fn create(
+        conn: &mut DbConn,
+        record: impl Into<DirtyOfflineMessage>,
+    ) -> Result<Self, Error> {
+        use diesel::insert_into;
+
+        Ok(insert_into(offline_messages::table)
+            .values(record.into())
+            .get_result(conn)?)
+    }
+
+    pub fn delete(conn: &mut DbConn, id: i64) -> Result<Self, Error> {
+        Ok(
+            diesel::delete(offline_messages::table.filter(offline_messages::id.eq(id)))
+                .get_result(conn)?,
+        )
+    }
+}
@@ -0,0 +1,133 @@
+use crate::schema::publications;
+use crate::{DbConn, Error};
+use common::chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+#[derive(Debug, Default)]
+pub struct SearchFilter {
+    pub presentity: Option<String>,
+    pub event: Option<String>,
+    pub entity_tag: Option<String>,
+    //skips publications that have expired but are not removed yet
+    pub active: bool,
+}
+
+//event state published by an EPA (RFC3903), like a PIDF document of a presentity
+#[derive(Queryable, AsChangeset, Insertable, Debug, Clone)]
+#[table_name = "publications"]
+pub struct Publication {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub presentity: String,
+    pub event: String,
+    pub entity_tag: String,
+    pub expires: DateTime<Utc>,
+    pub content_type: String,
+    pub document: String,
+}
+
+#[derive(AsChangeset, Insertable, Debug, Default)]
+#[table_name = "publications"]
+pub struct DirtyPublication {
+    pub presentity: Option<String>,
+    pub event: Option<String>,
+    pub entity_tag: Option<String>,
+    pub expires: Option<DateTime<Utc>>,
+    pub content_type: Option<String>,
+    pub document: Option<String>,
+}
+
+impl Publication {
+    fn query_boxed(filter: SearchFilter) -> publications::BoxedQuery<'static, diesel::pg::Pg> {
+        let mut query = publications::table.into_boxed();
+
+        if let Some(presentity) = filter.presentity {
+            query = query.filter(publications::presentity.eq(presentity));
+        }
+
+        if let Some(event) = filter.event {
+            query = query.filter(publications::event.eq(event));
+        }
+
+        if let Some(entity_tag) = filter.entity_tag {
+            query = query.filter(publications::entity_tag.eq(entity_tag));
+        }
+
+        if filter.active {
+            query = query.filter(publications::expires.gt(Utc::now()));
+        }
+
+        query.order(publications::created_at.asc())
+    }
+
+    pub fn search(conn: &mut DbConn, filter: SearchFilter) -> Result<Vec<Publication>, Error> {
+        Ok(Self::query_boxed(filter).load::<Publication>(conn)?)
+    }
+
+    //the unexpired publications of the presentity for the given event package
+    pub fn active_for(
+        conn: &mut DbConn,
+        presentity: &str,
+        event: &str,
+    ) -> Result<Vec<Publication>, Error> {
+        Self::search(
+            conn,
+            SearchFilter {
+                presentity: Some(presentity.into()),
+                event: Some(event.into()),
+                active: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn find_by_entity_tag(
+        conn: &mut DbConn,
+        presentity: &str,
+        event: &str,
+        entity_tag: &str,
+    ) -> Result<Option<Publication>, Error> {
+        Ok(Self::query_boxed(SearchFilter {
+            presentity: Some(presentity.into()),
+            event: Some(event.into()),
+            entity_tag: Some(entity_tag.into()),
+            active: true,
+        })
+        .get_result::<Publication>(conn)
+        .optional()?)
+    }
+
+    pub fn create(conn: &mut DbConn, record: impl Into<DirtyPublication>) -> Result<Self, Error> {
+        use diesel::insert_into;
+
+        Ok(insert_into(publications::table)
+            .values(record.into())
+            .get_result(conn)?)
+    }
+
+    pub fn update(
+        conn: &mut DbConn,
+        record: impl Into<DirtyPublication>,
+        id: i64,
+    ) -> Result<Self, Error> {
+        Ok(
+            diesel::update(publications::table.filter(publications::id.eq(id)))
+                .set(&record.into())
+                .get_result(conn)?,
+        )
+    }
+
+    pub fn delete(conn: &mut DbConn, id: i64) -> Result<Self, Error> {
+        Ok(diesel::delete(publications::table.filter(publications::id.eq(id))).get_result(conn)?)
+    }
+
+    //removes the publications that weren't refreshed in time, returning them so that
+    //the watchers of their presentities can be notified
+    pub fn delete_expired(conn: &mut DbConn) -> Result<Vec<Self>, Error> {
+        Ok(
+            diesel::delete(publications::table.filter(publications::expires.le(Utc::now())))
+                .get_results(conn)?,
+        )
+    }
+}
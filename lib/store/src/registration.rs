@@ -1,5 +1,5 @@
 use crate::schema::registrations;
-use crate::{db_conn, Error};
+use crate::{DbConn, Error};
 use common::{
     chrono::{DateTime, Duration, Utc},
     ipnetwork::IpNetwork,
@@ -91,70 +91,97 @@ impl Registration {
         query
     }
 
-    pub fn search(filter: SearchFilter) -> Result<Vec<Registration>, Error> {
-        Ok(Self::query_boxed(filter).load::<Registration>(&mut db_conn()?)?)
+    pub fn search(conn: &mut DbConn, filter: SearchFilter) -> Result<Vec<Registration>, Error> {
+        Ok(Self::query_boxed(filter).load::<Registration>(conn)?)
     }
 
-    pub fn count(filter: SearchFilter) -> Result<i64, Error> {
-        Ok(Self::query_boxed(filter)
-            .count()
-            .get_result(&mut db_conn()?)?)
+    //the unexpired contacts registered for the address of record
+    pub fn for_aor(
+        conn: &mut DbConn,
+        username: &str,
+        domain: &str,
+    ) -> Result<Vec<Registration>, Error> {
+        Ok(Self::query_boxed(SearchFilter {
+            username: Some(username.into()),
+            domain: Some(domain.into()),
+            ..Default::default()
+        })
+        .filter(registrations::expires.gt(Utc::now()))
+        .load::<Registration>(conn)?)
+    }
+
+    pub fn count(conn: &mut DbConn, filter: SearchFilter) -> Result<i64, Error> {
+        Ok(Self::query_boxed(filter).count().get_result(conn)?)
     }
 
-    pub fn find_by(filter: SearchFilter) -> Result<Option<Registration>, Error> {
+    pub fn find_by(conn: &mut DbConn, filter: SearchFilter) -> Result<Option<Registration>, Error> {
         Ok(Self::query_boxed(filter)
-            .get_result::<Registration>(&mut db_conn()?)
+            .get_result::<Registration>(conn)
             .optional()?)
     }
 
-    pub fn find(id: i64) -> Result<Registration, Error> {
+    pub fn find(conn: &mut DbConn, id: i64) -> Result<Registration, Error> {
         Ok(registrations::table
             .filter(registrations::id.eq(id))
-            .get_result::<Registration>(&mut db_conn()?)?)
+            .get_result::<Registration>(conn)?)
     }
 
-    pub fn create(record: impl Into<DirtyRegistration>) -> Result<Self, Error> {
+    pub fn create(conn: &mut DbConn, record: impl Into<DirtyRegistration>) -> Result<Self, Error> {
         use diesel::insert_into;
 
         Ok(insert_into(registrations::table)
             .values(record.into())
-            .get_result(&mut db_conn()?)?)
+            .get_result(conn)?)
     }
 
     //TODO: fix me by adding proper indexes and using proper ON CONFLICT clauses
-    pub fn upsert(record: impl Into<DirtyRegistration>) -> Result<Self, Error> {
+    pub fn upsert(conn: &mut DbConn, record: impl Into<DirtyRegistration>) -> Result<Self, Error> {
         let record = record.into();
 
-        let existing_record = Self::find_by(SearchFilter {
-            username: record.username.clone(),
-            domain: record.domain.clone(),
-            ..Default::default()
-        })?;
+        let existing_record = Self::find_by(
+            conn,
+            SearchFilter {
+                username: record.username.clone(),
+                domain: record.domain.clone(),
+                ..Default::default()
+            },
+        )?;
         match existing_record {
-            Some(existing_record) => Ok(Self::update(record, existing_record.id)?),
-            None => Ok(Self::create(record)?),
+            Some(existing_record) => Ok(Self::update(conn, record, existing_record.id)?),
+            None => Ok(Self::create(conn, record)?),
         }
     }
 
-    pub fn update(record: impl Into<DirtyRegistration>, id: i64) -> Result<Self, Error> {
+    pub fn update(
+        conn: &mut DbConn,
+        record: impl Into<DirtyRegistration>,
+        id: i64,
+    ) -> Result<Self, Error> {
         Ok(
             diesel::update(registrations::table.filter(registrations::id.eq(id)))
                 .set(&record.into())
-                .get_result(&mut db_conn()?)?,
+                .get_result(conn)?,
         )
     }
 
-    pub fn delete(id: i64) -> Result<Self, Error> {
+    pub fn delete(conn: &mut DbConn, id: i64) -> Result<Self, Error> {
         Ok(
             diesel::delete(registrations::table.filter(registrations::id.eq(id)))
-                .get_result(&mut db_conn()?)?,
+                .get_result(conn)?,
+        )
+    }
+
+    pub fn delete_expired(conn: &mut DbConn) -> Result<Vec<Self>, Error> {
+        Ok(
+            diesel::delete(registrations::table.filter(registrations::expires.le(Utc::now())))
+                .get_results(conn)?,
         )
     }
 
-    pub fn delete_by_uri(uri: String) -> Result<Self, Error> {
+    pub fn delete_by_uri(conn: &mut DbConn, uri: String) -> Result<Self, Error> {
         Ok(
             diesel::delete(registrations::table.filter(registrations::contact_uri.eq(uri)))
-                .get_result(&mut db_conn()?)?,
+                .get_result(conn)?,
         )
     }
 }
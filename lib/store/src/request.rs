@@ -1,5 +1,5 @@
 use crate::schema::requests;
-use crate::{db_conn, Error};
+use crate::{DbConn, Error};
 use common::{
     chrono::{DateTime, Utc},
     rsip::{self, prelude::*},
@@ -50,12 +50,12 @@ impl LazyQuery {
         self
     }
 
-    pub fn load(self) -> Result<Vec<Request>, Error> {
-        Ok(self.query.get_results(&db_conn()?)?)
+    pub fn load(self, conn: &mut DbConn) -> Result<Vec<Request>, Error> {
+        Ok(self.query.get_results(conn)?)
     }
 
-    pub fn first(self) -> Result<Request, Error> {
-        Ok(self.query.first(&db_conn()?)?)
+    pub fn first(self, conn: &mut DbConn) -> Result<Request, Error> {
+        Ok(self.query.first(conn)?)
     }
 }
 
@@ -64,26 +64,30 @@ impl Request {
         LazyQuery::new(requests::table.into_boxed())
     }
 
-    pub fn find(id: i64) -> Result<Self, Error> {
-        Ok(requests::table.find(id).first::<Self>(&db_conn()?)?)
+    pub fn find(conn: &mut DbConn, id: i64) -> Result<Self, Error> {
+        Ok(requests::table.find(id).first::<Self>(conn)?)
     }
 
-    pub fn create(record: impl Into<DirtyRequest>) -> Result<Self, Error> {
+    pub fn create(conn: &mut DbConn, record: impl Into<DirtyRequest>) -> Result<Self, Error> {
         use diesel::insert_into;
 
         Ok(insert_into(requests::table)
             .values(record.into())
-            .get_result(&db_conn()?)?)
+            .get_result(conn)?)
     }
 
-    pub fn update(record: impl Into<DirtyRequest>, id: i64) -> Result<Self, Error> {
+    pub fn update(
+        conn: &mut DbConn,
+        record: impl Into<DirtyRequest>,
+        id: i64,
+    ) -> Result<Self, Error> {
         Ok(diesel::update(requests::table.filter(requests::id.eq(id)))
             .set(&record.into())
-            .get_result(&db_conn()?)?)
+            .get_result(conn)?)
     }
 
-    pub fn delete(id: i64) -> Result<Self, Error> {
-        Ok(diesel::delete(requests::table.filter(requests::id.eq(id))).get_result(&db_conn()?)?)
+    pub fn delete(conn: &mut DbConn, id: i64) -> Result<Self, Error> {
+        Ok(diesel::delete(requests::table.filter(requests::id.eq(id))).get_result(conn)?)
     }
 }
 
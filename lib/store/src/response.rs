@@ -1,5 +1,5 @@
 use crate::schema::responses;
-use crate::{db_conn, Error};
+use crate::{DbConn, Error};
 use common::{
     chrono::{DateTime, Utc},
     rsip::{self, prelude::*},
@@ -48,12 +48,12 @@ impl LazyQuery {
         self
     }
 
-    pub fn load(self) -> Result<Vec<Response>, Error> {
-        Ok(self.query.get_results(&db_conn()?)?)
+    pub fn load(self, conn: &mut DbConn) -> Result<Vec<Response>, Error> {
+        Ok(self.query.get_results(conn)?)
     }
 
-    pub fn first(self) -> Result<Response, Error> {
-        Ok(self.query.first(&db_conn()?)?)
+    pub fn first(self, conn: &mut DbConn) -> Result<Response, Error> {
+        Ok(self.query.first(conn)?)
     }
 }
 
@@ -62,28 +62,32 @@ impl Response {
         LazyQuery::new(responses::table.into_boxed())
     }
 
-    pub fn find(id: i64) -> Result<Self, Error> {
-        Ok(responses::table.find(id).first::<Self>(&db_conn()?)?)
+    pub fn find(conn: &mut DbConn, id: i64) -> Result<Self, Error> {
+        Ok(responses::table.find(id).first::<Self>(conn)?)
     }
 
-    pub fn create(record: impl Into<DirtyResponse>) -> Result<Self, Error> {
+    pub fn create(conn: &mut DbConn, record: impl Into<DirtyResponse>) -> Result<Self, Error> {
         use diesel::insert_into;
 
         Ok(insert_into(responses::table)
             .values(record.into())
-            .get_result(&db_conn()?)?)
+            .get_result(conn)?)
     }
 
-    pub fn update(record: impl Into<DirtyResponse>, id: i64) -> Result<Self, Error> {
+    pub fn update(
+        conn: &mut DbConn,
+        record: impl Into<DirtyResponse>,
+        id: i64,
+    ) -> Result<Self, Error> {
         Ok(
             diesel::update(responses::table.filter(responses::id.eq(id)))
                 .set(&record.into())
-                .get_result(&db_conn()?)?,
+                .get_result(conn)?,
         )
     }
 
-    pub fn delete(id: i64) -> Result<Self, Error> {
-        Ok(diesel::delete(responses::table.filter(responses::id.eq(id))).get_result(&db_conn()?)?)
+    pub fn delete(conn: &mut DbConn, id: i64) -> Result<Self, Error> {
+        Ok(diesel::delete(responses::table.filter(responses::id.eq(id))).get_result(conn)?)
     }
 }
 
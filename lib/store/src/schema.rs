@@ -21,6 +21,32 @@ table! {
     }
 }
 
+table! {
+    offline_messages (id) {
+        id -> Int8,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        recipient -> Varchar,
+        sender -> Varchar,
+        content_type -> Varchar,
+        body -> Text,
+    }
+}
+
+table! {
+    publications (id) {
+        id -> Int8,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        presentity -> Varchar,
+        event -> Varchar,
+        entity_tag -> Varchar,
+        expires -> Timestamptz,
+        content_type -> Varchar,
+        document -> Text,
+    }
+}
+
 table! {
     registrations (id) {
         id -> Int8,
@@ -82,6 +108,8 @@ joinable!(transactions -> dialogs (dialog_id));
 allow_tables_to_appear_in_same_query!(
     auth_requests,
     dialogs,
+    offline_messages,
+    publications,
     registrations,
     requests,
     responses,
@@ -0,0 +1,394 @@
+use super::{
+    required, LocationStore, MessageLog, NonceStore, OfflineMessageStore, PublicationStore,
+};
+use crate::{
+    AuthRequest, DirtyAuthRequest, DirtyOfflineMessage, DirtyPublication, DirtyRegistration,
+    DirtyRequest, DirtyResponse, Error, OfflineMessage, Publication, Registration, Request,
+    Response,
+};
+use common::chrono::{Duration, Utc};
+use std::sync::{Mutex, MutexGuard};
+
+//keeps everything in the process and behaves like the tables do: ids increase, records are
+//kept in the order they were created and a missing record is a not found error
+#[derive(Debug, Default)]
+pub struct InMemory {
+    data: Mutex<Data>,
+}
+
+#[derive(Debug, Default)]
+struct Data {
+    last_id: i64,
+    registrations: Vec<Registration>,
+    auth_requests: Vec<AuthRequest>,
+    requests: Vec<Request>,
+    responses: Vec<Response>,
+    offline_messages: Vec<OfflineMessage>,
+    publications: Vec<Publication>,
+}
+
+impl InMemory {
+    fn data(&self) -> MutexGuard<'_, Data> {
+        //nothing panics while holding the lock, the data is fine even if it's poisoned
+        self.data.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Data {
+    fn next_id(&mut self) -> i64 {
+        self.last_id += 1;
+        self.last_id
+    }
+}
+
+impl LocationStore for InMemory {
+    fn for_aor(&self, username: &str, domain: &str) -> Result<Vec<Registration>, Error> {
+        let now = Utc::now();
+
+        Ok(self
+            .data()
+            .registrations
+            .iter()
+            .filter(|registration| {
+                registration.username == username
+                    && registration.domain.as_deref() == Some(domain)
+                    && registration.expires > now
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn all(&self) -> Result<Vec<Registration>, Error> {
+        Ok(self.data().registrations.clone())
+    }
+
+    fn upsert(&self, record: DirtyRegistration) -> Result<Registration, Error> {
+        let mut data = self.data();
+        let existing = data.registrations.iter_mut().find(|registration| {
+            record
+                .username
+                .iter()
+                .all(|username| *username == registration.username)
+                && record
+                    .domain
+                    .iter()
+                    .all(|domain| Some(domain) == registration.domain.as_ref())
+        });
+
+        match existing {
+            Some(registration) => {
+                update_registration(registration, record);
+                Ok(registration.clone())
+            }
+            None => {
+                let now = Utc::now();
+                let registration = Registration {
+                    id: data.next_id(),
+                    created_at: now,
+                    updated_at: now,
+                    username: required(record.username, "username")?,
+                    domain: record.domain,
+                    contact: required(record.contact, "contact")?,
+                    expires: record.expires.unwrap_or_else(|| now + Duration::hours(1)),
+                    call_id: required(record.call_id, "call_id")?,
+                    cseq: required(record.cseq, "cseq")?,
+                    user_agent: required(record.user_agent, "user_agent")?,
+                    instance: record.instance,
+                    ip_address: required(record.ip_address, "ip_address")?,
+                    port: required(record.port, "port")?,
+                    transport: required(record.transport, "transport")?,
+                    contact_uri: required(record.contact_uri, "contact_uri")?,
+                };
+                data.registrations.push(registration.clone());
+
+                Ok(registration)
+            }
+        }
+    }
+
+    fn delete_by_uri(&self, uri: &str) -> Result<Registration, Error> {
+        let mut deleted = take_where(&mut self.data().registrations, |registration| {
+            registration.contact_uri == uri
+        });
+
+        match deleted.is_empty() {
+            true => Err(not_found()),
+            false => Ok(deleted.remove(0)),
+        }
+    }
+
+    fn delete_expired(&self) -> Result<Vec<Registration>, Error> {
+        let now = Utc::now();
+
+        Ok(take_where(&mut self.data().registrations, |registration| {
+            registration.expires <= now
+        }))
+    }
+}
+
+impl NonceStore for InMemory {
+    fn create(&self) -> Result<AuthRequest, Error> {
+        let record = DirtyAuthRequest::default();
+        let mut data = self.data();
+        let now = Utc::now();
+        let auth_request = AuthRequest {
+            id: data.next_id(),
+            created_at: now,
+            updated_at: now,
+            nonce: required(record.nonce, "nonce")?,
+            consumed_at: record.consumed_at,
+        };
+        data.auth_requests.push(auth_request.clone());
+
+        Ok(auth_request)
+    }
+
+    fn consume(&self, nonce: &str) -> Result<AuthRequest, Error> {
+        let mut data = self.data();
+        let auth_request = data
+            .auth_requests
+            .iter_mut()
+            .find(|auth_request| auth_request.nonce == nonce)
+            .ok_or_else(not_found)?;
+        auth_request.consumed_at = Some(Utc::now());
+        auth_request.updated_at = Utc::now();
+
+        Ok(auth_request.clone())
+    }
+}
+
+//the log grows until the process exits
+impl MessageLog for InMemory {
+    fn log_request(&self, record: DirtyRequest) -> Result<Request, Error> {
+        let mut data = self.data();
+        let now = Utc::now();
+        let request = Request {
+            id: data.next_id(),
+            created_at: now,
+            updated_at: now,
+            method: required(record.method, "method")?,
+            uri: required(record.uri, "uri")?,
+            headers: required(record.headers, "headers")?,
+            body: record.body,
+            raw_message: record.raw_message,
+        };
+        data.requests.push(request.clone());
+
+        Ok(request)
+    }
+
+    fn log_response(&self, record: DirtyResponse) -> Result<Response, Error> {
+        let mut data = self.data();
+        let now = Utc::now();
+        let response = Response {
+            id: data.next_id(),
+            created_at: now,
+            updated_at: now,
+            code: required(record.code, "code")?,
+            headers: required(record.headers, "headers")?,
+            body: record.body,
+            raw_message: record.raw_message,
+        };
+        data.responses.push(response.clone());
+
+        Ok(response)
+    }
+}
+
+impl OfflineMessageStore for InMemory {
+    fn for_recipient(&self, recipient: &str) -> Result<Vec<OfflineMessage>, Error> {
+        Ok(self
+            .data()
+            .offline_messages
+            .iter()
+            .filter(|message| message.recipient == recipient)
+            .cloned()
+            .collect())
+    }
+
+    fn create(&self, record: DirtyOfflineMessage) -> Result<OfflineMessage, Error> {
+        let mut data = self.data();
+        let now = Utc::now();
+        let message = OfflineMessage {
+            id: data.next_id(),
+            created_at: now,
+            updated_at: now,
+            recipient: required(record.recipient, "recipient")?,
+            sender: required(record.sender, "sender")?,
+            content_type: required(record.content_type, "content_type")?,
+            body: required(record.body, "body")?,
+        };
+        data.offline_messages.push(message.clone());
+
+        Ok(message)
+    }
+
+    fn delete(&self, id: i64) -> Result<OfflineMessage, Error> {
+        take_where(&mut self.data().offline_messages, |message| {
+            message.id == id
+        })
+        .pop()
+        .ok_or_else(not_found)
+    }
+}
+
+impl PublicationStore for InMemory {
+    fn active_for(&self, presentity: &str, event: &str) -> Result<Vec<Publication>, Error> {
+        let now = Utc::now();
+
+        Ok(self
+            .data()
+            .publications
+            .iter()
+            .filter(|publication| {
+                publication.presentity == presentity
+                    && publication.event == event
+                    && publication.expires > now
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn find_by_entity_tag(
+        &self,
+        presentity: &str,
+        event: &str,
+        entity_tag: &str,
+    ) -> Result<Option<Publication>, Error> {
+        Ok(self
+            .active_for(presentity, event)?
+            .into_iter()
+            .find(|publication| publication.entity_tag == entity_tag))
+    }
+
+    fn create(&self, record: DirtyPublication) -> Result<Publication, Error> {
+        let mut data = self.data();
+        let entity_tag = required(record.entity_tag, "entity_tag")?;
+        if data
+            .publications
+            .iter()
+            .any(|publication| publication.entity_tag == entity_tag)
+        {
+            return Err(Error::custom(format!(
+                "entity tag {} is already taken",
+                entity_tag
+            )));
+        }
+
+        let now = Utc::now();
+        let publication = Publication {
+            id: data.next_id(),
+            created_at: now,
+            updated_at: now,
+            presentity: required(record.presentity, "presentity")?,
+            event: required(record.event, "event")?,
+            entity_tag,
+            expires: required(record.expires, "expires")?,
+            content_type: required(record.content_type, "content_type")?,
+            document: required(record.document, "document")?,
+        };
+        data.publications.push(publication.clone());
+
+        Ok(publication)
+    }
+
+    fn update(&self, record: DirtyPublication, id: i64) -> Result<Publication, Error> {
+        let mut data = self.data();
+        let publication = data
+            .publications
+            .iter_mut()
+            .find(|publication| publication.id == id)
+            .ok_or_else(not_found)?;
+
+        if let Some(presentity) = record.presentity {
+            publication.presentity = presentity;
+        }
+        if let Some(event) = record.event {
+            publication.event = event;
+        }
+        if let Some(entity_tag) = record.entity_tag {
+            publication.entity_tag = entity_tag;
+        }
+        if let Some(expires) = record.expires {
+            publication.expires = expires;
+        }
+        if let Some(content_type) = record.content_type {
+            publication.content_type = content_type;
+        }
+        if let Some(document) = record.document {
+            publication.document = document;
+        }
+        publication.updated_at = Utc::now();
+
+        Ok(publication.clone())
+    }
+
+    fn delete(&self, id: i64) -> Result<Publication, Error> {
+        take_where(&mut self.data().publications, |publication| {
+            publication.id == id
+        })
+        .pop()
+        .ok_or_else(not_found)
+    }
+
+    fn delete_expired(&self) -> Result<Vec<Publication>, Error> {
+        let now = Utc::now();
+
+        Ok(take_where(&mut self.data().publications, |publication| {
+            publication.expires <= now
+        }))
+    }
+}
+
+//like an AsChangeset, the fields that are None are left as they are
+fn update_registration(registration: &mut Registration, record: DirtyRegistration) {
+    if let Some(username) = record.username {
+        registration.username = username;
+    }
+    if record.domain.is_some() {
+        registration.domain = record.domain;
+    }
+    if let Some(contact) = record.contact {
+        registration.contact = contact;
+    }
+    if let Some(expires) = record.expires {
+        registration.expires = expires;
+    }
+    if let Some(call_id) = record.call_id {
+        registration.call_id = call_id;
+    }
+    if let Some(cseq) = record.cseq {
+        registration.cseq = cseq;
+    }
+    if let Some(user_agent) = record.user_agent {
+        registration.user_agent = user_agent;
+    }
+    if record.instance.is_some() {
+        registration.instance = record.instance;
+    }
+    if let Some(ip_address) = record.ip_address {
+        registration.ip_address = ip_address;
+    }
+    if let Some(port) = record.port {
+        registration.port = port;
+    }
+    if let Some(transport) = record.transport {
+        registration.transport = transport;
+    }
+    if let Some(contact_uri) = record.contact_uri {
+        registration.contact_uri = contact_uri;
+    }
+    registration.updated_at = Utc::now();
+}
+
+//removes the matching records, returning them in the order they were created
+fn take_where<T>(records: &mut Vec<T>, matches: impl Fn(&T) -> bool) -> Vec<T> {
+    let (taken, kept): (Vec<T>, Vec<T>) = records.drain(..).partition(|record| matches(record));
+    *records = kept;
+
+    taken
+}
+
+fn not_found() -> Error {
+    Error::from(diesel::result::Error::NotFound)
+}
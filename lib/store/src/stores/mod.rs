@@ -0,0 +1,116 @@
+mod in_memory;
+mod postgres;
+mod sqlite;
+
+pub use in_memory::InMemory;
+pub use postgres::Postgres;
+pub use sqlite::Sqlite;
+
+use crate::{
+    AuthRequest, DirtyOfflineMessage, DirtyPublication, DirtyRegistration, DirtyRequest,
+    DirtyResponse, Error, OfflineMessage, Publication, Registration, Request, Response,
+};
+use std::{fmt::Debug, sync::Arc};
+
+//the bindings of the registrar, RFC3261 10.3
+pub trait LocationStore: Send + Sync + Debug {
+    //the unexpired contacts registered for the address of record
+    fn for_aor(&self, username: &str, domain: &str) -> Result<Vec<Registration>, Error>;
+    fn all(&self) -> Result<Vec<Registration>, Error>;
+    //a binding replaces the one of the same username and domain
+    fn upsert(&self, record: DirtyRegistration) -> Result<Registration, Error>;
+    fn delete_by_uri(&self, uri: &str) -> Result<Registration, Error>;
+    fn delete_expired(&self) -> Result<Vec<Registration>, Error>;
+}
+
+//the nonces of the digest challenges, RFC2617 3.2.1
+pub trait NonceStore: Send + Sync + Debug {
+    fn create(&self) -> Result<AuthRequest, Error>;
+    fn consume(&self, nonce: &str) -> Result<AuthRequest, Error>;
+}
+
+//a trace of the messages that went through the element
+pub trait MessageLog: Send + Sync + Debug {
+    fn log_request(&self, record: DirtyRequest) -> Result<Request, Error>;
+    fn log_response(&self, record: DirtyResponse) -> Result<Response, Error>;
+}
+
+pub trait OfflineMessageStore: Send + Sync + Debug {
+    //the messages waiting for the recipient, oldest first
+    fn for_recipient(&self, recipient: &str) -> Result<Vec<OfflineMessage>, Error>;
+    fn create(&self, record: DirtyOfflineMessage) -> Result<OfflineMessage, Error>;
+    fn delete(&self, id: i64) -> Result<OfflineMessage, Error>;
+}
+
+//the event state compositor, RFC3903
+pub trait PublicationStore: Send + Sync + Debug {
+    //the unexpired publications of the presentity for the given event package, oldest first
+    fn active_for(&self, presentity: &str, event: &str) -> Result<Vec<Publication>, Error>;
+    fn find_by_entity_tag(
+        &self,
+        presentity: &str,
+        event: &str,
+        entity_tag: &str,
+    ) -> Result<Option<Publication>, Error>;
+    fn create(&self, record: DirtyPublication) -> Result<Publication, Error>;
+    fn update(&self, record: DirtyPublication, id: i64) -> Result<Publication, Error>;
+    fn delete(&self, id: i64) -> Result<Publication, Error>;
+    fn delete_expired(&self) -> Result<Vec<Publication>, Error>;
+}
+
+//what the elements keep, cheap to clone so that every element that shares the data gets a
+//clone. Without a database, in memory is the default
+#[derive(Debug, Clone)]
+pub struct Stores {
+    pub locations: Arc<dyn LocationStore>,
+    pub nonces: Arc<dyn NonceStore>,
+    pub messages: Arc<dyn MessageLog>,
+    pub offline_messages: Arc<dyn OfflineMessageStore>,
+    pub publications: Arc<dyn PublicationStore>,
+}
+
+impl Stores {
+    //the database of the pool, its migrations are run with Postgres::run_migrations
+    pub fn postgres(backend: Postgres) -> Self {
+        Self::all_in(Arc::new(backend))
+    }
+
+    //nothing survives a restart, for development and tests without a database
+    pub fn in_memory() -> Self {
+        Self::all_in(Arc::new(InMemory::default()))
+    }
+
+    //a database file, without a server. Its migrations are run with Sqlite::run_migrations
+    pub fn sqlite(backend: Sqlite) -> Self {
+        Self::all_in(Arc::new(backend))
+    }
+
+    fn all_in<T>(backend: Arc<T>) -> Self
+    where
+        T: LocationStore
+            + NonceStore
+            + MessageLog
+            + OfflineMessageStore
+            + PublicationStore
+            + 'static,
+    {
+        Self {
+            locations: backend.clone(),
+            nonces: backend.clone(),
+            messages: backend.clone(),
+            offline_messages: backend.clone(),
+            publications: backend,
+        }
+    }
+}
+
+impl Default for Stores {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+//what a NOT NULL column without a default would reject
+fn required<T>(value: Option<T>, field: &str) -> Result<T, Error> {
+    value.ok_or_else(|| Error::custom(format!("missing {}", field)))
+}
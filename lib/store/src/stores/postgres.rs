@@ -0,0 +1,142 @@
+use super::{LocationStore, MessageLog, NonceStore, OfflineMessageStore, PublicationStore};
+use crate::{
+    AuthRequest, DbConn, DbPool, DirtyAuthRequest, DirtyOfflineMessage, DirtyPublication,
+    DirtyRegistration, DirtyRequest, DirtyResponse, Error, OfflineMessage, Publication,
+    Registration, Request, Response,
+};
+use diesel::{pg::PgConnection, r2d2::ConnectionManager};
+use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
+use std::fmt;
+
+const MIGRATIONS: EmbeddedMigrations = diesel_migrations::embed_migrations!("../../migrations");
+
+//the models on a pool of their own, so elements with different databases can share a process
+#[derive(Clone)]
+pub struct Postgres {
+    pool: DbPool,
+}
+
+impl Postgres {
+    pub fn new(database_url: &str) -> Result<Self, Error> {
+        let pool = DbPool::builder()
+            .max_size(20)
+            .build(ConnectionManager::<PgConnection>::new(database_url))?;
+
+        Ok(Self::with_pool(pool))
+    }
+
+    //a pool built elsewhere, like the single connection of a test transaction
+    pub fn with_pool(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    //runs the migrations that the database is missing, returns the versions that were run
+    pub fn run_migrations(&self) -> Result<Vec<String>, Error> {
+        Ok(self
+            .conn()?
+            .run_pending_migrations(MIGRATIONS)
+            .map_err(|err| Error::custom(err.to_string()))?
+            .into_iter()
+            .map(|version| version.to_string())
+            .collect())
+    }
+
+    pub fn conn(&self) -> Result<DbConn, Error> {
+        Ok(self.pool.get()?)
+    }
+}
+
+impl fmt::Debug for Postgres {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Postgres")
+            .field("connections", &self.pool.state().connections)
+            .finish()
+    }
+}
+
+impl LocationStore for Postgres {
+    fn for_aor(&self, username: &str, domain: &str) -> Result<Vec<Registration>, Error> {
+        Registration::for_aor(&mut self.conn()?, username, domain)
+    }
+
+    fn all(&self) -> Result<Vec<Registration>, Error> {
+        Registration::search(&mut self.conn()?, Default::default())
+    }
+
+    fn upsert(&self, record: DirtyRegistration) -> Result<Registration, Error> {
+        Registration::upsert(&mut self.conn()?, record)
+    }
+
+    fn delete_by_uri(&self, uri: &str) -> Result<Registration, Error> {
+        Registration::delete_by_uri(&mut self.conn()?, uri.into())
+    }
+
+    fn delete_expired(&self) -> Result<Vec<Registration>, Error> {
+        Registration::delete_expired(&mut self.conn()?)
+    }
+}
+
+impl NonceStore for Postgres {
+    fn create(&self) -> Result<AuthRequest, Error> {
+        AuthRequest::create(&mut self.conn()?, DirtyAuthRequest::default())
+    }
+
+    fn consume(&self, nonce: &str) -> Result<AuthRequest, Error> {
+        AuthRequest::consumed(&mut self.conn()?, nonce.into())
+    }
+}
+
+impl MessageLog for Postgres {
+    fn log_request(&self, record: DirtyRequest) -> Result<Request, Error> {
+        Request::create(&mut self.conn()?, record)
+    }
+
+    fn log_response(&self, record: DirtyResponse) -> Result<Response, Error> {
+        Response::create(&mut self.conn()?, record)
+    }
+}
+
+impl OfflineMessageStore for Postgres {
+    fn for_recipient(&self, recipient: &str) -> Result<Vec<OfflineMessage>, Error> {
+        OfflineMessage::for_recipient(&mut self.conn()?, recipient)
+    }
+
+    fn create(&self, record: DirtyOfflineMessage) -> Result<OfflineMessage, Error> {
+        OfflineMessage::create(&mut self.conn()?, record)
+    }
+
+    fn delete(&self, id: i64) -> Result<OfflineMessage, Error> {
+        OfflineMessage::delete(&mut self.conn()?, id)
+    }
+}
+
+impl PublicationStore for Postgres {
+    fn active_for(&self, presentity: &str, event: &str) -> Result<Vec<Publication>, Error> {
+        Publication::active_for(&mut self.conn()?, presentity, event)
+    }
+
+    fn find_by_entity_tag(
+        &self,
+        presentity: &str,
+        event: &str,
+        entity_tag: &str,
+    ) -> Result<Option<Publication>, Error> {
+        Publication::find_by_entity_tag(&mut self.conn()?, presentity, event, entity_tag)
+    }
+
+    fn create(&self, record: DirtyPublication) -> Result<Publication, Error> {
+        Publication::create(&mut self.conn()?, record)
+    }
+
+    fn update(&self, record: DirtyPublication, id: i64) -> Result<Publication, Error> {
+        Publication::update(&mut self.conn()?, record, id)
+    }
+
+    fn delete(&self, id: i64) -> Result<Publication, Error> {
+        Publication::delete(&mut self.conn()?, id)
+    }
+
+    fn delete_expired(&self) -> Result<Vec<Publication>, Error> {
+        Publication::delete_expired(&mut self.conn()?)
+    }
+}
@@ -0,0 +1,769 @@
+mod schema;
+
+use super::{
+    required, LocationStore, MessageLog, NonceStore, OfflineMessageStore, PublicationStore,
+};
+use crate::{
+    AuthRequest, DirtyAuthRequest, DirtyOfflineMessage, DirtyPublication, DirtyRegistration,
+    DirtyRequest, DirtyResponse, Error, OfflineMessage, Publication, Registration, Request,
+    Response, Transport,
+};
+use common::{
+    chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc},
+    ipnetwork::IpNetwork,
+};
+use diesel::{
+    prelude::*,
+    r2d2::{ConnectionManager, Pool, PooledConnection},
+    result::Error as DieselError,
+    sql_types::BigInt,
+    sqlite::SqliteConnection,
+};
+use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
+use schema::{auth_requests, offline_messages, publications, registrations, requests, responses};
+use std::{convert::TryFrom, fmt};
+
+const MIGRATIONS: EmbeddedMigrations =
+    diesel_migrations::embed_migrations!("../../migrations_sqlite");
+
+type Conn = PooledConnection<ConnectionManager<SqliteConnection>>;
+
+//a database file instead of a server, the database_url is its path. SQLite has a single
+//writer anyway, so there is a single connection that is never closed, which also keeps a
+//:memory: database around
+pub struct Sqlite {
+    database_url: String,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+}
+
+impl Sqlite {
+    pub fn new(database_url: &str) -> Result<Self, Error> {
+        let pool = Pool::builder()
+            .max_size(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .build(ConnectionManager::<SqliteConnection>::new(database_url))?;
+
+        Ok(Self {
+            database_url: database_url.into(),
+            pool,
+        })
+    }
+
+    //runs the migrations that the database is missing, returns the versions that were run
+    pub fn run_migrations(&self) -> Result<Vec<String>, Error> {
+        Ok(self
+            .conn()?
+            .run_pending_migrations(MIGRATIONS)
+            .map_err(|err| Error::custom(err.to_string()))?
+            .into_iter()
+            .map(|version| version.to_string())
+            .collect())
+    }
+
+    fn conn(&self) -> Result<Conn, Error> {
+        Ok(self.pool.get()?)
+    }
+}
+
+impl fmt::Debug for Sqlite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sqlite")
+            .field("database_url", &self.database_url)
+            .finish()
+    }
+}
+
+impl LocationStore for Sqlite {
+    fn for_aor(&self, username: &str, domain: &str) -> Result<Vec<Registration>, Error> {
+        registrations::table
+            .filter(registrations::username.eq(username))
+            .filter(registrations::domain.eq(domain))
+            .filter(registrations::expires.gt(Utc::now().naive_utc()))
+            .order(registrations::id.asc())
+            .load::<RegistrationRow>(&mut self.conn()?)?
+            .into_iter()
+            .map(Registration::try_from)
+            .collect()
+    }
+
+    fn all(&self) -> Result<Vec<Registration>, Error> {
+        registrations::table
+            .order(registrations::id.asc())
+            .load::<RegistrationRow>(&mut self.conn()?)?
+            .into_iter()
+            .map(Registration::try_from)
+            .collect()
+    }
+
+    //like the Postgres one, the binding of the same username and domain is updated
+    fn upsert(&self, record: DirtyRegistration) -> Result<Registration, Error> {
+        let mut query: registrations::BoxedQuery<'_, diesel::sqlite::Sqlite> =
+            registrations::table.into_boxed();
+        if let Some(username) = record.username.clone() {
+            query = query.filter(registrations::username.eq(username));
+        }
+        if let Some(domain) = record.domain.clone() {
+            query = query.filter(registrations::domain.eq(domain));
+        }
+
+        let mut conn = self.conn()?;
+        let existing = query
+            .first::<RegistrationRow>(&mut conn)
+            .optional()?
+            .map(|row| row.id);
+        let row = match existing {
+            Some(id) => {
+                let changes = RegistrationChanges::from(record);
+                conn.transaction(|conn| {
+                    diesel::update(registrations::table.find(id))
+                        .set(&changes)
+                        .execute(conn)?;
+                    registrations::table.find(id).first::<RegistrationRow>(conn)
+                })?
+            }
+            None => {
+                let new = NewRegistration::try_from(record)?;
+                conn.transaction(|conn| {
+                    diesel::insert_into(registrations::table)
+                        .values(&new)
+                        .execute(conn)?;
+                    registrations::table
+                        .find(last_id(conn)?)
+                        .first::<RegistrationRow>(conn)
+                })?
+            }
+        };
+
+        Registration::try_from(row)
+    }
+
+    fn delete_by_uri(&self, uri: &str) -> Result<Registration, Error> {
+        let mut rows = self.conn()?.transaction(|conn| {
+            let rows = registrations::table
+                .filter(registrations::contact_uri.eq(uri))
+                .order(registrations::id.asc())
+                .load::<RegistrationRow>(conn)?;
+            diesel::delete(registrations::table.filter(registrations::contact_uri.eq(uri)))
+                .execute(conn)?;
+            Ok::<_, DieselError>(rows)
+        })?;
+
+        match rows.is_empty() {
+            true => Err(DieselError::NotFound.into()),
+            false => Registration::try_from(rows.remove(0)),
+        }
+    }
+
+    fn delete_expired(&self) -> Result<Vec<Registration>, Error> {
+        let now = Utc::now().naive_utc();
+        self.conn()?
+            .transaction(|conn| {
+                let rows = registrations::table
+                    .filter(registrations::expires.le(now))
+                    .order(registrations::id.asc())
+                    .load::<RegistrationRow>(conn)?;
+                diesel::delete(registrations::table.filter(registrations::expires.le(now)))
+                    .execute(conn)?;
+                Ok::<_, DieselError>(rows)
+            })?
+            .into_iter()
+            .map(Registration::try_from)
+            .collect()
+    }
+}
+
+impl NonceStore for Sqlite {
+    fn create(&self) -> Result<AuthRequest, Error> {
+        let record = DirtyAuthRequest::default();
+        let now = Utc::now().naive_utc();
+        let new = NewAuthRequest {
+            created_at: now,
+            updated_at: now,
+            nonce: required(record.nonce, "nonce")?,
+            consumed_at: record.consumed_at.map(|at| at.naive_utc()),
+        };
+
+        let row = self.conn()?.transaction(|conn| {
+            diesel::insert_into(auth_requests::table)
+                .values(&new)
+                .execute(conn)?;
+            auth_requests::table
+                .find(last_id(conn)?)
+                .first::<AuthRequestRow>(conn)
+        })?;
+
+        Ok(row.into())
+    }
+
+    fn consume(&self, nonce: &str) -> Result<AuthRequest, Error> {
+        let now = Utc::now().naive_utc();
+        let row = self.conn()?.transaction(|conn| {
+            let row = auth_requests::table
+                .filter(auth_requests::nonce.eq(nonce))
+                .first::<AuthRequestRow>(conn)?;
+            diesel::update(auth_requests::table.find(row.id))
+                .set((
+                    auth_requests::consumed_at.eq(now),
+                    auth_requests::updated_at.eq(now),
+                ))
+                .execute(conn)?;
+            auth_requests::table
+                .find(row.id)
+                .first::<AuthRequestRow>(conn)
+        })?;
+
+        Ok(row.into())
+    }
+}
+
+impl MessageLog for Sqlite {
+    fn log_request(&self, record: DirtyRequest) -> Result<Request, Error> {
+        let now = Utc::now().naive_utc();
+        let new = NewRequest {
+            created_at: now,
+            updated_at: now,
+            method: required(record.method, "method")?,
+            uri: required(record.uri, "uri")?,
+            headers: required(record.headers, "headers")?,
+            body: record.body,
+            raw_message: record.raw_message,
+        };
+
+        let row = self.conn()?.transaction(|conn| {
+            diesel::insert_into(requests::table)
+                .values(&new)
+                .execute(conn)?;
+            requests::table
+                .find(last_id(conn)?)
+                .first::<RequestRow>(conn)
+        })?;
+
+        Ok(row.into())
+    }
+
+    fn log_response(&self, record: DirtyResponse) -> Result<Response, Error> {
+        let now = Utc::now().naive_utc();
+        let new = NewResponse {
+            created_at: now,
+            updated_at: now,
+            code: required(record.code, "code")?,
+            headers: required(record.headers, "headers")?,
+            body: record.body,
+            raw_message: record.raw_message,
+        };
+
+        let row = self.conn()?.transaction(|conn| {
+            diesel::insert_into(responses::table)
+                .values(&new)
+                .execute(conn)?;
+            responses::table
+                .find(last_id(conn)?)
+                .first::<ResponseRow>(conn)
+        })?;
+
+        Ok(row.into())
+    }
+}
+
+impl OfflineMessageStore for Sqlite {
+    fn for_recipient(&self, recipient: &str) -> Result<Vec<OfflineMessage>, Error> {
+        Ok(offline_messages::table
+            .filter(offline_messages::recipient.eq(recipient))
+            .order((
+                offline_messages::created_at.asc(),
+                offline_messages::id.asc(),
+            ))
+            .load::<OfflineMessageRow>(&mut self.conn()?)?
+            .into_iter()
+            .map(OfflineMessage::from)
+            .collect())
+    }
+
+    fn create(&self, record: DirtyOfflineMessage) -> Result<OfflineMessage, Error> {
+        let now = Utc::now().naive_utc();
+        let new = NewOfflineMessage {
+            created_at: now,
+            updated_at: now,
+            recipient: required(record.recipient, "recipient")?,
+            sender: required(record.sender, "sender")?,
+            content_type: required(record.content_type, "content_type")?,
+            body: required(record.body, "body")?,
+        };
+
+        let row = self.conn()?.transaction(|conn| {
+            diesel::insert_into(offline_messages::table)
+                .values(&new)
+                .execute(conn)?;
+            offline_messages::table
+                .find(last_id(conn)?)
+                .first::<OfflineMessageRow>(conn)
+        })?;
+
+        Ok(row.into())
+    }
+
+    fn delete(&self, id: i64) -> Result<OfflineMessage, Error> {
+        let row = self.conn()?.transaction(|conn| {
+            let row = offline_messages::table
+                .find(id)
+                .first::<OfflineMessageRow>(conn)?;
+            diesel::delete(offline_messages::table.find(id)).execute(conn)?;
+            Ok::<_, DieselError>(row)
+        })?;
+
+        Ok(row.into())
+    }
+}
+
+impl PublicationStore for Sqlite {
+    fn active_for(&self, presentity: &str, event: &str) -> Result<Vec<Publication>, Error> {
+        Ok(publications::table
+            .filter(publications::presentity.eq(presentity))
+            .filter(publications::event.eq(event))
+            .filter(publications::expires.gt(Utc::now().naive_utc()))
+            .order((publications::created_at.asc(), publications::id.asc()))
+            .load::<PublicationRow>(&mut self.conn()?)?
+            .into_iter()
+            .map(Publication::from)
+            .collect())
+    }
+
+    fn find_by_entity_tag(
+        &self,
+        presentity: &str,
+        event: &str,
+        entity_tag: &str,
+    ) -> Result<Option<Publication>, Error> {
+        Ok(publications::table
+            .filter(publications::presentity.eq(presentity))
+            .filter(publications::event.eq(event))
+            .filter(publications::entity_tag.eq(entity_tag))
+            .filter(publications::expires.gt(Utc::now().naive_utc()))
+            .first::<PublicationRow>(&mut self.conn()?)
+            .optional()?
+            .map(Publication::from))
+    }
+
+    fn create(&self, record: DirtyPublication) -> Result<Publication, Error> {
+        let now = Utc::now().naive_utc();
+        let new = NewPublication {
+            created_at: now,
+            updated_at: now,
+            presentity: required(record.presentity, "presentity")?,
+            event: required(record.event, "event")?,
+            entity_tag: required(record.entity_tag, "entity_tag")?,
+            expires: required(record.expires, "expires")?.naive_utc(),
+            content_type: required(record.content_type, "content_type")?,
+            document: required(record.document, "document")?,
+        };
+
+        let row = self.conn()?.transaction(|conn| {
+            diesel::insert_into(publications::table)
+                .values(&new)
+                .execute(conn)?;
+            publications::table
+                .find(last_id(conn)?)
+                .first::<PublicationRow>(conn)
+        })?;
+
+        Ok(row.into())
+    }
+
+    fn update(&self, record: DirtyPublication, id: i64) -> Result<Publication, Error> {
+        let changes = PublicationChanges::from(record);
+        let row = self.conn()?.transaction(|conn| {
+            diesel::update(publications::table.find(id))
+                .set(&changes)
+                .execute(conn)?;
+            publications::table.find(id).first::<PublicationRow>(conn)
+        })?;
+
+        Ok(row.into())
+    }
+
+    fn delete(&self, id: i64) -> Result<Publication, Error> {
+        let row = self.conn()?.transaction(|conn| {
+            let row = publications::table.find(id).first::<PublicationRow>(conn)?;
+            diesel::delete(publications::table.find(id)).execute(conn)?;
+            Ok::<_, DieselError>(row)
+        })?;
+
+        Ok(row.into())
+    }
+
+    fn delete_expired(&self) -> Result<Vec<Publication>, Error> {
+        let now = Utc::now().naive_utc();
+        let rows = self.conn()?.transaction(|conn| {
+            let rows = publications::table
+                .filter(publications::expires.le(now))
+                .order(publications::id.asc())
+                .load::<PublicationRow>(conn)?;
+            diesel::delete(publications::table.filter(publications::expires.le(now)))
+                .execute(conn)?;
+            Ok::<_, DieselError>(rows)
+        })?;
+
+        Ok(rows.into_iter().map(Publication::from).collect())
+    }
+}
+
+//the id of the row that was just inserted, on the same connection
+fn last_id(conn: &mut SqliteConnection) -> Result<i64, DieselError> {
+    diesel::select(diesel::dsl::sql::<BigInt>("last_insert_rowid()")).get_result(conn)
+}
+
+fn utc(at: NaiveDateTime) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&at)
+}
+
+//what FromStr of Transport parses back
+fn transport_name(transport: &Transport) -> String {
+    match transport {
+        Transport::TlsSctp => "tls-sctp".into(),
+        transport => transport.to_string().to_lowercase(),
+    }
+}
+
+#[derive(Queryable)]
+struct RegistrationRow {
+    id: i64,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    username: String,
+    domain: Option<String>,
+    contact: String,
+    expires: NaiveDateTime,
+    call_id: String,
+    cseq: i32,
+    user_agent: String,
+    instance: Option<String>,
+    ip_address: String,
+    port: i16,
+    transport: String,
+    contact_uri: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "registrations"]
+struct NewRegistration {
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    username: String,
+    domain: Option<String>,
+    contact: String,
+    expires: NaiveDateTime,
+    call_id: String,
+    cseq: i32,
+    user_agent: String,
+    instance: Option<String>,
+    ip_address: String,
+    port: i16,
+    transport: String,
+    contact_uri: String,
+}
+
+//like DirtyRegistration, the fields that are None are left as they are
+#[derive(AsChangeset)]
+#[table_name = "registrations"]
+struct RegistrationChanges {
+    updated_at: NaiveDateTime,
+    username: Option<String>,
+    domain: Option<String>,
+    contact: Option<String>,
+    expires: Option<NaiveDateTime>,
+    call_id: Option<String>,
+    cseq: Option<i32>,
+    user_agent: Option<String>,
+    instance: Option<String>,
+    ip_address: Option<String>,
+    port: Option<i16>,
+    transport: Option<String>,
+    contact_uri: Option<String>,
+}
+
+impl TryFrom<RegistrationRow> for Registration {
+    type Error = Error;
+
+    fn try_from(row: RegistrationRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            created_at: utc(row.created_at),
+            updated_at: utc(row.updated_at),
+            username: row.username,
+            domain: row.domain,
+            contact: row.contact,
+            expires: utc(row.expires),
+            call_id: row.call_id,
+            cseq: row.cseq,
+            user_agent: row.user_agent,
+            instance: row.instance,
+            ip_address: row
+                .ip_address
+                .parse::<IpNetwork>()
+                .map_err(|err| Error::custom(err.to_string()))?,
+            port: row.port,
+            transport: row.transport.parse::<Transport>()?,
+            contact_uri: row.contact_uri,
+        })
+    }
+}
+
+impl TryFrom<DirtyRegistration> for NewRegistration {
+    type Error = Error;
+
+    fn try_from(record: DirtyRegistration) -> Result<Self, Self::Error> {
+        let now = Utc::now();
+
+        Ok(Self {
+            created_at: now.naive_utc(),
+            updated_at: now.naive_utc(),
+            username: required(record.username, "username")?,
+            domain: record.domain,
+            contact: required(record.contact, "contact")?,
+            //the default of the Postgres column
+            expires: record
+                .expires
+                .unwrap_or_else(|| now + Duration::hours(1))
+                .naive_utc(),
+            call_id: required(record.call_id, "call_id")?,
+            cseq: required(record.cseq, "cseq")?,
+            user_agent: required(record.user_agent, "user_agent")?,
+            instance: record.instance,
+            ip_address: required(record.ip_address, "ip_address")?.to_string(),
+            port: required(record.port, "port")?,
+            transport: transport_name(&required(record.transport, "transport")?),
+            contact_uri: required(record.contact_uri, "contact_uri")?,
+        })
+    }
+}
+
+impl From<DirtyRegistration> for RegistrationChanges {
+    fn from(record: DirtyRegistration) -> Self {
+        Self {
+            updated_at: Utc::now().naive_utc(),
+            username: record.username,
+            domain: record.domain,
+            contact: record.contact,
+            expires: record.expires.map(|expires| expires.naive_utc()),
+            call_id: record.call_id,
+            cseq: record.cseq,
+            user_agent: record.user_agent,
+            instance: record.instance,
+            ip_address: record.ip_address.map(|ip_address| ip_address.to_string()),
+            port: record.port,
+            transport: record.transport.as_ref().map(transport_name),
+            contact_uri: record.contact_uri,
+        }
+    }
+}
+
+#[derive(Queryable)]
+struct AuthRequestRow {
+    id: i64,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    nonce: String,
+    consumed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "auth_requests"]
+struct NewAuthRequest {
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    nonce: String,
+    consumed_at: Option<NaiveDateTime>,
+}
+
+impl From<AuthRequestRow> for AuthRequest {
+    fn from(row: AuthRequestRow) -> Self {
+        Self {
+            id: row.id,
+            created_at: utc(row.created_at),
+            updated_at: utc(row.updated_at),
+            nonce: row.nonce,
+            consumed_at: row.consumed_at.map(utc),
+        }
+    }
+}
+
+#[derive(Queryable)]
+struct RequestRow {
+    id: i64,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    method: String,
+    uri: String,
+    headers: String,
+    body: Option<String>,
+    raw_message: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "requests"]
+struct NewRequest {
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    method: String,
+    uri: String,
+    headers: String,
+    body: Option<String>,
+    raw_message: Option<String>,
+}
+
+impl From<RequestRow> for Request {
+    fn from(row: RequestRow) -> Self {
+        Self {
+            id: row.id,
+            created_at: utc(row.created_at),
+            updated_at: utc(row.updated_at),
+            method: row.method,
+            uri: row.uri,
+            headers: row.headers,
+            body: row.body,
+            raw_message: row.raw_message,
+        }
+    }
+}
+
+#[derive(Queryable)]
+struct ResponseRow {
+    id: i64,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    code: i16,
+    headers: String,
+    body: Option<String>,
+    raw_message: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "responses"]
+struct NewResponse {
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    code: i16,
+    headers: String,
+    body: Option<String>,
+    raw_message: Option<String>,
+}
+
+impl From<ResponseRow> for Response {
+    fn from(row: ResponseRow) -> Self {
+        Self {
+            id: row.id,
+            created_at: utc(row.created_at),
+            updated_at: utc(row.updated_at),
+            code: row.code,
+            headers: row.headers,
+            body: row.body,
+            raw_message: row.raw_message,
+        }
+    }
+}
+
+#[derive(Queryable)]
+struct OfflineMessageRow {
+    id: i64,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    recipient: String,
+    sender: String,
+    content_type: String,
+    body: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "offline_messages"]
+struct NewOfflineMessage {
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    recipient: String,
+    sender: String,
+    content_type: String,
+    body: String,
+}
+
+impl From<OfflineMessageRow> for OfflineMessage {
+    fn from(row: OfflineMessageRow) -> Self {
+        Self {
+            id: row.id,
+            created_at: utc(row.created_at),
+            updated_at: utc(row.updated_at),
+            recipient: row.recipient,
+            sender: row.sender,
+            content_type: row.content_type,
+            body: row.body,
+        }
+    }
+}
+
+#[derive(Queryable)]
+struct PublicationRow {
+    id: i64,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    presentity: String,
+    event: String,
+    entity_tag: String,
+    expires: NaiveDateTime,
+    content_type: String,
+    document: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "publications"]
+struct NewPublication {
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    presentity: String,
+    event: String,
+    entity_tag: String,
+    expires: NaiveDateTime,
+    content_type: String,
+    document: String,
+}
+
+//like DirtyPublication, the fields that are None are left as they are
+#[derive(AsChangeset)]
+#[table_name = "publications"]
+struct PublicationChanges {
+    updated_at: NaiveDateTime,
+    presentity: Option<String>,
+    event: Option<String>,
+    entity_tag: Option<String>,
+    expires: Option<NaiveDateTime>,
+    content_type: Option<String>,
+    document: Option<String>,
+}
+
+impl From<PublicationRow> for Publication {
+    fn from(row: PublicationRow) -> Self {
+        Self {
+            id: row.id,
+            created_at: utc(row.created_at),
+            updated_at: utc(row.updated_at),
+            presentity: row.presentity,
+            event: row.event,
+            entity_tag: row.entity_tag,
+            expires: utc(row.expires),
+            content_type: row.content_type,
+            document: row.document,
+        }
+    }
+}
+
+impl From<DirtyPublication> for PublicationChanges {
+    fn from(record: DirtyPublication) -> Self {
+        Self {
+            updated_at: Utc::now().naive_utc(),
+            presentity: record.presentity,
+            event: record.event,
+            entity_tag: record.entity_tag,
+            expires: record.expires.map(|expires| expires.naive_utc()),
+            content_type: record.content_type,
+            document: record.document,
+        }
+    }
+}
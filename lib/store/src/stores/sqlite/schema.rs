@@ -0,0 +1,90 @@
+//the tables of migrations_sqlite, SQLite keeps timestamps as UTC text and addresses as text
+table! {
+    auth_requests (id) {
+        id -> BigInt,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        nonce -> Text,
+        consumed_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    offline_messages (id) {
+        id -> BigInt,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        recipient -> Text,
+        sender -> Text,
+        content_type -> Text,
+        body -> Text,
+    }
+}
+
+table! {
+    publications (id) {
+        id -> BigInt,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        presentity -> Text,
+        event -> Text,
+        entity_tag -> Text,
+        expires -> Timestamp,
+        content_type -> Text,
+        document -> Text,
+    }
+}
+
+table! {
+    registrations (id) {
+        id -> BigInt,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        username -> Text,
+        domain -> Nullable<Text>,
+        contact -> Text,
+        expires -> Timestamp,
+        call_id -> Text,
+        cseq -> Integer,
+        user_agent -> Text,
+        instance -> Nullable<Text>,
+        ip_address -> Text,
+        port -> SmallInt,
+        transport -> Text,
+        contact_uri -> Text,
+    }
+}
+
+table! {
+    requests (id) {
+        id -> BigInt,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        method -> Text,
+        uri -> Text,
+        headers -> Text,
+        body -> Nullable<Text>,
+        raw_message -> Nullable<Text>,
+    }
+}
+
+table! {
+    responses (id) {
+        id -> BigInt,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        code -> SmallInt,
+        headers -> Text,
+        body -> Nullable<Text>,
+        raw_message -> Nullable<Text>,
+    }
+}
+
+allow_tables_to_appear_in_same_query!(
+    auth_requests,
+    offline_messages,
+    publications,
+    registrations,
+    requests,
+    responses,
+);
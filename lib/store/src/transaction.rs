@@ -1,5 +1,5 @@
 use crate::schema::transactions;
-use crate::{db_conn, Error};
+use crate::{DbConn, Error};
 use common::chrono::{DateTime, Utc};
 use diesel::{
     deserialize::FromSql,
@@ -61,12 +61,12 @@ impl<'a> LazyQuery<'a> {
         self
     }
 
-    pub fn load(self) -> Result<Vec<Transaction>, Error> {
-        Ok(self.query.get_results(&mut db_conn()?)?)
+    pub fn load(self, conn: &mut DbConn) -> Result<Vec<Transaction>, Error> {
+        Ok(self.query.get_results(conn)?)
     }
 
-    pub fn first(self) -> Result<Transaction, Error> {
-        Ok(self.query.first(&mut db_conn()?)?)
+    pub fn first(self, conn: &mut DbConn) -> Result<Transaction, Error> {
+        Ok(self.query.first(conn)?)
     }
 }
 
@@ -75,33 +75,32 @@ impl<'a> Transaction {
         LazyQuery::new(transactions::table.into_boxed())
     }
 
-    pub fn find(id: i64) -> Result<Self, Error> {
-        Ok(transactions::table
-            .find(id)
-            .first::<Self>(&mut db_conn()?)?)
+    pub fn find(conn: &mut DbConn, id: i64) -> Result<Self, Error> {
+        Ok(transactions::table.find(id).first::<Self>(conn)?)
     }
 
-    pub fn create(record: impl Into<DirtyTransaction>) -> Result<Self, Error> {
+    pub fn create(conn: &mut DbConn, record: impl Into<DirtyTransaction>) -> Result<Self, Error> {
         use diesel::insert_into;
 
         Ok(insert_into(transactions::table)
             .values(record.into())
-            .get_result(&mut db_conn()?)?)
+            .get_result(conn)?)
     }
 
-    pub fn update(record: impl Into<DirtyTransaction>, id: i64) -> Result<Self, Error> {
+    pub fn update(
+        conn: &mut DbConn,
+        record: impl Into<DirtyTransaction>,
+        id: i64,
+    ) -> Result<Self, Error> {
         Ok(
             diesel::update(transactions::table.filter(transactions::id.eq(id)))
                 .set(&record.into())
-                .get_result(&mut db_conn()?)?,
+                .get_result(conn)?,
         )
     }
 
-    pub fn delete(id: i64) -> Result<Self, Error> {
-        Ok(
-            diesel::delete(transactions::table.filter(transactions::id.eq(id)))
-                .get_result(&mut db_conn()?)?,
-        )
+    pub fn delete(conn: &mut DbConn, id: i64) -> Result<Self, Error> {
+        Ok(diesel::delete(transactions::table.filter(transactions::id.eq(id))).get_result(conn)?)
     }
 }
 
@@ -0,0 +1,13 @@
+CREATE TABLE publications(
+  id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  presentity VARCHAR NOT NULL,
+  event VARCHAR NOT NULL,
+  entity_tag VARCHAR NOT NULL UNIQUE,
+  expires TIMESTAMP WITH TIME ZONE NOT NULL,
+  content_type VARCHAR NOT NULL,
+  document TEXT NOT NULL
+);
+CREATE INDEX publications_presentity_event_idx ON publications(presentity, event);
+SELECT diesel_manage_updated_at('publications');
@@ -0,0 +1,11 @@
+CREATE TABLE offline_messages(
+  id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  recipient VARCHAR NOT NULL,
+  sender VARCHAR NOT NULL,
+  content_type VARCHAR NOT NULL,
+  body TEXT NOT NULL
+);
+CREATE INDEX offline_messages_recipient_idx ON offline_messages(recipient);
+SELECT diesel_manage_updated_at('offline_messages');
@@ -0,0 +1,6 @@
+DROP TABLE publications;
+DROP TABLE offline_messages;
+DROP TABLE responses;
+DROP TABLE requests;
+DROP TABLE auth_requests;
+DROP TABLE registrations;
@@ -0,0 +1,72 @@
+CREATE TABLE registrations(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  username TEXT NOT NULL,
+  domain TEXT NULL,
+  contact TEXT NOT NULL,
+  expires TIMESTAMP NOT NULL,
+  call_id TEXT NOT NULL,
+  cseq INTEGER NOT NULL,
+  user_agent TEXT NOT NULL,
+  instance TEXT NULL,
+  ip_address TEXT NOT NULL,
+  port SMALLINT NOT NULL,
+  transport TEXT NOT NULL,
+  contact_uri TEXT NOT NULL
+);
+CREATE INDEX registrations_username_domain_idx ON registrations(username, domain);
+
+CREATE TABLE auth_requests(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  nonce TEXT NOT NULL,
+  consumed_at TIMESTAMP NULL
+);
+CREATE INDEX auth_requests_nonce_idx ON auth_requests(nonce);
+
+CREATE TABLE requests(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  method TEXT NOT NULL,
+  uri TEXT NOT NULL,
+  headers TEXT NOT NULL,
+  body TEXT NULL,
+  raw_message TEXT NULL
+);
+
+CREATE TABLE responses(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  code SMALLINT NOT NULL,
+  headers TEXT NOT NULL,
+  body TEXT NULL,
+  raw_message TEXT NULL
+);
+
+CREATE TABLE offline_messages(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  recipient TEXT NOT NULL,
+  sender TEXT NOT NULL,
+  content_type TEXT NOT NULL,
+  body TEXT NOT NULL
+);
+CREATE INDEX offline_messages_recipient_idx ON offline_messages(recipient);
+
+CREATE TABLE publications(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  presentity TEXT NOT NULL,
+  event TEXT NOT NULL,
+  entity_tag TEXT NOT NULL UNIQUE,
+  expires TIMESTAMP NOT NULL,
+  content_type TEXT NOT NULL,
+  document TEXT NOT NULL
+);
+CREATE INDEX publications_presentity_event_idx ON publications(presentity, event);
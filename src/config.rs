@@ -0,0 +1,233 @@
+use common::{ipnetwork::IpNetwork, rsip};
+use serde::Deserialize;
+use sip_server::{transaction::sm::Timers, transport::middleware::Acl};
+use std::{convert::TryFrom, net::SocketAddr, path::Path, time::Duration};
+
+//the config file of the server, database_url and listen_addrs win over the DATABASE_URL and
+//LISTEN_ADDRS env vars, which are still used for whatever the file leaves out
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub database_url: Option<String>,
+    //the addresses that the element is reached at, like in the Contact and Via it adds
+    pub listen_addrs: Option<Vec<String>>,
+    //where the UDP socket is bound
+    #[serde(default = "default_bind_addr")]
+    pub bind_addr: SocketAddr,
+    //seconds that the calls in flight get to finish on shutdown
+    #[serde(default = "default_shutdown_deadline")]
+    pub shutdown_deadline: u64,
+    #[serde(default)]
+    pub storage: Storage,
+    #[serde(default)]
+    pub elements: Elements,
+    #[serde(default)]
+    pub acl: AclConfig,
+    #[serde(default)]
+    pub timers: TimersConfig,
+}
+
+//where registrations, publications and offline messages are kept. sqlite is a database file,
+//with database_url as its path, in_memory needs no database and loses them on restart
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Storage {
+    #[default]
+    Postgres,
+    Sqlite,
+    InMemory,
+}
+
+//what the server does besides the UA itself
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct Elements {
+    pub registrar: bool,
+    pub capabilities: bool,
+    pub presence: bool,
+    pub b2bua: bool,
+    pub proxy: bool,
+}
+
+//the peers that get through, the deny rules are checked before the allow ones. Denied
+//requests are dropped unless reject_with is set
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AclConfig {
+    pub allow_by_default: bool,
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    pub reject_with: Option<u16>,
+}
+
+//RFC3261 17.1.1.1, in milliseconds
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimersConfig {
+    pub t1: u64,
+    pub t2: u64,
+    pub t4: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            database_url: None,
+            listen_addrs: None,
+            bind_addr: default_bind_addr(),
+            shutdown_deadline: default_shutdown_deadline(),
+            storage: Default::default(),
+            elements: Default::default(),
+            acl: Default::default(),
+            timers: Default::default(),
+        }
+    }
+}
+
+impl Default for Elements {
+    fn default() -> Self {
+        Self {
+            registrar: true,
+            capabilities: true,
+            presence: false,
+            b2bua: false,
+            proxy: false,
+        }
+    }
+}
+
+impl Default for AclConfig {
+    fn default() -> Self {
+        Self {
+            allow_by_default: true,
+            allow: vec![],
+            deny: vec![],
+            reject_with: None,
+        }
+    }
+}
+
+impl Default for TimersConfig {
+    fn default() -> Self {
+        let timers = Timers::default();
+
+        Self {
+            t1: timers.t1.as_millis() as u64,
+            t2: timers.t2.as_millis() as u64,
+            t4: timers.t4.as_millis() as u64,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+
+        toml::from_str(&contents).map_err(|err| format!("invalid {}: {}", path.display(), err))
+    }
+
+    //catches what would otherwise fail only once serving
+    pub fn check(&self) -> Result<(), String> {
+        if self.elements.proxy {
+            return Err("the proxy element is not available yet".into());
+        }
+        for addr in self.listen_addrs.iter().flatten() {
+            rsip::HostWithPort::try_from(addr.as_str())
+                .map_err(|err| format!("invalid listen addr {}: {}", addr, err))?;
+        }
+
+        self.acl.acl()?;
+        self.timers.timers()?;
+
+        let common = self.common()?;
+        if self.storage != Storage::InMemory && common.database_url.is_none() {
+            return Err("missing database url, neither given nor in DATABASE_URL env var".into());
+        }
+
+        Ok(())
+    }
+
+    pub fn common(&self) -> Result<common::Config, String> {
+        common::Config::new(self.database_url.clone(), self.listen_addrs.clone())
+    }
+}
+
+impl Storage {
+    pub fn stores(&self, config: &common::Config) -> Result<store::Stores, String> {
+        match self {
+            Self::Postgres => Ok(store::Stores::postgres(open_postgres(config)?)),
+            Self::Sqlite => Ok(store::Stores::sqlite(open_sqlite(config)?)),
+            Self::InMemory => Ok(store::Stores::in_memory()),
+        }
+    }
+}
+
+//a pool on the database of the postgres storage, every element gets the one of its config
+pub fn open_postgres(config: &common::Config) -> Result<store::Postgres, String> {
+    let database_url = config
+        .database_url
+        .as_deref()
+        .ok_or("missing database url, neither given nor in DATABASE_URL env var")?;
+
+    store::Postgres::new(database_url).map_err(|err| err.to_string())
+}
+
+//the database file of the sqlite storage
+pub fn open_sqlite(config: &common::Config) -> Result<store::Sqlite, String> {
+    let database_url = config
+        .database_url
+        .as_deref()
+        .ok_or("missing database url, neither given nor in DATABASE_URL env var")?;
+
+    store::Sqlite::new(database_url).map_err(|err| err.to_string())
+}
+
+impl AclConfig {
+    pub fn acl(&self) -> Result<Acl, String> {
+        let mut acl = match self.allow_by_default {
+            true => Acl::allowing_all(),
+            false => Acl::denying_all(),
+        };
+        for network in self.deny.iter() {
+            acl = acl.deny(network_from(network)?);
+        }
+        for network in self.allow.iter() {
+            acl = acl.allow(network_from(network)?);
+        }
+
+        match self.reject_with {
+            Some(code @ 400..=699) => Ok(acl.rejecting_with(code)),
+            Some(code) => Err(format!("invalid acl reject_with: {}", code)),
+            None => Ok(acl),
+        }
+    }
+}
+
+impl TimersConfig {
+    pub fn timers(&self) -> Result<Timers, String> {
+        if self.t1 == 0 || self.t2 < self.t1 {
+            return Err("timers t1 must be above 0 and t2 at least t1".into());
+        }
+
+        Ok(Timers {
+            t1: Duration::from_millis(self.t1),
+            t2: Duration::from_millis(self.t2),
+            t4: Duration::from_millis(self.t4),
+        })
+    }
+}
+
+fn network_from(network: &str) -> Result<IpNetwork, String> {
+    network
+        .parse()
+        .map_err(|err| format!("invalid acl network {}: {}", network, err))
+}
+
+fn default_bind_addr() -> SocketAddr {
+    ([0, 0, 0, 0], 5060).into()
+}
+
+fn default_shutdown_deadline() -> u64 {
+    32
+}
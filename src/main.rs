@@ -1,35 +1,261 @@
-// fn main() {}
-
-//TODO: this should be moved in a different/outside crate
+mod config;
 
+use common::{async_trait::async_trait, rsip};
+use config::{open_postgres, open_sqlite, Elements, ServerConfig, Storage};
+use models::Handlers;
 use sip_server::{
-    transaction::Transaction,
-    transport::Transport,
-    tu::elements::{Capabilities, Registrar},
+    transport::{
+        middleware::{Acl, Reloadable},
+        DnsLookup, Pipeline, SystemDnsLookup, TransportProcessor,
+    },
+    tu::{
+        calls::IncomingCall,
+        elements::{B2bua, Capabilities, Registrar, UserAgent},
+        router::{Route, Router},
+        subscriptions::packages::{Presence, Reg},
+        CallHandler,
+    },
+    Element, ElementBuilder,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    task::JoinHandle,
 };
 
+const USAGE: &str = "usage: viska [--config <path>] <serve|migrate|check-config>";
+
+#[derive(Debug)]
+enum Command {
+    Serve,
+    Migrate,
+    CheckConfig,
+}
+
+//calls are bridged when the B2BUA is enabled, there is nothing to take them otherwise
+#[derive(Debug)]
+enum Calls {
+    Bridged(B2bua),
+    Rejected,
+}
+
+#[async_trait]
+impl CallHandler for Calls {
+    async fn incoming_call(&self, call: IncomingCall) -> Result<(), sip_server::Error> {
+        match self {
+            Self::Bridged(b2bua) => b2bua.incoming_call(call).await,
+            Self::Rejected => call.reject(480).await,
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     common::pretty_env_logger::init_timed();
-    let _ = common::Config::default();
 
-    let (handlers, receivers) = models::channels_builder();
-    // let _ = UserAgent::new(
-    //     handlers.clone(),
-    //     receivers.tu,
-    //     Registrar::new(handlers.clone()),
-    //     Capabilities::new(handlers.clone()),
-    // );
+    let (command, config_path) = match parse_args(std::env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let config = load_config(config_path.as_deref());
+
+    let result = match (command, config) {
+        (_, Err(err)) => Err(err),
+        (Command::CheckConfig, Ok(_)) => {
+            println!("config is valid");
+            Ok(())
+        }
+        (Command::Migrate, Ok(config)) => migrate(config),
+        (Command::Serve, Ok(config)) => serve(config, config_path).await,
+    };
 
-    let _ = Transaction::new(handlers.clone(), receivers.transaction);
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
 
-    // let _ = Transport::new(handlers.clone(), receivers.transport);
+fn parse_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<(Command, Option<PathBuf>), String> {
+    let mut command = None;
+    let mut config_path = None;
 
-    tokio::spawn(async move {
-        loop {
-            std::thread::sleep(std::time::Duration::from_millis(4000))
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" | "--config" => match args.next() {
+                Some(path) => config_path = Some(path.into()),
+                None => return Err(format!("missing path after {}", arg)),
+            },
+            "serve" if command.is_none() => command = Some(Command::Serve),
+            "migrate" if command.is_none() => command = Some(Command::Migrate),
+            "check-config" if command.is_none() => command = Some(Command::CheckConfig),
+            _ => return Err(format!("unexpected argument: {}", arg)),
         }
-    })
-    .await
-    .expect("sleeping");
+    }
+
+    match command {
+        Some(command) => Ok((command, config_path)),
+        None => Err("missing command".into()),
+    }
+}
+
+fn load_config(path: Option<&Path>) -> Result<ServerConfig, String> {
+    let config = match path {
+        Some(path) => ServerConfig::from_file(path)?,
+        None => ServerConfig::default(),
+    };
+    config.check()?;
+
+    Ok(config)
+}
+
+fn migrate(config: ServerConfig) -> Result<(), String> {
+    let common = config.common()?;
+    let versions = match config.storage {
+        Storage::Postgres => open_postgres(&common)?.run_migrations(),
+        Storage::Sqlite => open_sqlite(&common)?.run_migrations(),
+        Storage::InMemory => return Err("in_memory storage has no database to migrate".into()),
+    }
+    .map_err(|err| err.to_string())?;
+    match versions.is_empty() {
+        true => println!("database is up to date"),
+        false => versions
+            .iter()
+            .for_each(|version| println!("ran migration {}", version)),
+    };
+
+    Ok(())
+}
+
+async fn serve(config: ServerConfig, config_path: Option<PathBuf>) -> Result<(), String> {
+    let common = config.common()?;
+    let elements = config.elements;
+    let stores = config.storage.stores(&common)?;
+    let builder = ElementBuilder::new(common, SystemDnsLookup);
+    //reloads only swap the router around it, so that its events keep their listeners
+    let registrar = Arc::new(Registrar::new(builder.handlers()).with_stores(stores.clone()));
+    //kept to swap the ACL of the pipeline on reload
+    let acl = Reloadable::new(config.acl.acl()?);
+    let pipeline = Pipeline::new(builder.handlers()).with(acl.clone());
+    let element = builder
+        .with_processor(pipeline)
+        .with_listen_addr(config.bind_addr)
+        .with_timers(config.timers.timers()?)
+        .with_shutdown_deadline(Duration::from_secs(config.shutdown_deadline))
+        .with_stores(stores.clone())
+        .build(|handlers, messages_rx, stores| {
+            let router = router_for(elements, handlers.clone(), &registrar);
+            let calls = match elements.b2bua {
+                true => Calls::Bridged(B2bua::default()),
+                false => Calls::Rejected,
+            };
+
+            UserAgent::with_stores(handlers, messages_rx, router, calls, stores)
+        })
+        .map_err(|err| err.to_string())?;
+
+    if elements.presence {
+        element
+            .tu()
+            .register_event_package(Presence::new().with_stores(stores.clone()))
+            .await;
+    }
+    let mut tasks: Vec<JoinHandle<()>> = vec![];
+    if elements.registrar {
+        let reg = Reg::new().with_stores(stores.clone());
+        element.tu().register_event_package(reg.clone()).await;
+        let (subscriptions, events) = (element.tu().subscriptions(), registrar.events());
+        tasks.push(tokio::spawn(
+            async move { reg.run(subscriptions, events).await },
+        ));
+        let registrar = registrar.clone();
+        tasks.push(tokio::spawn(
+            async move { registrar.run_registrations().await },
+        ));
+    }
+    common::log::info!("serving on {}", element.local_addr());
+
+    let mut hangup = signal(SignalKind::hangup()).map_err(|err| err.to_string())?;
+    loop {
+        tokio::select! {
+            _ = shutdown_signal() => break,
+            _ = hangup.recv() => match reload(&element, &acl, &registrar, &config, config_path.as_deref()).await {
+                Ok(()) => common::log::info!("reloaded routing, ACL and timers"),
+                Err(err) => common::log::error!("not reloading, keeping the old config: {}", err),
+            },
+        }
+    }
+    element.shutdown().await;
+    tasks.iter().for_each(JoinHandle::abort);
+
+    Ok(())
+}
+
+//swaps what can change while serving, the rest needs a restart
+async fn reload<P: TransportProcessor, D: DnsLookup>(
+    element: &Element<P, D, UserAgent<Calls>>,
+    acl: &Reloadable<Acl>,
+    registrar: &Arc<Registrar>,
+    config: &ServerConfig,
+    config_path: Option<&Path>,
+) -> Result<(), String> {
+    let new_config = load_config(config_path)?;
+    let (new_acl, timers) = (new_config.acl.acl()?, new_config.timers.timers()?);
+    if new_config.database_url != config.database_url
+        || new_config.listen_addrs != config.listen_addrs
+        || new_config.bind_addr != config.bind_addr
+        || new_config.storage != config.storage
+        || new_config.elements.registrar != config.elements.registrar
+        || new_config.elements.presence != config.elements.presence
+        || new_config.elements.b2bua != config.elements.b2bua
+    {
+        common::log::warn!(
+            "addresses, database, storage, registrar, presence and b2bua only change on restart"
+        );
+    }
+    //the expiry of the bindings runs only when the registrar was there from the start
+    let elements = Elements {
+        registrar: config.elements.registrar,
+        ..new_config.elements
+    };
+
+    acl.reload(new_acl).await;
+    element.reload_timers(timers).await;
+    element
+        .tu()
+        .reload_router(router_for(elements, element.handlers(), registrar))
+        .await;
+
+    Ok(())
+}
+
+//the registrar shares the stores of the UA, which delivers the offline messages
+fn router_for(elements: Elements, handlers: Handlers, registrar: &Arc<Registrar>) -> Router {
+    let mut router = Router::builder(handlers.clone());
+    if elements.registrar {
+        router = router.route(Route::shared(registrar.clone()).method(rsip::Method::Register));
+    }
+    if elements.capabilities {
+        router = router.method(rsip::Method::Options, Capabilities::new(handlers));
+    }
+
+    router.build()
+}
+
+//SIGTERM is what deploys send, ctrl-c is for running it by hand
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("listening for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = terminate.recv() => (),
+    }
 }
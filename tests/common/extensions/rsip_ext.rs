@@ -5,7 +5,7 @@ impl Randomized for rsip::Request {
     fn default() -> Self {
         Self {
             method: Method::default(),
-            uri: common::CONFIG.default_addr().into(),
+            uri: crate::common::config().default_addr().into(),
             version: Default::default(),
             headers: Randomized::default(),
             body: vec![],
@@ -23,7 +23,7 @@ impl Randomized for Headers {
     fn default() -> Self {
         let mut headers: Headers = Default::default();
 
-        let base_uri: Uri = common::CONFIG.default_addr().into();
+        let base_uri: Uri = crate::common::config().default_addr().into();
 
         let from_uri = base_uri.clone().with_user("filippos");
         let to_uri = base_uri.clone().with_user("fil").with_port(5090);
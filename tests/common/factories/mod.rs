@@ -1,6 +1,7 @@
 mod common;
 pub mod requests;
 pub mod responses;
+pub mod sessions;
 
 pub trait RandomizedBuilder {
     type Item;
@@ -12,6 +13,7 @@ pub mod prelude {
     pub use super::common::*;
     pub use super::requests;
     pub use super::responses;
+    pub use super::sessions;
     pub use super::RandomizedBuilder;
     pub use crate::common::extensions::*;
     pub use crate::common::factories;
@@ -48,6 +48,42 @@ pub fn invite_request() -> rsip::Request {
     }
 }
 
+pub fn subscribe_request(event: &str) -> rsip::Request {
+    let mut headers: Headers = Randomized::default();
+    headers.unique_push(typed::CSeq::from((1, Method::Subscribe)).into());
+    headers.push(Event::new(event).into());
+
+    let typed_to_header = rsip::header_opt!(headers.iter(), Header::To)
+        .unwrap()
+        .typed()
+        .unwrap();
+
+    rsip::Request {
+        method: Method::Subscribe,
+        uri: typed_to_header.uri.stripped(),
+        headers,
+        ..Randomized::default()
+    }
+}
+
+pub fn publish_request(event: &str) -> rsip::Request {
+    let mut headers: Headers = Randomized::default();
+    headers.unique_push(typed::CSeq::from((1, Method::Publish)).into());
+    headers.push(Event::new(event).into());
+
+    let typed_to_header = rsip::header_opt!(headers.iter(), Header::To)
+        .unwrap()
+        .typed()
+        .unwrap();
+
+    rsip::Request {
+        method: Method::Publish,
+        uri: typed_to_header.uri.stripped(),
+        headers,
+        ..Randomized::default()
+    }
+}
+
 pub fn bye_request() -> rsip::Request {
     let mut headers: Headers = Randomized::default();
     headers.unique_push(typed::CSeq::from((1, Method::Bye)).into());
@@ -69,7 +105,7 @@ pub fn register_query_request() -> rsip::Request {
     let mut headers: Headers = Randomized::default();
     headers.unique_push(typed::CSeq::from((1, Method::Register)).into());
 
-    let base_uri: Uri = common::CONFIG.default_addr().into();
+    let base_uri: Uri = crate::common::config().default_addr().into();
     let from_uri = base_uri.clone().with_user("filippos");
     let to_uri = from_uri.clone();
 
@@ -122,7 +158,7 @@ pub fn options_request() -> rsip::Request {
     let mut headers: Headers = Randomized::default();
     headers.unique_push(typed::CSeq::from((1, Method::Options)).into());
 
-    let base_uri: Uri = common::CONFIG.default_addr().into();
+    let base_uri: Uri = crate::common::config().default_addr().into();
     let to_uri = base_uri.clone().with_user("filippos");
 
     headers.unique_push(typed::To::from(to_uri.clone()).into());
@@ -0,0 +1,24 @@
+//SDP bodies, the session version is given so that successive offers can be built
+
+pub fn audio(version: u64) -> String {
+    format!(
+        "v=0\r\n\
+        o=alice 2890844526 {} IN IP4 10.0.0.1\r\n\
+        s=-\r\n\
+        c=IN IP4 10.0.0.1\r\n\
+        t=0 0\r\n\
+        m=audio 49170 RTP/AVP 0 8 97\r\n\
+        a=rtpmap:97 opus/48000/2\r\n\
+        a=fmtp:97 useinbandfec=1\r\n\
+        a=sendrecv\r\n",
+        version
+    )
+}
+
+pub fn audio_video(version: u64) -> String {
+    format!(
+        "{}m=video 51372 RTP/AVP 96\r\n\
+        a=rtpmap:96 H264/90000\r\n",
+        audio(version)
+    )
+}
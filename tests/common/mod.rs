@@ -2,71 +2,41 @@ pub mod extensions;
 pub mod factories;
 pub mod snitches;
 
+use diesel::{
+    connection::Connection,
+    pg::PgConnection,
+    r2d2::{ConnectionManager, CustomizeConnection},
+};
 use std::time::Duration;
 
-// use diesel_migrations;
-// diesel_migrations::embed_migrations!();
-
-use store::DbConn;
-
-pub fn setup() -> DbConn {
-    let conn = conn();
-    match std::env::var("TEST_ENV") {
-        Ok(_) => (),
-        //should run only once
-        Err(_) => {
-            std::env::set_var("TEST_ENV", "true");
-            std::env::set_var(
-                "DATABASE_URL",
-                std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL env var"),
-            );
-            //recreate_db(&conn);
-            setup_suite();
-        }
-    };
-
-    clean_db(&conn);
-
-    conn
-}
-
-pub fn setup_suite() {
-    //enable to debug tests
-    common::pretty_env_logger::init_timed();
+//the config of the elements under test, read from the env like the one of the binary
+pub fn config() -> common::Config {
+    common::Config::default()
 }
 
-pub fn conn() -> DbConn {
-    store::db_conn().expect("db conn")
+//a postgres store of its own for every test: a single connection in a transaction that is
+//never committed, so tests don't see each other's records and run in parallel
+pub fn postgres() -> store::Postgres {
+    let database_url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL env var");
+    let pool = store::DbPool::builder()
+        .max_size(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connection_customizer(Box::new(TestTransaction))
+        .build(ConnectionManager::new(database_url))
+        .expect("test database pool");
+
+    store::Postgres::with_pool(pool)
 }
 
-pub fn clean_db(conn: &DbConn) {
-    use diesel::RunQueryDsl;
-    use store::schema::auth_requests;
-    use store::schema::dialogs;
-    use store::schema::registrations;
-    use store::schema::requests;
-    use store::schema::responses;
-    //use store::schema::transactions;
+#[derive(Debug)]
+struct TestTransaction;
 
-    // embedded_migrations::run(conn).expect("running migrations");
-    diesel::delete(auth_requests::table)
-        .execute(conn)
-        .expect("deleting auth_requests");
-    diesel::delete(dialogs::table)
-        .execute(conn)
-        .expect("deleting dialogs");
-    diesel::delete(registrations::table)
-        .execute(conn)
-        .expect("deleting registrations");
-    diesel::delete(requests::table)
-        .execute(conn)
-        .expect("deleting requests");
-    diesel::delete(responses::table)
-        .execute(conn)
-        .expect("deleting responses");
-    diesel::delete(responses::table)
-        .execute(conn)
-        .expect("deleting responses");
+impl CustomizeConnection<PgConnection, diesel::r2d2::Error> for TestTransaction {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.begin_test_transaction()
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
 }
 
 pub async fn advance_for(duration: Duration) {
@@ -1,3 +1,4 @@
+pub mod sdp;
 pub mod sip_server;
 
 pub fn debug(udp_tuple: &models::transport::UdpTuple) {
@@ -0,0 +1,2 @@
+pub mod offer_answer;
+pub mod session;
@@ -0,0 +1,144 @@
+use crate::common::factories::prelude::*;
+use sdp::{OfferAnswer, OfferAnswerState, SessionDescription};
+
+fn session(body: String) -> SessionDescription {
+    body.parse().unwrap()
+}
+
+#[test]
+fn completes_an_exchange() {
+    let mut offer_answer = OfferAnswer::new();
+
+    offer_answer
+        .local_offer(session(sessions::audio(1)))
+        .unwrap();
+    assert!(offer_answer.has_pending_offer());
+
+    offer_answer
+        .remote_answer(session(sessions::audio(1)))
+        .unwrap();
+    assert_eq!(offer_answer.state, OfferAnswerState::Stable);
+    assert_eq!(offer_answer.local, Some(session(sessions::audio(1))));
+    assert_eq!(offer_answer.remote, Some(session(sessions::audio(1))));
+}
+
+#[test]
+fn allows_one_outstanding_offer() {
+    let mut offer_answer = OfferAnswer::new();
+
+    offer_answer
+        .local_offer(session(sessions::audio(1)))
+        .unwrap();
+    assert!(offer_answer
+        .remote_offer(session(sessions::audio(1)))
+        .is_err());
+    assert!(offer_answer
+        .local_answer(session(sessions::audio(1)))
+        .is_err());
+}
+
+#[test]
+fn rejects_an_answer_that_does_not_match_the_offer() {
+    let mut offer_answer = OfferAnswer::new();
+
+    offer_answer
+        .remote_offer(session(sessions::audio_video(1)))
+        .unwrap();
+    assert!(offer_answer
+        .local_answer(session(sessions::audio(1)))
+        .is_err());
+
+    let mut rejected_video = session(sessions::audio_video(1));
+    rejected_video.media[1].port = 0;
+    offer_answer.rollback();
+    offer_answer.remote_offer(rejected_video).unwrap();
+    assert!(offer_answer
+        .local_answer(session(sessions::audio_video(1)))
+        .is_err());
+    assert!(offer_answer.has_pending_offer());
+}
+
+#[test]
+fn rejects_a_new_offer_that_removes_media() {
+    let mut offer_answer = OfferAnswer::new();
+    offer_answer
+        .remote_offer(session(sessions::audio_video(1)))
+        .unwrap();
+    offer_answer
+        .local_answer(session(sessions::audio_video(1)))
+        .unwrap();
+
+    assert!(offer_answer
+        .remote_offer(session(sessions::audio(2)))
+        .is_err());
+    assert!(offer_answer
+        .remote_offer(session(sessions::audio_video(2)))
+        .is_ok());
+}
+
+#[test]
+fn requires_a_new_version_for_a_changed_description() {
+    let mut offer_answer = OfferAnswer::new();
+    offer_answer
+        .local_offer(session(sessions::audio(1)))
+        .unwrap();
+    offer_answer
+        .remote_answer(session(sessions::audio(1)))
+        .unwrap();
+
+    //same content and version, like a session refresh
+    offer_answer
+        .local_offer(session(sessions::audio(1)))
+        .unwrap();
+    offer_answer.rollback();
+
+    let changed = sessions::audio(1).replace("a=sendrecv", "a=sendonly");
+    assert!(offer_answer.local_offer(session(changed)).is_err());
+    assert!(offer_answer
+        .local_offer(session(sessions::audio(0)))
+        .is_err());
+}
+
+#[test]
+fn rolls_back_a_rejected_offer() {
+    let mut offer_answer = OfferAnswer::new();
+    offer_answer
+        .local_offer(session(sessions::audio(1)))
+        .unwrap();
+    offer_answer
+        .remote_answer(session(sessions::audio(1)))
+        .unwrap();
+
+    offer_answer
+        .local_offer(session(sessions::audio_video(2)))
+        .unwrap();
+    offer_answer.rollback();
+
+    assert_eq!(offer_answer.state, OfferAnswerState::Stable);
+    assert_eq!(offer_answer.local, Some(session(sessions::audio(1))));
+}
+
+#[test]
+fn ignores_a_repeated_answer() {
+    let mut offer_answer = OfferAnswer::new();
+    offer_answer
+        .remote_offer(session(sessions::audio(1)))
+        .unwrap();
+
+    //the answer in a reliable provisional, repeated in the 2xx
+    offer_answer
+        .local_description(session(sessions::audio(1)))
+        .unwrap();
+    offer_answer
+        .local_description(session(sessions::audio(1)))
+        .unwrap();
+    assert_eq!(offer_answer.state, OfferAnswerState::Stable);
+
+    offer_answer
+        .local_description(session(sessions::audio(2)))
+        .unwrap();
+    assert!(matches!(
+        offer_answer.state,
+        OfferAnswerState::LocalOffer(_)
+    ));
+}
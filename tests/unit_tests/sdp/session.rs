@@ -0,0 +1,96 @@
+use crate::common::factories::prelude::*;
+use sdp::{Attribute, Codec, Direction, MediaType, SessionDescription};
+use std::convert::TryFrom;
+
+#[test]
+fn parses_a_session_description() {
+    let session: SessionDescription = sessions::audio_video(1).parse().unwrap();
+
+    assert_eq!(session.version, 0);
+    assert_eq!(session.origin.username, "alice");
+    assert_eq!(session.origin.session_id, 2890844526);
+    assert_eq!(session.origin.session_version, 1);
+    assert_eq!(session.session_name, "-");
+    assert_eq!(session.connection.as_ref().unwrap().address, "10.0.0.1");
+    assert_eq!(session.timing.start, 0);
+    assert_eq!(session.media.len(), 2);
+
+    let audio = &session.media[0];
+    assert_eq!(audio.media, MediaType::Audio);
+    assert_eq!(audio.port, 49170);
+    assert_eq!(audio.protocol, "RTP/AVP");
+    assert_eq!(audio.formats, vec!["0", "8", "97"]);
+    assert_eq!(audio.direction(), Some(Direction::SendRecv));
+
+    let video = &session.media[1];
+    assert_eq!(video.media, MediaType::Video);
+    assert_eq!(video.direction(), None);
+    assert_eq!(session.media_direction(video), Direction::SendRecv);
+}
+
+#[test]
+fn serializes_back_to_the_same_description() {
+    let body = sessions::audio_video(1);
+    let session = SessionDescription::try_from(body.as_bytes()).unwrap();
+
+    assert_eq!(session.to_string(), body);
+    assert_eq!(Vec::<u8>::from(session), body.into_bytes());
+}
+
+#[test]
+fn resolves_static_and_dynamic_codecs() {
+    let session: SessionDescription = sessions::audio(1).parse().unwrap();
+
+    assert_eq!(
+        session.media[0].codecs(),
+        vec![
+            Codec::from_static(0).unwrap(),
+            Codec::from_static(8).unwrap(),
+            Codec {
+                payload_type: 97,
+                name: "opus".into(),
+                clock_rate: 48000,
+                channels: Some(2),
+                fmtp: Some("useinbandfec=1".into()),
+            },
+        ]
+    );
+}
+
+#[test]
+fn keeps_unknown_attributes() {
+    let body = sessions::audio(1).replace("a=sendrecv", "a=ptime:20\r\na=rtcp-mux");
+    let session: SessionDescription = body.parse().unwrap();
+
+    assert!(session.media[0]
+        .attributes
+        .contains(&Attribute::Value("ptime".into(), "20".into())));
+    assert!(session.media[0]
+        .attributes
+        .contains(&Attribute::Property("rtcp-mux".into())));
+    assert_eq!(session.to_string(), body);
+}
+
+#[test]
+fn sets_the_direction_of_a_media_line() {
+    let mut session: SessionDescription = sessions::audio(1).parse().unwrap();
+
+    session.media[0].set_direction(Direction::SendOnly);
+    assert_eq!(session.media[0].direction(), Some(Direction::SendOnly));
+    assert_eq!(
+        session.media[0].direction().unwrap().reversed(),
+        Direction::RecvOnly
+    );
+    assert_eq!(session.to_string().matches("a=sendonly").count(), 1);
+    assert!(!session.to_string().contains("a=sendrecv"));
+}
+
+#[test]
+fn fails_on_missing_mandatory_lines() {
+    let body = sessions::audio(1).replace("o=alice 2890844526 1 IN IP4 10.0.0.1\r\n", "");
+
+    assert!(body.parse::<SessionDescription>().is_err());
+    assert!("m=audio port RTP/AVP 0"
+        .parse::<SessionDescription>()
+        .is_err());
+}
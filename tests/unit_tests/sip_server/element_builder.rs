@@ -0,0 +1,150 @@
+use crate::common::{delay_for, factories::prelude::*, snitches::SpySnitch};
+use common::{
+    async_trait::async_trait,
+    bytes::Bytes,
+    rsip::{self, prelude::*},
+    tokio::{net::UdpSocket, sync::Mutex, time::timeout},
+};
+use models::transaction::TransactionLayerMsg;
+use sip_server::{
+    transport::{DefaultProcessor, SystemDnsLookup},
+    tu::{calls::IncomingCall, elements::UserAgent, router::Router, CallHandler},
+    Element, ElementBuilder, TuElement,
+};
+use std::{
+    convert::TryFrom,
+    time::{Duration, Instant},
+};
+
+//keeps the calls ringing, so that they are still in flight on shutdown
+#[derive(Debug, Default)]
+struct Holder(Mutex<Vec<IncomingCall>>);
+
+#[async_trait]
+impl CallHandler for Holder {
+    async fn incoming_call(&self, call: IncomingCall) -> Result<(), sip_server::Error> {
+        self.0.lock().await.push(call);
+        Ok(())
+    }
+}
+
+fn element_with_deadline(
+    deadline: Duration,
+) -> Element<DefaultProcessor, SystemDnsLookup, UserAgent<Holder>> {
+    ElementBuilder::new(crate::common::config(), SystemDnsLookup)
+        .with_listen_addr("127.0.0.1:0".parse().unwrap())
+        .with_shutdown_deadline(deadline)
+        .build(|handlers, messages_rx, ()| {
+            let router = Router::builder(handlers.clone()).build();
+            UserAgent::new(handlers, messages_rx, router, Holder::default())
+        })
+        .unwrap()
+}
+
+//the responses of the element come back to the Via of the request
+fn from_socket(mut request: rsip::Request, socket: &UdpSocket) -> rsip::Request {
+    request.headers.unique_push(
+        rsip::headers::Via::new(format!(
+            "SIP/2.0/UDP {};branch=z9hG4bK{}",
+            socket.local_addr().unwrap(),
+            common::uuid::Uuid::new_v4().simple()
+        ))
+        .into(),
+    );
+
+    request
+}
+
+//None when nothing comes back, like when the element is down
+async fn exchange(socket: &UdpSocket, request: &rsip::Request) -> Option<rsip::Response> {
+    socket.send(request.to_string().as_bytes()).await.unwrap();
+
+    let mut buf = vec![0; 4096];
+    loop {
+        let len = timeout(Duration::from_millis(500), socket.recv(&mut buf))
+            .await
+            .ok()?
+            .ok()?;
+
+        match rsip::SipMessage::try_from(Bytes::copy_from_slice(&buf[..len])).unwrap() {
+            rsip::SipMessage::Response(response)
+                if response.call_id_header().unwrap() == request.call_id_header().unwrap() =>
+            {
+                return Some(response)
+            }
+            _ => continue,
+        }
+    }
+}
+
+#[tokio::test]
+async fn runs_the_layers_until_shutdown() {
+    let element = element_with_deadline(Duration::from_secs(1));
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(element.local_addr()).await.unwrap();
+
+    let request = from_socket(requests::bye_request(), &socket);
+    let response = exchange(&socket, &request).await.expect("response");
+    assert_eq!(response.status_code, 405.into());
+
+    element.shutdown().await;
+    assert!(exchange(&socket, &request).await.is_none());
+}
+
+#[tokio::test]
+async fn drains_in_flight_calls_before_closing_the_socket() {
+    let element = element_with_deadline(Duration::from_secs(1));
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(element.local_addr()).await.unwrap();
+
+    let invite = from_socket(requests::invite_request(), &socket);
+    socket.send(invite.to_string().as_bytes()).await.unwrap();
+    delay_for(Duration::from_millis(100)).await;
+    assert!(!element.tu().is_idle().await);
+
+    let started = Instant::now();
+    let shutdown = tokio::spawn(element.shutdown());
+    delay_for(Duration::from_millis(100)).await;
+
+    //new requests are turned away while the call is still ringing
+    let request = from_socket(requests::bye_request(), &socket);
+    let response = exchange(&socket, &request).await.expect("response");
+    assert_eq!(response.status_code, 503.into());
+    assert!(response.headers.iter().any(|header| matches!(
+        header,
+        rsip::Header::Other(name, value) if name == "Retry-After" && value == "1"
+    )));
+
+    shutdown.await.unwrap();
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert!(exchange(&socket, &request).await.is_none());
+}
+
+//the 503 of a non-INVITE goes through a transaction too, which absorbs the retransmissions
+#[tokio::test]
+async fn turns_away_requests_through_transactions_while_draining() {
+    let (handlers, receivers) = models::channels_builder(crate::common::config());
+    let transaction = SpySnitch::new(handlers.clone(), receivers.transaction).expect("transaction");
+    let transport = SpySnitch::new(handlers.clone(), receivers.transport).expect("transport");
+    let router = Router::builder(handlers.clone()).build();
+    let ua = UserAgent::new(handlers.clone(), receivers.tu, router, Holder::default()).unwrap();
+
+    ua.drain(Duration::from_secs(5)).await;
+    handlers
+        .tu
+        .process(requests::bye_request().into())
+        .await
+        .unwrap();
+    delay_for(Duration::from_millis(10)).await;
+
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUas(request, Some(response)) => {
+            assert_eq!(request.method, rsip::Method::Bye);
+            assert_eq!(response.status_code, 503.into());
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert_eq!(transport.messages().await.len().await, 0);
+}
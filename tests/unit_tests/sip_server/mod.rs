@@ -1,3 +1,4 @@
+pub mod element_builder;
 pub mod transaction;
 pub mod transport;
 pub mod tu;
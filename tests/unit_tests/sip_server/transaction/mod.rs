@@ -10,7 +10,7 @@ pub async fn setup() -> (
     Transaction,
     SpySnitch<TransportLayerMsg>,
 ) {
-    let (handlers, receivers) = models::channels_builder();
+    let (handlers, receivers) = models::channels_builder(crate::common::config());
     let transport = SpySnitch::new(handlers.clone(), receivers.transport).expect("transport");
     let transaction =
         Transaction::new(handlers.clone(), receivers.transaction).expect("transaction");
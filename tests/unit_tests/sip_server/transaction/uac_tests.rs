@@ -1,7 +1,7 @@
 use super::setup;
 use crate::common::{advance_for, extensions::TransactionUacExt, factories::prelude::*};
 use common::rsip::{self, prelude::*};
-use sip_server::transaction::sm::uac::TIMER_M;
+use sip_server::transaction::sm::{uac::TIMER_M, Timers};
 use std::time::Duration;
 
 #[tokio::test]
@@ -55,6 +55,39 @@ async fn if_peer_not_responding() {
     );
 }
 
+#[tokio::test]
+async fn with_reloaded_timers() {
+    let (_, transaction, transport) = setup().await;
+    transaction
+        .reload_timers(Timers {
+            t1: Duration::from_millis(100),
+            ..Default::default()
+        })
+        .await;
+
+    let request: rsip::Request = requests::invite_request();
+    transaction
+        .handler()
+        .new_uac_invite(request.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(transport.messages().await.len().await, 1);
+    advance_for(Duration::from_millis(200)).await;
+    assert_eq!(transport.messages().await.len().await, 2);
+    advance_for(Duration::from_millis(6400)).await;
+    assert!(
+        transaction
+            .is_uac_terminated(
+                request
+                    .transaction_id()
+                    .expect("response transaction id")
+                    .into()
+            )
+            .await
+    );
+}
+
 #[tokio::test]
 async fn with_trying_goes_through_proceeding() {
     let (tu, transaction, transport) = setup().await;
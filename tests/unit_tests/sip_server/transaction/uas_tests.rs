@@ -69,6 +69,34 @@ async fn multiple_invite_on_proceeding() {
     assert_eq!(transaction.inner.state.read().await.len(), 1);
 }
 
+#[tokio::test]
+async fn cancel_on_proceeding_is_forwarded_to_tu() {
+    let (tu, transaction, transport) = setup().await;
+
+    let request: rsip::Request = requests::invite_request();
+    transaction
+        .handler()
+        .new_uas_invite(request.clone(), Some(request.provisional_of(180)))
+        .await
+        .unwrap();
+
+    let mut cancel = request.clone();
+    cancel.method = rsip::Method::Cancel;
+    transaction.handler().process(cancel.into()).await.unwrap();
+    assert_eq!(tu.messages().await.len().await, 1);
+    assert_eq!(transport.messages().await.len().await, 1);
+    assert!(
+        !transaction
+            .is_uas_errored(
+                request
+                    .transaction_id()
+                    .expect("response transaction id")
+                    .into()
+            )
+            .await
+    );
+}
+
 #[tokio::test]
 async fn with_redirect_response_moves_to_completed() {
     let (_, transaction, transport) = setup().await;
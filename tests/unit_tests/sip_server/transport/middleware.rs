@@ -0,0 +1,210 @@
+use crate::common::factories::prelude::*;
+use common::{
+    async_trait::async_trait,
+    rsip::{self, prelude::*},
+};
+use models::transport::{RequestMsg, TransportLayerMsg};
+use sip_server::transport::{
+    middleware::{Acl, Action, HeaderNormalization, RateLimit, Reloadable},
+    Middleware, Pipeline, TransportProcessor,
+};
+use std::net::SocketAddr;
+
+//leaves its name in the requests that it sees
+#[derive(Debug)]
+struct Mark(&'static str);
+
+#[async_trait]
+impl Middleware for Mark {
+    async fn incoming_request(&self, mut msg: RequestMsg) -> Result<Action, sip_server::Error> {
+        msg.sip_request
+            .headers
+            .push(rsip::Header::Other("X-Mark".into(), self.0.into()));
+        Ok(Action::Continue(msg))
+    }
+
+    async fn outgoing_request(&self, mut msg: RequestMsg) -> Result<Action, sip_server::Error> {
+        msg.sip_request
+            .headers
+            .push(rsip::Header::Other("X-Mark".into(), self.0.into()));
+        Ok(Action::Continue(msg))
+    }
+}
+
+fn marks_of(request: &rsip::Request) -> Vec<String> {
+    request
+        .headers
+        .iter()
+        .filter_map(|header| match header {
+            rsip::Header::Other(name, value) if name == "X-Mark" => Some(value.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn request_msg_from(peer: &str) -> RequestMsg {
+    RequestMsg::new(
+        requests::invite_request(),
+        peer.parse::<SocketAddr>().unwrap(),
+        rsip::Transport::Udp,
+    )
+}
+
+#[tokio::test]
+async fn runs_the_middlewares_in_order() {
+    let (tu, _, _) = super::super::tu::calls::setup().await;
+    let pipeline = Pipeline::new(tu.handlers())
+        .with(Mark("first"))
+        .with(Mark("second"));
+
+    let incoming = pipeline
+        .process_incoming_request(request_msg_from("127.0.0.1:5090"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(marks_of(&incoming.sip_request), vec!["first", "second"]);
+
+    let outgoing = pipeline
+        .process_outgoing_request(request_msg_from("127.0.0.1:5090"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(marks_of(&outgoing.sip_request), vec!["second", "first"]);
+}
+
+#[tokio::test]
+async fn short_circuits_with_a_response() {
+    let (tu, _, transport) = super::super::tu::calls::setup().await;
+    let pipeline = Pipeline::new(tu.handlers()).with(
+        Acl::denying_all()
+            .allow("127.0.0.0/8".parse().unwrap())
+            .rejecting_with(403),
+    );
+
+    assert!(pipeline
+        .process_incoming_request(request_msg_from("127.0.0.1:5090"))
+        .await
+        .unwrap()
+        .is_some());
+    assert_eq!(transport.messages().await.len().await, 0);
+
+    assert!(pipeline
+        .process_incoming_request(request_msg_from("10.0.0.1:5060"))
+        .await
+        .unwrap()
+        .is_none());
+    match transport.messages().await.first().await {
+        TransportLayerMsg::Outgoing(rsip::SipMessage::Response(response)) => {
+            assert_eq!(response.status_code, 403.into())
+        }
+        _ => panic!("unexpected transport msg"),
+    }
+}
+
+#[tokio::test]
+async fn acl_drops_denied_peers_silently() {
+    let acl = Acl::allowing_all().deny("10.0.0.0/8".parse().unwrap());
+
+    assert!(matches!(
+        acl.incoming_request(request_msg_from("10.1.2.3:5060"))
+            .await
+            .unwrap(),
+        Action::Drop
+    ));
+    assert!(matches!(
+        acl.incoming_request(request_msg_from("192.168.1.1:5060"))
+            .await
+            .unwrap(),
+        Action::Continue(_)
+    ));
+}
+
+#[tokio::test]
+async fn reloads_the_acl_in_place() {
+    let acl = Reloadable::new(Acl::allowing_all());
+    let pipeline_acl = acl.clone();
+
+    assert!(matches!(
+        pipeline_acl
+            .incoming_request(request_msg_from("10.1.2.3:5060"))
+            .await
+            .unwrap(),
+        Action::Continue(_)
+    ));
+
+    acl.reload(Acl::allowing_all().deny("10.0.0.0/8".parse().unwrap()))
+        .await;
+    assert!(matches!(
+        pipeline_acl
+            .incoming_request(request_msg_from("10.1.2.3:5060"))
+            .await
+            .unwrap(),
+        Action::Drop
+    ));
+}
+
+#[tokio::test]
+async fn rate_limit_answers_with_503() {
+    let rate_limit = RateLimit::new(1, 2);
+
+    for _ in 0..2 {
+        assert!(matches!(
+            rate_limit
+                .incoming_request(request_msg_from("10.0.0.1:5060"))
+                .await
+                .unwrap(),
+            Action::Continue(_)
+        ));
+    }
+    match rate_limit
+        .incoming_request(request_msg_from("10.0.0.1:5060"))
+        .await
+        .unwrap()
+    {
+        Action::Respond(response) => {
+            assert_eq!(response.status_code, 503.into());
+            assert!(response.headers.iter().any(|header| matches!(
+                header,
+                rsip::Header::Other(name, value) if name == "Retry-After" && value == "1"
+            )));
+        }
+        _ => panic!("unexpected action"),
+    }
+
+    //buckets are per peer
+    assert!(matches!(
+        rate_limit
+            .incoming_request(request_msg_from("10.0.0.2:5060"))
+            .await
+            .unwrap(),
+        Action::Continue(_)
+    ));
+}
+
+#[tokio::test]
+async fn normalizes_extension_headers() {
+    let normalization = HeaderNormalization::new().removing("X-Internal-Route");
+
+    let mut msg = request_msg_from("10.0.0.1:5060");
+    msg.sip_request
+        .headers
+        .push(rsip::Header::Other("x-account-CODE".into(), " 42 ".into()));
+    msg.sip_request.headers.push(rsip::Header::Other(
+        "x-internal-route".into(),
+        "pbx-2".into(),
+    ));
+
+    let request = match normalization.outgoing_request(msg).await.unwrap() {
+        Action::Continue(msg) => msg.sip_request,
+        _ => panic!("unexpected action"),
+    };
+    assert!(request.headers.iter().any(|header| matches!(
+        header,
+        rsip::Header::Other(name, value) if name == "X-Account-Code" && value == "42"
+    )));
+    assert!(!request.headers.iter().any(|header| matches!(
+        header,
+        rsip::Header::Other(name, _) if name.eq_ignore_ascii_case("X-Internal-Route")
+    )));
+    assert!(request.call_id_header().is_ok());
+}
@@ -1 +1,3 @@
+pub mod middleware;
 pub mod processor;
+pub mod topology_hiding;
@@ -2,13 +2,14 @@ use crate::common::factories::prelude::*;
 use common::rsip::{self, prelude::*};
 use models::transport::TransportMsg;
 use sip_server::transport::{DefaultProcessor, TransportProcessor};
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
+use std::sync::Arc;
 
 #[tokio::test]
 async fn incoming_response_asserts_with_wrong_sent_by() -> Result<(), sip_server::Error> {
     use rsip::Uri;
 
-    let processor = DefaultProcessor::default();
+    let processor = DefaultProcessor::new(Arc::new(crate::common::config()));
 
     let mut response: rsip::Response =
         responses::response(Some(Uri::default()), Some(Uri::default().with_port(5090)));
@@ -42,7 +43,7 @@ async fn incoming_response_asserts_with_wrong_sent_by() -> Result<(), sip_server
 async fn incoming_response_asserts_with_correct_sent_by() -> Result<(), sip_server::Error> {
     use rsip::Uri;
 
-    let processor = DefaultProcessor::default();
+    let processor = DefaultProcessor::new(Arc::new(crate::common::config()));
 
     let response: rsip::Response =
         responses::response(Some(Uri::default()), Some(Uri::default().with_port(5090)));
@@ -59,11 +60,50 @@ async fn incoming_response_asserts_with_correct_sent_by() -> Result<(), sip_serv
     Ok(())
 }
 
+//elements in the same process check the sent-by against their own listen addrs
+#[tokio::test]
+async fn incoming_response_asserts_sent_by_of_its_own_config() -> Result<(), sip_server::Error> {
+    let processor_for = |listen_addr: &str| {
+        DefaultProcessor::new(Arc::new(
+            common::Config::new(
+                Some("postgres://localhost/viska".into()),
+                Some(vec![listen_addr.into()]),
+            )
+            .expect("config"),
+        ))
+    };
+    let registrar = processor_for("127.0.0.1:5060");
+    let proxy = processor_for("127.0.0.1:5070");
+
+    let mut response: rsip::Response = responses::response(None, None);
+    let via_header = response.via_header_mut()?;
+    via_header.replace(
+        via_header
+            .typed()?
+            .with_uri(rsip::Uri::from(rsip::HostWithPort::try_from("127.0.0.1:5070")?).into()),
+    );
+    let server_msg = || models::transport::UdpTuple {
+        bytes: response.clone().into(),
+        peer: SocketAddrBuilder::localhost_with_port(5070).into(),
+    };
+
+    assert!(registrar
+        .process_incoming_response(server_msg().try_into()?)
+        .await
+        .is_err());
+    assert!(proxy
+        .process_incoming_response(server_msg().try_into()?)
+        .await
+        .is_ok());
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn outgoing_transaction_request_applies_maddr() -> Result<(), sip_server::Error> {
     use rsip::{param::Maddr, Param};
 
-    let processor = DefaultProcessor::default();
+    let processor = DefaultProcessor::new(Arc::new(crate::common::config()));
 
     let transport_msg = models::transport::TransportMsg {
         peer: SocketAddrBuilder {
@@ -104,7 +144,7 @@ async fn outgoing_transaction_request_applies_maddr() -> Result<(), sip_server::
 async fn outgoing_transaction_request_applies_ttl() -> Result<(), sip_server::Error> {
     use rsip::{param::Ttl, Param};
 
-    let processor = DefaultProcessor::default();
+    let processor = DefaultProcessor::new(Arc::new(crate::common::config()));
 
     let transport_msg = TransportMsg {
         peer: SocketAddrBuilder {
@@ -140,7 +180,7 @@ async fn outgoing_transaction_request_applies_ttl() -> Result<(), sip_server::Er
 
 #[tokio::test]
 async fn outgoing_transaction_request_applies_sent_by() -> Result<(), sip_server::Error> {
-    let processor = DefaultProcessor::default();
+    let processor = DefaultProcessor::new(Arc::new(crate::common::config()));
 
     let transport_msg = TransportMsg {
         peer: SocketAddrBuilder {
@@ -164,7 +204,7 @@ async fn outgoing_transaction_request_applies_sent_by() -> Result<(), sip_server
 
     assert_eq!(
         typed_via_header.uri.host_with_port,
-        common::CONFIG.default_addr()
+        crate::common::config().default_addr()
     );
 
     Ok(())
@@ -174,7 +214,7 @@ async fn outgoing_transaction_request_applies_sent_by() -> Result<(), sip_server
 async fn outgoing_core_request_applies_maddr() -> Result<(), sip_server::Error> {
     use rsip::{param::Maddr, Param};
 
-    let processor = DefaultProcessor::default();
+    let processor = DefaultProcessor::new(Arc::new(crate::common::config()));
 
     let transport_msg = TransportMsg {
         peer: SocketAddrBuilder {
@@ -214,7 +254,7 @@ async fn outgoing_core_request_applies_maddr() -> Result<(), sip_server::Error>
 async fn outgoing_core_request_applies_ttl() -> Result<(), sip_server::Error> {
     use rsip::{param::Ttl, Param};
 
-    let processor = DefaultProcessor::default();
+    let processor = DefaultProcessor::new(Arc::new(crate::common::config()));
 
     let transport_msg = TransportMsg {
         peer: SocketAddrBuilder {
@@ -249,7 +289,7 @@ async fn outgoing_core_request_applies_ttl() -> Result<(), sip_server::Error> {
 
 #[tokio::test]
 async fn outgoing_core_request_applies_sent_by() -> Result<(), sip_server::Error> {
-    let processor = DefaultProcessor::default();
+    let processor = DefaultProcessor::new(Arc::new(crate::common::config()));
 
     let transport_msg = TransportMsg {
         peer: SocketAddrBuilder {
@@ -273,7 +313,7 @@ async fn outgoing_core_request_applies_sent_by() -> Result<(), sip_server::Error
 
     assert_eq!(
         typed_via_header.uri.host_with_port,
-        common::CONFIG.default_addr()
+        crate::common::config().default_addr()
     );
 
     Ok(())
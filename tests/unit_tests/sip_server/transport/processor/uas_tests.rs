@@ -3,13 +3,14 @@ use common::rsip::{self, prelude::*};
 use sip_server::transport::{DefaultProcessor, TransportProcessor};
 use std::convert::TryInto;
 use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
 
 #[tokio::test]
 async fn incoming_request_with_other_sent_by_adds_received_param() -> Result<(), sip_server::Error>
 {
     use rsip::{Param, Uri};
 
-    let processor = DefaultProcessor::default();
+    let processor = DefaultProcessor::new(Arc::new(crate::common::config()));
 
     let request: rsip::Request =
         requests::request(Some(Uri::default()), Some(Uri::default().with_port(5090)));
@@ -37,13 +38,13 @@ async fn incoming_request_with_other_sent_by_adds_received_param() -> Result<(),
 async fn incoming_request_with_same_sent_by_param() -> Result<(), sip_server::Error> {
     use rsip::{Param, Uri};
 
-    let processor = DefaultProcessor::default();
+    let processor = DefaultProcessor::new(Arc::new(crate::common::config()));
 
     let request: rsip::Request =
         requests::request(Some(Uri::default()), Some(Uri::default().with_port(5090)));
     let server_msg = models::transport::UdpTuple {
         bytes: request.into(),
-        peer: common::CONFIG.default_addr().try_into()?,
+        peer: crate::common::config().default_addr().try_into()?,
     };
 
     let message = processor
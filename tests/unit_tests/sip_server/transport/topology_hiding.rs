@@ -0,0 +1,172 @@
+use crate::common::factories::prelude::*;
+use common::rsip::{self, headers::UntypedHeader, prelude::*};
+use sip_server::transport::TopologyHiding;
+use std::{convert::TryFrom, sync::Arc};
+
+const INTERNAL_VIA: &str = "SIP/2.0/UDP 10.0.0.5:5060;branch=z9hG4bKinternal";
+const INTERNAL_RECORD_ROUTE: &str = "<sip:10.0.0.5;lr>";
+const PUBLIC_RECORD_ROUTE: &str = "<sip:203.0.113.1:5060;lr>";
+
+fn topology_hiding() -> TopologyHiding {
+    TopologyHiding::new(
+        Arc::new(crate::common::config()),
+        rsip::HostWithPort::try_from("203.0.113.1:5060").unwrap(),
+    )
+    .with_call_id_obfuscation()
+}
+
+fn internal_invite() -> rsip::Request {
+    let mut request = requests::invite_request();
+    request
+        .headers
+        .push(rsip::headers::Via::new(INTERNAL_VIA).into());
+    request
+        .headers
+        .push(rsip::headers::RecordRoute::new(INTERNAL_RECORD_ROUTE).into());
+
+    request
+}
+
+fn values_of(headers: &rsip::Headers, name: &str) -> Vec<String> {
+    headers
+        .iter()
+        .filter_map(|header| match header {
+            rsip::Header::Via(via) if name == "Via" => Some(via.value().to_string()),
+            rsip::Header::RecordRoute(rr) if name == "Record-Route" => Some(rr.value().to_string()),
+            rsip::Header::Route(route) if name == "Route" => Some(route.value().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn hides_and_restores_the_internal_hops() {
+    let topology_hiding = topology_hiding();
+    let request = internal_invite();
+
+    let hidden = topology_hiding.hide_request(request.clone()).await.unwrap();
+    assert_eq!(
+        values_of(&hidden.headers, "Via"),
+        vec![request.via_header().unwrap().value().to_string()]
+    );
+    assert_eq!(
+        values_of(&hidden.headers, "Record-Route"),
+        vec![PUBLIC_RECORD_ROUTE.to_string()]
+    );
+    assert_ne!(
+        hidden.call_id_header().unwrap(),
+        request.call_id_header().unwrap()
+    );
+    let contact = hidden.contact_header().unwrap().typed().unwrap().uri;
+    assert_eq!(contact.host_with_port.to_string(), "203.0.113.1:5060");
+    assert!(contact.auth.unwrap().user.ends_with("-filippos"));
+
+    let restored = topology_hiding
+        .restore_response(responses::ok_response_from(hidden))
+        .await
+        .unwrap();
+    assert_eq!(
+        values_of(&restored.headers, "Via"),
+        values_of(&request.headers, "Via")
+    );
+    assert_eq!(
+        values_of(&restored.headers, "Record-Route"),
+        vec![INTERNAL_RECORD_ROUTE.to_string()]
+    );
+    assert_eq!(
+        restored.call_id_header().unwrap(),
+        request.call_id_header().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn restores_the_requests_of_the_peer() {
+    let topology_hiding = topology_hiding();
+    let request = internal_invite();
+    let hidden = topology_hiding.hide_request(request.clone()).await.unwrap();
+
+    //the peer hangs up through the route set and the contact that we gave it
+    let mut bye = requests::bye_request();
+    bye.uri = hidden.contact_header().unwrap().typed().unwrap().uri;
+    bye.headers
+        .unique_push(hidden.call_id_header().unwrap().clone().into());
+    bye.headers
+        .push(rsip::headers::Route::new(PUBLIC_RECORD_ROUTE).into());
+
+    let restored = topology_hiding.restore_request(bye).await.unwrap();
+    assert_eq!(
+        restored.uri,
+        request.contact_header().unwrap().typed().unwrap().uri
+    );
+    assert_eq!(
+        restored.call_id_header().unwrap(),
+        request.call_id_header().unwrap()
+    );
+    assert_eq!(
+        values_of(&restored.headers, "Route"),
+        vec![INTERNAL_RECORD_ROUTE.to_string()]
+    );
+}
+
+#[tokio::test]
+async fn tells_apart_the_contacts_of_different_uas() {
+    let topology_hiding = topology_hiding();
+    let contacts = [
+        "<sip:filippos@10.0.0.7:5060>",
+        "<sip:filippos@10.0.0.8:5060>",
+    ];
+
+    let mut hidden = vec![];
+    for contact in contacts {
+        let mut request = internal_invite();
+        request
+            .headers
+            .unique_push(rsip::headers::Contact::new(contact).into());
+        let request = topology_hiding.hide_request(request).await.unwrap();
+        hidden.push(request.contact_header().unwrap().typed().unwrap().uri);
+    }
+    assert_ne!(hidden[0], hidden[1]);
+
+    //each one reaches its own UA, even with the params that the peer added
+    for (contact, mut uri) in contacts.iter().zip(hidden) {
+        uri.params.push(rsip::Param::Lr);
+        let mut bye = requests::bye_request();
+        bye.uri = uri;
+
+        let restored = topology_hiding.restore_request(bye).await.unwrap();
+        assert_eq!(
+            restored.uri,
+            rsip::headers::Contact::new(*contact).typed().unwrap().uri
+        );
+    }
+}
+
+#[tokio::test]
+async fn hides_the_internal_record_routes_of_responses() {
+    let topology_hiding = topology_hiding();
+
+    //calls of the peer keep their Call-ID
+    let request = requests::invite_request();
+    let restored = topology_hiding
+        .restore_request(request.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        restored.call_id_header().unwrap(),
+        request.call_id_header().unwrap()
+    );
+
+    let mut response = responses::ok_response_from(restored);
+    response
+        .headers
+        .push(rsip::headers::RecordRoute::new(INTERNAL_RECORD_ROUTE).into());
+    let hidden = topology_hiding.hide_response(response).await.unwrap();
+    assert_eq!(
+        values_of(&hidden.headers, "Record-Route"),
+        vec![PUBLIC_RECORD_ROUTE.to_string()]
+    );
+    assert_eq!(
+        hidden.call_id_header().unwrap(),
+        request.call_id_header().unwrap()
+    );
+}
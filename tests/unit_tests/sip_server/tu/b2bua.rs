@@ -0,0 +1,210 @@
+use crate::common::{delay_for, factories::prelude::*};
+use common::rsip::{self, headers::UntypedHeader, prelude::*};
+use models::{rsip_ext::*, transaction::TransactionLayerMsg, tu::TuLayerMsg};
+use sip_server::tu::{
+    calls::{CallEvent, Dialer, IncomingCall},
+    dialogs::Dialogs,
+    elements::B2bua,
+};
+use std::{sync::Arc, time::Duration};
+
+#[tokio::test]
+async fn rejects_calls_without_a_dialer() {
+    let (tu, _, _) = super::calls::setup().await;
+    let (_, events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let call = IncomingCall::new(tu.handlers(), requests::invite_request(), events_rx);
+    assert!(B2bua::default().bridge(call).await.is_err());
+
+    match tu.messages().await.first().await {
+        TuLayerMsg::Outgoing(rsip::SipMessage::Response(response)) => {
+            assert_eq!(response.status_code, 500.into())
+        }
+        _ => panic!("unexpected tu msg"),
+    }
+}
+
+#[tokio::test]
+async fn bridges_the_legs_until_one_hangs_up() {
+    let (tu, transaction, _) = super::calls::setup().await;
+    let (events_tx, events_rx) = tokio::sync::mpsc::unbounded_channel();
+    let dialogs = Arc::new(Dialogs::new(tu.handlers()));
+
+    let mut request = requests::invite_request();
+    request.body = sessions::audio(1).into_bytes();
+    let call = IncomingCall::new(tu.handlers(), request.clone(), events_rx)
+        .with_dialer(Dialer::new(tu.handlers(), dialogs.clone()));
+    let bridge = tokio::spawn(async move { B2bua::default().bridge(call).await });
+    delay_for(Duration::from_millis(10)).await;
+
+    //the outgoing leg keeps the caller and the SDP offer
+    let invite = transaction
+        .messages()
+        .await
+        .try_latest()
+        .await
+        .new_uac_msg();
+    assert_eq!(invite.method, rsip::Method::Invite);
+    assert_eq!(invite.uri, request.to_header().unwrap().uri().unwrap());
+    assert_eq!(invite.body, request.body);
+    assert_eq!(
+        invite.from_header().unwrap().uri().unwrap(),
+        request.from_header().unwrap().uri().unwrap()
+    );
+    assert_ne!(
+        invite.call_id_header().unwrap(),
+        request.call_id_header().unwrap()
+    );
+
+    let mut answer = responses::ok_response_from(invite.clone());
+    answer.body = sessions::audio(2).into_bytes();
+    dialogs.process_incoming_response(answer).await.unwrap();
+    delay_for(Duration::from_millis(10)).await;
+
+    match tu.messages().await.first().await {
+        TuLayerMsg::Outgoing(rsip::SipMessage::Response(response)) => {
+            assert_eq!(response.status_code, 200.into());
+            assert_eq!(response.body, sessions::audio(2).into_bytes());
+        }
+        _ => panic!("unexpected tu msg"),
+    }
+
+    //the caller hangs up, so does the callee
+    events_tx.send(CallEvent::Terminated).unwrap();
+    bridge.await.unwrap().unwrap();
+    match tu.messages().await.last().await {
+        TuLayerMsg::Outgoing(rsip::SipMessage::Request(bye)) => {
+            assert_eq!(bye.method, rsip::Method::Bye);
+            assert_eq!(
+                bye.call_id_header().unwrap(),
+                invite.call_id_header().unwrap()
+            );
+        }
+        _ => panic!("unexpected tu msg"),
+    }
+}
+
+//a BYE of the callee inside the dialog of the outgoing leg
+fn bye_from_callee(invite: &rsip::Request, answer: &rsip::Response) -> rsip::Request {
+    let mut bye = invite.clone();
+    bye.method = rsip::Method::Bye;
+    bye.headers
+        .unique_push(rsip::headers::From::new(answer.to_header().unwrap().value()).into());
+    bye.headers
+        .unique_push(rsip::headers::To::new(invite.from_header().unwrap().value()).into());
+    bye.headers
+        .unique_push(rsip::typed::CSeq::from((1, rsip::Method::Bye)).into());
+    bye.body = Default::default();
+    bye
+}
+
+#[tokio::test]
+async fn hangs_up_the_caller_when_the_callee_hangs_up() {
+    let (tu, transaction, _) = super::calls::setup().await;
+    let (events_tx, events_rx) = tokio::sync::mpsc::unbounded_channel();
+    let dialogs = Arc::new(Dialogs::new(tu.handlers()));
+
+    let mut request = requests::invite_request();
+    request.body = sessions::audio(1).into_bytes();
+    dialogs
+        .new_uas_call(request.clone(), events_tx)
+        .await
+        .unwrap();
+    let call = IncomingCall::new(tu.handlers(), request.clone(), events_rx)
+        .with_dialer(Dialer::new(tu.handlers(), dialogs.clone()));
+    let bridge = tokio::spawn(async move { B2bua::default().bridge(call).await });
+    delay_for(Duration::from_millis(10)).await;
+
+    let invite = transaction
+        .messages()
+        .await
+        .try_latest()
+        .await
+        .new_uac_msg();
+    let mut answer = responses::ok_response_from(invite.clone());
+    answer.body = sessions::audio(2).into_bytes();
+    dialogs
+        .process_incoming_response(answer.clone())
+        .await
+        .unwrap();
+    delay_for(Duration::from_millis(10)).await;
+
+    //the caller is answered through its dialog, which the ACK confirms
+    match tu.messages().await.first().await {
+        TuLayerMsg::Outgoing(rsip::SipMessage::Response(response)) => {
+            dialogs.process_outgoing_response(response).await.unwrap()
+        }
+        _ => panic!("unexpected tu msg"),
+    }
+    let ok_response = match transaction.messages().await.latest().await {
+        TransactionLayerMsg::Reply(response) => response,
+        _ => panic!("unexpected transaction msg"),
+    };
+    dialogs
+        .process_incoming_request(request.ack_request_from(ok_response.clone()))
+        .await
+        .unwrap();
+
+    //the callee hangs up, so does the caller
+    dialogs
+        .process_incoming_request(bye_from_callee(&invite, &answer))
+        .await
+        .unwrap();
+    bridge.await.unwrap().unwrap();
+
+    match tu.messages().await.last().await {
+        TuLayerMsg::Outgoing(rsip::SipMessage::Request(bye)) => {
+            dialogs.process_outgoing_request(bye).await.unwrap()
+        }
+        _ => panic!("unexpected tu msg"),
+    }
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUac(bye) => {
+            assert_eq!(bye.method, rsip::Method::Bye);
+            assert_eq!(
+                bye.call_id_header().unwrap(),
+                request.call_id_header().unwrap()
+            );
+            assert_eq!(
+                bye.from_header().unwrap().tag().unwrap(),
+                ok_response.to_header().unwrap().tag().unwrap()
+            );
+            assert_eq!(
+                bye.to_header().unwrap().tag().unwrap(),
+                request.from_header().unwrap().tag().unwrap()
+            );
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert!(!dialogs.is_active_for(&request).await);
+}
+
+#[tokio::test]
+async fn relays_the_failure_of_the_outgoing_leg() {
+    let (tu, transaction, _) = super::calls::setup().await;
+    let (_events_tx, events_rx) = tokio::sync::mpsc::unbounded_channel();
+    let dialogs = Arc::new(Dialogs::new(tu.handlers()));
+
+    let call = IncomingCall::new(tu.handlers(), requests::invite_request(), events_rx)
+        .with_dialer(Dialer::new(tu.handlers(), dialogs.clone()));
+    let bridge = tokio::spawn(async move { B2bua::default().bridge(call).await });
+    delay_for(Duration::from_millis(10)).await;
+
+    let invite = transaction
+        .messages()
+        .await
+        .try_latest()
+        .await
+        .new_uac_msg();
+    let mut busy = responses::ok_response_from(invite);
+    busy.status_code = 486.into();
+    dialogs.process_incoming_response(busy).await.unwrap();
+    bridge.await.unwrap().unwrap();
+
+    match tu.messages().await.first().await {
+        TuLayerMsg::Outgoing(rsip::SipMessage::Response(response)) => {
+            assert_eq!(response.status_code, 486.into())
+        }
+        _ => panic!("unexpected tu msg"),
+    }
+}
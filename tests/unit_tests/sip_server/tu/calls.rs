@@ -0,0 +1,213 @@
+use crate::common::{factories::prelude::*, snitches::SpySnitch};
+use common::rsip::{self, prelude::*};
+use models::{
+    rsip_ext::*, transaction::TransactionLayerMsg, transport::TransportLayerMsg, tu::TuLayerMsg,
+};
+use sip_server::tu::{
+    calls::{CallSession, IncomingCall},
+    dialogs::Dialogs,
+};
+
+pub async fn setup() -> (
+    SpySnitch<TuLayerMsg>,
+    SpySnitch<TransactionLayerMsg>,
+    SpySnitch<TransportLayerMsg>,
+) {
+    let (handlers, receivers) = models::channels_builder(crate::common::config());
+    let transport = SpySnitch::new(handlers.clone(), receivers.transport).expect("transport");
+    let transaction = SpySnitch::new(handlers.clone(), receivers.transaction).expect("transaction");
+    let tu = SpySnitch::new(handlers.clone(), receivers.tu).expect("tu");
+
+    (tu, transaction, transport)
+}
+
+#[tokio::test]
+async fn accept_sends_200_with_sdp_through_tu() {
+    let (tu, transaction, transport) = setup().await;
+
+    let (_, events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let call = IncomingCall::new(tu.handlers(), requests::invite_request(), events_rx);
+    call.accept(b"v=0".to_vec()).await.unwrap();
+
+    assert_eq!(tu.messages().await.len().await, 1);
+    assert_eq!(transaction.messages().await.len().await, 0);
+    assert_eq!(transport.messages().await.len().await, 0);
+    match tu.messages().await.first().await {
+        TuLayerMsg::Outgoing(rsip::SipMessage::Response(response)) => {
+            assert_eq!(response.status_code, 200.into());
+            assert_eq!(response.body, b"v=0".to_vec());
+        }
+        _ => panic!("unexpected tu msg"),
+    }
+}
+
+#[tokio::test]
+async fn reject_with_non_failure_code_fails() {
+    let (tu, _, _) = setup().await;
+
+    let (_, events_rx) = tokio::sync::mpsc::unbounded_channel();
+    let call = IncomingCall::new(tu.handlers(), requests::invite_request(), events_rx);
+    assert!(call.reject(180).await.is_err());
+
+    let (_, events_rx) = tokio::sync::mpsc::unbounded_channel();
+    let call = IncomingCall::new(tu.handlers(), requests::invite_request(), events_rx);
+    assert!(call.reject(486).await.is_ok());
+
+    assert_eq!(tu.messages().await.len().await, 1);
+}
+
+#[tokio::test]
+async fn redirect_adds_targets_as_contacts() {
+    let (tu, _, _) = setup().await;
+
+    let (_, events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let call = IncomingCall::new(tu.handlers(), requests::invite_request(), events_rx);
+    let target = call.callee().unwrap();
+    call.redirect(302, vec![target.clone()]).await.unwrap();
+
+    match tu.messages().await.first().await {
+        TuLayerMsg::Outgoing(rsip::SipMessage::Response(response)) => {
+            assert_eq!(response.status_code, 302.into());
+            assert_eq!(
+                response.contact_header().unwrap().typed().unwrap().uri,
+                target
+            );
+        }
+        _ => panic!("unexpected tu msg"),
+    }
+}
+
+#[tokio::test]
+async fn hangup_sends_bye_for_the_dialog_through_tu() {
+    let (tu, _, _) = setup().await;
+    let (_, events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let request = requests::invite_request();
+    let session = CallSession::new(tu.handlers(), request.clone(), events_rx);
+    session.hangup().await.unwrap();
+
+    match tu.messages().await.first().await {
+        TuLayerMsg::Outgoing(rsip::SipMessage::Request(bye)) => {
+            assert_eq!(bye.method, rsip::Method::Bye);
+            assert_eq!(
+                bye.from_header().unwrap().tag().unwrap(),
+                request.from_header().unwrap().tag().unwrap()
+            );
+            assert_eq!(
+                bye.call_id_header().unwrap(),
+                request.call_id_header().unwrap()
+            );
+        }
+        _ => panic!("unexpected tu msg"),
+    }
+}
+
+#[tokio::test]
+async fn hangup_of_an_accepted_call_goes_through_the_uas_dialog() {
+    let (tu, transaction, _) = setup().await;
+    let (events_tx, events_rx) = tokio::sync::mpsc::unbounded_channel();
+    let dialogs = Dialogs::new(tu.handlers());
+
+    let request = requests::invite_request();
+    dialogs
+        .new_uas_call(request.clone(), events_tx)
+        .await
+        .unwrap();
+    let call = IncomingCall::new(tu.handlers(), request.clone(), events_rx);
+    let session = call.accept(sessions::audio(1).into_bytes()).await.unwrap();
+
+    match tu.messages().await.first().await {
+        TuLayerMsg::Outgoing(rsip::SipMessage::Response(response)) => {
+            dialogs.process_outgoing_response(response).await.unwrap()
+        }
+        _ => panic!("unexpected tu msg"),
+    }
+    let ok_response = match transaction.messages().await.latest().await {
+        TransactionLayerMsg::Reply(response) => response,
+        _ => panic!("unexpected transaction msg"),
+    };
+    dialogs
+        .process_incoming_request(request.ack_request_from(ok_response.clone()))
+        .await
+        .unwrap();
+
+    //the session only knows the INVITE, which has no To tag
+    session.hangup().await.unwrap();
+    match tu.messages().await.last().await {
+        TuLayerMsg::Outgoing(rsip::SipMessage::Request(bye)) => {
+            dialogs.process_outgoing_request(bye).await.unwrap()
+        }
+        _ => panic!("unexpected tu msg"),
+    }
+
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUac(bye) => {
+            assert_eq!(bye.method, rsip::Method::Bye);
+            assert_eq!(
+                bye.from_header().unwrap().tag().unwrap(),
+                ok_response.to_header().unwrap().tag().unwrap()
+            );
+            assert_eq!(
+                bye.to_header().unwrap().tag().unwrap(),
+                request.from_header().unwrap().tag().unwrap()
+            );
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+}
+
+#[tokio::test]
+async fn update_sends_sdp_offer_through_tu() {
+    let (tu, _, _) = setup().await;
+    let (_, events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let session = CallSession::new(tu.handlers(), requests::invite_request(), events_rx);
+    session.update(b"v=0".to_vec()).await.unwrap();
+
+    match tu.messages().await.first().await {
+        TuLayerMsg::Outgoing(rsip::SipMessage::Request(update)) => {
+            assert_eq!(update.method, rsip::Method::Update);
+            assert_eq!(update.body, b"v=0".to_vec());
+        }
+        _ => panic!("unexpected tu msg"),
+    }
+}
+
+#[tokio::test]
+async fn answers_that_do_not_match_the_offer_fail() {
+    let (tu, _, _) = setup().await;
+
+    let mut request = requests::invite_request();
+    request.body = sessions::audio_video(1).into_bytes();
+    let (_, events_rx) = tokio::sync::mpsc::unbounded_channel();
+    let call = IncomingCall::new(tu.handlers(), request.clone(), events_rx);
+    assert!(call.accept(sessions::audio(1).into_bytes()).await.is_err());
+
+    let (_, events_rx) = tokio::sync::mpsc::unbounded_channel();
+    let session = CallSession::new(tu.handlers(), requests::invite_request(), events_rx);
+    assert!(session
+        .accept_modification(&request, sessions::audio(1).into_bytes())
+        .await
+        .is_err());
+    assert!(session
+        .accept_modification(&request, sessions::audio_video(1).into_bytes())
+        .await
+        .is_ok());
+
+    assert_eq!(tu.messages().await.len().await, 1);
+}
+
+#[tokio::test]
+async fn reject_modification_with_non_failure_code_fails() {
+    let (tu, _, _) = setup().await;
+    let (_, events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let request = requests::invite_request();
+    let session = CallSession::new(tu.handlers(), request.clone(), events_rx);
+    assert!(session.reject_modification(&request, 200).await.is_err());
+    assert!(session.reject_modification(&request, 488).await.is_ok());
+
+    assert_eq!(tu.messages().await.len().await, 1);
+}
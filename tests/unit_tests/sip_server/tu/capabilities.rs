@@ -7,7 +7,7 @@ pub async fn setup() -> (
     SpySnitch<TransactionLayerMsg>,
     SpySnitch<TransportLayerMsg>,
 ) {
-    let (handlers, receivers) = models::channels_builder();
+    let (handlers, receivers) = models::channels_builder(crate::common::config());
     let transport = SpySnitch::new(handlers.clone(), receivers.transport).expect("transport");
     let transaction = SpySnitch::new(handlers.clone(), receivers.transaction).expect("transaction");
     let tu = SpySnitch::new(handlers.clone(), receivers.tu).expect("tu");
@@ -16,7 +16,6 @@ pub async fn setup() -> (
 }
 
 #[tokio::test]
-#[serial_test::serial]
 async fn sending_an_options_request_receives_busy() {
     let (_, _, transport) = setup().await;
 
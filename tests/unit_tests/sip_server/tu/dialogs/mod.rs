@@ -1 +1,4 @@
+pub mod reaping;
+pub mod replaces;
 pub mod uac;
+pub mod uas;
@@ -0,0 +1,71 @@
+use crate::common::{advance_for, factories::prelude::*, snitches::SpySnitch};
+use common::rsip::{self, prelude::*};
+use models::{transaction::TransactionLayerMsg, tu::TuLayerMsg, Handlers};
+use sip_server::{transaction::sm::Timers, tu::dialogs::Dialogs};
+use std::time::Duration;
+
+pub async fn setup() -> (
+    Handlers,
+    (SpySnitch<TuLayerMsg>, SpySnitch<TransactionLayerMsg>),
+) {
+    let (handlers, receivers) = models::channels_builder(crate::common::config());
+    let transaction = SpySnitch::new(handlers.clone(), receivers.transaction).expect("transaction");
+    let tu = SpySnitch::new(handlers.clone(), receivers.tu).expect("tu");
+
+    (handlers, (tu, transaction))
+}
+
+//a call that the peer cancelled while ringing
+async fn cancelled_call(dialogs: &Dialogs) -> rsip::Request {
+    let (events_tx, _events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let request = requests::invite_request();
+    dialogs
+        .new_uas_call(request.clone(), events_tx)
+        .await
+        .unwrap();
+    dialogs
+        .process_outgoing_response(responses::ringing_response_from(request.clone()))
+        .await
+        .unwrap();
+
+    let mut cancel = request.clone();
+    cancel.method = rsip::Method::Cancel;
+    cancel
+        .headers
+        .unique_push(rsip::typed::CSeq::from((1, rsip::Method::Cancel)).into());
+    dialogs.process_incoming_request(cancel).await.unwrap();
+    assert!(!dialogs.has_active_dialogs().await);
+
+    request
+}
+
+#[tokio::test]
+async fn drops_ended_dialogs_once_they_lingered_for_64_t1() {
+    let (handlers, _snitches) = setup().await;
+    let dialogs = Dialogs::new(handlers);
+    let request = cancelled_call(&dialogs).await;
+
+    //retransmissions of the INVITE still find the dialog
+    dialogs.check_dialogs().await;
+    assert!(dialogs.has_dialog_for(&request).await);
+
+    advance_for(Duration::from_secs(33)).await;
+    dialogs.check_dialogs().await;
+    assert!(!dialogs.has_dialog_for(&request).await);
+}
+
+#[tokio::test]
+async fn lingers_with_the_reloaded_timers() {
+    let (handlers, _snitches) = setup().await;
+    let dialogs = Dialogs::new(handlers);
+    dialogs.reload_timers(Timers {
+        t1: Duration::from_millis(100),
+        ..Default::default()
+    });
+    let request = cancelled_call(&dialogs).await;
+
+    advance_for(Duration::from_millis(6500)).await;
+    dialogs.check_dialogs().await;
+    assert!(!dialogs.has_dialog_for(&request).await);
+}
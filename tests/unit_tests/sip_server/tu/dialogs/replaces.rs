@@ -0,0 +1,166 @@
+use crate::common::{factories::prelude::*, snitches::SpySnitch};
+use common::rsip::{self, headers::UntypedHeader, prelude::*};
+use models::{
+    rsip_ext::*, transaction::TransactionLayerMsg, transport::TransportLayerMsg, tu::TuLayerMsg,
+    Handlers,
+};
+use sip_server::tu::dialogs::{replaces::Replaces, Dialogs};
+
+pub async fn setup() -> (
+    Handlers,
+    (
+        SpySnitch<TuLayerMsg>,
+        SpySnitch<TransactionLayerMsg>,
+        SpySnitch<TransportLayerMsg>,
+    ),
+) {
+    let (handlers, receivers) = models::channels_builder(crate::common::config());
+    let transport = SpySnitch::new(handlers.clone(), receivers.transport).expect("transport");
+    let transaction = SpySnitch::new(handlers.clone(), receivers.transaction).expect("transaction");
+    let tu = SpySnitch::new(handlers.clone(), receivers.tu).expect("tu");
+
+    (handlers, (tu, transaction, transport))
+}
+
+//an answered incoming call, and the Replaces that matches its dialog
+async fn confirmed_call(
+    dialogs: &Dialogs,
+    transaction: &SpySnitch<TransactionLayerMsg>,
+) -> Replaces {
+    let request = requests::invite_request();
+    let (events_tx, _) = tokio::sync::mpsc::unbounded_channel();
+    dialogs
+        .new_uas_call(request.clone(), events_tx)
+        .await
+        .unwrap();
+
+    let ok_response = responses::ok_response_from(request.clone());
+    dialogs
+        .process_outgoing_response(ok_response.clone())
+        .await
+        .unwrap();
+    let ok_response = match transaction.messages().await.latest().await {
+        TransactionLayerMsg::Reply(response) => response,
+        _ => panic!("unexpected transaction msg"),
+    };
+    dialogs
+        .process_incoming_request(request.ack_request_from(ok_response.clone()))
+        .await
+        .unwrap();
+
+    Replaces {
+        call_id: request.call_id_header().unwrap().value().into(),
+        to_tag: ok_response
+            .to_header()
+            .unwrap()
+            .tag()
+            .unwrap()
+            .unwrap()
+            .to_string(),
+        from_tag: request
+            .from_header()
+            .unwrap()
+            .tag()
+            .unwrap()
+            .unwrap()
+            .to_string(),
+        early_only: false,
+    }
+}
+
+fn replacing_invite_request(replaces: Replaces) -> rsip::Request {
+    let mut request = requests::invite_request();
+    request.headers.push(replaces.into());
+    request
+}
+
+#[test]
+fn parses_the_replaces_header() {
+    let mut headers: rsip::Headers = Default::default();
+    assert!(matches!(Replaces::from_headers(&headers), Ok(None)));
+
+    headers.push(rsip::Header::Other(
+        "Replaces".into(),
+        "98asjd8@test.com;to-tag=12345;from-tag=54321;early-only".into(),
+    ));
+    assert_eq!(
+        Replaces::from_headers(&headers).unwrap(),
+        Some(Replaces {
+            call_id: "98asjd8@test.com".into(),
+            to_tag: "12345".into(),
+            from_tag: "54321".into(),
+            early_only: true,
+        })
+    );
+
+    headers.push(rsip::Header::Other(
+        "Replaces".into(),
+        "98asjd8@test.com;to-tag=12345;from-tag=54321".into(),
+    ));
+    assert!(Replaces::from_headers(&headers).is_err());
+
+    let mut headers: rsip::Headers = Default::default();
+    headers.push(rsip::Header::Other(
+        "Replaces".into(),
+        "98asjd8@test.com;to-tag=12345".into(),
+    ));
+    assert!(Replaces::from_headers(&headers).is_err());
+}
+
+#[tokio::test]
+async fn replaces_a_confirmed_dialog() {
+    let (handlers, (_, transaction, _)) = setup().await;
+    let dialogs = Dialogs::new(handlers);
+
+    let replaces = confirmed_call(&dialogs, &transaction).await;
+
+    let request = replacing_invite_request(replaces.clone());
+    let (events_tx, _) = tokio::sync::mpsc::unbounded_channel();
+    let status_code = dialogs
+        .replace_with_uas_call(request.clone(), &replaces, events_tx)
+        .await
+        .unwrap();
+    assert_eq!(status_code, None);
+    assert!(dialogs.exists(request.dialog_id().unwrap()).await);
+
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUac(bye) => {
+            assert_eq!(bye.method, rsip::Method::Bye);
+            assert_eq!(bye.call_id_header().unwrap().value(), replaces.call_id);
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+}
+
+#[tokio::test]
+async fn rejects_replaces_that_cannot_take_place() {
+    let (handlers, (_, transaction, _)) = setup().await;
+    let dialogs = Dialogs::new(handlers);
+
+    let replaces = confirmed_call(&dialogs, &transaction).await;
+
+    let unknown = Replaces {
+        from_tag: "unknown".into(),
+        ..replaces.clone()
+    };
+    let request = replacing_invite_request(unknown.clone());
+    let (events_tx, _) = tokio::sync::mpsc::unbounded_channel();
+    let status_code = dialogs
+        .replace_with_uas_call(request.clone(), &unknown, events_tx)
+        .await
+        .unwrap();
+    assert_eq!(status_code, Some(481.into()));
+    assert!(!dialogs.exists(request.dialog_id().unwrap()).await);
+
+    let early_only = Replaces {
+        early_only: true,
+        ..replaces
+    };
+    let request = replacing_invite_request(early_only.clone());
+    let (events_tx, _) = tokio::sync::mpsc::unbounded_channel();
+    let status_code = dialogs
+        .replace_with_uas_call(request, &early_only, events_tx)
+        .await
+        .unwrap();
+    assert_eq!(status_code, Some(486.into()));
+}
@@ -1,9 +1,13 @@
-use crate::common::{factories::prelude::*, snitches::SpySnitch};
+use crate::common::{advance_for, factories::prelude::*, snitches::SpySnitch};
 use common::rsip::{self, common::Uri, message::HeadersExt};
 use models::{
     transaction::TransactionLayerMsg, transport::TransportLayerMsg, tu::TuLayerMsg, Handlers,
 };
-use sip_server::tu::dialogs::uac::dialog_sm::{DialogSm, DialogState};
+use sip_server::tu::dialogs::{
+    modification::Modification,
+    reliable,
+    uac::dialog_sm::{DialogSm, DialogState},
+};
 
 pub async fn setup() -> (
     Handlers,
@@ -13,7 +17,7 @@ pub async fn setup() -> (
         SpySnitch<TransportLayerMsg>,
     ),
 ) {
-    let (handlers, receivers) = models::channels_builder();
+    let (handlers, receivers) = models::channels_builder(crate::common::config());
     let transport = SpySnitch::new(handlers.clone(), receivers.transport).expect("transport");
     let transaction = SpySnitch::new(handlers.clone(), receivers.transaction).expect("transaction");
     let tu = SpySnitch::new(handlers.clone(), receivers.tu).expect("tu");
@@ -146,13 +150,18 @@ async fn modifies_a_confirmed_dialog() {
     assert_eq!(invite_req.cseq_header().unwrap().seq().unwrap(), 2);
     assert_eq!(invite_req.contact_header().unwrap().uri().unwrap(), new_uri);
 }
-/*
 #[tokio::test]
 async fn peer_modifies_a_confirmed_dialog() {
+    use sip_server::tu::calls::CallEvent;
+
     let (handlers, (tu, transaction, transport)) = setup().await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
 
     let mut request = requests::invite_request();
-    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone())
+        .await
+        .unwrap()
+        .with_events(events_tx);
 
     let ok_response = responses::ok_response_from(request.clone());
     dialog_sm
@@ -163,6 +172,7 @@ async fn peer_modifies_a_confirmed_dialog() {
     assert_eq!(transport.messages().await.len().await, 1);
 
     assert!(matches!(dialog_sm.state, DialogState::Confirmed(..)));
+    assert!(matches!(events_rx.try_recv(), Ok(CallEvent::Answered(_))));
 
     let new_uri = Uri::default().sip().with_user("another");
     request
@@ -170,6 +180,7 @@ async fn peer_modifies_a_confirmed_dialog() {
         .unwrap()
         .mut_uri(new_uri.clone())
         .unwrap();
+    request.cseq_header_mut().unwrap().mut_seq(2).unwrap();
     dialog_sm.process_incoming_request(request).await;
     assert_eq!(transaction.messages().await.len().await, 2);
     let invite_req = transaction
@@ -177,10 +188,176 @@ async fn peer_modifies_a_confirmed_dialog() {
         .await
         .try_latest()
         .await
-        .new_uac_invite_msg();
+        .new_uas_invite_msg();
     assert_eq!(invite_req.cseq_header().unwrap().seq().unwrap(), 2);
-    assert_eq!(invite_req.contact_header().unwrap().uri().unwrap(), new_uri);
-}*/
+    assert!(matches!(dialog_sm.state, DialogState::Confirmed(..)));
+    assert_eq!(dialog_sm.remote_seqn, Some(2));
+    assert_eq!(dialog_sm.remote_target, Some(new_uri));
+    assert!(matches!(
+        events_rx.try_recv(),
+        Ok(CallEvent::ModificationRequested(_))
+    ));
+}
+
+#[tokio::test]
+async fn glare_is_answered_with_491_and_retried() {
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let mut request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    dialog_sm
+        .process_incoming_response(responses::ok_response_from(request.clone()))
+        .await;
+    assert!(matches!(dialog_sm.state, DialogState::Confirmed(..)));
+
+    dialog_sm.process_outgoing_request(request.clone()).await;
+    assert_eq!(transaction.messages().await.len().await, 2);
+    let reinvite = transaction
+        .messages()
+        .await
+        .try_latest()
+        .await
+        .new_uac_invite_msg();
+
+    request.cseq_header_mut().unwrap().mut_seq(2).unwrap();
+    dialog_sm.process_incoming_request(request).await;
+    assert_eq!(transaction.messages().await.len().await, 3);
+    match transaction.messages().await.try_latest().await {
+        TransactionLayerMsg::NewUasInvite(_, Some(response)) => {
+            assert_eq!(response.status_code, 491.into())
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+
+    let mut pending = responses::request_failure_response_from(reinvite.clone());
+    pending.status_code = 491.into();
+    dialog_sm.process_incoming_response(pending).await;
+    assert!(matches!(
+        dialog_sm.modification,
+        Some(Modification::Retrying { .. })
+    ));
+    assert!(matches!(dialog_sm.state, DialogState::Confirmed(..)));
+
+    //the owner of the Call-ID waits between 2.1s and 4s
+    dialog_sm.next().await;
+    assert_eq!(transaction.messages().await.len().await, 3);
+
+    advance_for(std::time::Duration::from_secs(4)).await;
+    dialog_sm.next().await;
+    assert_eq!(transaction.messages().await.len().await, 4);
+    let retried = transaction
+        .messages()
+        .await
+        .try_latest()
+        .await
+        .new_uac_invite_msg();
+    assert_eq!(retried.cseq_header().unwrap().seq().unwrap(), 3);
+    assert!(matches!(
+        dialog_sm.modification,
+        Some(Modification::Outgoing(_))
+    ));
+}
+
+#[tokio::test]
+async fn reports_a_reinvite_sent_while_another_is_pending() {
+    use sip_server::tu::calls::CallEvent;
+
+    let (handlers, (_, transaction, _)) = setup().await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone())
+        .await
+        .unwrap()
+        .with_events(events_tx);
+    dialog_sm
+        .process_incoming_response(responses::ok_response_from(request.clone()))
+        .await;
+    assert!(matches!(events_rx.try_recv(), Ok(CallEvent::Answered(_))));
+
+    dialog_sm.process_outgoing_request(request.clone()).await;
+    assert_eq!(transaction.messages().await.len().await, 2);
+
+    dialog_sm.process_outgoing_request(request).await;
+    assert_eq!(transaction.messages().await.len().await, 2);
+    match events_rx.try_recv() {
+        Ok(CallEvent::ModificationRejected(response)) => {
+            assert_eq!(response.status_code, 491.into())
+        }
+        _ => panic!("unexpected call event"),
+    }
+    assert!(matches!(
+        dialog_sm.modification,
+        Some(Modification::Outgoing(_))
+    ));
+    assert!(matches!(dialog_sm.state, DialogState::Confirmed(..)));
+}
+
+#[tokio::test]
+async fn transfers_a_confirmed_dialog() {
+    use common::rsip::headers::UntypedHeader;
+    use sip_server::tu::{calls::CallEvent, dialogs::refer};
+
+    let (handlers, (_, transaction, _)) = setup().await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone())
+        .await
+        .unwrap()
+        .with_events(events_tx);
+
+    dialog_sm
+        .process_incoming_response(responses::ok_response_from(request.clone()))
+        .await;
+    assert!(matches!(events_rx.try_recv(), Ok(CallEvent::Answered(_))));
+
+    let mut refer_request = request.clone();
+    refer_request.method = rsip::Method::Refer;
+    refer_request.headers.push(refer::refer_to_header(
+        &Uri::default().sip().with_user("carol"),
+    ));
+    dialog_sm.process_outgoing_request(refer_request).await;
+    let refer_request = match transaction.messages().await.try_latest().await {
+        TransactionLayerMsg::NewUac(request) => request,
+        _ => panic!("unexpected transaction msg"),
+    };
+    assert_eq!(refer_request.method, rsip::Method::Refer);
+    assert_eq!(refer_request.cseq_header().unwrap().seq().unwrap(), 2);
+
+    let mut accepted = responses::ok_response_from(refer_request);
+    accepted.status_code = 202.into();
+    dialog_sm.process_incoming_response(accepted).await;
+    assert!(matches!(
+        events_rx.try_recv(),
+        Ok(CallEvent::TransferAccepted(_))
+    ));
+
+    let mut notify = request;
+    notify.method = rsip::Method::Notify;
+    notify
+        .headers
+        .unique_push(rsip::typed::CSeq::from((1, rsip::Method::Notify)).into());
+    notify.headers.push(refer::event_header());
+    notify
+        .headers
+        .push(rsip::headers::SubscriptionState::new("terminated;reason=noresource").into());
+    notify.body = refer::sipfrag(&200.into());
+    dialog_sm.process_incoming_request(notify).await;
+    match transaction.messages().await.try_latest().await {
+        TransactionLayerMsg::NewUas(request, Some(response)) => {
+            assert_eq!(request.method, rsip::Method::Notify);
+            assert_eq!(response.status_code, 200.into());
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert!(matches!(
+        events_rx.try_recv(),
+        Ok(CallEvent::TransferProgress(rsip::StatusCode::OK))
+    ));
+    assert!(dialog_sm.transfer.is_none());
+}
 
 #[tokio::test]
 async fn closing_a_dialog() {
@@ -211,3 +388,278 @@ async fn closing_a_dialog() {
     assert_eq!(invite_req.cseq_header().unwrap().seq().unwrap(), 2);
     assert!(matches!(dialog_sm.state, DialogState::Terminated(..)));
 }
+
+#[tokio::test]
+async fn reports_call_progress_through_events() {
+    use sip_server::tu::calls::CallEvent;
+
+    let (handlers, _) = setup().await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone())
+        .await
+        .unwrap()
+        .with_events(events_tx);
+
+    dialog_sm
+        .process_incoming_response(responses::ringing_response_from(request.clone()))
+        .await;
+    assert!(matches!(events_rx.try_recv(), Ok(CallEvent::Ringing(_))));
+
+    dialog_sm
+        .process_incoming_response(responses::ok_response_from(request.clone()))
+        .await;
+    assert!(matches!(events_rx.try_recv(), Ok(CallEvent::Answered(_))));
+
+    dialog_sm
+        .process_outgoing_request(requests::bye_request())
+        .await;
+    assert!(matches!(events_rx.try_recv(), Ok(CallEvent::Terminated)));
+}
+
+#[tokio::test]
+async fn reports_rejected_call() {
+    use sip_server::tu::calls::CallEvent;
+
+    let (handlers, _) = setup().await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone())
+        .await
+        .unwrap()
+        .with_events(events_tx);
+
+    let response = responses::request_failure_response_from(request.clone());
+    dialog_sm.process_incoming_response(response.clone()).await;
+    assert!(matches!(dialog_sm.state, DialogState::Terminated(..)));
+    match events_rx.try_recv() {
+        Ok(CallEvent::Failed { status_code, .. }) => {
+            assert_eq!(status_code, Some(response.status_code))
+        }
+        _ => panic!("unexpected call event"),
+    }
+}
+
+#[tokio::test]
+async fn hanging_up_an_early_dialog_cancels_the_invite() {
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    dialog_sm
+        .process_incoming_response(responses::ringing_response_from(request.clone()))
+        .await;
+    dialog_sm
+        .process_outgoing_request(requests::bye_request())
+        .await;
+    assert_eq!(transaction.messages().await.len().await, 2);
+    let cancel = transaction
+        .messages()
+        .await
+        .try_latest()
+        .await
+        .new_uac_msg();
+    assert_eq!(cancel.method, rsip::Method::Cancel);
+    assert_eq!(
+        cancel.cseq_header().unwrap().seq().unwrap(),
+        request.cseq_header().unwrap().seq().unwrap()
+    );
+    assert!(matches!(dialog_sm.state, DialogState::Early(..)));
+}
+
+fn reliable_ringing_response_from(request: rsip::Request, rseq: u32) -> rsip::Response {
+    use common::rsip::headers::UntypedHeader;
+
+    let mut response = responses::ringing_response_from(request);
+    response
+        .headers
+        .push(rsip::headers::Require::new("100rel").into());
+    response.headers.push(reliable::rseq_header(rseq));
+    response
+}
+
+#[tokio::test]
+async fn pracks_reliable_provisionals() {
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let response = reliable_ringing_response_from(request.clone(), 10);
+    dialog_sm.process_incoming_response(response.clone()).await;
+    assert!(matches!(dialog_sm.state, DialogState::Early(..)));
+    assert_eq!(dialog_sm.remote_rseq, Some(10));
+    assert_eq!(transaction.messages().await.len().await, 2);
+    let prack = transaction
+        .messages()
+        .await
+        .try_latest()
+        .await
+        .new_uac_msg();
+    assert_eq!(prack.method, rsip::Method::PRack);
+    assert_eq!(prack.cseq_header().unwrap().seq().unwrap(), 2);
+    assert_eq!(
+        reliable::RAck::from_headers(&prack.headers),
+        Some(reliable::RAck {
+            rseq: 10,
+            cseq: 1,
+            method: rsip::Method::Invite
+        })
+    );
+
+    //retransmissions are not PRACKed again
+    dialog_sm.process_incoming_response(response).await;
+    assert_eq!(transaction.messages().await.len().await, 2);
+
+    dialog_sm
+        .process_incoming_response(reliable_ringing_response_from(request, 11))
+        .await;
+    assert_eq!(dialog_sm.remote_rseq, Some(11));
+    assert_eq!(transaction.messages().await.len().await, 3);
+}
+
+#[tokio::test]
+async fn pracks_through_the_route_set_of_the_early_dialog() {
+    use common::rsip::{headers::UntypedHeader, prelude::*};
+
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let mut response = reliable_ringing_response_from(request, 10);
+    let typed_to_header = response.to_header().unwrap().typed().unwrap();
+    response.headers.unique_push(
+        typed_to_header
+            .with_tag(rsip::param::Tag::new("callee"))
+            .into(),
+    );
+    response.headers.push(
+        rsip::headers::RecordRoute::new("<sip:edge.example.com;lr>, <sip:core.example.com;lr>")
+            .into(),
+    );
+    dialog_sm.process_incoming_response(response.clone()).await;
+    let prack = transaction
+        .messages()
+        .await
+        .try_latest()
+        .await
+        .new_uac_msg();
+    assert_eq!(prack.method, rsip::Method::PRack);
+    assert_eq!(
+        prack.uri,
+        response.contact_header().unwrap().typed().unwrap().uri
+    );
+    let routes = prack
+        .headers
+        .iter()
+        .filter_map(|header| match header {
+            rsip::Header::Route(route) => Some(route.value().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(routes.len(), 2);
+    assert!(routes[0].contains("core.example.com"));
+    assert!(routes[1].contains("edge.example.com"));
+}
+
+#[tokio::test]
+async fn offer_in_reliable_provisional_waits_for_the_application() {
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let mut request = requests::invite_request();
+    request.body = Default::default();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let mut response = reliable_ringing_response_from(request.clone(), 1);
+    response.body = b"v=0".to_vec();
+    dialog_sm.process_incoming_response(response).await;
+    assert!(dialog_sm.pending_prack.is_some());
+    assert_eq!(transaction.messages().await.len().await, 1);
+
+    let mut prack = requests::bye_request();
+    prack.method = rsip::Method::PRack;
+    prack.body = b"v=0".to_vec();
+    dialog_sm.process_outgoing_request(prack).await;
+    assert!(dialog_sm.pending_prack.is_none());
+    assert_eq!(transaction.messages().await.len().await, 2);
+    let prack = transaction
+        .messages()
+        .await
+        .try_latest()
+        .await
+        .new_uac_msg();
+    assert_eq!(prack.method, rsip::Method::PRack);
+    assert_eq!(prack.body, b"v=0".to_vec());
+}
+
+#[tokio::test]
+async fn refreshes_the_session_when_refresher() {
+    use sip_server::tu::dialogs::session_timer::{Refresher, SessionExpires};
+
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let mut ok_response = responses::ok_response_from(request.clone());
+    ok_response
+        .headers
+        .push(SessionExpires::new(90, Some(Refresher::Uac)).into());
+    dialog_sm.process_incoming_response(ok_response).await;
+    assert!(dialog_sm.session_timer.as_ref().unwrap().local_refresher);
+
+    advance_for(std::time::Duration::from_secs(46)).await;
+    dialog_sm.next().await;
+    assert_eq!(transaction.messages().await.len().await, 2);
+    let refresh = transaction
+        .messages()
+        .await
+        .try_latest()
+        .await
+        .new_uac_invite_msg();
+    assert_eq!(refresh.cseq_header().unwrap().seq().unwrap(), 2);
+    assert_eq!(
+        SessionExpires::from_headers(&refresh.headers),
+        Some(SessionExpires::new(90, Some(Refresher::Uac)))
+    );
+
+    let ok_response = responses::ok_response_from(refresh);
+    dialog_sm.process_incoming_response(ok_response).await;
+    assert!(dialog_sm.modification.is_none());
+    assert!(!dialog_sm.session_timer.as_ref().unwrap().refresh_sent);
+}
+
+#[tokio::test]
+async fn retries_the_invite_after_422() {
+    use sip_server::tu::dialogs::session_timer::{self, SessionExpires};
+
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let mut request = requests::invite_request();
+    request.headers.push(SessionExpires::new(60, None).into());
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let mut response = responses::request_failure_response_from(request.clone());
+    response.status_code = 422.into();
+    response.headers.push(session_timer::min_se_header(600));
+    dialog_sm.process_incoming_response(response).await;
+    assert!(matches!(dialog_sm.state, DialogState::Unconfirmed(..)));
+    assert_eq!(transaction.messages().await.len().await, 2);
+
+    let retried = transaction
+        .messages()
+        .await
+        .try_latest()
+        .await
+        .new_uac_invite_msg();
+    assert_eq!(retried.cseq_header().unwrap().seq().unwrap(), 2);
+    assert_eq!(
+        SessionExpires::from_headers(&retried.headers),
+        Some(SessionExpires::new(600, None))
+    );
+    assert_eq!(dialog_sm.request, retried);
+}
@@ -1 +1,2 @@
 pub mod dialog_sm;
+pub mod multi_dialog;
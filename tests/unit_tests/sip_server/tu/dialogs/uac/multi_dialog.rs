@@ -0,0 +1,151 @@
+use super::dialog_sm::setup;
+use crate::common::factories::prelude::*;
+use common::rsip::{self, headers::UntypedHeader, prelude::*};
+use models::{transaction::TransactionLayerMsg, transport::TransportLayerMsg};
+use sip_server::tu::{calls::CallEvent, dialogs::uac::MultiDialog};
+
+fn from_fork(mut response: rsip::Response, tag: &str) -> rsip::Response {
+    let typed_to_header = response.to_header().unwrap().typed().unwrap();
+    response
+        .headers
+        .unique_push(typed_to_header.with_tag(rsip::param::Tag::new(tag)).into());
+    response
+}
+
+#[tokio::test]
+async fn creates_an_early_dialog_per_fork() {
+    let (handlers, _) = setup().await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let request = requests::invite_request();
+    let multi_dialog = MultiDialog::new(
+        handlers,
+        request.clone(),
+        Some(events_tx),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+
+    for tag in ["fork-a", "fork-b"] {
+        multi_dialog
+            .process_incoming_response(from_fork(
+                responses::ringing_response_from(request.clone()),
+                tag,
+            ))
+            .await
+            .unwrap();
+    }
+    assert!(matches!(events_rx.try_recv(), Ok(CallEvent::Ringing(_))));
+    assert!(matches!(events_rx.try_recv(), Ok(CallEvent::Ringing(_))));
+
+    //a final failure ends the early dialogs of every fork
+    let mut busy = from_fork(responses::ok_response_from(request.clone()), "fork-a");
+    busy.status_code = 486.into();
+    multi_dialog.process_incoming_response(busy).await.unwrap();
+    assert!(matches!(events_rx.try_recv(), Ok(CallEvent::Failed { .. })));
+    assert!(events_rx.try_recv().is_err());
+    assert!(!multi_dialog.is_active().await);
+}
+
+#[tokio::test]
+async fn hangs_up_forks_that_answer_late() {
+    let (handlers, (_, transaction, transport)) = setup().await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let request = requests::invite_request();
+    let multi_dialog = MultiDialog::new(
+        handlers,
+        request.clone(),
+        Some(events_tx),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+
+    for tag in ["fork-a", "fork-b", "fork-c"] {
+        multi_dialog
+            .process_incoming_response(from_fork(
+                responses::ringing_response_from(request.clone()),
+                tag,
+            ))
+            .await
+            .unwrap();
+    }
+
+    multi_dialog
+        .process_incoming_response(from_fork(
+            responses::ok_response_from(request.clone()),
+            "fork-b",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(transport.messages().await.len().await, 1);
+    let mut answered = 0;
+    while let Ok(event) = events_rx.try_recv() {
+        if matches!(event, CallEvent::Answered(_)) {
+            answered += 1;
+        }
+    }
+    assert_eq!(answered, 1);
+
+    let mut late_ok = from_fork(responses::ok_response_from(request.clone()), "fork-a");
+    late_ok.headers.push(
+        rsip::headers::RecordRoute::new("<sip:edge.example.com;lr>, <sip:core.example.com;lr>")
+            .into(),
+    );
+    multi_dialog
+        .process_incoming_response(late_ok.clone())
+        .await
+        .unwrap();
+    match transport.messages().await.latest().await {
+        TransportLayerMsg::Outgoing(rsip::SipMessage::Request(ack)) => {
+            assert_eq!(ack.method, rsip::Method::Ack);
+            assert_eq!(
+                ack.to_header().unwrap().tag().unwrap(),
+                Some(rsip::param::Tag::new("fork-a"))
+            );
+        }
+        _ => panic!("unexpected transport msg"),
+    }
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUac(bye) => {
+            assert_eq!(bye.method, rsip::Method::Bye);
+            assert_eq!(
+                bye.to_header().unwrap().tag().unwrap(),
+                Some(rsip::param::Tag::new("fork-a"))
+            );
+            //to the fork, through the proxies that it recorded, in reverse order
+            assert_eq!(
+                bye.uri,
+                late_ok.contact_header().unwrap().typed().unwrap().uri
+            );
+            let routes = bye
+                .headers
+                .iter()
+                .filter_map(|header| match header {
+                    rsip::Header::Route(route) => Some(route.to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(routes.len(), 2);
+            assert!(routes[0].contains("core.example.com"));
+            assert!(routes[1].contains("edge.example.com"));
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert!(events_rx.try_recv().is_err());
+
+    //a retransmission of the late 2xx is only acked again
+    let transaction_messages = transaction.messages().await.len().await;
+    multi_dialog
+        .process_incoming_response(late_ok)
+        .await
+        .unwrap();
+    assert_eq!(transport.messages().await.len().await, 3);
+    assert_eq!(
+        transaction.messages().await.len().await,
+        transaction_messages
+    );
+    assert!(multi_dialog.is_active().await);
+}
@@ -0,0 +1,810 @@
+use crate::common::{advance_for, factories::prelude::*, snitches::SpySnitch};
+use common::rsip::{self, message::HeadersExt};
+use models::{
+    rsip_ext::*, transaction::TransactionLayerMsg, transport::TransportLayerMsg, tu::TuLayerMsg,
+    Handlers,
+};
+use sip_server::tu::dialogs::{
+    reliable,
+    uas::dialog_sm::{DialogSm, DialogState},
+};
+
+pub async fn setup() -> (
+    Handlers,
+    (
+        SpySnitch<TuLayerMsg>,
+        SpySnitch<TransactionLayerMsg>,
+        SpySnitch<TransportLayerMsg>,
+    ),
+) {
+    let (handlers, receivers) = models::channels_builder(crate::common::config());
+    let transport = SpySnitch::new(handlers.clone(), receivers.transport).expect("transport");
+    let transaction = SpySnitch::new(handlers.clone(), receivers.transaction).expect("transaction");
+    let tu = SpySnitch::new(handlers.clone(), receivers.tu).expect("tu");
+
+    (handlers, (tu, transaction, transport))
+}
+
+fn bye_request_from(invite: rsip::Request) -> rsip::Request {
+    let mut request = invite;
+    request.method = rsip::Method::Bye;
+    request
+        .headers
+        .unique_push(rsip::typed::CSeq::from((2, rsip::Method::Bye)).into());
+    request
+}
+
+#[tokio::test]
+async fn creates_unestablished_dialog_and_initializes_correctly() {
+    let (handlers, (tu, transaction, transport)) = setup().await;
+
+    let request: rsip::Request = requests::invite_request();
+    let dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    assert_eq!(transaction.messages().await.len().await, 1);
+    assert_eq!(tu.messages().await.len().await, 0);
+    assert_eq!(transport.messages().await.len().await, 0);
+    assert!(matches!(dialog_sm.state, DialogState::Unestablished(..)));
+
+    assert_eq!(
+        dialog_sm.remote_tag,
+        request.from_header().unwrap().tag().ok().flatten().unwrap()
+    );
+    assert_eq!(dialog_sm.local_seqn, None);
+    assert_eq!(dialog_sm.remote_seqn, 1);
+    assert_eq!(
+        dialog_sm.local_uri,
+        request.to_header().unwrap().uri().unwrap()
+    );
+    assert_eq!(dialog_sm.call_id, *request.call_id_header().unwrap());
+
+    match transaction.messages().await.first().await {
+        TransactionLayerMsg::NewUasInvite(_, Some(trying)) => {
+            assert_eq!(trying.status_code, rsip::StatusCode::Trying);
+            assert_eq!(
+                trying.to_header().unwrap().tag().unwrap(),
+                Some(dialog_sm.local_tag.clone())
+            );
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+}
+
+#[tokio::test]
+async fn creates_a_confirmed_dialog() {
+    let (handlers, (tu, transaction, transport)) = setup().await;
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+    assert!(matches!(dialog_sm.state, DialogState::Unestablished(..)));
+
+    dialog_sm
+        .process_outgoing_response(responses::ringing_response_from(request.clone()))
+        .await;
+    assert_eq!(transaction.messages().await.len().await, 2);
+    assert!(matches!(dialog_sm.state, DialogState::Early(..)));
+
+    let ok_response = responses::ok_response_from(request.clone());
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    assert_eq!(transaction.messages().await.len().await, 3);
+    assert!(matches!(dialog_sm.state, DialogState::UnAcked(..)));
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::Reply(response) => {
+            assert_eq!(response.status_code, 200.into());
+            assert_eq!(
+                response.to_header().unwrap().tag().unwrap(),
+                Some(dialog_sm.local_tag.clone())
+            );
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+
+    dialog_sm
+        .process_incoming_request(request.ack_request_from(ok_response))
+        .await;
+    assert_eq!(transaction.messages().await.len().await, 3);
+    assert_eq!(tu.messages().await.len().await, 0);
+    assert_eq!(transport.messages().await.len().await, 0);
+    assert!(matches!(dialog_sm.state, DialogState::Confirmed(..)));
+}
+
+#[tokio::test]
+async fn terminates_on_incoming_bye() {
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let ok_response = responses::ok_response_from(request.clone());
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    dialog_sm
+        .process_incoming_request(request.ack_request_from(ok_response))
+        .await;
+    assert!(matches!(dialog_sm.state, DialogState::Confirmed(..)));
+
+    dialog_sm
+        .process_incoming_request(bye_request_from(request))
+        .await;
+    assert!(matches!(dialog_sm.state, DialogState::Terminated(..)));
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUas(request, Some(response)) => {
+            assert_eq!(request.method, rsip::Method::Bye);
+            assert_eq!(response.status_code, 200.into());
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+}
+
+#[tokio::test]
+async fn terminates_a_ringing_call_on_incoming_cancel() {
+    use sip_server::tu::calls::CallEvent;
+
+    let (handlers, (_, transaction, _)) = setup().await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone())
+        .await
+        .unwrap()
+        .with_events(events_tx);
+    dialog_sm
+        .process_outgoing_response(responses::ringing_response_from(request.clone()))
+        .await;
+    assert!(matches!(dialog_sm.state, DialogState::Early(..)));
+
+    let mut cancel = request.clone();
+    cancel.method = rsip::Method::Cancel;
+    cancel
+        .headers
+        .unique_push(rsip::typed::CSeq::from((1, rsip::Method::Cancel)).into());
+    dialog_sm.process_incoming_request(cancel).await;
+    assert!(matches!(dialog_sm.state, DialogState::Terminated(..)));
+    assert!(matches!(events_rx.try_recv(), Ok(CallEvent::Terminated)));
+
+    let messages = transaction.messages().await;
+    let messages = messages.0.lock().await;
+    match &messages[messages.len() - 2] {
+        TransactionLayerMsg::NewUas(request, Some(response)) => {
+            assert_eq!(request.method, rsip::Method::Cancel);
+            assert_eq!(response.status_code, 200.into());
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    match messages.last() {
+        Some(TransactionLayerMsg::Reply(response)) => {
+            assert_eq!(response.status_code, 487.into());
+            assert_eq!(
+                response.to_header().unwrap().tag().unwrap(),
+                Some(dialog_sm.local_tag.clone())
+            );
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+}
+
+#[tokio::test]
+async fn hangs_up_once_the_2xx_is_acked() {
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let ok_response = responses::ok_response_from(request.clone());
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    let sent = transaction.messages().await.len().await;
+
+    //the BYE waits for the ACK
+    dialog_sm
+        .process_outgoing_request(requests::bye_request())
+        .await;
+    assert!(matches!(dialog_sm.state, DialogState::UnAcked(..)));
+    assert_eq!(transaction.messages().await.len().await, sent);
+
+    dialog_sm
+        .process_incoming_request(request.ack_request_from(ok_response))
+        .await;
+    assert!(matches!(dialog_sm.state, DialogState::Terminated(..)));
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUac(bye) => assert_eq!(bye.method, rsip::Method::Bye),
+        _ => panic!("unexpected transaction msg"),
+    }
+}
+
+#[tokio::test]
+async fn retransmits_2xx_until_acked() {
+    let (handlers, (_, _, transport)) = setup().await;
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    dialog_sm
+        .process_outgoing_response(responses::ok_response_from(request.clone()))
+        .await;
+    assert!(matches!(dialog_sm.state, DialogState::UnAcked(..)));
+
+    dialog_sm.next().await;
+    assert_eq!(transport.messages().await.len().await, 0);
+
+    advance_for(std::time::Duration::from_millis(600)).await;
+    dialog_sm.next().await;
+    assert_eq!(transport.messages().await.len().await, 1);
+    assert!(matches!(dialog_sm.state, DialogState::UnAcked(..)));
+}
+
+#[tokio::test]
+async fn peer_modifies_a_confirmed_dialog() {
+    use sip_server::tu::calls::CallEvent;
+
+    let (handlers, (_, transaction, _)) = setup().await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone())
+        .await
+        .unwrap()
+        .with_events(events_tx);
+
+    let ok_response = responses::ok_response_from(request.clone());
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    dialog_sm
+        .process_incoming_request(request.ack_request_from(ok_response))
+        .await;
+    assert!(matches!(dialog_sm.state, DialogState::Confirmed(..)));
+
+    let mut reinvite = request.clone();
+    reinvite
+        .headers
+        .unique_push(rsip::typed::CSeq::from((2, rsip::Method::Invite)).into());
+    dialog_sm.process_incoming_request(reinvite.clone()).await;
+    assert_eq!(dialog_sm.remote_seqn, 2);
+    assert!(matches!(
+        events_rx.try_recv(),
+        Ok(CallEvent::ModificationRequested(_))
+    ));
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUasInvite(_, Some(trying)) => {
+            assert_eq!(trying.status_code, rsip::StatusCode::Trying)
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+
+    let ok_response = responses::ok_response_from(reinvite.clone());
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::Reply(response) => {
+            assert_eq!(response.status_code, 200.into());
+            assert_eq!(
+                response.to_header().unwrap().tag().unwrap(),
+                Some(dialog_sm.local_tag.clone())
+            );
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+
+    dialog_sm
+        .process_incoming_request(reinvite.ack_request_from(ok_response))
+        .await;
+    assert!(dialog_sm.modification.is_none());
+    assert!(matches!(dialog_sm.state, DialogState::Confirmed(..)));
+}
+
+#[tokio::test]
+async fn hangs_up_when_the_ack_of_a_reinvite_is_lost() {
+    let (handlers, (_, transaction, transport)) = setup().await;
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let ok_response = responses::ok_response_from(request.clone());
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    dialog_sm
+        .process_incoming_request(request.ack_request_from(ok_response))
+        .await;
+
+    let mut reinvite = request.clone();
+    reinvite
+        .headers
+        .unique_push(rsip::typed::CSeq::from((2, rsip::Method::Invite)).into());
+    dialog_sm.process_incoming_request(reinvite.clone()).await;
+    dialog_sm
+        .process_outgoing_response(responses::ok_response_from(reinvite))
+        .await;
+    assert!(dialog_sm.modification.is_some());
+
+    advance_for(std::time::Duration::from_millis(600)).await;
+    dialog_sm.next().await;
+    assert_eq!(transport.messages().await.len().await, 1);
+
+    //RFC3261 14.2, the ACK never arrived
+    advance_for(std::time::Duration::from_secs(33)).await;
+    dialog_sm.next().await;
+    assert!(dialog_sm.modification.is_none());
+    assert!(matches!(dialog_sm.state, DialogState::Terminated(..)));
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUac(bye) => assert_eq!(bye.method, rsip::Method::Bye),
+        _ => panic!("unexpected transaction msg"),
+    }
+}
+
+#[tokio::test]
+async fn rejects_overlapping_modifications_of_the_peer() {
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let ok_response = responses::ok_response_from(request.clone());
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    dialog_sm
+        .process_incoming_request(request.ack_request_from(ok_response))
+        .await;
+
+    let mut reinvite = request.clone();
+    reinvite
+        .headers
+        .unique_push(rsip::typed::CSeq::from((2, rsip::Method::Invite)).into());
+    dialog_sm.process_incoming_request(reinvite).await;
+
+    let mut update = request;
+    update.method = rsip::Method::Update;
+    update
+        .headers
+        .unique_push(rsip::typed::CSeq::from((3, rsip::Method::Update)).into());
+    dialog_sm.process_incoming_request(update).await;
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUas(request, Some(response)) => {
+            assert_eq!(request.method, rsip::Method::Update);
+            assert_eq!(response.status_code, 500.into());
+            assert!(response
+                .headers
+                .iter()
+                .any(|header| matches!(header, rsip::Header::RetryAfter(_))));
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert!(matches!(dialog_sm.state, DialogState::Confirmed(..)));
+}
+
+#[tokio::test]
+async fn negotiates_the_offer_of_the_invite() {
+    let (handlers, _) = setup().await;
+
+    let mut request = requests::invite_request();
+    request.body = sessions::audio(1).into_bytes();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+    assert!(dialog_sm.offer_answer.has_pending_offer());
+
+    let mut ok_response = responses::ok_response_from(request.clone());
+    ok_response.body = sessions::audio(1).into_bytes();
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    assert!(!dialog_sm.offer_answer.has_pending_offer());
+    assert_eq!(
+        dialog_sm.offer_answer.local,
+        Some(sessions::audio(1).parse().unwrap())
+    );
+    assert_eq!(
+        dialog_sm.offer_answer.remote,
+        Some(sessions::audio(1).parse().unwrap())
+    );
+}
+
+#[tokio::test]
+async fn rejects_an_invite_with_an_unacceptable_offer() {
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let mut request = requests::invite_request();
+    request.body = b"v=0".to_vec();
+    let dialog_sm = DialogSm::new(handlers, request).await.unwrap();
+
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUasInvite(_, Some(response)) => {
+            assert_eq!(response.status_code, 488.into())
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert!(!dialog_sm.offer_answer.has_pending_offer());
+    assert!(matches!(dialog_sm.state, DialogState::Terminated(..)));
+}
+
+#[tokio::test]
+async fn rejects_a_reoffer_that_removes_media() {
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let mut request = requests::invite_request();
+    request.body = sessions::audio_video(1).into_bytes();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let mut ok_response = responses::ok_response_from(request.clone());
+    ok_response.body = sessions::audio_video(1).into_bytes();
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    dialog_sm
+        .process_incoming_request(request.ack_request_from(ok_response))
+        .await;
+
+    let mut reinvite = request;
+    reinvite
+        .headers
+        .unique_push(rsip::typed::CSeq::from((2, rsip::Method::Invite)).into());
+    reinvite.body = sessions::audio(2).into_bytes();
+    dialog_sm.process_incoming_request(reinvite).await;
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUasInvite(_, Some(response)) => {
+            assert_eq!(response.status_code, 488.into())
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert!(dialog_sm.modification.is_none());
+    assert!(!dialog_sm.offer_answer.has_pending_offer());
+    assert!(matches!(dialog_sm.state, DialogState::Confirmed(..)));
+}
+
+fn refer_request_from(invite: rsip::Request, seq: u32) -> rsip::Request {
+    let mut request = invite;
+    request.method = rsip::Method::Refer;
+    request
+        .headers
+        .unique_push(rsip::typed::CSeq::from((seq, rsip::Method::Refer)).into());
+    request.headers.push(rsip::Header::Other(
+        "Refer-To".into(),
+        "<sip:carol@example.com>".into(),
+    ));
+    request
+}
+
+#[tokio::test]
+async fn peer_transfers_a_confirmed_dialog() {
+    use sip_server::tu::{calls::CallEvent, dialogs::refer};
+
+    let (handlers, (_, transaction, _)) = setup().await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone())
+        .await
+        .unwrap()
+        .with_events(events_tx);
+
+    let ok_response = responses::ok_response_from(request.clone());
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    dialog_sm
+        .process_incoming_request(request.ack_request_from(ok_response))
+        .await;
+
+    let refer_request = refer_request_from(request, 2);
+    dialog_sm
+        .process_incoming_request(refer_request.clone())
+        .await;
+    assert!(matches!(
+        events_rx.try_recv(),
+        Ok(CallEvent::TransferRequested(_))
+    ));
+
+    let accepted = sip_server::presets::response_from(refer_request, 202.into()).unwrap();
+    dialog_sm.process_outgoing_response(accepted).await;
+    let messages = transaction.messages().await;
+    let messages = messages.0.lock().await;
+    match &messages[messages.len() - 2] {
+        TransactionLayerMsg::NewUas(request, Some(response)) => {
+            assert_eq!(request.method, rsip::Method::Refer);
+            assert_eq!(response.status_code, 202.into());
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    match messages.last() {
+        Some(TransactionLayerMsg::NewUac(notify)) => {
+            assert_eq!(notify.method, rsip::Method::Notify);
+            assert!(refer::is_refer_event(&notify.headers));
+            assert_eq!(
+                refer::parse_sipfrag(&notify.body),
+                Some(rsip::StatusCode::Trying)
+            );
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert!(matches!(
+        dialog_sm.transfer,
+        Some(refer::Transfer::Accepted(_))
+    ));
+}
+
+#[tokio::test]
+async fn rejects_a_refer_without_refer_to() {
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let ok_response = responses::ok_response_from(request.clone());
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    dialog_sm
+        .process_incoming_request(request.ack_request_from(ok_response))
+        .await;
+
+    let mut refer_request = refer_request_from(request, 2);
+    refer_request
+        .headers
+        .retain(|header| !matches!(header, rsip::Header::Other(key, _) if key == "Refer-To"));
+    dialog_sm.process_incoming_request(refer_request).await;
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUas(_, Some(response)) => {
+            assert_eq!(response.status_code, 400.into())
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert!(dialog_sm.transfer.is_none());
+}
+
+fn reliable_invite_request() -> rsip::Request {
+    use common::rsip::headers::UntypedHeader;
+
+    let mut request = requests::invite_request();
+    request
+        .headers
+        .push(rsip::headers::Require::new("100rel").into());
+    request
+}
+
+fn prack_request_from(invite: rsip::Request, rack: reliable::RAck) -> rsip::Request {
+    let mut request = invite;
+    request.method = rsip::Method::PRack;
+    request
+        .headers
+        .unique_push(rsip::typed::CSeq::from((2, rsip::Method::PRack)).into());
+    request.headers.push(rack.into());
+    request
+}
+
+#[tokio::test]
+async fn retransmits_reliable_provisionals_until_pracked() {
+    use sip_server::tu::calls::CallEvent;
+
+    let (handlers, (_, transaction, transport)) = setup().await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let request = reliable_invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone())
+        .await
+        .unwrap()
+        .with_events(events_tx);
+
+    dialog_sm
+        .process_outgoing_response(responses::ringing_response_from(request.clone()))
+        .await;
+    assert!(matches!(dialog_sm.state, DialogState::Early(..)));
+    let rseq = match transaction.messages().await.latest().await {
+        TransactionLayerMsg::Reply(response) => {
+            assert!(reliable::requires_100rel(&response.headers));
+            reliable::rseq(&response.headers).expect("rseq")
+        }
+        _ => panic!("unexpected transaction msg"),
+    };
+    assert_eq!(dialog_sm.unpracked.as_ref().map(|u| u.rseq), Some(rseq));
+
+    advance_for(std::time::Duration::from_millis(600)).await;
+    dialog_sm.next().await;
+    assert_eq!(transport.messages().await.len().await, 1);
+
+    let rack = reliable::RAck {
+        rseq,
+        cseq: 1,
+        method: rsip::Method::Invite,
+    };
+    dialog_sm
+        .process_incoming_request(prack_request_from(request, rack))
+        .await;
+    assert!(dialog_sm.unpracked.is_none());
+    assert!(matches!(events_rx.try_recv(), Ok(CallEvent::Pracked(_))));
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUas(request, Some(response)) => {
+            assert_eq!(request.method, rsip::Method::PRack);
+            assert_eq!(response.status_code, 200.into());
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+
+    advance_for(std::time::Duration::from_millis(1100)).await;
+    dialog_sm.next().await;
+    assert_eq!(transport.messages().await.len().await, 1);
+}
+
+#[tokio::test]
+async fn queues_reliable_provisionals_until_the_previous_is_pracked() {
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let request = reliable_invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    dialog_sm
+        .process_outgoing_response(responses::ringing_response_from(request.clone()))
+        .await;
+    dialog_sm
+        .process_outgoing_response(responses::ringing_response_from(request.clone()))
+        .await;
+    assert_eq!(transaction.messages().await.len().await, 2);
+    assert_eq!(dialog_sm.queued_provisionals.len(), 1);
+
+    let rseq = dialog_sm.unpracked.as_ref().expect("unpracked").rseq;
+    let rack = reliable::RAck {
+        rseq,
+        cseq: 1,
+        method: rsip::Method::Invite,
+    };
+    dialog_sm
+        .process_incoming_request(prack_request_from(request, rack))
+        .await;
+    assert_eq!(transaction.messages().await.len().await, 4);
+    assert!(dialog_sm.queued_provisionals.is_empty());
+    assert_eq!(dialog_sm.unpracked.as_ref().map(|u| u.rseq), Some(rseq + 1));
+}
+
+#[tokio::test]
+async fn rejects_prack_that_matches_nothing() {
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let request = reliable_invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    dialog_sm
+        .process_outgoing_response(responses::ringing_response_from(request.clone()))
+        .await;
+    let rseq = dialog_sm.unpracked.as_ref().expect("unpracked").rseq;
+
+    let rack = reliable::RAck {
+        rseq: rseq + 1,
+        cseq: 1,
+        method: rsip::Method::Invite,
+    };
+    dialog_sm
+        .process_incoming_request(prack_request_from(request, rack))
+        .await;
+    assert!(dialog_sm.unpracked.is_some());
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUas(_, Some(response)) => {
+            assert_eq!(response.status_code, 481.into())
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+}
+
+#[tokio::test]
+async fn refreshes_the_session_when_refresher() {
+    use sip_server::tu::dialogs::session_timer::{Refresher, SessionExpires};
+
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let ok_response = responses::ok_response_from(request.clone());
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::Reply(response) => assert_eq!(
+            SessionExpires::from_headers(&response.headers),
+            Some(SessionExpires::new(1800, Some(Refresher::Uas)))
+        ),
+        _ => panic!("unexpected transaction msg"),
+    }
+    dialog_sm
+        .process_incoming_request(request.ack_request_from(ok_response))
+        .await;
+    assert_eq!(transaction.messages().await.len().await, 2);
+
+    advance_for(std::time::Duration::from_secs(901)).await;
+    dialog_sm.next().await;
+    assert_eq!(transaction.messages().await.len().await, 3);
+    let refresh = transaction
+        .messages()
+        .await
+        .try_latest()
+        .await
+        .new_uac_invite_msg();
+    assert_eq!(
+        SessionExpires::from_headers(&refresh.headers),
+        Some(SessionExpires::new(1800, Some(Refresher::Uac)))
+    );
+
+    //a single refresh, until it gets answered
+    dialog_sm.next().await;
+    assert_eq!(transaction.messages().await.len().await, 3);
+}
+
+#[tokio::test]
+async fn sends_bye_when_the_session_expires() {
+    use common::rsip::headers::UntypedHeader;
+    use sip_server::tu::dialogs::session_timer::SessionExpires;
+
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let mut request = requests::invite_request();
+    request
+        .headers
+        .push(rsip::headers::Supported::new("timer").into());
+    request.headers.push(SessionExpires::new(120, None).into());
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let ok_response = responses::ok_response_from(request.clone());
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    dialog_sm
+        .process_incoming_request(request.ack_request_from(ok_response))
+        .await;
+    assert!(!dialog_sm.session_timer.as_ref().unwrap().local_refresher);
+
+    advance_for(std::time::Duration::from_secs(80)).await;
+    dialog_sm.next().await;
+    assert!(matches!(dialog_sm.state, DialogState::Confirmed(..)));
+
+    advance_for(std::time::Duration::from_secs(10)).await;
+    dialog_sm.next().await;
+    assert!(matches!(dialog_sm.state, DialogState::Terminated(..)));
+    let bye = transaction
+        .messages()
+        .await
+        .try_latest()
+        .await
+        .new_uac_msg();
+    assert_eq!(bye.method, rsip::Method::Bye);
+}
+
+#[tokio::test]
+async fn rejects_a_refresh_below_min_se() {
+    use sip_server::tu::dialogs::session_timer::{self, SessionExpires};
+
+    let (handlers, (_, transaction, _)) = setup().await;
+
+    let request = requests::invite_request();
+    let mut dialog_sm = DialogSm::new(handlers, request.clone()).await.unwrap();
+
+    let ok_response = responses::ok_response_from(request.clone());
+    dialog_sm
+        .process_outgoing_response(ok_response.clone())
+        .await;
+    dialog_sm
+        .process_incoming_request(request.ack_request_from(ok_response))
+        .await;
+
+    let mut refresh = request.clone();
+    refresh
+        .headers
+        .unique_push(rsip::typed::CSeq::from((2, rsip::Method::Invite)).into());
+    refresh.headers.push(SessionExpires::new(0, None).into());
+    dialog_sm.process_incoming_request(refresh).await;
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUasInvite(_, Some(response)) => {
+            assert_eq!(response.status_code, 422.into());
+            assert_eq!(
+                session_timer::min_se(&response.headers),
+                Some(session_timer::MIN_SE)
+            );
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert!(dialog_sm.modification.is_none());
+    assert_eq!(dialog_sm.session_timer.as_ref().unwrap().interval, 1800);
+    assert!(matches!(dialog_sm.state, DialogState::Confirmed(..)));
+}
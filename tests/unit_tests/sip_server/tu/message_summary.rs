@@ -0,0 +1,60 @@
+use crate::common::factories::prelude::*;
+use common::rsip::{self, prelude::*};
+use sip_server::tu::subscriptions::{
+    packages::{
+        message_summary::{self, Summary},
+        MessageSummary,
+    },
+    EventPackage, Subscription, Subscriptions,
+};
+use std::convert::TryFrom;
+
+#[test]
+fn formats_the_simple_message_summary() {
+    let account = rsip::Uri::try_from("sip:fil@example.com").unwrap();
+
+    let summary = Summary {
+        new: 2,
+        old: 8,
+        new_urgent: 0,
+        old_urgent: 2,
+    };
+    assert_eq!(
+        message_summary::body(&account, &summary),
+        "Messages-Waiting: yes\r\nMessage-Account: sip:fil@example.com\r\nVoice-Message: 2/8 (0/2)\r\n"
+    );
+
+    assert!(message_summary::body(&account, &Default::default())
+        .starts_with("Messages-Waiting: no\r\n"));
+}
+
+#[tokio::test]
+async fn updates_the_counts_of_the_mailbox() {
+    let (handlers, _) = models::channels_builder(crate::common::config());
+    let subscriptions = Subscriptions::new(handlers);
+    let package = MessageSummary::new();
+    subscriptions.register(package.clone()).await;
+
+    let request = requests::subscribe_request(message_summary::EVENT);
+    let subscription = Subscription::new(
+        &crate::common::config(),
+        request.clone(),
+        message_summary::EVENT.into(),
+        package.default_expires(),
+    )
+    .unwrap();
+    let account = request.to_header().unwrap().uri().unwrap();
+
+    let summary = Summary {
+        new: 1,
+        ..Default::default()
+    };
+    package
+        .update(&subscriptions, &account, summary)
+        .await
+        .unwrap();
+    assert_eq!(package.summary(&account).await, summary);
+
+    let body = String::from_utf8(package.state(&subscription).await.unwrap()).unwrap();
+    assert!(body.contains("Voice-Message: 1/0 (0/0)"));
+}
@@ -0,0 +1,336 @@
+use crate::common::{advance_for, factories::prelude::*, snitches::SpySnitch};
+use common::rsip::{self, prelude::*};
+use models::{transaction::TransactionLayerMsg, transport::TransportLayerMsg, tu::TuLayerMsg};
+use sip_server::{
+    presets,
+    tu::elements::{Messenger, Registrar},
+    ReqProcessor,
+};
+
+pub async fn setup() -> (
+    SpySnitch<TuLayerMsg>,
+    SpySnitch<TransactionLayerMsg>,
+    SpySnitch<TransportLayerMsg>,
+) {
+    let (handlers, receivers) = models::channels_builder(crate::common::config());
+    let transport = SpySnitch::new(handlers.clone(), receivers.transport).expect("transport");
+    let transaction = SpySnitch::new(handlers.clone(), receivers.transaction).expect("transaction");
+    let tu = SpySnitch::new(handlers.clone(), receivers.tu).expect("tu");
+
+    (tu, transaction, transport)
+}
+
+//a MESSAGE to the user of the register requests
+fn message_request() -> rsip::Request {
+    let config = crate::common::config();
+    let base_uri: rsip::Uri = config.default_addr().into();
+
+    presets::message_request(
+        &config,
+        base_uri.clone().with_user("fil"),
+        base_uri.clone().with_user("filippos"),
+        base_uri.with_user("filippos"),
+        "text/plain",
+        b"hello".to_vec(),
+    )
+}
+
+#[tokio::test]
+async fn keeps_messages_until_the_recipient_registers() {
+    let postgres = crate::common::postgres();
+    let stores = store::Stores::postgres(postgres.clone());
+    let (_, transaction, transport) = setup().await;
+
+    let messenger = Messenger::new(transport.handlers()).with_stores(stores.clone());
+    let registrar = Registrar::new(transport.handlers()).with_stores(stores);
+
+    messenger
+        .process_incoming_request(message_request())
+        .await
+        .unwrap();
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUas(_, Some(response)) => {
+            assert_eq!(response.status_code, 202.into())
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert_eq!(
+        store::OfflineMessage::count(&mut postgres.conn().unwrap()).unwrap(),
+        1
+    );
+
+    let register_request = requests::register_request();
+    registrar
+        .process_incoming_request(register_request.clone())
+        .await
+        .unwrap();
+    let message = match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUac(message) => message,
+        _ => panic!("unexpected transaction msg"),
+    };
+    assert_eq!(message.method, rsip::Method::Message);
+    assert_eq!(message.body, b"hello".to_vec());
+    assert_eq!(
+        message.uri,
+        register_request
+            .contact_header()
+            .unwrap()
+            .typed()
+            .unwrap()
+            .uri
+    );
+    assert_eq!(
+        store::OfflineMessage::count(&mut postgres.conn().unwrap()).unwrap(),
+        1
+    );
+
+    //kept until the contact takes it
+    let response = presets::response_from(message, 200.into()).unwrap();
+    assert!(messenger.has_message_for(&response).await);
+    messenger.process_incoming_response(response).await.unwrap();
+    assert_eq!(
+        store::OfflineMessage::count(&mut postgres.conn().unwrap()).unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn keeps_messages_that_no_contact_takes() {
+    let postgres = crate::common::postgres();
+    let stores = store::Stores::postgres(postgres.clone());
+    let (_, transaction, transport) = setup().await;
+
+    let messenger = Messenger::new(transport.handlers()).with_stores(stores.clone());
+    let registrar = Registrar::new(transport.handlers()).with_stores(stores);
+
+    registrar
+        .process_incoming_request(requests::register_request())
+        .await
+        .unwrap();
+    messenger
+        .process_incoming_request(message_request())
+        .await
+        .unwrap();
+
+    let messages = transaction.messages().await;
+    let messages = messages.0.lock().await;
+    let fork = match &messages[0] {
+        TransactionLayerMsg::NewUac(fork) => fork.clone(),
+        _ => panic!("unexpected transaction msg"),
+    };
+    match &messages[1] {
+        TransactionLayerMsg::NewUas(_, Some(response)) => {
+            assert_eq!(response.status_code, 202.into())
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert_eq!(
+        store::OfflineMessage::count(&mut postgres.conn().unwrap()).unwrap(),
+        0
+    );
+
+    let response = presets::response_from(fork, 480.into()).unwrap();
+    assert!(messenger.has_message_for(&response).await);
+    messenger.process_incoming_response(response).await.unwrap();
+    assert_eq!(
+        store::OfflineMessage::count(&mut postgres.conn().unwrap()).unwrap(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn shares_the_in_memory_stores_with_the_registrar() {
+    let (_, transaction, transport) = setup().await;
+    let stores = store::Stores::in_memory();
+
+    let messenger = Messenger::new(transport.handlers()).with_stores(stores.clone());
+    let registrar = Registrar::new(transport.handlers()).with_stores(stores.clone());
+
+    messenger
+        .process_incoming_request(message_request())
+        .await
+        .unwrap();
+    //the address of record, without the port of the request uri
+    let recipient = format!(
+        "sip:filippos@{}",
+        crate::common::config().default_addr().host
+    );
+    assert_eq!(
+        stores
+            .offline_messages
+            .for_recipient(&recipient)
+            .unwrap()
+            .len(),
+        1
+    );
+
+    registrar
+        .process_incoming_request(requests::register_request())
+        .await
+        .unwrap();
+    let message = match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUac(message) => message,
+        _ => panic!("unexpected transaction msg"),
+    };
+    assert_eq!(message.method, rsip::Method::Message);
+
+    let response = presets::response_from(message, 200.into()).unwrap();
+    messenger.process_incoming_response(response).await.unwrap();
+    assert!(stores
+        .offline_messages
+        .for_recipient(&recipient)
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn keeps_messages_whose_forks_fail_without_a_response() {
+    let (_, transaction, transport) = setup().await;
+    let stores = store::Stores::in_memory();
+
+    let messenger = Messenger::new(transport.handlers()).with_stores(stores.clone());
+    let registrar = Registrar::new(transport.handlers()).with_stores(stores.clone());
+    registrar
+        .process_incoming_request(requests::register_request())
+        .await
+        .unwrap();
+    let recipient = format!(
+        "sip:filippos@{}",
+        crate::common::config().default_addr().host
+    );
+
+    //the contact can't be reached
+    messenger
+        .process_incoming_request(message_request())
+        .await
+        .unwrap();
+    let fork = transaction
+        .messages()
+        .await
+        .0
+        .lock()
+        .await
+        .iter()
+        .find_map(|msg| match msg {
+            TransactionLayerMsg::NewUac(fork) => Some(fork.clone()),
+            _ => None,
+        })
+        .unwrap();
+    assert!(messenger.has_message_for(&fork).await);
+    messenger.transport_error(fork.into()).await.unwrap();
+    assert_eq!(
+        stores
+            .offline_messages
+            .for_recipient(&recipient)
+            .unwrap()
+            .len(),
+        1
+    );
+
+    //the contact never answers
+    messenger
+        .process_incoming_request(message_request())
+        .await
+        .unwrap();
+    messenger.check_deliveries().await.unwrap();
+    assert_eq!(
+        stores
+            .offline_messages
+            .for_recipient(&recipient)
+            .unwrap()
+            .len(),
+        1
+    );
+    advance_for(std::time::Duration::from_secs(33)).await;
+    messenger.check_deliveries().await.unwrap();
+    assert_eq!(
+        stores
+            .offline_messages
+            .for_recipient(&recipient)
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn delivers_kept_messages_only_to_new_contacts() {
+    let (_, transaction, transport) = setup().await;
+    let stores = store::Stores::in_memory();
+
+    let messenger = Messenger::new(transport.handlers()).with_stores(stores.clone());
+    let registrar = Registrar::new(transport.handlers()).with_stores(stores);
+    messenger
+        .process_incoming_request(message_request())
+        .await
+        .unwrap();
+
+    registrar
+        .process_incoming_request(requests::register_request())
+        .await
+        .unwrap();
+    //a refresh while the message is still in flight
+    registrar
+        .process_incoming_request(requests::register_request())
+        .await
+        .unwrap();
+
+    let messages = transaction.messages().await;
+    let messages = messages.0.lock().await;
+    assert_eq!(
+        messages
+            .iter()
+            .filter(|msg| matches!(msg, TransactionLayerMsg::NewUac(_)))
+            .count(),
+        1
+    );
+    assert_eq!(transport.messages().await.len().await, 2);
+}
+
+#[tokio::test]
+async fn tells_apart_messages_that_share_a_call_id() {
+    let (_, transaction, transport) = setup().await;
+    let stores = store::Stores::in_memory();
+
+    let messenger = Messenger::new(transport.handlers()).with_stores(stores.clone());
+    let registrar = Registrar::new(transport.handlers()).with_stores(stores.clone());
+    registrar
+        .process_incoming_request(requests::register_request())
+        .await
+        .unwrap();
+
+    let first = message_request();
+    let mut second = first.clone();
+    second
+        .headers
+        .unique_push(rsip::typed::CSeq::from((2, rsip::Method::Message)).into());
+    second.body = b"bye".to_vec();
+    messenger.process_incoming_request(first).await.unwrap();
+    messenger.process_incoming_request(second).await.unwrap();
+
+    let forks = transaction
+        .messages()
+        .await
+        .0
+        .lock()
+        .await
+        .iter()
+        .filter_map(|msg| match msg {
+            TransactionLayerMsg::NewUac(fork) => Some(fork.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(forks.len(), 2);
+    let response = presets::response_from(forks[0].clone(), 480.into()).unwrap();
+    messenger.process_incoming_response(response).await.unwrap();
+    let response = presets::response_from(forks[1].clone(), 200.into()).unwrap();
+    messenger.process_incoming_response(response).await.unwrap();
+
+    let recipient = format!(
+        "sip:filippos@{}",
+        crate::common::config().default_addr().host
+    );
+    let kept = stores.offline_messages.for_recipient(&recipient).unwrap();
+    assert_eq!(kept.len(), 1);
+    assert_eq!(kept[0].body, "hello");
+}
@@ -1,3 +1,11 @@
+pub mod b2bua;
+pub mod calls;
 pub mod capabilities;
 pub mod dialogs;
+pub mod messenger;
+pub mod message_summary;
+pub mod presence;
+pub mod reg;
 pub mod registrar;
+pub mod router;
+pub mod subscriptions;
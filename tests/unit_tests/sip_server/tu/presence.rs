@@ -0,0 +1,118 @@
+use crate::common::{factories::prelude::*, snitches::SpySnitch};
+use common::rsip::{self, headers::UntypedHeader};
+use models::{
+    transaction::TransactionLayerMsg, transport::TransportLayerMsg, tu::TuLayerMsg, Handlers,
+};
+use sip_server::tu::subscriptions::{
+    packages::{presence, Presence},
+    Subscriptions,
+};
+use std::convert::TryFrom;
+
+pub async fn setup() -> (
+    Handlers,
+    (
+        SpySnitch<TuLayerMsg>,
+        SpySnitch<TransactionLayerMsg>,
+        SpySnitch<TransportLayerMsg>,
+    ),
+) {
+    let (handlers, receivers) = models::channels_builder(crate::common::config());
+    let transport = SpySnitch::new(handlers.clone(), receivers.transport).expect("transport");
+    let transaction = SpySnitch::new(handlers.clone(), receivers.transaction).expect("transaction");
+    let tu = SpySnitch::new(handlers.clone(), receivers.tu).expect("tu");
+
+    (handlers, (tu, transaction, transport))
+}
+
+#[test]
+fn composes_the_published_tuples() {
+    let phone = r#"<?xml version="1.0" encoding="UTF-8"?>
+<presence xmlns="urn:ietf:params:xml:ns:pidf" entity="pres:fil@example.com">
+  <tuple id="phone"><status><basic>closed</basic></status></tuple>
+</presence>"#;
+    let laptop = r#"<?xml version="1.0" encoding="UTF-8"?>
+<presence xmlns="urn:ietf:params:xml:ns:pidf" entity="pres:fil@example.com">
+  <tuple id="laptop"><status><basic>open</basic></status></tuple>
+  <tuple id="phone"><status><basic>open</basic></status></tuple>
+</presence>"#;
+
+    let document = presence::compose("pres:fil@example.com", &[phone, laptop]);
+    assert!(document.contains(r#"entity="pres:fil@example.com""#));
+    assert_eq!(document.matches("<tuple ").count(), 2);
+    assert!(!document.contains("closed"));
+    assert!(document.contains(r#"<tuple id="laptop">"#));
+
+    let document = presence::compose("pres:fil@example.com", &[]);
+    assert!(!document.contains("<tuple"));
+    assert!(document.ends_with("</presence>\r\n"));
+}
+
+#[test]
+fn uses_the_pres_uri_of_the_presentity() {
+    let resource = rsip::Uri::try_from("sip:fil@example.com:5090;transport=tcp").unwrap();
+    assert_eq!(presence::entity(&resource), "pres:fil@example.com");
+}
+
+#[tokio::test]
+async fn rejects_watchers_of_other_domains() {
+    let (handlers, (_, transaction, _)) = setup().await;
+    let subscriptions = Subscriptions::new(handlers);
+    subscriptions.register(Presence::new()).await;
+
+    let mut request = requests::subscribe_request("presence");
+    let watcher = rsip::Uri::try_from("sip:filippos@example.com").unwrap();
+    request.headers.unique_push(
+        rsip::typed::From::from(watcher)
+            .with_tag(Default::default())
+            .into(),
+    );
+    subscriptions
+        .process_incoming_request(request)
+        .await
+        .unwrap();
+
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUas(_, Some(response)) => {
+            assert_eq!(response.status_code, 403.into())
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+}
+
+#[tokio::test]
+async fn rejects_invalid_publications() {
+    let (handlers, (_, transaction, _)) = setup().await;
+    let subscriptions = Subscriptions::new(handlers);
+    subscriptions.register(Presence::new()).await;
+
+    subscriptions
+        .process_incoming_request(requests::publish_request("dialog"))
+        .await
+        .unwrap();
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUas(_, Some(response)) => {
+            assert_eq!(response.status_code, 489.into())
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+
+    let mut request = requests::publish_request("presence");
+    request
+        .headers
+        .push(rsip::headers::Expires::new("10").into());
+    subscriptions
+        .process_incoming_request(request)
+        .await
+        .unwrap();
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUas(_, Some(response)) => {
+            assert_eq!(response.status_code, 423.into());
+            assert!(response.headers.iter().any(|header| matches!(
+                header,
+                rsip::Header::Other(key, value) if key == "Min-Expires" && value == "60"
+            )));
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+}
@@ -0,0 +1,100 @@
+use crate::common::factories::prelude::*;
+use common::rsip::{self, prelude::*};
+use sip_server::tu::{
+    elements::{ContactEvent, RegistrationEvent},
+    subscriptions::{
+        packages::{reg, Reg},
+        EventPackage, Subscription, Subscriptions,
+    },
+};
+use std::convert::TryFrom;
+
+#[test]
+fn formats_the_reginfo_document() {
+    let aor = rsip::Uri::try_from("sip:fil@example.com:5090").unwrap();
+    assert!(reg::reginfo(&aor, 0, &[]).contains(r#"aor="sip:fil@example.com" "#));
+    assert!(reg::reginfo(&aor, 0, &[]).contains(r#"state="init""#));
+
+    let contacts = vec![
+        reg::ContactState {
+            uri: rsip::Uri::try_from("sip:fil@192.168.0.3:5060").unwrap(),
+            event: ContactEvent::Created,
+            expires: 600,
+        },
+        reg::ContactState {
+            uri: rsip::Uri::try_from("sip:fil@192.168.0.4:5060").unwrap(),
+            event: ContactEvent::Expired,
+            expires: 0,
+        },
+    ];
+    let document = reg::reginfo(&aor, 3, &contacts);
+    assert!(document.contains(r#"version="3" state="full""#));
+    assert!(document.contains(r#"state="active" event="created" expires="600""#));
+    assert!(document.contains(r#"state="terminated" event="expired""#));
+    assert!(document.contains("<uri>sip:fil@192.168.0.4:5060</uri>"));
+}
+
+#[tokio::test]
+async fn follows_the_registration_events() {
+    let (handlers, _) = models::channels_builder(crate::common::config());
+    let subscriptions = Subscriptions::new(handlers);
+    let package = Reg::new();
+    subscriptions.register(package.clone()).await;
+
+    let request = requests::subscribe_request(reg::EVENT);
+    let subscription = Subscription::new(
+        &crate::common::config(),
+        request.clone(),
+        reg::EVENT.into(),
+        package.default_expires(),
+    )
+    .unwrap();
+    let aor = request.to_header().unwrap().uri().unwrap();
+    let contact = rsip::Uri::try_from("sip:fil@192.168.0.3:5060").unwrap();
+
+    package
+        .process(
+            &subscriptions,
+            RegistrationEvent {
+                aor: aor.clone(),
+                contact: contact.clone(),
+                event: ContactEvent::Created,
+                expires: 600,
+            },
+        )
+        .await
+        .unwrap();
+    let document = String::from_utf8(package.state(&subscription).await.unwrap()).unwrap();
+    assert!(document.contains(r#"state="active" event="created""#));
+
+    package
+        .process(
+            &subscriptions,
+            RegistrationEvent {
+                aor: aor.clone(),
+                contact: contact.clone(),
+                event: ContactEvent::Unregistered,
+                expires: 0,
+            },
+        )
+        .await
+        .unwrap();
+    let document = String::from_utf8(package.state(&subscription).await.unwrap()).unwrap();
+    assert!(document.contains(r#"state="terminated">"#));
+    assert_eq!(document.matches("<contact ").count(), 1);
+
+    //terminated contacts are reported only once
+    package
+        .process(
+            &subscriptions,
+            RegistrationEvent {
+                aor: aor.clone(),
+                contact: rsip::Uri::try_from("sip:fil@192.168.0.4:5060").unwrap(),
+                event: ContactEvent::Created,
+                expires: 600,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(package.contacts(&aor).await.unwrap().len(), 1);
+}
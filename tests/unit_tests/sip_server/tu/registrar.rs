@@ -1,7 +1,7 @@
 use crate::common::{factories::prelude::*, snitches::SpySnitch};
 use common::{
     ipnetwork::IpNetwork,
-    rsip::{self, headers::UntypedHeader},
+    rsip::{self, headers::UntypedHeader, prelude::*},
 };
 use models::{transaction::TransactionLayerMsg, transport::TransportLayerMsg, tu::TuLayerMsg};
 use sip_server::{tu::elements::Registrar, ReqProcessor};
@@ -11,7 +11,7 @@ pub async fn setup() -> (
     SpySnitch<TransactionLayerMsg>,
     SpySnitch<TransportLayerMsg>,
 ) {
-    let (handlers, receivers) = models::channels_builder();
+    let (handlers, receivers) = models::channels_builder(crate::common::config());
     let transport = SpySnitch::new(handlers.clone(), receivers.transport).expect("transport");
     let transaction = SpySnitch::new(handlers.clone(), receivers.transaction).expect("transaction");
     let tu = SpySnitch::new(handlers.clone(), receivers.tu).expect("tu");
@@ -20,12 +20,12 @@ pub async fn setup() -> (
 }
 
 #[tokio::test]
-#[serial_test::serial]
 async fn with_no_records_returns_empty_list() {
-    let _ = crate::common::setup();
+    let postgres = crate::common::postgres();
     let (_, _, transport) = setup().await;
 
-    let registrar = Registrar::new(transport.handlers());
+    let registrar =
+        Registrar::new(transport.handlers()).with_stores(store::Stores::postgres(postgres.clone()));
 
     registrar
         .process_incoming_request(requests::register_query_request())
@@ -48,15 +48,15 @@ async fn with_no_records_returns_empty_list() {
 }
 
 #[tokio::test]
-#[serial_test::serial]
 async fn with_records_returns_a_list_of_contacts() {
-    let _ = crate::common::setup();
+    let postgres = crate::common::postgres();
     let (_, _, transport) = setup().await;
 
-    let registrar = Registrar::new(transport.handlers());
+    let registrar =
+        Registrar::new(transport.handlers()).with_stores(store::Stores::postgres(postgres.clone()));
 
-    create_registration();
-    create_registration();
+    create_registration(&postgres);
+    create_registration(&postgres);
 
     registrar
         .process_incoming_request(requests::register_query_request())
@@ -81,14 +81,14 @@ async fn with_records_returns_a_list_of_contacts() {
 }
 
 #[tokio::test]
-#[serial_test::serial]
 async fn with_new_register_request_saves_the_contact() {
-    let _ = crate::common::setup();
+    let postgres = crate::common::postgres();
     let (_, _, transport) = setup().await;
 
-    let registrar = Registrar::new(transport.handlers());
+    let registrar =
+        Registrar::new(transport.handlers()).with_stores(store::Stores::postgres(postgres.clone()));
 
-    create_registration();
+    create_registration(&postgres);
 
     registrar
         .process_incoming_request(requests::register_request())
@@ -112,20 +112,21 @@ async fn with_new_register_request_saves_the_contact() {
     );
 
     assert_eq!(
-        store::Registration::count(Default::default()).expect("registrations count"),
+        store::Registration::count(&mut postgres.conn().unwrap(), Default::default())
+            .expect("registrations count"),
         2
     )
 }
 
 #[tokio::test]
-#[serial_test::serial]
 async fn with_wrong_from_to_register() {
     use rsip::Uri;
 
-    let _ = crate::common::setup();
+    let postgres = crate::common::postgres();
     let (_, _, transport) = setup().await;
 
-    let registrar = Registrar::new(transport.handlers());
+    let registrar =
+        Registrar::new(transport.handlers()).with_stores(store::Stores::postgres(postgres.clone()));
 
     let mut request = requests::register_request();
     request
@@ -138,14 +139,14 @@ async fn with_wrong_from_to_register() {
 }
 
 #[tokio::test]
-#[serial_test::serial]
 async fn delete_registration() {
-    let _ = crate::common::setup();
+    let postgres = crate::common::postgres();
     let (_, _, transport) = setup().await;
 
-    let registrar = Registrar::new(transport.handlers());
+    let registrar =
+        Registrar::new(transport.handlers()).with_stores(store::Stores::postgres(postgres.clone()));
 
-    let (_registration, uri) = create_registration();
+    let (_registration, uri) = create_registration(&postgres);
 
     registrar
         .process_incoming_request(requests::register_delete_request_with_uri(uri))
@@ -169,12 +170,124 @@ async fn delete_registration() {
     );
 
     assert_eq!(
-        store::Registration::count(Default::default()).expect("registrations count"),
+        store::Registration::count(&mut postgres.conn().unwrap(), Default::default())
+            .expect("registrations count"),
         0
     )
 }
 
-fn create_registration() -> (store::Registration, rsip::Uri) {
+#[tokio::test]
+async fn emits_the_state_changes_of_the_bindings() {
+    use sip_server::tu::elements::ContactEvent;
+
+    let postgres = crate::common::postgres();
+    let (_, _, transport) = setup().await;
+
+    let registrar =
+        Registrar::new(transport.handlers()).with_stores(store::Stores::postgres(postgres.clone()));
+    let mut events = registrar.events();
+
+    registrar
+        .process_incoming_request(requests::register_request())
+        .await
+        .unwrap();
+    let event = events.try_recv().unwrap();
+    assert_eq!(event.event, ContactEvent::Created);
+    assert_eq!(event.aor.user(), Some("filippos"));
+    assert!(event.aor.port().is_none());
+
+    registrar
+        .process_incoming_request(requests::register_request())
+        .await
+        .unwrap();
+    assert_eq!(events.try_recv().unwrap().event, ContactEvent::Refreshed);
+
+    registrar
+        .process_incoming_request(requests::register_delete_request_with_uri(
+            event.contact.clone(),
+        ))
+        .await
+        .unwrap();
+    let event = events.try_recv().unwrap();
+    assert_eq!(event.event, ContactEvent::Unregistered);
+    assert_eq!(event.expires, 0);
+}
+
+#[tokio::test]
+async fn terminates_the_contact_that_a_new_one_replaces() {
+    use sip_server::tu::elements::ContactEvent;
+
+    let (_, _, transport) = setup().await;
+
+    let registrar = Registrar::new(transport.handlers()).with_stores(store::Stores::in_memory());
+    let mut events = registrar.events();
+
+    let request = requests::register_request();
+    let old_contact = request.contact_header().unwrap().typed().unwrap().uri;
+    registrar.process_incoming_request(request).await.unwrap();
+    assert_eq!(events.try_recv().unwrap().event, ContactEvent::Created);
+
+    let mut request = requests::register_request();
+    let new_contact = rsip::Uri {
+        host_with_port: rsip::HostWithPort::try_from("127.0.0.2:5070").unwrap(),
+        ..old_contact.clone()
+    };
+    request
+        .headers
+        .unique_push(rsip::typed::Contact::from(new_contact.clone()).into());
+    registrar.process_incoming_request(request).await.unwrap();
+
+    let event = events.try_recv().unwrap();
+    assert_eq!(event.event, ContactEvent::Unregistered);
+    assert_eq!(event.contact, old_contact);
+    let event = events.try_recv().unwrap();
+    assert_eq!(event.event, ContactEvent::Created);
+    assert_eq!(event.contact, new_contact);
+}
+
+#[tokio::test]
+async fn keeps_the_bindings_in_memory_stores() {
+    keeps_the_bindings_in(store::Stores::in_memory()).await
+}
+
+#[tokio::test]
+async fn keeps_the_bindings_in_sqlite_stores() {
+    let sqlite = store::Sqlite::new(":memory:").expect("sqlite");
+    assert!(!sqlite.run_migrations().expect("migrations").is_empty());
+
+    keeps_the_bindings_in(store::Stores::sqlite(sqlite)).await
+}
+
+async fn keeps_the_bindings_in(stores: store::Stores) {
+    let (_, _, transport) = setup().await;
+
+    let registrar = Registrar::new(transport.handlers()).with_stores(stores.clone());
+
+    registrar
+        .process_incoming_request(requests::register_request())
+        .await
+        .unwrap();
+    let sent_response = transport
+        .messages()
+        .await
+        .latest()
+        .await
+        .outgoing_response();
+    assert_eq!(sent_response.status_code, 200.into());
+    let registrations = stores.locations.all().expect("registrations");
+    assert_eq!(registrations.len(), 1);
+    assert_eq!(registrations[0].username, "filippos");
+
+    let uri = rsip::Uri::try_from(registrations[0].contact_uri.as_str()).unwrap();
+    registrar
+        .process_incoming_request(requests::register_delete_request_with_uri(uri))
+        .await
+        .unwrap();
+    assert!(stores.locations.all().expect("registrations").is_empty());
+    assert_eq!(transport.messages().await.len().await, 2);
+}
+
+fn create_registration(postgres: &store::Postgres) -> (store::Registration, rsip::Uri) {
     use ::common::chrono::{Duration, Utc};
     use std::convert::TryInto;
 
@@ -232,7 +345,8 @@ fn create_registration() -> (store::Registration, rsip::Uri) {
         .expect("contact try into");
 
     (
-        store::Registration::create(new_registration).expect("registration create"),
+        store::Registration::create(&mut postgres.conn().unwrap(), new_registration)
+            .expect("registration create"),
         uri,
     )
 }
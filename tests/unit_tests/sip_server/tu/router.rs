@@ -0,0 +1,148 @@
+use crate::common::factories::prelude::*;
+use common::{
+    async_trait::async_trait,
+    rsip::{self, headers::UntypedHeader},
+    tokio::sync::Mutex,
+};
+use models::transport::TransportLayerMsg;
+use sip_server::{
+    tu::router::{Route, Router},
+    ReqProcessor,
+};
+use std::sync::Arc;
+
+//keeps the methods of the requests that it receives, tagged with its name
+#[derive(Debug, Clone)]
+struct Recorder {
+    name: &'static str,
+    received: Arc<Mutex<Vec<(&'static str, rsip::Method)>>>,
+}
+
+#[async_trait]
+impl ReqProcessor for Recorder {
+    async fn process_incoming_request(
+        &self,
+        request: rsip::Request,
+    ) -> Result<(), sip_server::Error> {
+        self.received.lock().await.push((self.name, request.method));
+        Ok(())
+    }
+}
+
+fn recorders(names: &[&'static str]) -> Vec<Recorder> {
+    let received = Arc::new(Mutex::new(vec![]));
+
+    names
+        .iter()
+        .map(|name| Recorder {
+            name,
+            received: received.clone(),
+        })
+        .collect()
+}
+
+fn with_user(mut request: rsip::Request, user: &str) -> rsip::Request {
+    request.uri = request.uri.with_user(user);
+    request
+}
+
+#[tokio::test]
+async fn routes_by_method_uri_and_event() {
+    let (tu, _, transport) = super::calls::setup().await;
+    let recorders = recorders(&["toll_free", "presence", "registrar", "priority"]);
+    let router = Router::builder(tu.handlers())
+        .route(
+            Route::new(recorders[0].clone())
+                .method(rsip::Method::Invite)
+                .user("1800*"),
+        )
+        .event("presence", recorders[1].clone())
+        .method(rsip::Method::Register, recorders[2].clone())
+        .route(Route::new(recorders[3].clone()).header("priority", "emergency"))
+        .build();
+
+    let mut urgent = requests::invite_request();
+    urgent
+        .headers
+        .push(rsip::Header::Other("Priority".into(), "emergency".into()));
+
+    for request in vec![
+        with_user(requests::invite_request(), "18005550100"),
+        requests::subscribe_request("presence"),
+        requests::register_request(),
+        urgent,
+    ] {
+        router.process_incoming_request(request).await.unwrap();
+    }
+
+    assert_eq!(
+        recorders[0].received.lock().await.clone(),
+        vec![
+            ("toll_free", rsip::Method::Invite),
+            ("presence", rsip::Method::Subscribe),
+            ("registrar", rsip::Method::Register),
+            ("priority", rsip::Method::Invite),
+        ]
+    );
+    assert!(router
+        .processor_for(&with_user(requests::invite_request(), "2125550100"))
+        .is_none());
+    assert!(router
+        .processor_for(&requests::subscribe_request("dialog"))
+        .is_none());
+    assert_eq!(transport.messages().await.len().await, 0);
+}
+
+#[tokio::test]
+async fn answers_unrouted_requests_with_405() {
+    let (tu, _, transport) = super::calls::setup().await;
+    let recorders = recorders(&["registrar", "capabilities"]);
+    let router = Router::builder(tu.handlers())
+        .method(rsip::Method::Register, recorders[0].clone())
+        .method(rsip::Method::Options, recorders[1].clone())
+        .build()
+        .allowing(&[rsip::Method::Invite, rsip::Method::Register]);
+
+    router
+        .process_incoming_request(requests::bye_request())
+        .await
+        .unwrap();
+
+    assert!(recorders[0].received.lock().await.is_empty());
+    match transport.messages().await.first().await {
+        TransportLayerMsg::Outgoing(rsip::SipMessage::Response(response)) => {
+            assert_eq!(response.status_code, 405.into());
+            let allow = response
+                .headers
+                .iter()
+                .find_map(|header| match header {
+                    rsip::Header::Allow(allow) => Some(allow.value().to_string()),
+                    _ => None,
+                })
+                .expect("allow header");
+            assert_eq!(allow, "REGISTER, OPTIONS, INVITE");
+        }
+        _ => panic!("unexpected transport msg"),
+    }
+}
+
+#[tokio::test]
+async fn hands_unrouted_requests_to_the_fallback() {
+    let (tu, _, transport) = super::calls::setup().await;
+    let recorders = recorders(&["registrar", "fallback"]);
+    let router = Router::builder(tu.handlers())
+        .method(rsip::Method::Register, recorders[0].clone())
+        .fallback(recorders[1].clone())
+        .build();
+
+    router
+        .process_incoming_request(requests::bye_request())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        recorders[0].received.lock().await.clone(),
+        vec![("fallback", rsip::Method::Bye)]
+    );
+    assert_eq!(transport.messages().await.len().await, 0);
+}
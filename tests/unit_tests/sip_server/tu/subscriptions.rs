@@ -0,0 +1,299 @@
+use crate::common::{factories::prelude::*, snitches::SpySnitch};
+use common::{
+    async_trait::async_trait,
+    rsip::{self, headers::UntypedHeader, prelude::*},
+};
+use models::{
+    rsip_ext::*, transaction::TransactionLayerMsg, transport::TransportLayerMsg, tu::TuLayerMsg,
+    Handlers,
+};
+use sip_server::tu::subscriptions::{EventPackage, Subscription, Subscriptions, TerminationReason};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+#[derive(Debug)]
+struct Counter;
+
+#[async_trait]
+impl EventPackage for Counter {
+    fn name(&self) -> &str {
+        "counter"
+    }
+
+    fn content_type(&self) -> &str {
+        "text/plain"
+    }
+
+    async fn state(&self, _subscription: &Subscription) -> Result<Vec<u8>, sip_server::Error> {
+        Ok(b"1".to_vec())
+    }
+}
+
+//its state can't be read once broken
+#[derive(Debug, Default, Clone)]
+struct Flaky {
+    broken: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl EventPackage for Flaky {
+    fn name(&self) -> &str {
+        "flaky"
+    }
+
+    fn content_type(&self) -> &str {
+        "text/plain"
+    }
+
+    async fn state(&self, _subscription: &Subscription) -> Result<Vec<u8>, sip_server::Error> {
+        match self.broken.load(Ordering::SeqCst) {
+            true => Err(sip_server::Error::from("state is gone")),
+            false => Ok(b"1".to_vec()),
+        }
+    }
+}
+
+pub async fn setup() -> (
+    Handlers,
+    (
+        SpySnitch<TuLayerMsg>,
+        SpySnitch<TransactionLayerMsg>,
+        SpySnitch<TransportLayerMsg>,
+    ),
+) {
+    let (handlers, receivers) = models::channels_builder(crate::common::config());
+    let transport = SpySnitch::new(handlers.clone(), receivers.transport).expect("transport");
+    let transaction = SpySnitch::new(handlers.clone(), receivers.transaction).expect("transaction");
+    let tu = SpySnitch::new(handlers.clone(), receivers.tu).expect("tu");
+
+    (handlers, (tu, transaction, transport))
+}
+
+fn subscription_state(request: &rsip::Request) -> Option<String> {
+    request.headers.iter().find_map(|header| match header {
+        rsip::Header::SubscriptionState(state) => Some(state.value().to_string()),
+        _ => None,
+    })
+}
+
+#[tokio::test]
+async fn accepts_a_subscription_and_notifies_the_state() {
+    let (handlers, (_, transaction, _)) = setup().await;
+    let subscriptions = Subscriptions::new(handlers);
+    subscriptions.register(Counter).await;
+
+    let mut request = requests::subscribe_request("counter");
+    request
+        .headers
+        .push(rsip::headers::Expires::new("600").into());
+    subscriptions
+        .process_incoming_request(request.clone())
+        .await
+        .unwrap();
+
+    let messages = transaction.messages().await;
+    let messages = messages.0.lock().await;
+    assert_eq!(messages.len(), 2);
+    let dialog_id = match &messages[0] {
+        TransactionLayerMsg::NewUas(_, Some(response)) => {
+            assert_eq!(response.status_code, 200.into());
+            assert_eq!(response.expires_header().unwrap().seconds().unwrap(), 600);
+            response.uas_dialog_id().unwrap()
+        }
+        _ => panic!("unexpected transaction msg"),
+    };
+    match &messages[1] {
+        TransactionLayerMsg::NewUac(notify) => {
+            assert_eq!(notify.method, rsip::Method::Notify);
+            assert_eq!(notify.body, b"1".to_vec());
+            assert!(subscription_state(notify)
+                .unwrap()
+                .starts_with("active;expires="));
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert!(subscriptions.exists(&dialog_id).await);
+}
+
+#[tokio::test]
+async fn rejects_unknown_events_and_brief_intervals() {
+    let (handlers, (_, transaction, _)) = setup().await;
+    let subscriptions = Subscriptions::new(handlers);
+    subscriptions.register(Counter).await;
+
+    subscriptions
+        .process_incoming_request(requests::subscribe_request("presence"))
+        .await
+        .unwrap();
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUas(_, Some(response)) => {
+            assert_eq!(response.status_code, 489.into());
+            assert!(response.headers.iter().any(|header| matches!(
+                header,
+                rsip::Header::Other(key, value) if key == "Allow-Events" && value == "counter"
+            )));
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+
+    let mut request = requests::subscribe_request("counter");
+    request
+        .headers
+        .push(rsip::headers::Expires::new("10").into());
+    subscriptions
+        .process_incoming_request(request)
+        .await
+        .unwrap();
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUas(_, Some(response)) => {
+            assert_eq!(response.status_code, 423.into())
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+}
+
+#[tokio::test]
+async fn unsubscribing_terminates_the_subscription() {
+    let (handlers, (_, transaction, _)) = setup().await;
+    let subscriptions = Subscriptions::new(handlers);
+    subscriptions.register(Counter).await;
+
+    let request = requests::subscribe_request("counter");
+    subscriptions
+        .process_incoming_request(request.clone())
+        .await
+        .unwrap();
+    let response = match transaction.messages().await.first().await {
+        TransactionLayerMsg::NewUas(_, Some(response)) => response,
+        _ => panic!("unexpected transaction msg"),
+    };
+
+    let mut unsubscribe = request;
+    unsubscribe
+        .headers
+        .unique_push(response.to_header().unwrap().clone().into());
+    unsubscribe
+        .headers
+        .unique_push(rsip::typed::CSeq::from((2, rsip::Method::Subscribe)).into());
+    unsubscribe
+        .headers
+        .push(rsip::headers::Expires::new("0").into());
+    assert!(subscriptions.has_subscription_for(&unsubscribe).await);
+    subscriptions
+        .process_incoming_request(unsubscribe.clone())
+        .await
+        .unwrap();
+
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUac(notify) => {
+            assert_eq!(
+                subscription_state(&notify),
+                Some("terminated;reason=timeout".into())
+            );
+            assert_eq!(notify.cseq_header().unwrap().seq().unwrap(), 2);
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert!(!subscriptions.has_subscription_for(&unsubscribe).await);
+}
+
+#[tokio::test]
+async fn failing_notifies_do_not_keep_subscriptions_around() {
+    let (handlers, (_, transaction, _)) = setup().await;
+    let subscriptions = Subscriptions::new(handlers);
+    let flaky = Flaky::default();
+    subscriptions.register(flaky.clone()).await;
+
+    let request = requests::subscribe_request("flaky");
+    subscriptions
+        .process_incoming_request(request.clone())
+        .await
+        .unwrap();
+    let dialog_id = match transaction.messages().await.first().await {
+        TransactionLayerMsg::NewUas(_, Some(response)) => response.uas_dialog_id().unwrap(),
+        _ => panic!("unexpected transaction msg"),
+    };
+
+    flaky.broken.store(true, Ordering::SeqCst);
+    let resource = request.to_header().unwrap().uri().unwrap();
+    assert!(subscriptions.notify("flaky", &resource).await.is_ok());
+    assert!(subscriptions
+        .terminate("flaky", &resource, TerminationReason::Timeout)
+        .await
+        .is_ok());
+    assert!(!subscriptions.exists(&dialog_id).await);
+}
+
+#[tokio::test]
+async fn answers_refreshes_out_of_order_with_500() {
+    let (handlers, (_, transaction, _)) = setup().await;
+    let subscriptions = Subscriptions::new(handlers);
+    subscriptions.register(Counter).await;
+
+    let request = requests::subscribe_request("counter");
+    subscriptions
+        .process_incoming_request(request.clone())
+        .await
+        .unwrap();
+    let response = match transaction.messages().await.first().await {
+        TransactionLayerMsg::NewUas(_, Some(response)) => response,
+        _ => panic!("unexpected transaction msg"),
+    };
+
+    //same CSeq as the initial SUBSCRIBE
+    let mut refresh = request;
+    refresh
+        .headers
+        .unique_push(response.to_header().unwrap().clone().into());
+    subscriptions
+        .process_incoming_request(refresh.clone())
+        .await
+        .unwrap();
+
+    match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUas(_, Some(response)) => {
+            assert_eq!(response.status_code, 500.into())
+        }
+        _ => panic!("unexpected transaction msg"),
+    }
+    assert!(subscriptions.has_subscription_for(&refresh).await);
+}
+
+#[tokio::test]
+async fn notifies_through_the_route_set_of_the_subscribe() {
+    let (handlers, (_, transaction, _)) = setup().await;
+    let subscriptions = Subscriptions::new(handlers);
+    subscriptions.register(Counter).await;
+
+    let mut request = requests::subscribe_request("counter");
+    request.headers.push(
+        rsip::headers::RecordRoute::new("<sip:edge.example.com;lr>, <sip:core.example.com;lr>")
+            .into(),
+    );
+    subscriptions
+        .process_incoming_request(request.clone())
+        .await
+        .unwrap();
+
+    let notify = match transaction.messages().await.latest().await {
+        TransactionLayerMsg::NewUac(notify) => notify,
+        _ => panic!("unexpected transaction msg"),
+    };
+    assert_eq!(
+        notify.uri,
+        request.contact_header().unwrap().typed().unwrap().uri
+    );
+    let routes = notify
+        .headers
+        .iter()
+        .filter_map(|header| match header {
+            rsip::Header::Route(route) => Some(route.value().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(routes.len(), 2);
+    assert!(routes[0].contains("edge.example.com"));
+    assert!(routes[1].contains("core.example.com"));
+}